@@ -29,9 +29,9 @@ use libearthquake::{collections::{
             Movie as MovieInfo,
         },
         Version,
-    }, name, player::score::Frame, player::score::Score, resources::{cast::{CastMap, Member, MemberId}, config::{Config, Version as ConfigVersion}, movie::CastList}};
-use libcommon::{Reader, SharedStream, encodings::MAC_ROMAN};
-use libmactoolbox::{OSType, ResourceFile, ResourceId, ResourceSource, vfs::HostFileSystem};
+    }, name, player::{action_list::{Action, ActionList}, score::{Frame, FrameNum, Score}}, resources::{cast::{CastMap, Member, MemberId}, config::{Config, Version as ConfigVersion}, font_map::{CrossPlatformFontMap, FontMap}, movie::CastList}};
+use libcommon::{Reader, SharedStream, encodings::DecoderRef};
+use libmactoolbox::{OSType, ResourceFile, ResourceId, ResourceSource, script_manager::ScriptCode, vfs::HostFileSystem};
 use pico_args::Arguments;
 use std::{convert::{TryFrom, TryInto}, env, io::SeekFrom, path::{Path, PathBuf}, process::exit};
 
@@ -42,6 +42,7 @@ enum Command {
     PrintCastMember(Vec<MemberId>),
     PrintCastMembers,
     PrintConfig,
+    PrintFonts,
     PrintResource(Vec<ResourceId>),
     PrintResources,
     PrintScore(i16, Option<(i16, i16)>, Option<Vec<String>>),
@@ -82,6 +83,10 @@ impl Options {
         matches!(self.command, Command::PrintConfig)
     }
 
+    fn print_fonts(&self) -> bool {
+        matches!(self.command, Command::PrintFonts)
+    }
+
     fn print_resource(&self) -> Option<&Vec<ResourceId>> {
         match self.command {
             Command::PrintResource(ref resources) => Some(resources),
@@ -181,6 +186,7 @@ fn parse_command(args: &mut Arguments) -> AResult<Command> {
             "print-cast-member" => Command::PrintCastMember(args.values_from_fn::<_, MemberId, _>("--id", parse_member_id)?),
             "print-cast-members" => Command::PrintCastMembers,
             "print-casts" => Command::PrintCasts,
+            "print-fonts" => Command::PrintFonts,
             "print-resource" => Command::PrintResource(args.values_from_fn::<_, ResourceId, _>("--id", parse_resource_id)?),
             "print-resources" => Command::PrintResources,
             "print-score" => Command::PrintScore(
@@ -195,11 +201,12 @@ fn parse_command(args: &mut Arguments) -> AResult<Command> {
     }
 }
 
-fn print_frame(frame: &Frame, fields: &[String]) -> bool {
+fn print_frame(frame: &Frame, actions: &ActionList, fields: &[String]) -> bool {
     let mut print_sprites = false;
     for field in fields.iter() {
         match field.as_str() {
             "script" => println!("Script: {:?}", frame.script),
+            "action" => println!("Action: {:?} {:?}", frame.action, actions.get(frame.action).map(Action::text)),
             "sound_1" => println!("Sound 1: {:?}", frame.sound_1),
             "sound_2" => println!("Sound 2: {:?}", frame.sound_2),
             "transition" => println!("Transition: {:?}", frame.transition),
@@ -217,7 +224,7 @@ fn print_frame(frame: &Frame, fields: &[String]) -> bool {
     print_sprites
 }
 
-fn print_frame_sprites(frame: &Frame, fields: &[String]) {
+fn print_frame_sprites(frame: &Frame, actions: &ActionList, fields: &[String]) {
     for (i, sprite) in frame.sprites.as_ref().iter().enumerate() {
         for field in fields.iter() {
             match field.as_str() {
@@ -230,6 +237,7 @@ fn print_frame_sprites(frame: &Frame, fields: &[String]) {
                 "sprites.blend" => println!("Sprite {} blend: {:?}", i + 1, sprite.blend()),
                 "sprites.id" => println!("Sprite {} id: {:?}", i + 1, sprite.id()),
                 "sprites.script" => println!("Sprite {} script: {:?}", i + 1, sprite.script()),
+                "sprites.action" => println!("Sprite {} action: {:?} {:?}", i + 1, sprite.action(), actions.get(sprite.action()).map(Action::text)),
                 "sprites.fore_color_index" => println!("Sprite {} fore color index: {:?}", i + 1, sprite.fore_color_index()),
                 "sprites.back_color_index" => println!("Sprite {} back color index: {:?}", i + 1, sprite.back_color_index()),
                 "sprites.origin" => println!("Sprite {} origin: {:?}", i + 1, sprite.origin()),
@@ -300,13 +308,18 @@ fn main() -> AResult<()> {
     Ok(())
 }
 
-fn inspect_riff(stream: &mut impl Reader, options: &Options) -> AResult<()> {
+/// Returns the encoding of text in a file with the given detected charset.
+fn charset_decoder(charset: Option<ScriptCode>) -> DecoderRef {
+    charset.unwrap_or(ScriptCode::Roman).encoding()
+}
+
+fn inspect_riff(stream: &mut impl Reader, options: &Options, decoder: DecoderRef) -> AResult<()> {
     let riff = Riff::new(stream)?;
-    inspect_riff_contents(&riff, options)?;
+    inspect_riff_contents(&riff, options, decoder)?;
     Ok(())
 }
 
-fn inspect_riff_contents(riff: &Riff<impl Reader>, options: &Options) -> AResult<()> {
+fn inspect_riff_contents(riff: &Riff<impl Reader>, options: &Options, decoder: DecoderRef) -> AResult<()> {
     let config_id = if riff.contains((b"VWCF", 1024)) {
         Some(ResourceId::new(b"VWCF", 1024))
     } else if riff.contains((b"DRCF", 1024)) {
@@ -333,7 +346,7 @@ fn inspect_riff_contents(riff: &Riff<impl Reader>, options: &Options) -> AResult
         for resource in riff.iter() {
             let id = resource.id();
             if id.os_type().as_bytes() == b"MCsL" {
-                let cast_list = riff.load::<CastList>(id, &(MAC_ROMAN, ))?;
+                let cast_list = riff.load::<CastList>(id, &(decoder, ))?;
                 println!("{:?}", cast_list);
                 for (i, cast) in cast_list.iter().enumerate() {
                     println!("{}: {:?}", i, cast);
@@ -368,7 +381,7 @@ fn inspect_riff_contents(riff: &Riff<impl Reader>, options: &Options) -> AResult
                 if chunk_index > ChunkIndex::new(0) {
                     let cast_member_num = min_cast_num + i16::try_from(i).unwrap();
                     if options.print_cast_members() || options.print_cast_member().unwrap().contains(&MemberId::new(0, cast_member_num)) {
-                        match riff.load_chunk::<Member>(chunk_index, &(chunk_index, version, MAC_ROMAN)) {
+                        match riff.load_chunk::<Member>(chunk_index, &(chunk_index, version, decoder)) {
                             Ok(member) => println!("{}: {:#?}", cast_member_num, member),
                             Err(err) => println!("Failed to inspect cast member {}: {:#}", cast_member_num, err),
                         }
@@ -380,18 +393,19 @@ fn inspect_riff_contents(riff: &Riff<impl Reader>, options: &Options) -> AResult
         }
     }
 
-    print_score(options, riff);
+    print_fonts(options, riff, decoder);
+    print_score(options, riff, decoder);
 
     Ok(())
 }
 
-fn inspect_riff_container(stream: impl Reader, options: &Options) -> AResult<()> {
+fn inspect_riff_container(stream: impl Reader, options: &Options, decoder: DecoderRef) -> AResult<()> {
     let riff_container = RiffContainer::new(stream)?;
     for index in 0..riff_container.len() {
         println!("\nFile {}: {}", index + 1, riff_container.filename(index).unwrap().to_string_lossy());
         if options.recursive() && riff_container.kind(index).unwrap() != ChunkFileKind::Xtra {
             match riff_container.load_file(index) {
-                Ok(riff) => inspect_riff_contents(&riff, options)?,
+                Ok(riff) => inspect_riff_contents(&riff, options, decoder)?,
                 Err(e) => eprintln!("Could not inspect file: {}", e)
             }
         }
@@ -400,7 +414,54 @@ fn inspect_riff_container(stream: impl Reader, options: &Options) -> AResult<()>
     Ok(())
 }
 
-fn print_score(options: &Options, source: &impl ResourceSource) {
+fn print_fonts(options: &Options, source: &impl ResourceSource, decoder: DecoderRef) {
+    if !options.print_fonts() {
+        return;
+    }
+
+    let font_map_id = ResourceId::new(b"VWFM", 1024);
+    if source.contains(font_map_id) {
+        match source.load::<FontMap>(font_map_id, &(decoder, )) {
+            Ok(font_map) => {
+                println!("Fonts:");
+                for (font_num, name) in font_map.iter() {
+                    println!("{}: {}", font_num, name);
+                }
+            },
+            Err(e) => eprintln!("Failed to read font map: {:#}", e),
+        }
+    } else {
+        println!("No font map");
+    }
+
+    let default_map = CrossPlatformFontMap::default();
+    let substitutions_id = ResourceId::new(b"FXmp", 1024);
+    let substitutions = if source.contains(substitutions_id) {
+        match source.load::<CrossPlatformFontMap>(substitutions_id, &(decoder, )) {
+            Ok(substitutions) => (*substitutions).clone().with_fallback(&default_map),
+            Err(e) => {
+                eprintln!("Failed to read font substitutions: {:#}", e);
+                default_map
+            },
+        }
+    } else {
+        default_map
+    };
+
+    println!("Font substitutions:");
+    for substitution in substitutions.substitutions() {
+        print!("{:?}:{} => {:?}:{}", substitution.from_platform, substitution.from_name, substitution.to_platform, substitution.to_name);
+        if !substitution.map_chars {
+            print!(" MAP NONE");
+        }
+        for (from, to) in &substitution.sizes {
+            print!(" {}=>{}", from, to);
+        }
+        println!();
+    }
+}
+
+fn print_score(options: &Options, source: &impl ResourceSource, decoder: DecoderRef) {
     if let Some((score_num, frames, fields)) = options.print_score() {
         let config_id = ResourceId::new(b"VWCF", score_num);
         let config = match source.load::<Config>(config_id, &()) {
@@ -411,21 +472,34 @@ fn print_score(options: &Options, source: &impl ResourceSource) {
             }
         };
 
-        match source.load::<Score>(ResourceId::new(b"VWSC", score_num), &(config.version(), )) {
+        match Score::load_movie(source, score_num, &config, decoder) {
             Ok(score) => {
+                let labels = score.labels().clone();
+                let actions = score.actions().clone();
                 let (start, end) = frames.unwrap_or((0, i16::MAX));
-                for (i, frame) in (*score).clone().skip(start.try_into().unwrap()).take((end - start).try_into().unwrap()).enumerate() {
+                for (i, frame) in score.skip(start.try_into().unwrap()).take((end - start).try_into().unwrap()).enumerate() {
                     let frame_num = i16::try_from(i).unwrap() + start + 1;
                     match frame {
                         Ok(frame) => {
-                            println!("Frame {}:", frame_num);
+                            match labels.get(FrameNum(frame_num)) {
+                                Some(label) => println!("Frame {} ({:?}):", frame_num, label.name()),
+                                None => println!("Frame {}:", frame_num),
+                            }
                             if let Some(ref fields) = fields {
-                                let print_sprites = print_frame(&frame, fields);
+                                let print_sprites = print_frame(&frame, &actions, fields);
                                 if print_sprites {
-                                    print_frame_sprites(&frame, fields);
+                                    print_frame_sprites(&frame, &actions, fields);
                                 }
                             } else {
                                 println!("{:#?}", frame);
+                                if let Some(action) = actions.get(frame.action) {
+                                    println!("Frame action: {:?}", action.frame_script());
+                                }
+                                for (i, sprite) in frame.sprites.iter().enumerate() {
+                                    if let Some(action) = actions.get(sprite.action()) {
+                                        println!("Sprite {} action: {:?}", i + 1, action.sprite_script());
+                                    }
+                                }
                             }
                         },
                         Err(e) => {
@@ -434,12 +508,12 @@ fn print_score(options: &Options, source: &impl ResourceSource) {
                     }
                 }
             },
-            Err(e) => eprintln!("{}", e),
+            Err(e) => eprintln!("{:#}", e),
         }
     }
 }
 
-fn read_embedded_movie(num_movies: u16, stream: impl Reader, options: &Options) -> AResult<()> {
+fn read_embedded_movie(num_movies: u16, stream: impl Reader, options: &Options, decoder: DecoderRef) -> AResult<()> {
     let rom = ResourceFile::new(stream)?;
 
     if options.print_config() {
@@ -471,7 +545,8 @@ fn read_embedded_movie(num_movies: u16, stream: impl Reader, options: &Options)
         todo!("D3 cast member inspection");
     }
 
-    print_score(options, &rom);
+    print_fonts(options, &rom, decoder);
+    print_score(options, &rom, decoder);
 
     Ok(())
 }
@@ -499,9 +574,10 @@ fn read_file(filename: &str, options: &Options) -> AResult<()> {
 }
 
 fn read_movie(info: &MovieDetectionInfo, mut stream: impl Reader, options: &Options) -> AResult<()> {
+    let decoder = charset_decoder(info.charset());
     match info.kind() {
-        MovieKind::Movie | MovieKind::Cast => inspect_riff(&mut stream, options),
-        MovieKind::Accelerator | MovieKind::Embedded => read_embedded_movie(1, stream, options),
+        MovieKind::Movie | MovieKind::Cast => inspect_riff(&mut stream, options, decoder),
+        MovieKind::Accelerator | MovieKind::Embedded => read_embedded_movie(1, stream, options, decoder),
     }
 }
 
@@ -510,6 +586,7 @@ fn read_projector(
     mut stream: impl Reader,
     options: &Options
 ) -> AResult<()> {
+    let decoder = charset_decoder(info.charset());
     match info.movie() {
         MovieInfo::D3Win(movies) => {
             println!("{} embedded movies", movies.len());
@@ -518,14 +595,14 @@ fn read_projector(
                 println!("Internal movie at {}", movie.offset);
                 if options.recursive() {
                     let mut stream = stream.substream(movie.offset.into(), (movie.offset + movie.size).into());
-                    inspect_riff(&mut stream, options)?;
+                    inspect_riff(&mut stream, options, decoder)?;
                 }
             }
         },
         &MovieInfo::Internal(offset) => {
             println!("Internal movie at {}", offset);
             stream.seek(SeekFrom::Start(offset.into()))?;
-            inspect_riff_container(stream, options)?;
+            inspect_riff_container(stream, options, decoder)?;
         },
         MovieInfo::External(filenames) => {
             for filename in filenames {
@@ -560,7 +637,7 @@ fn read_projector(
         MovieInfo::Embedded(num_movies) => {
             println!("{} embedded movies", num_movies);
             if options.recursive() {
-                read_embedded_movie(*num_movies, stream, options)?;
+                read_embedded_movie(*num_movies, stream, options, decoder)?;
             }
         },
     }
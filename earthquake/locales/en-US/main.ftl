@@ -16,6 +16,7 @@ charset_russian = Russian
 charset_right-left-symbols = Right-Left Symbols
 charset_devanagari = Devanagari
 charset_gurmukhi = Gurmukhi
+charset_gujarati = Gujarati
 charset_oriya = Oriya
 charset_bengali = Bengali
 charset_tamil = Tamil
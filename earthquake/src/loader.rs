@@ -616,8 +616,7 @@ impl Loader {
                                     "platform" => info.config().platform().to_string()
                                 ]
                             ));
-                            // TODO: Heuristic detection of character set
-                            self.tabs.options.charset.set_current_index(0);
+                            self.tabs.options.charset.set_current_index(info.charset().map_or(0, |charset| charset as i32));
                             true
                         },
                         FileType::Movie(info, ..) if info.kind() != MovieKind::Cast => {
@@ -631,8 +630,7 @@ impl Loader {
                                     "kind" => info.kind().to_string()
                                 ]
                             ));
-                            // TODO: Heuristic detection of character set
-                            self.tabs.options.charset.set_current_index(0);
+                            self.tabs.options.charset.set_current_index(info.charset().map_or(0, |charset| charset as i32));
                             true
                         },
                         FileType::Movie(..) => {
@@ -91,7 +91,7 @@ impl <'vfs> Player<'vfs> {
     ) -> AResult<Self> {
         let (script_code, system_resources, movies) = match file.info {
             FileType::Projector(p) => (
-                p.charset().unwrap_or_else(|| charset.unwrap_or(ScriptCode::Roman)),
+                charset.or_else(|| p.charset()).unwrap_or(ScriptCode::Roman),
                 p.system_resources().cloned(),
                 match p.movie() {
                     &ProjectorMovie::Embedded(count) => {
@@ -116,7 +116,7 @@ impl <'vfs> Player<'vfs> {
                 },
             ),
             FileType::Movie(m) => (
-                charset.unwrap_or(ScriptCode::Roman),
+                charset.or_else(|| m.charset()).unwrap_or(ScriptCode::Roman),
                 None,
                 if m.version() == Version::D3 {
                     if let Some(resource_fork) = file.resource_fork.take() {
@@ -136,8 +136,12 @@ impl <'vfs> Player<'vfs> {
             ),
         };
 
+        let mut system = System::new(vfs.clone(), script_code, system_resources).context("Can’t create Macintosh Toolbox")?;
+        let stage_window = unsafe { Self::new_stage_window() };
+        unsafe { system.event_manager_mut().set_port(&stage_window); }
+
         Ok(Self {
-            system: System::new(vfs.clone(), script_code, system_resources).context("Can’t create Macintosh Toolbox")?,
+            system,
             movies,
             next_movie_event_kind,
             some_tick_count_51145c: None,
@@ -145,7 +149,7 @@ impl <'vfs> Player<'vfs> {
             paused: false,
             root_movie: Movie,
             root_score: Score::default(),
-            stage_window: unsafe { Self::new_stage_window() },
+            stage_window,
             windows: Vec::new(),
             vfs,
         })
@@ -1,4 +1,5 @@
-use encoding::{all as encodings, types::{DecoderTrap, Encoding as _}};
+use anyhow::{bail, Result as AResult};
+use encoding::{all as encodings, types::{DecoderTrap, EncoderTrap, Encoding as _, EncodingRef}};
 
 // Mappings: https://www.unicode.org/Public/MAPPINGS/VENDORS/
 
@@ -16,14 +17,29 @@ pub trait Decoder: std::fmt::Debug + Sync {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-pub struct MacJapanese;
-impl Decoder for MacJapanese {
-    fn decode(&self, _text: &[u8]) -> String {
-        todo!("MacJapanese decoder")
+pub trait Encoder: std::fmt::Debug + Sync {
+    /// Encodes a string into a byte vector. Characters which have no mapping
+    /// in the target encoding are handled according to the given policy.
+    fn encode(&self, text: &str, unmappable: Unmappable) -> AResult<Vec<u8>>;
+}
+
+/// What to do when a character cannot be represented by an encoding.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Unmappable {
+    /// Stop and return an error.
+    Fail,
+    /// Omit the character from the output.
+    Ignore,
+    /// Write a question mark in place of the character, like the Mac OS Text
+    /// Encoding Converter does.
+    Replace,
+}
+
+impl Default for Unmappable {
+    fn default() -> Self {
+        Self::Replace
     }
 }
-pub const MAC_JAPANESE: &MacJapanese = &MacJapanese;
 
 macro_rules! encodings_decoder(
     ($name:ident, $id:ident, $($module:ident)::+) => (
@@ -34,6 +50,13 @@ macro_rules! encodings_decoder(
                 $($module)::+.decode(text.as_ref(), DecoderTrap::Replace).unwrap()
             }
         }
+        impl Encoder for $id {
+            fn encode(&self, text: &str, unmappable: Unmappable) -> AResult<Vec<u8>> {
+                encode_chars(text, unmappable, |c, output| {
+                    encode_base($($module)::+, c).map(|bytes| output.extend(bytes)).is_some()
+                })
+            }
+        }
         pub const $name: &$id = &$id;
     );
 );
@@ -44,4 +67,512 @@ encodings_decoder!(WIN_CYRILLIC, WinCyrillic, encodings::WINDOWS_1251);
 encodings_decoder!(WIN_JAPANESE, WinJapanese, encodings::WINDOWS_31J);
 encodings_decoder!(WIN_ROMAN, WinRoman, encodings::WINDOWS_1252);
 
+/// Declares a double-byte Mac encoding which is a superset of a common CJK
+/// encoding. Apple’s variants add some extra single-byte characters outside of
+/// the lead byte range; everything else is handed off to the base encoding.
+macro_rules! multi_byte_decoder(
+    ($name:ident, $id:ident, $encoding:expr, $($lead:pat)|+, $($trail:pat)|+, { $($byte:literal => $char:literal),* $(,)? }) => (
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $id;
+        impl $id {
+            fn special(byte: u8) -> Option<char> {
+                match byte {
+                    $($byte => Some($char),)*
+                    _ => None,
+                }
+            }
+        }
+        impl Decoder for $id {
+            fn decode(&self, text: &[u8]) -> String {
+                decode_multi_byte(text, $encoding, |byte| matches!(byte, $($lead)|+), Self::special)
+            }
+        }
+        impl Encoder for $id {
+            fn encode(&self, text: &str, unmappable: Unmappable) -> AResult<Vec<u8>> {
+                encode_chars(text, unmappable, |c, output| {
+                    if let Some(byte) = reverse_lookup(c, Self::special) {
+                        output.push(byte);
+                        return true;
+                    }
+
+                    let bytes = encode_base($encoding, c);
+                    let is_valid = match bytes.as_deref() {
+                        Some(&[ byte ]) => {
+                            Self::special(byte).is_none() && !matches!(byte, $($lead)|+)
+                        },
+                        Some(&[ lead, trail ]) => {
+                            matches!(lead, $($lead)|+) && matches!(trail, $($trail)|+)
+                        },
+                        _ => false,
+                    };
+
+                    if is_valid {
+                        output.extend(bytes.unwrap());
+                    }
+                    is_valid
+                })
+            }
+        }
+        pub const $name: &$id = &$id;
+    );
+);
+
+/// Declares a single-byte Mac encoding which is ASCII in the low half and uses
+/// a lookup table for the high half. Bytes listed after the table are
+/// decoded normally but never used for encoding, for tables where a byte
+/// which Apple maps to a private use character is decoded to the same
+/// character as another byte.
+macro_rules! single_byte_decoder(
+    ($name:ident, $id:ident, $table:ident) => (
+        single_byte_decoder!($name, $id, $table, []);
+    );
+    ($name:ident, $id:ident, $table:ident, [ $($decode_only:literal),* ]) => (
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $id;
+        impl Decoder for $id {
+            fn decode(&self, text: &[u8]) -> String {
+                decode_single_byte(text, &$table)
+            }
+        }
+        impl Encoder for $id {
+            fn encode(&self, text: &str, unmappable: Unmappable) -> AResult<Vec<u8>> {
+                encode_chars(text, unmappable, |c, output| {
+                    encode_single_byte(c, &$table, &[ $($decode_only),* ]).map(|byte| output.push(byte)).is_some()
+                })
+            }
+        }
+        pub const $name: &$id = &$id;
+    );
+);
+
+/// Declares a single-byte Mac encoding which is a regional variant of Mac
+/// OS Roman that replaces only a few code points.
+macro_rules! roman_variant_decoder(
+    ($name:ident, $id:ident, { $($byte:literal => $char:literal),* $(,)? }) => (
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $id;
+        impl $id {
+            fn special(byte: u8) -> Option<char> {
+                match byte {
+                    $($byte => Some($char),)*
+                    _ => None,
+                }
+            }
+        }
+        impl Decoder for $id {
+            fn decode(&self, text: &[u8]) -> String {
+                decode_roman_variant(text, Self::special)
+            }
+        }
+        impl Encoder for $id {
+            fn encode(&self, text: &str, unmappable: Unmappable) -> AResult<Vec<u8>> {
+                encode_chars(text, unmappable, |c, output| {
+                    encode_roman_variant(c, Self::special).map(|byte| output.push(byte)).is_some()
+                })
+            }
+        }
+        pub const $name: &$id = &$id;
+    );
+);
+
+// Apple’s mappings for the CJK encodings also contain a handful of code points
+// in the vendor-specific rows which are either vertical presentation forms or
+// differ from the Microsoft equivalents. These are decoded according to the
+// base encoding.
+multi_byte_decoder!(MAC_CHINESE_SIMPLIFIED, MacChineseSimplified, encodings::GBK, 0xa1..=0xfc, 0xa1..=0xfe, {
+    0x80 => '\u{fc}',
+    0xa0 => '\u{a0}',
+    0xfd => '\u{a9}',
+    0xfe => '\u{2122}',
+    0xff => '\u{2026}',
+});
+multi_byte_decoder!(MAC_CHINESE_TRADITIONAL, MacChineseTraditional, encodings::BIG5_2003, 0xa1..=0xfc, 0x40..=0x7e | 0xa1..=0xfe, {
+    0x80 => '\\',
+    0xa0 => '\u{a0}',
+    0xfd => '\u{a9}',
+    0xfe => '\u{2122}',
+    0xff => '\u{2026}',
+});
+multi_byte_decoder!(MAC_JAPANESE, MacJapanese, encodings::WINDOWS_31J, 0x81..=0x9f | 0xe0..=0xfc, 0x40..=0x7e | 0x80..=0xfc, {
+    b'\\' => '\u{a5}',
+    0x80 => '\\',
+    0xa0 => '\u{a0}',
+    0xfd => '\u{a9}',
+    0xfe => '\u{2122}',
+    0xff => '\u{2026}',
+});
+multi_byte_decoder!(MAC_KOREAN, MacKorean, encodings::WINDOWS_949, 0xa1..=0xfd, 0xa1..=0xfe, {
+    0x80 => '\u{a0}',
+    0x81 => '\u{20a9}',
+    0x82 => '\u{2010}',
+    0x83 => '\u{a9}',
+    0xfe => '\u{2122}',
+    0xff => '\u{2026}',
+});
+
+single_byte_decoder!(MAC_ARABIC, MacArabic, MAC_ARABIC_TABLE);
+single_byte_decoder!(MAC_CENTRAL_EUROPE, MacCentralEurope, MAC_CENTRAL_EUROPE_TABLE);
+single_byte_decoder!(MAC_FARSI, MacFarsi, MAC_FARSI_TABLE);
+single_byte_decoder!(MAC_GREEK, MacGreek, MAC_GREEK_TABLE);
+single_byte_decoder!(MAC_HEBREW, MacHebrew, MAC_HEBREW_TABLE, [ 0xc0 ]);
+single_byte_decoder!(MAC_THAI, MacThai, MAC_THAI_TABLE);
+
+roman_variant_decoder!(MAC_CROATIAN, MacCroatian, {
+    0xa9 => '\u{160}',
+    0xae => '\u{17d}',
+    0xb4 => '\u{2206}',
+    0xb9 => '\u{161}',
+    0xbe => '\u{17e}',
+    0xc6 => '\u{106}',
+    0xc8 => '\u{10c}',
+    0xd0 => '\u{110}',
+    0xd8 => '\u{fffd}',
+    0xd9 => '\u{a9}',
+    0xde => '\u{c6}',
+    0xdf => '\u{bb}',
+    0xe0 => '\u{2013}',
+    0xe6 => '\u{107}',
+    0xe8 => '\u{10d}',
+    0xf0 => '\u{111}',
+    0xf9 => '\u{3c0}',
+    0xfa => '\u{cb}',
+    0xfd => '\u{ca}',
+    0xfe => '\u{e6}',
+});
+roman_variant_decoder!(MAC_ICELANDIC, MacIcelandic, {
+    0xa0 => '\u{dd}',
+    0xdc => '\u{d0}',
+    0xdd => '\u{f0}',
+    0xde => '\u{de}',
+    0xdf => '\u{fe}',
+    0xe0 => '\u{fd}',
+});
+roman_variant_decoder!(MAC_ROMANIAN, MacRomanian, {
+    0xae => '\u{102}',
+    0xaf => '\u{218}',
+    0xbe => '\u{103}',
+    0xbf => '\u{219}',
+    0xde => '\u{21a}',
+    0xdf => '\u{21b}',
+});
+roman_variant_decoder!(MAC_TURKISH, MacTurkish, {
+    0xda => '\u{11e}',
+    0xdb => '\u{11f}',
+    0xdc => '\u{130}',
+    0xdd => '\u{131}',
+    0xde => '\u{15e}',
+    0xdf => '\u{15f}',
+    0xf5 => '\u{fffd}',
+});
+
 pub type DecoderRef = &'static dyn Decoder;
+pub type EncoderRef = &'static dyn Encoder;
+
+fn decode_multi_byte(
+    text: &[u8],
+    encoding: EncodingRef,
+    is_lead: impl Fn(u8) -> bool,
+    special: impl Fn(u8) -> Option<char>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let decode_run = |run: &[u8], output: &mut String| {
+        if !run.is_empty() {
+            output.push_str(&encoding.decode(run, DecoderTrap::Replace).unwrap());
+        }
+    };
+
+    let mut run_start = 0;
+    let mut index = 0;
+    while index < text.len() {
+        let byte = text[index];
+        if is_lead(byte) && index + 1 < text.len() {
+            index += 2;
+        } else if let Some(c) = special(byte) {
+            decode_run(&text[run_start..index], &mut output);
+            output.push(c);
+            index += 1;
+            run_start = index;
+        } else {
+            index += 1;
+        }
+    }
+    decode_run(&text[run_start..], &mut output);
+    output
+}
+
+fn decode_roman_variant(text: &[u8], special: impl Fn(u8) -> Option<char>) -> String {
+    // Mac OS Roman maps every byte to exactly one character, so the decoded
+    // characters line up with the input bytes
+    encodings::MAC_ROMAN.decode(text, DecoderTrap::Replace).unwrap()
+        .chars()
+        .zip(text)
+        .map(|(c, &byte)| special(byte).unwrap_or(c))
+        .collect()
+}
+
+fn decode_single_byte(text: &[u8], table: &[u16; 128]) -> String {
+    text.iter().map(|&byte| {
+        if byte < 0x80 {
+            char::from(byte)
+        } else {
+            char::from_u32(table[usize::from(byte - 0x80)].into())
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+        }
+    }).collect()
+}
+
+fn encode_base(encoding: EncodingRef, c: char) -> Option<Vec<u8>> {
+    encoding.encode(c.encode_utf8(&mut [ 0; 4 ]), EncoderTrap::Strict).ok()
+}
+
+fn encode_chars(
+    text: &str,
+    unmappable: Unmappable,
+    mut encode_char: impl FnMut(char, &mut Vec<u8>) -> bool,
+) -> AResult<Vec<u8>> {
+    let mut output = Vec::with_capacity(text.len());
+    for (index, c) in text.char_indices() {
+        if !encode_char(c, &mut output) {
+            match unmappable {
+                Unmappable::Fail => bail!("Can’t encode {:?} at offset {}", c, index),
+                Unmappable::Ignore => {},
+                Unmappable::Replace => output.push(b'?'),
+            }
+        }
+    }
+    Ok(output)
+}
+
+fn encode_roman_variant(c: char, special: impl Fn(u8) -> Option<char>) -> Option<u8> {
+    reverse_lookup(c, &special).or_else(|| {
+        match encode_base(encodings::MAC_ROMAN, c).as_deref() {
+            Some(&[ byte ]) if special(byte).is_none() => Some(byte),
+            _ => None,
+        }
+    })
+}
+
+fn encode_single_byte(c: char, table: &[u16; 128], decode_only: &[u8]) -> Option<u8> {
+    if c.is_ascii() {
+        Some(c as u8)
+    } else if c == char::REPLACEMENT_CHARACTER {
+        None
+    } else {
+        table.iter()
+            .zip(0x80..=0xff)
+            .find(|&(&value, byte)| u32::from(value) == u32::from(c) && !decode_only.contains(&byte))
+            .map(|(_, byte)| byte)
+    }
+}
+
+fn reverse_lookup(c: char, special: impl Fn(u8) -> Option<char>) -> Option<u8> {
+    if c == char::REPLACEMENT_CHARACTER {
+        None
+    } else {
+        (0..=0xff).find(|&byte| special(byte) == Some(c))
+    }
+}
+
+// Characters which Apple maps to private use code points or to sequences with
+// bidirectional overrides are decoded to the closest single character, or to
+// the replacement character if there is none. When a character appears more
+// than once, the first occurrence is used for encoding, unless the byte is
+// marked as decode-only.
+
+const MAC_ARABIC_TABLE: [u16; 128] = [
+    0x00c4, 0x00a0, 0x00c7, 0x00c9, 0x00d1, 0x00d6, 0x00dc, 0x00e1,
+    0x00e0, 0x00e2, 0x00e4, 0x06ba, 0x00ab, 0x00e7, 0x00e9, 0x00e8,
+    0x00ea, 0x00eb, 0x00ed, 0x2026, 0x00ee, 0x00ef, 0x00f1, 0x00f3,
+    0x00bb, 0x00f4, 0x00f6, 0x00f7, 0x00fa, 0x00f9, 0x00fb, 0x00fc,
+    0x0020, 0x0021, 0x0022, 0x0023, 0x0024, 0x066a, 0x0026, 0x0027,
+    0x0028, 0x0029, 0x002a, 0x002b, 0x060c, 0x002d, 0x002e, 0x002f,
+    0x0660, 0x0661, 0x0662, 0x0663, 0x0664, 0x0665, 0x0666, 0x0667,
+    0x0668, 0x0669, 0x003a, 0x061b, 0x003c, 0x003d, 0x003e, 0x061f,
+    0x274a, 0x0621, 0x0622, 0x0623, 0x0624, 0x0625, 0x0626, 0x0627,
+    0x0628, 0x0629, 0x062a, 0x062b, 0x062c, 0x062d, 0x062e, 0x062f,
+    0x0630, 0x0631, 0x0632, 0x0633, 0x0634, 0x0635, 0x0636, 0x0637,
+    0x0638, 0x0639, 0x063a, 0x005b, 0x005c, 0x005d, 0x005e, 0x005f,
+    0x0640, 0x0641, 0x0642, 0x0643, 0x0644, 0x0645, 0x0646, 0x0647,
+    0x0648, 0x0649, 0x064a, 0x064b, 0x064c, 0x064d, 0x064e, 0x064f,
+    0x0650, 0x0651, 0x0652, 0x067e, 0x0679, 0x0686, 0x06d5, 0x06a4,
+    0x06af, 0x0688, 0x0691, 0x007b, 0x007c, 0x007d, 0x0698, 0x06d2,
+];
+
+const MAC_CENTRAL_EUROPE_TABLE: [u16; 128] = [
+    0x00c4, 0x0100, 0x0101, 0x00c9, 0x0104, 0x00d6, 0x00dc, 0x00e1,
+    0x0105, 0x010c, 0x00e4, 0x010d, 0x0106, 0x0107, 0x00e9, 0x0179,
+    0x017a, 0x010e, 0x00ed, 0x010f, 0x0112, 0x0113, 0x0116, 0x00f3,
+    0x0117, 0x00f4, 0x00f6, 0x00f5, 0x00fa, 0x011a, 0x011b, 0x00fc,
+    0x2020, 0x00b0, 0x0118, 0x00a3, 0x00a7, 0x2022, 0x00b6, 0x00df,
+    0x00ae, 0x00a9, 0x2122, 0x0119, 0x00a8, 0x2260, 0x0123, 0x012e,
+    0x012f, 0x012a, 0x2264, 0x2265, 0x012b, 0x0136, 0x2202, 0x2211,
+    0x0142, 0x013b, 0x013c, 0x013d, 0x013e, 0x0139, 0x013a, 0x0145,
+    0x0146, 0x0143, 0x00ac, 0x221a, 0x0144, 0x0147, 0x2206, 0x00ab,
+    0x00bb, 0x2026, 0x00a0, 0x0148, 0x0150, 0x00d5, 0x0151, 0x014c,
+    0x2013, 0x2014, 0x201c, 0x201d, 0x2018, 0x2019, 0x00f7, 0x25ca,
+    0x014d, 0x0154, 0x0155, 0x0158, 0x2039, 0x203a, 0x0159, 0x0156,
+    0x0157, 0x0160, 0x201a, 0x201e, 0x0161, 0x015a, 0x015b, 0x00c1,
+    0x0164, 0x0165, 0x00cd, 0x017d, 0x017e, 0x016a, 0x00d3, 0x00d4,
+    0x016b, 0x016e, 0x00da, 0x016f, 0x0170, 0x0171, 0x0172, 0x0173,
+    0x00dd, 0x00fd, 0x0137, 0x017b, 0x0141, 0x017c, 0x0122, 0x02c7,
+];
+
+/// Mac OS Farsi is Mac OS Arabic with Extended Arabic-Indic digits.
+const MAC_FARSI_TABLE: [u16; 128] = [
+    0x00c4, 0x00a0, 0x00c7, 0x00c9, 0x00d1, 0x00d6, 0x00dc, 0x00e1,
+    0x00e0, 0x00e2, 0x00e4, 0x06ba, 0x00ab, 0x00e7, 0x00e9, 0x00e8,
+    0x00ea, 0x00eb, 0x00ed, 0x2026, 0x00ee, 0x00ef, 0x00f1, 0x00f3,
+    0x00bb, 0x00f4, 0x00f6, 0x00f7, 0x00fa, 0x00f9, 0x00fb, 0x00fc,
+    0x0020, 0x0021, 0x0022, 0x0023, 0x0024, 0x066a, 0x0026, 0x0027,
+    0x0028, 0x0029, 0x002a, 0x002b, 0x060c, 0x002d, 0x002e, 0x002f,
+    0x06f0, 0x06f1, 0x06f2, 0x06f3, 0x06f4, 0x06f5, 0x06f6, 0x06f7,
+    0x06f8, 0x06f9, 0x003a, 0x061b, 0x003c, 0x003d, 0x003e, 0x061f,
+    0x274a, 0x0621, 0x0622, 0x0623, 0x0624, 0x0625, 0x0626, 0x0627,
+    0x0628, 0x0629, 0x062a, 0x062b, 0x062c, 0x062d, 0x062e, 0x062f,
+    0x0630, 0x0631, 0x0632, 0x0633, 0x0634, 0x0635, 0x0636, 0x0637,
+    0x0638, 0x0639, 0x063a, 0x005b, 0x005c, 0x005d, 0x005e, 0x005f,
+    0x0640, 0x0641, 0x0642, 0x0643, 0x0644, 0x0645, 0x0646, 0x0647,
+    0x0648, 0x0649, 0x064a, 0x064b, 0x064c, 0x064d, 0x064e, 0x064f,
+    0x0650, 0x0651, 0x0652, 0x067e, 0x0679, 0x0686, 0x06d5, 0x06a4,
+    0x06af, 0x0688, 0x0691, 0x007b, 0x007c, 0x007d, 0x0698, 0x06d2,
+];
+
+const MAC_GREEK_TABLE: [u16; 128] = [
+    0x00c4, 0x00b9, 0x00b2, 0x00c9, 0x00b3, 0x00d6, 0x00dc, 0x0385,
+    0x00e0, 0x00e2, 0x00e4, 0x0384, 0x00a8, 0x00e7, 0x00e9, 0x00e8,
+    0x00ea, 0x00eb, 0x00a3, 0x2122, 0x00ee, 0x00ef, 0x2022, 0x00bd,
+    0x2030, 0x00f4, 0x00f6, 0x00a6, 0x20ac, 0x00f9, 0x00fb, 0x00fc,
+    0x2020, 0x0393, 0x0394, 0x0398, 0x039b, 0x039e, 0x03a0, 0x00df,
+    0x00ae, 0x00a9, 0x03a3, 0x03aa, 0x00a7, 0x2260, 0x00b0, 0x00b7,
+    0x0391, 0x00b1, 0x2264, 0x2265, 0x00a5, 0x0392, 0x0395, 0x0396,
+    0x0397, 0x0399, 0x039a, 0x039c, 0x03a6, 0x03ab, 0x03a8, 0x03a9,
+    0x03ac, 0x039d, 0x00ac, 0x039f, 0x03a1, 0x2248, 0x03a4, 0x00ab,
+    0x00bb, 0x2026, 0x00a0, 0x03a5, 0x03a7, 0x0386, 0x0388, 0x0153,
+    0x2013, 0x2015, 0x201c, 0x201d, 0x2018, 0x2019, 0x00f7, 0x0389,
+    0x038a, 0x038c, 0x038e, 0x03ad, 0x03ae, 0x03af, 0x03cc, 0x038f,
+    0x03cd, 0x03b1, 0x03b2, 0x03c8, 0x03b4, 0x03b5, 0x03c6, 0x03b3,
+    0x03b7, 0x03b9, 0x03be, 0x03ba, 0x03bb, 0x03bc, 0x03bd, 0x03bf,
+    0x03c0, 0x03ce, 0x03c1, 0x03c3, 0x03c4, 0x03b8, 0x03c9, 0x03c2,
+    0x03c7, 0x03c5, 0x03b6, 0x03ca, 0x03cb, 0x0390, 0x03b0, 0x00ad,
+];
+
+const MAC_HEBREW_TABLE: [u16; 128] = [
+    0x00c4, 0xfb1f, 0x00c7, 0x00c9, 0x00d1, 0x00d6, 0x00dc, 0x00e1,
+    0x00e0, 0x00e2, 0x00e4, 0x00e3, 0x00e5, 0x00e7, 0x00e9, 0x00e8,
+    0x00ea, 0x00eb, 0x00ed, 0x00ec, 0x00ee, 0x00ef, 0x00f1, 0x00f3,
+    0x00f2, 0x00f4, 0x00f6, 0x00f5, 0x00fa, 0x00f9, 0x00fb, 0x00fc,
+    0x0020, 0x0021, 0x0022, 0x0023, 0x0024, 0x0025, 0x20aa, 0x0027,
+    0x0028, 0x0029, 0x002a, 0x002b, 0x002c, 0x002d, 0x002e, 0x002f,
+    0x0030, 0x0031, 0x0032, 0x0033, 0x0034, 0x0035, 0x0036, 0x0037,
+    0x0038, 0x0039, 0x003a, 0x003b, 0x003c, 0x003d, 0x003e, 0x003f,
+    0x05dc, 0x201e, 0xfffd, 0xfffd, 0xfffd, 0xfffd, 0x05bc, 0xfb4b,
+    0xfb35, 0x2026, 0x00a0, 0x05b8, 0x05b7, 0x05b5, 0x05b6, 0x05b4,
+    0x2013, 0x2014, 0x201c, 0x201d, 0x2018, 0x2019, 0xfb2a, 0xfb2b,
+    0x05bf, 0x05b0, 0x05b2, 0x05b1, 0x05bb, 0x05b9, 0x05b8, 0x05b3,
+    0x05d0, 0x05d1, 0x05d2, 0x05d3, 0x05d4, 0x05d5, 0x05d6, 0x05d7,
+    0x05d8, 0x05d9, 0x05da, 0x05db, 0x05dc, 0x05dd, 0x05de, 0x05df,
+    0x05e0, 0x05e1, 0x05e2, 0x05e3, 0x05e4, 0x05e5, 0x05e6, 0x05e7,
+    0x05e8, 0x05e9, 0x05ea, 0x007d, 0x005d, 0x007b, 0x005b, 0x007c,
+];
+
+/// Mac OS Thai is TIS-620 plus some punctuation. The positional variants of
+/// the tone marks in 0x83–0x9C have no Unicode equivalent.
+const MAC_THAI_TABLE: [u16; 128] = [
+    0x00ab, 0x00bb, 0x2026, 0xfffd, 0xfffd, 0xfffd, 0xfffd, 0xfffd,
+    0xfffd, 0xfffd, 0xfffd, 0xfffd, 0xfffd, 0xfffd, 0xfffd, 0xfffd,
+    0xfffd, 0xfffd, 0xfffd, 0x201c, 0x201d, 0xfffd, 0xfffd, 0x2022,
+    0xfffd, 0xfffd, 0xfffd, 0xfffd, 0xfffd, 0x2018, 0x2019, 0xfffd,
+    0x00a0, 0x0e01, 0x0e02, 0x0e03, 0x0e04, 0x0e05, 0x0e06, 0x0e07,
+    0x0e08, 0x0e09, 0x0e0a, 0x0e0b, 0x0e0c, 0x0e0d, 0x0e0e, 0x0e0f,
+    0x0e10, 0x0e11, 0x0e12, 0x0e13, 0x0e14, 0x0e15, 0x0e16, 0x0e17,
+    0x0e18, 0x0e19, 0x0e1a, 0x0e1b, 0x0e1c, 0x0e1d, 0x0e1e, 0x0e1f,
+    0x0e20, 0x0e21, 0x0e22, 0x0e23, 0x0e24, 0x0e25, 0x0e26, 0x0e27,
+    0x0e28, 0x0e29, 0x0e2a, 0x0e2b, 0x0e2c, 0x0e2d, 0x0e2e, 0x0e2f,
+    0x0e30, 0x0e31, 0x0e32, 0x0e33, 0x0e34, 0x0e35, 0x0e36, 0x0e37,
+    0x0e38, 0x0e39, 0x0e3a, 0xfeff, 0x200b, 0x2013, 0x2014, 0x0e3f,
+    0x0e40, 0x0e41, 0x0e42, 0x0e43, 0x0e44, 0x0e45, 0x0e46, 0x0e47,
+    0x0e48, 0x0e49, 0x0e4a, 0x0e4b, 0x0e4c, 0x0e4d, 0x2122, 0x0e4f,
+    0x0e50, 0x0e51, 0x0e52, 0x0e53, 0x0e54, 0x0e55, 0x0e56, 0x0e57,
+    0x0e58, 0x0e59, 0x00ae, 0x00a9, 0xfffd, 0xfffd, 0xfffd, 0xfffd,
+];
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    #[test]
+    fn mac_japanese() {
+        assert_eq!(MAC_JAPANESE.decode(b"\x93\xfa\x96\x7b\x8c\xea"), "日本語");
+        assert_eq!(MAC_JAPANESE.decode(b"\\100\x80\xb1\xfd"), "¥100\\ｱ©");
+        // 0x5c is a valid trail byte
+        assert_eq!(MAC_JAPANESE.decode(b"\x83\x5c"), "ソ");
+    }
+
+    #[test]
+    fn mac_cjk() {
+        assert_eq!(MAC_CHINESE_SIMPLIFIED.decode(b"\xd6\xd0\xce\xc4\xfe"), "中文™");
+        assert_eq!(MAC_CHINESE_TRADITIONAL.decode(b"\xa4\xa4\xa4\xe5\xff"), "中文…");
+        assert_eq!(MAC_KOREAN.decode(b"\xc7\xd1\xb1\xdb\x81"), "한글₩");
+    }
+
+    #[test]
+    fn mac_single_byte() {
+        assert_eq!(MAC_CENTRAL_EUROPE.decode(b"\xe1\x8b"), "Šč");
+        assert_eq!(MAC_GREEK.decode(b"\xe1\xe2\xe7"), "αβγ");
+        assert_eq!(MAC_HEBREW.decode(b"\xf9\xec\xe5\xed"), "שלום");
+        assert_eq!(MAC_ARABIC.decode(b"\xb1\xc7"), "١ا");
+        assert_eq!(MAC_FARSI.decode(b"\xb1\xc7"), "۱ا");
+        assert_eq!(MAC_THAI.decode(b"\xa1\xdf"), "ก฿");
+    }
+
+    #[test]
+    fn mac_roman_variants() {
+        assert_eq!(MAC_ROMAN.decode(b"\xda\x8a"), "⁄ä");
+        assert_eq!(MAC_TURKISH.decode(b"\xda\x8a"), "Ğä");
+        assert_eq!(MAC_CROATIAN.decode(b"\xa9\xd9"), "Š©");
+        assert_eq!(MAC_ICELANDIC.decode(b"\xde\xe0"), "Þý");
+        assert_eq!(MAC_ROMANIAN.decode(b"\xae\xbe"), "Ăă");
+    }
+
+    fn round_trip<T: Decoder + Encoder + ?Sized>(codec: &T, text: &[u8]) {
+        let decoded = codec.decode(text);
+        assert_eq!(codec.encode(&decoded, Unmappable::Fail).unwrap(), text, "{:?}", decoded);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        round_trip(MAC_ROMAN, b"caf\x8e \xa5");
+        round_trip(MAC_JAPANESE, b"\x93\xfa\x96\x7b\x8c\xea \\100\x80\xb1\xfd\x83\x5c");
+        round_trip(MAC_CHINESE_SIMPLIFIED, b"\xd6\xd0\xce\xc4\x80\xfe");
+        round_trip(MAC_CHINESE_TRADITIONAL, b"\xa4\xa4\xa4\xe5\x80\xff");
+        round_trip(MAC_KOREAN, b"\xc7\xd1\xb1\xdb\x81");
+        round_trip(MAC_CENTRAL_EUROPE, b"\xe1\x8b");
+        round_trip(MAC_GREEK, b"\xe1\xe2\xe7");
+        round_trip(MAC_HEBREW, b"\xf9\xec\xe5\xed");
+        assert_eq!(MAC_HEBREW.encode(&MAC_HEBREW.decode(b"\xc0"), Unmappable::Fail).unwrap(), b"\xec");
+        round_trip(MAC_ARABIC, b"\xb1\xc7");
+        round_trip(MAC_FARSI, b"\xb1\xc7");
+        round_trip(MAC_THAI, b"\xa1\xdf");
+        round_trip(MAC_TURKISH, b"\xda\x8a");
+        round_trip(MAC_CROATIAN, b"\xa9\xd9");
+        round_trip(MAC_ICELANDIC, b"\xde\xe0");
+        round_trip(MAC_ROMANIAN, b"\xae\xbe");
+        round_trip(MAC_CYRILLIC, b"\x80\xe0");
+        round_trip(WIN_ROMAN, b"caf\xe9");
+        round_trip(WIN_JAPANESE, b"\x93\xfa\x5c");
+        round_trip(WIN_CYRILLIC, b"\xc0\xe0");
+    }
+
+    #[test]
+    fn encode_unmappable() {
+        assert!(MAC_ROMAN.encode("a\u{3042}b", Unmappable::Fail).is_err());
+        assert_eq!(MAC_ROMAN.encode("a\u{3042}b", Unmappable::Ignore).unwrap(), b"ab");
+        assert_eq!(MAC_ROMAN.encode("a\u{3042}b", Unmappable::Replace).unwrap(), b"a?b");
+        // ⁄ is in Mac OS Roman, but its position is used by Ğ in Mac OS Turkish
+        assert_eq!(MAC_TURKISH.encode("\u{2044}", Unmappable::Replace).unwrap(), b"?");
+        // Characters outside of EUC-KR which are only valid in UHC
+        assert_eq!(MAC_KOREAN.encode("\u{ac02}", Unmappable::Replace).unwrap(), b"?");
+        assert_eq!(MAC_GREEK.encode("\u{fffd}", Unmappable::Replace).unwrap(), b"?");
+    }
+}
@@ -507,6 +507,7 @@ fn detect_subtype<T: Reader>(reader: &mut T) -> Option<DetectionInfo> {
             // This version of Director incorrectly includes the
             // size of the chunk header in the RIFF chunk size
             size: LittleEndian::read_u32(&chunk_size_raw) - 8,
+            charset: None,
         }),
         b"MV93" | b"39VM" => {
             let (endianness, size) = get_riff_attributes(sub_type, &chunk_size_raw);
@@ -516,6 +517,7 @@ fn detect_subtype<T: Reader>(reader: &mut T) -> Option<DetectionInfo> {
                 version: Version::D4,
                 kind: MovieKind::Movie,
                 size,
+                charset: None,
             })
         },
         b"MC95" | b"59CM" => {
@@ -526,6 +528,7 @@ fn detect_subtype<T: Reader>(reader: &mut T) -> Option<DetectionInfo> {
                 version: Version::D4,
                 kind: MovieKind::Cast,
                 size,
+                charset: None,
             })
         },
         b"APPL" | b"LPPA" => {
@@ -536,6 +539,7 @@ fn detect_subtype<T: Reader>(reader: &mut T) -> Option<DetectionInfo> {
                 version: Version::D4,
                 kind: MovieKind::Embedded,
                 size,
+                charset: None,
             })
         },
         _ => None
@@ -562,6 +566,102 @@ fn get_riff_attributes(os_type: OSType, raw_size: &[u8]) -> (Endianness, u32) {
 }
 
 #[cfg(test)]
-mod tests {
-    // TODO
+pub(crate) mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+    use libcommon::vfs::VirtualFileSystem;
+    use libmactoolbox::vfs::MemoryFileSystem;
+
+    fn chunk(data: &mut Vec<u8>, os_type: &[u8; 4], payload: &[u8]) {
+        data.extend_from_slice(os_type);
+        data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        data.extend_from_slice(payload);
+    }
+
+    /// A minimal big-endian Director 4 movie containing a single `VWCF` 1024
+    /// chunk with the payload “test”.
+    pub(crate) fn d4_movie() -> Vec<u8> {
+        const IMAP_OFFSET: u32 = 12;
+        const MMAP_OFFSET: u32 = 32;
+        const KEYS_OFFSET: u32 = 164;
+        const VWCF_OFFSET: u32 = 196;
+        const END_OFFSET: u32 = 208;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"RIFX");
+        data.extend_from_slice(&(END_OFFSET - 8).to_be_bytes());
+        data.extend_from_slice(b"MV93");
+
+        chunk(&mut data, b"imap", &{
+            let mut imap = Vec::new();
+            imap.extend_from_slice(&1_u32.to_be_bytes());
+            imap.extend_from_slice(&MMAP_OFFSET.to_be_bytes());
+            imap.extend_from_slice(&[ 0; 4 ]);
+            imap
+        });
+
+        chunk(&mut data, b"mmap", &{
+            let entries: [ (&[u8; 4], u32, u32); 5 ] = [
+                (b"RIFX", END_OFFSET - 8, 0),
+                (b"imap", MMAP_OFFSET - IMAP_OFFSET - 8, IMAP_OFFSET),
+                (b"mmap", KEYS_OFFSET - MMAP_OFFSET - 8, MMAP_OFFSET),
+                (b"KEY*", VWCF_OFFSET - KEYS_OFFSET - 8, KEYS_OFFSET),
+                (b"VWCF", END_OFFSET - VWCF_OFFSET - 8, VWCF_OFFSET),
+            ];
+            let mut mmap = Vec::new();
+            mmap.extend_from_slice(&0x18_u16.to_be_bytes());
+            mmap.extend_from_slice(&0x14_u16.to_be_bytes());
+            mmap.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+            mmap.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+            mmap.extend_from_slice(&(-1_i32).to_be_bytes());
+            mmap.extend_from_slice(&[ 0; 4 ]);
+            mmap.extend_from_slice(&(-1_i32).to_be_bytes());
+            for (os_type, size, offset) in &entries {
+                mmap.extend_from_slice(*os_type);
+                mmap.extend_from_slice(&size.to_be_bytes());
+                mmap.extend_from_slice(&offset.to_be_bytes());
+                mmap.extend_from_slice(&[ 0; 4 ]);
+                mmap.extend_from_slice(&(-1_i32).to_be_bytes());
+            }
+            mmap
+        });
+
+        chunk(&mut data, b"KEY*", &{
+            let mut keys = Vec::new();
+            keys.extend_from_slice(&12_u16.to_be_bytes());
+            keys.extend_from_slice(&12_u16.to_be_bytes());
+            keys.extend_from_slice(&1_u32.to_be_bytes());
+            keys.extend_from_slice(&1_u32.to_be_bytes());
+            keys.extend_from_slice(&4_i32.to_be_bytes());
+            keys.extend_from_slice(&1024_i32.to_be_bytes());
+            keys.extend_from_slice(b"VWCF");
+            keys
+        });
+
+        chunk(&mut data, b"VWCF", b"test");
+        assert_eq!(data.len(), END_OFFSET as usize);
+        data
+    }
+
+    #[test]
+    fn load_d4_movie() {
+        let fs = MemoryFileSystem::new().with_data_fork("Movie", d4_movie());
+        let riff = Riff::new(fs.open(&"Movie").unwrap()).unwrap();
+        assert_eq!(riff.version(), Version::D4);
+        assert_eq!(riff.kind(), MovieKind::Movie);
+        assert_eq!(riff.first_of_kind(b"KEY*"), ChunkIndex::new(3));
+        assert_eq!(riff.first_of_kind(b"CASt"), ChunkIndex::new(-1));
+
+        let id = ResourceId::new(b"VWCF", 1024);
+        assert!(riff.contains(id));
+        assert!(!riff.contains(ResourceId::new(b"VWCF", 1025)));
+        assert_eq!(*riff.load::<Vec<u8>>(id, &()).unwrap(), b"test");
+        assert_eq!(riff.iter().map(|chunk| chunk.id()).collect::<Vec<_>>(), vec![ id ]);
+    }
+
+    #[test]
+    fn detect_non_riff() {
+        let fs = MemoryFileSystem::new().with_data_fork("Movie", &b"XXXX\0\0\0\0MV93"[..]);
+        assert!(detect(&mut fs.open(&"Movie").unwrap()).is_err());
+    }
 }
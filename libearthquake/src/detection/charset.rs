@@ -0,0 +1,413 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use crate::{
+    collections::riff::Riff,
+    resources::{cast::{CastMap, Member}, config::{Config, Platform}},
+};
+use libcommon::{
+    encodings::{DecoderRef, Encoder, Unmappable, MAC_ROMAN, WIN_CYRILLIC, WIN_JAPANESE, WIN_ROMAN},
+    Reader,
+};
+use libmactoolbox::{
+    ResourceFile,
+    ResourceId,
+    ResourceSource,
+    resources::version::Version as VersionResource,
+    script_manager::{CountryCode, ScriptCode},
+};
+
+/// The scripts which are considered when guessing the character set of some
+/// text. The order matters: when two scripts have the same score, the earlier
+/// one wins.
+const CANDIDATES: [ScriptCode; 11] = [
+    ScriptCode::Roman,
+    ScriptCode::Japanese,
+    ScriptCode::ChineseTraditional,
+    ScriptCode::ChineseSimplified,
+    ScriptCode::Korean,
+    ScriptCode::Arabic,
+    ScriptCode::Hebrew,
+    ScriptCode::Greek,
+    ScriptCode::Russian,
+    ScriptCode::Thai,
+    ScriptCode::NonCyrillicSlavic,
+];
+
+/// Weight given to a region setting which says the file is from a region that
+/// does not use the Roman script.
+const REGION_WEIGHT: i32 = 20;
+
+/// Weight given to a region setting which says the file is from a region that
+/// uses the Roman script. This is lower than [`REGION_WEIGHT`] because many
+/// localised titles never changed the region from the default.
+const ROMAN_REGION_WEIGHT: i32 = 5;
+
+/// Guesses the character set of some files by scoring each candidate script
+/// against the available evidence.
+///
+/// Evidence comes from region settings, which are strong hints, and from the
+/// byte statistics of text samples. Text is scored by decoding it with each
+/// candidate encoding and rewarding characters which are native to that script
+/// while penalising invalid sequences and unlikely characters.
+#[derive(Debug)]
+pub struct CharsetDetector {
+    platform: Platform,
+    scores: [i32; CANDIDATES.len()],
+    has_evidence: bool,
+}
+
+impl Default for CharsetDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CharsetDetector {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            platform: Platform::Unknown,
+            scores: [ 0; CANDIDATES.len() ],
+            has_evidence: false,
+        }
+    }
+
+    /// Adds evidence from a Mac region code, like the one in a `vers`
+    /// resource.
+    pub fn add_country_code(&mut self, country_code: CountryCode) {
+        self.add_script(country_code.script());
+    }
+
+    /// Adds a text sample. Samples which are pure ASCII carry no information
+    /// and are ignored.
+    pub fn add_text(&mut self, text: &[u8]) {
+        if text.is_ascii() {
+            return;
+        }
+
+        self.has_evidence = true;
+        for (score, &script) in self.scores.iter_mut().zip(CANDIDATES.iter()) {
+            *score += score_text(script, decoder(script, self.platform), text);
+        }
+    }
+
+    /// Adds evidence from a Windows language identifier, like the one in the
+    /// version information of an executable.
+    pub fn add_windows_language(&mut self, language: u16) {
+        self.add_script(windows_language_script(language));
+    }
+
+    /// Returns the script with the highest score, or `None` if no evidence was
+    /// added.
+    #[must_use]
+    pub fn detect(&self) -> Option<ScriptCode> {
+        if !self.has_evidence {
+            return None;
+        }
+
+        let mut best = 0;
+        for (index, &score) in self.scores.iter().enumerate() {
+            if score > self.scores[best] {
+                best = index;
+            }
+        }
+
+        Some(CANDIDATES[best])
+    }
+
+    /// Sets the platform which created the text samples. Windows authoring
+    /// environments store text using Windows code pages instead of Mac
+    /// encodings. This must be set before adding text.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+    }
+
+    fn add_script(&mut self, script: ScriptCode) {
+        self.has_evidence = true;
+        if let Some(index) = CANDIDATES.iter().position(|&candidate| candidate == script) {
+            self.scores[index] += if script == ScriptCode::Roman {
+                ROMAN_REGION_WEIGHT
+            } else {
+                REGION_WEIGHT
+            };
+        }
+    }
+}
+
+/// Guesses the character set of a Director 3 for Mac movie from its region
+/// setting and the text of its resources.
+#[must_use]
+pub fn detect_resource_file(rom: &ResourceFile<impl Reader>) -> Option<ScriptCode> {
+    let mut detector = CharsetDetector::new();
+    detector.set_platform(Platform::Mac);
+
+    if let Ok(version) = rom.load::<VersionResource>(ResourceId::new(b"vers", 1), &()) {
+        detector.add_country_code(version.country_code());
+    }
+
+    for id in rom.iter() {
+        if let Some(name) = rom.name_of(id) {
+            detector.add_text(name);
+        }
+    }
+
+    for id in rom.iter_kind(b"STXT") {
+        if let Ok(data) = rom.load::<Vec<u8>>(id, &()) {
+            if let Some(text) = styled_text(&data) {
+                detector.add_text(text);
+            }
+        }
+    }
+
+    detector.detect()
+}
+
+/// Guesses the character set of a Director 4+ movie from the authoring
+/// platform, cast member names, and field text.
+#[must_use]
+pub fn detect_riff(riff: &Riff<impl Reader>) -> Option<ScriptCode> {
+    let mut detector = CharsetDetector::new();
+
+    let config = [ b"VWCF", b"DRCF" ].iter()
+        .find_map(|&os_type| riff.load::<Config>(ResourceId::new(os_type, 1024), &()).ok());
+
+    if let Some(config) = config {
+        detector.set_platform(config.platform());
+
+        // TODO: Handle multiple internal casts
+        if let Ok(cast) = riff.load::<CastMap>(ResourceId::new(b"CAS*", 1024), &()) {
+            for &chunk_index in cast.iter() {
+                // Names are decoded as Mac OS Roman and re-encoded so that the
+                // original bytes can be scored; this is lossless because every
+                // byte has a mapping in Mac OS Roman
+                let name = riff.load_chunk::<Member>(chunk_index, &(chunk_index, config.version(), MAC_ROMAN))
+                    .ok()
+                    .and_then(|member| member.info().and_then(|info| info.name()))
+                    .and_then(|name| MAC_ROMAN.encode(&name, Unmappable::Fail).ok());
+                if let Some(name) = name {
+                    detector.add_text(&name);
+                }
+            }
+        }
+    }
+
+    for chunk in riff.iter().filter(|chunk| chunk.id().os_type().as_bytes() == b"STXT") {
+        if let Ok(data) = chunk.load::<Vec<u8>>(&()) {
+            if let Some(text) = styled_text(&data) {
+                detector.add_text(text);
+            }
+        }
+    }
+
+    detector.detect()
+}
+
+fn decoder(script: ScriptCode, platform: Platform) -> DecoderRef {
+    match (platform, script) {
+        (Platform::Win, ScriptCode::Roman) => WIN_ROMAN,
+        (Platform::Win, ScriptCode::Japanese) => WIN_JAPANESE,
+        (Platform::Win, ScriptCode::Russian) => WIN_CYRILLIC,
+        _ => script.encoding(),
+    }
+}
+
+/// The most frequently used characters in Korean text.
+const FREQUENT_KOREAN: &str = "이다는의가에을고하지를한서기로도사리으자나들대인어시아적수게일해정그니전만있요것국상라주면보장";
+
+/// The most frequently used characters in Simplified Chinese text.
+const FREQUENT_SIMPLIFIED_CHINESE: &str = "的一是不了人我在有他这中大来上国个到说们为子和你地出道也时年得就那要下以生会自着去之过家学对可里后小么心多天而能好都然没日于起还发成事只作当想看文无开手十用主行方又如前所本见经头面公同三已老从动两长";
+
+/// The most frequently used characters in Traditional Chinese text.
+const FREQUENT_TRADITIONAL_CHINESE: &str = "的一是不了人我在有他這中大來上國個到說們為子和你地出道也時年得就那要下以生會自著去之過家學對可裡後小麼心多天而能好都然沒日於起還發成事只作當想看文無開手十用主行方又如前所本見經頭面公同三已老從動兩長";
+
+/// Scores a decoded character against the script it was decoded as. Returns
+/// `None` if the character is not native to the script.
+fn native_score(script: ScriptCode, c: char, run_length: usize) -> Option<i32> {
+    let code = u32::from(c);
+    let is_cjk = matches!(code, 0x3000..=0x303f | 0x4e00..=0x9fff | 0xff01..=0xff60);
+    let is_latin_1_letter = matches!(code, 0xc0..=0xff) && c != '×' && c != '÷';
+    let frequent = |list: &str, is_native| if list.contains(c) {
+        Some(3)
+    } else if is_native {
+        Some(1)
+    } else {
+        None
+    };
+
+    // Real text in a single-byte Roman encoding rarely has more than two
+    // non-ASCII characters in a row, whereas text in any other encoding
+    // decoded as Roman consists mostly of such runs
+    let is_native = match script {
+        ScriptCode::Roman => is_latin_1_letter && run_length <= 2,
+        ScriptCode::NonCyrillicSlavic => {
+            (is_latin_1_letter || matches!(code, 0x100..=0x17f)) && run_length <= 2
+        },
+        ScriptCode::Japanese => match code {
+            0x3040..=0x30ff => return Some(3),
+            // Half-width katakana are valid but uncommon, and text in the
+            // other CJK encodings often decodes to them
+            0xff61..=0xff9f => return Some(0),
+            _ => is_cjk,
+        },
+        ScriptCode::ChineseSimplified => return frequent(FREQUENT_SIMPLIFIED_CHINESE, is_cjk),
+        ScriptCode::ChineseTraditional => return frequent(FREQUENT_TRADITIONAL_CHINESE, is_cjk),
+        ScriptCode::Korean => return frequent(FREQUENT_KOREAN, is_cjk || matches!(code, 0x3130..=0x318f | 0xac00..=0xd7af)),
+        ScriptCode::Arabic => matches!(code, 0x600..=0x6ff),
+        ScriptCode::Hebrew => matches!(code, 0x590..=0x5ff | 0xfb1d..=0xfb4f),
+        ScriptCode::Greek => matches!(code, 0x370..=0x3ff),
+        ScriptCode::Russian => matches!(code, 0x400..=0x4ff),
+        ScriptCode::Thai => matches!(code, 0xe00..=0xe7f),
+        _ => false,
+    };
+
+    if is_native {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+fn score_char(script: ScriptCode, c: char, run_length: usize, touches_latin: bool) -> i32 {
+    if let Some(score) = native_score(script, c, run_length) {
+        // Characters from other scripts are unlikely to appear directly next
+        // to Latin letters, but accented Latin letters are supposed to
+        if touches_latin && script != ScriptCode::Roman && script != ScriptCode::NonCyrillicSlavic {
+            0
+        } else {
+            score
+        }
+    } else if c == char::REPLACEMENT_CHARACTER {
+        -8
+    } else if matches!(u32::from(c), 0xa0 | 0xa9 | 0xae | 0x2010..=0x2027 | 0x2122) {
+        // Typographic punctuation exists in every Mac encoding
+        0
+    } else {
+        -1
+    }
+}
+
+fn score_text(script: ScriptCode, decoder: DecoderRef, text: &[u8]) -> i32 {
+    let is_latin = |c: &char| c.is_ascii_alphabetic();
+    let chars = decoder.decode(text).chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut index = 0;
+    while index < chars.len() {
+        if chars[index].is_ascii() {
+            index += 1;
+            continue;
+        }
+
+        let run_end = chars[index..].iter()
+            .position(char::is_ascii)
+            .map_or(chars.len(), |length| index + length);
+        let touches_latin = (index > 0 && is_latin(&chars[index - 1]))
+            || chars.get(run_end).map_or(false, is_latin);
+        for &c in &chars[index..run_end] {
+            score += score_char(script, c, run_end - index, touches_latin);
+        }
+        index = run_end;
+    }
+    score
+}
+
+/// Returns the text part of an `STXT` resource.
+fn styled_text(data: &[u8]) -> Option<&[u8]> {
+    const HEADER_SIZE: u32 = 12;
+    if data.len() < HEADER_SIZE as usize {
+        return None;
+    }
+
+    let size = if BigEndian::read_u32(data) == HEADER_SIZE {
+        BigEndian::read_u32(&data[4..])
+    } else if LittleEndian::read_u32(data) == HEADER_SIZE {
+        LittleEndian::read_u32(&data[4..])
+    } else {
+        return None;
+    };
+
+    let end = HEADER_SIZE.checked_add(size)?;
+    data.get(HEADER_SIZE as usize..end as usize)
+}
+
+fn windows_language_script(language: u16) -> ScriptCode {
+    const SUBLANG_CHINESE_TRADITIONAL: u16 = 1;
+    const SUBLANG_CHINESE_HONGKONG: u16 = 3;
+    const SUBLANG_CHINESE_MACAU: u16 = 5;
+
+    let primary = language & 0x3ff;
+    let sub = language >> 10;
+    match primary {
+        0x01 | 0x20 | 0x29 => ScriptCode::Arabic,
+        0x02 | 0x19 | 0x22 | 0x23 | 0x2f => ScriptCode::Russian,
+        0x04 if matches!(sub, SUBLANG_CHINESE_TRADITIONAL | SUBLANG_CHINESE_HONGKONG | SUBLANG_CHINESE_MACAU) => {
+            ScriptCode::ChineseTraditional
+        },
+        0x04 => ScriptCode::ChineseSimplified,
+        0x05 | 0x0e | 0x15 | 0x1b | 0x25 | 0x26 | 0x27 => ScriptCode::NonCyrillicSlavic,
+        0x08 => ScriptCode::Greek,
+        0x0d => ScriptCode::Hebrew,
+        0x11 => ScriptCode::Japanese,
+        0x12 => ScriptCode::Korean,
+        0x1e => ScriptCode::Thai,
+        _ => ScriptCode::Roman,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    #[test]
+    fn no_evidence() {
+        let mut detector = CharsetDetector::new();
+        detector.add_text(b"Plain ASCII");
+        assert_eq!(detector.detect(), None);
+    }
+
+    #[test]
+    fn region_codes() {
+        let mut detector = CharsetDetector::new();
+        detector.add_country_code(CountryCode::Japan);
+        assert_eq!(detector.detect(), Some(ScriptCode::Japanese));
+
+        let mut detector = CharsetDetector::new();
+        detector.add_windows_language(0x0404);
+        assert_eq!(detector.detect(), Some(ScriptCode::ChineseTraditional));
+
+        let mut detector = CharsetDetector::new();
+        detector.add_windows_language(0x0409);
+        assert_eq!(detector.detect(), Some(ScriptCode::Roman));
+    }
+
+    #[test]
+    fn byte_statistics() {
+        let detect = |platform, text: &[u8]| {
+            let mut detector = CharsetDetector::new();
+            detector.set_platform(platform);
+            detector.add_text(text);
+            detector.detect()
+        };
+
+        let roman = Some(ScriptCode::Roman);
+        assert_eq!(detect(Platform::Mac, b"Caf\x8e M\x8enage"), roman);
+        assert_eq!(detect(Platform::Mac, b"Cr\x8fme br\x9el\x8ee"), roman);
+        assert_eq!(detect(Platform::Mac, b"\x86ber Gr\x9a\xa7e"), roman);
+        assert_eq!(detect(Platform::Win, b"Caf\xe9 M\xe9nage"), roman);
+        assert_eq!(detect(Platform::Mac, b"\x93\xfa\x96{\x8c\xea\x82\xcc\x83^\x83C\x83g\x83\x8b"), Some(ScriptCode::Japanese));
+        assert_eq!(detect(Platform::Mac, b"\xb0\xd4\xc0\xd3 \xbd\xc3\xc0\xdb"), Some(ScriptCode::Korean));
+        assert_eq!(detect(Platform::Mac, b"\xce\xd2\xb5\xc4\xd3\xce\xcf\xb7"), Some(ScriptCode::ChineseSimplified));
+        assert_eq!(detect(Platform::Mac, b"\xa7\xda\xaa\xba\xb9C\xc0\xb8"), Some(ScriptCode::ChineseTraditional));
+        assert_eq!(detect(Platform::Mac, b"\x8f\xf0\xe8\xe2\xe5\xf2 \xec\xe8\xf0"), Some(ScriptCode::Russian));
+        assert_eq!(detect(Platform::Mac, b"\xba\xe5\xdd\xed\xe5\xee\xef"), Some(ScriptCode::Greek));
+        assert_eq!(detect(Platform::Mac, b"\xeblu\xe9ou\x8bk\xf9 k\xf3\xcb"), Some(ScriptCode::NonCyrillicSlavic));
+    }
+
+    #[test]
+    fn styled_text_header() {
+        assert_eq!(styled_text(b"\0\0\0\x0c\0\0\0\x02\0\0\0\0hi"), Some(&b"hi"[..]));
+        assert_eq!(styled_text(b"\x0c\0\0\0\x02\0\0\0\0\0\0\0hi"), Some(&b"hi"[..]));
+        assert_eq!(styled_text(b"\0\0\0\x0c\0\0\0\x08\0\0\0\0hi"), None);
+        assert_eq!(styled_text(b"hi"), None);
+        assert_eq!(styled_text(b"\0\0\0\x0c\xff\xff\xff\xff\0\0\0\0hi"), None);
+    }
+}
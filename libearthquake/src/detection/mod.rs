@@ -1,3 +1,4 @@
+pub mod charset;
 pub mod movie;
 pub mod projector;
 pub mod projector_settings;
@@ -80,8 +81,45 @@ fn detect_mac(resource_fork: &mut impl Reader, data_fork: Option<&mut impl Reade
 
 fn detect_riff(data_fork: &mut impl Reader) -> AResult<FileType> {
     let start_pos = data_fork.pos()?;
-    riff::detect(data_fork.by_ref()).and_then(|m| {
+    riff::detect(data_fork.by_ref()).and_then(|mut m| {
+        data_fork.seek(SeekFrom::Start(start_pos))?;
+        m.charset = riff::Riff::new(data_fork.by_ref()).ok().and_then(|riff| charset::detect_riff(&riff));
         data_fork.seek(SeekFrom::Start(start_pos))?;
         Ok(FileType::Movie(m))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+    use crate::collections::riff::tests::d4_movie;
+    use libmactoolbox::vfs::MemoryFileSystem;
+
+    #[test]
+    fn detect_riff_movie() {
+        let fs = MemoryFileSystem::new().with_data_fork("Movie.dir", d4_movie());
+        let detection = detect(&fs, "Movie.dir").unwrap();
+        assert!(detection.data_fork.is_some());
+        assert!(detection.resource_fork.is_none());
+        match detection.info {
+            FileType::Movie(info) => {
+                assert_eq!(info.version(), Version::D4);
+                assert_eq!(info.kind(), movie::Kind::Movie);
+            },
+            FileType::Projector(_) => panic!("Detected a movie as a projector"),
+        }
+    }
+
+    #[test]
+    fn detect_missing_file() {
+        let fs = MemoryFileSystem::new();
+        assert!(detect(&fs, "Missing").is_err());
+    }
+
+    #[test]
+    fn detect_unknown_file() {
+        let fs = MemoryFileSystem::new().with_data_fork("Unknown", &b"not a movie"[..]);
+        assert!(detect(&fs, "Unknown").is_err());
+    }
+}
@@ -2,8 +2,8 @@ use anyhow::{bail, Result as AResult};
 use byteordered::Endianness;
 use derive_more::Display;
 use libcommon::Reader;
-use libmactoolbox::{ResourceFile, ResourceId, ResourceSource};
-use super::Version;
+use libmactoolbox::{ResourceFile, ResourceId, ResourceSource, script_manager::ScriptCode};
+use super::{charset, Version};
 
 #[derive(Clone, Debug)]
 pub struct DetectionInfo {
@@ -12,9 +12,17 @@ pub struct DetectionInfo {
     pub(crate) version: Version,
     pub(crate) kind: Kind,
     pub(crate) size: u32,
+    pub(crate) charset: Option<ScriptCode>,
 }
 
 impl DetectionInfo {
+    /// The guessed character set of the movie, or `None` if there was not
+    /// enough information to guess.
+    #[must_use]
+    pub fn charset(&self) -> Option<ScriptCode> {
+        self.charset
+    }
+
     #[must_use]
     pub fn data_endianness(&self) -> Endianness {
         self.data_endianness
@@ -59,6 +67,7 @@ pub fn detect_mac<T: Reader>(reader: &mut T) -> AResult<DetectionInfo> {
             version: Version::D3,
             kind: Kind::Accelerator,
             size: 0,
+            charset: charset::detect_resource_file(&rom),
         })
     } else if rom.count(b"VWCF") > 1 || (rom.count(b"VWCF") == 1 && rom.id_of_name(b"VWCF", b"Tiles").is_none()) {
         Ok(DetectionInfo {
@@ -67,6 +76,7 @@ pub fn detect_mac<T: Reader>(reader: &mut T) -> AResult<DetectionInfo> {
             version: Version::D3,
             kind: Kind::Embedded,
             size: 0,
+            charset: charset::detect_resource_file(&rom),
         })
     } else {
         bail!("No Director 3 movie configuration resource")
@@ -2,18 +2,19 @@ use anyhow::{Context, Result as AResult, anyhow, bail, ensure};
 use bitflags::bitflags;
 use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use crate::{
-    collections::riff::detect as detect_riff,
+    collections::{riff::detect as detect_riff, riff_container::{ChunkFileKind, RiffContainer}},
     panic_sample,
+    resources::config::Platform as ConfigPlatform,
 };
 use derive_more::Display;
 use libcommon::{
-    encodings::{Decoder, MAC_ROMAN, WIN_ROMAN},
+    encodings::{Decoder, Encoder, MAC_ROMAN, Unmappable, WIN_ROMAN},
     Reader,
     string::ReadExt,
 };
-use libmactoolbox::{ResourceFile, ResourceId, ResourceSource, resources::string_list::StringList as StringListResource, script_manager::ScriptCode};
+use libmactoolbox::{ResourceFile, ResourceId, ResourceSource, resources::{string_list::StringList as StringListResource, version::Version as VersionResource}, script_manager::ScriptCode};
 use std::{convert::TryInto, io::{Read, SeekFrom}, path::PathBuf, rc::Rc};
-use super::{projector_settings::ProjectorSettings, Version};
+use super::{charset::{CharsetDetector, detect_riff as detect_riff_charset}, projector_settings::ProjectorSettings, Version};
 
 #[derive(Clone)]
 pub struct DetectionInfo {
@@ -227,6 +228,7 @@ pub fn detect_mac(mut resource_fork: impl Reader, data_fork: Option<impl Reader>
     };
 
     let name = rom.name();
+    let charset = detect_mac_charset(&rom, name.as_ref(), &movie);
 
     let system_resources = if version == Version::D3 {
         None
@@ -240,9 +242,7 @@ pub fn detect_mac(mut resource_fork: impl Reader, data_fork: Option<impl Reader>
 
     Ok(DetectionInfo {
         name,
-        // TODO: Detect the character encoding. Reading the file creator name
-        // from VWFI might be the best way to do this.
-        charset: None,
+        charset,
         version,
         movie,
         system_resources,
@@ -250,6 +250,30 @@ pub fn detect_mac(mut resource_fork: impl Reader, data_fork: Option<impl Reader>
     })
 }
 
+fn detect_mac_charset(rom: &ResourceFile<impl Reader>, name: Option<&String>, movie: &Movie) -> Option<ScriptCode> {
+    let mut detector = CharsetDetector::new();
+    detector.set_platform(ConfigPlatform::Mac);
+
+    if let Ok(version) = rom.load::<VersionResource>(ResourceId::new(b"vers", 1), &()) {
+        detector.add_country_code(version.country_code());
+    }
+
+    // Names were decoded as Mac OS Roman, so re-encoding them recovers the
+    // original bytes
+    let filenames = if let Movie::External(filenames) = movie {
+        filenames.as_slice()
+    } else {
+        &[]
+    };
+    for text in name.into_iter().chain(filenames) {
+        if let Ok(text) = MAC_ROMAN.encode(text, Unmappable::Fail) {
+            detector.add_text(&text);
+        }
+    }
+
+    detector.detect()
+}
+
 fn d3_win_movie_info(input: &mut impl Reader, i: u16) -> AResult<(u32, String)> {
     let size = input.read_u32::<LittleEndian>()
         .with_context(|| format!("Can’t read movie {} size", i))?;
@@ -295,7 +319,7 @@ pub fn detect_win(input: &mut impl Reader) -> AResult<DetectionInfo> {
         Version::D3
     };
 
-    let (platform, name) = get_exe_info(input)?;
+    let (platform, name, language) = get_exe_info(input)?;
     let (config, movie, system_resources) = if version == Version::D3 {
         input.seek(SeekFrom::Start((offset + 7).into()))?;
         let config = ProjectorSettings::parse_win(version, platform, &header[0..7])?;
@@ -365,10 +389,19 @@ pub fn detect_win(input: &mut impl Reader) -> AResult<DetectionInfo> {
         )
     };
 
+    // Unlike the Mac path, the projector name and movie file names are not
+    // checked, so projectors without a version resource language fall back
+    // to detection of their first internal movie.
+    let charset = language.and_then(|language| {
+        let mut detector = CharsetDetector::new();
+        detector.set_platform(ConfigPlatform::Win);
+        detector.add_windows_language(language);
+        detector.detect()
+    }).or_else(|| detect_internal_movie_charset(input, &movie));
+
     Ok(DetectionInfo {
         name,
-        // TODO: Detect the character encoding.
-        charset: None,
+        charset,
         version,
         movie,
         system_resources,
@@ -376,6 +409,18 @@ pub fn detect_win(input: &mut impl Reader) -> AResult<DetectionInfo> {
     })
 }
 
+fn detect_internal_movie_charset(input: &mut impl Reader, movie: &Movie) -> Option<ScriptCode> {
+    if let Movie::Internal(offset) = *movie {
+        input.seek(SeekFrom::Start(offset.into())).ok()?;
+        let container = RiffContainer::new(input.by_ref()).ok()?;
+        (0..container.len())
+            .filter(|&index| container.kind(index) == Some(ChunkFileKind::Movie))
+            .find_map(|index| container.load_file(index).ok().and_then(|riff| detect_riff_charset(&riff)))
+    } else {
+        None
+    }
+}
+
 fn data_version(raw_version: &[u8]) -> Option<Version> {
     match &raw_version[0..4] {
         b"PJ93" | b"39JP" => Some(Version::D4),
@@ -388,7 +433,7 @@ fn data_version(raw_version: &[u8]) -> Option<Version> {
     }
 }
 
-fn get_exe_info(input: &mut impl Reader) -> AResult<(Platform, Option<String>)> {
+fn get_exe_info(input: &mut impl Reader) -> AResult<(Platform, Option<String>, Option<u16>)> {
     input.seek(SeekFrom::Start(0x3c))?;
     let exe_header_offset = input.read_u16::<LittleEndian>()?;
     input.seek(SeekFrom::Start(exe_header_offset.into()))?;
@@ -400,7 +445,9 @@ fn get_exe_info(input: &mut impl Reader) -> AResult<(Platform, Option<String>)>
     };
 
     if signature == *b"PE\0\0" {
-        Ok((Platform::Win(WinVersion::Win95), pe::read_product_name(input)))
+        let (language, name) = pe::read_version_info(input)
+            .map_or((None, None), |(language, name)| (Some(language), name));
+        Ok((Platform::Win(WinVersion::Win95), name, language))
     } else if signature[0..2] == *b"NE" {
         // 32 bytes from start of NE header, -4 since we consumed 4 bytes of
         // the header already
@@ -411,10 +458,10 @@ fn get_exe_info(input: &mut impl Reader) -> AResult<(Platform, Option<String>)>
         let non_resident_table_offset = input.read_u32::<LittleEndian>()?;
 
         if non_resident_table_size == 0 {
-            Ok((Platform::Win(WinVersion::Win3), None))
+            Ok((Platform::Win(WinVersion::Win3), None, None))
         } else {
             input.seek(SeekFrom::Start(non_resident_table_offset.into()))?;
-            Ok((Platform::Win(WinVersion::Win3), Some(input.read_pascal_str(WIN_ROMAN)?)))
+            Ok((Platform::Win(WinVersion::Win3), Some(input.read_pascal_str(WIN_ROMAN)?), None))
         }
     } else {
         bail!("Not a Windows executable")
@@ -515,17 +562,19 @@ mod pe {
         None
     }
 
-    pub(super) fn read_product_name(input: &mut impl Reader) -> Option<String> {
+    /// Reads the language and product name from the version information of
+    /// a PE executable. The first language in the resource directory is used,
+    /// since localised projectors do not include a US English version.
+    pub(super) fn read_version_info(input: &mut impl Reader) -> Option<(u16, Option<String>)> {
         const VERSION_INFO_TYPE: u32 = 0x10;
         const VERSION_INFO_ID: u32 = 1;
-        const VERSION_INFO_LANG: u32 = 1033;
 
         let (virtual_address, from_offset) = seek_to_resource_segment(input).ok()?;
-        seek_to_directory_entry(input, from_offset, VERSION_INFO_TYPE).ok()?;
-        seek_to_directory_entry(input, from_offset, VERSION_INFO_ID).ok()?;
-        seek_to_directory_entry(input, from_offset, VERSION_INFO_LANG).ok()?;
+        seek_to_directory_entry(input, from_offset, Some(VERSION_INFO_TYPE)).ok()?;
+        seek_to_directory_entry(input, from_offset, Some(VERSION_INFO_ID)).ok()?;
+        let language = seek_to_directory_entry(input, from_offset, None).ok()?;
         seek_to_resource_data(input, virtual_address, from_offset).ok()?;
-        read_version_struct(input).ok()?
+        Some((language.try_into().ok()?, read_version_struct(input).ok()?))
     }
 
     fn read_version_struct(input: &mut impl Reader) -> AResult<Option<String>> {
@@ -546,7 +595,7 @@ mod pe {
             input.skip(key_padding_size.try_into().unwrap())?;
         }
 
-        let is_string_table = key == "StringFileInfo" || (key.len() == 8 && &key[4..8] == "04b0");
+        let is_string_table = key == "StringFileInfo" || (key.len() == 8 && key.chars().all(|c| c.is_ascii_hexdigit()));
 
         match key.as_ref() {
             "ProductName" => Ok(Some(input.read_utf16_c_str::<LittleEndian>()?)),
@@ -569,7 +618,9 @@ mod pe {
         }
     }
 
-    fn seek_to_directory_entry(input: &mut impl Reader, from_offset: u32, id: u32) -> io::Result<()> {
+    /// Seeks to the directory entry with the given ID, or to the first entry
+    /// if `id` is `None`, and returns the ID of the entry.
+    fn seek_to_directory_entry(input: &mut impl Reader, from_offset: u32, id: Option<u32>) -> io::Result<u32> {
         const ENTRY_SIZE: usize = 8;
         input.skip(12)?;
         let skip_entries = input.read_u16::<LittleEndian>()?;
@@ -579,11 +630,11 @@ mod pe {
             let mut entry = [ 0; ENTRY_SIZE ];
             input.read_exact(&mut entry)?;
             let found_id = LittleEndian::read_u32(&entry);
-            if found_id == id {
+            if id.map_or(true, |id| found_id == id) {
                 const HAS_CHILDREN_FLAG: u32 = 0x8000_0000;
                 let offset = LittleEndian::read_u32(&entry[4..]) & !HAS_CHILDREN_FLAG;
                 input.seek(SeekFrom::Start((from_offset + offset).into()))?;
-                return Ok(());
+                return Ok(found_id);
             }
         }
 
@@ -0,0 +1,159 @@
+//! The scripts of frames and sprites in Director 3 and earlier.
+//!
+//! Before Director 4, frame and sprite scripts were not cast members. The
+//! score instead refers to them by their number in a `VWAC` action list. An
+//! action which has no handler declaration is the body of an `exitFrame`
+//! handler when it belongs to a frame, or a `mouseUp` handler when it
+//! belongs to a sprite.
+
+use anyhow::{Context, Result as AResult};
+use crate::ensure_sample;
+use libcommon::{encodings::DecoderRef, Reader, Resource, resource::Input};
+use std::{borrow::Cow, collections::BTreeMap, convert::TryInto, io::Read};
+use super::score::ActionNum;
+
+/// A script from the action list.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Action {
+    field_1: u8,
+    text: String,
+}
+
+impl Action {
+    /// The Lingo source of the script when it is used as a frame script.
+    #[must_use]
+    pub fn frame_script(&self) -> Cow<'_, str> {
+        self.script("exitFrame")
+    }
+
+    /// The Lingo source of the script when it is used as a sprite script.
+    #[must_use]
+    pub fn sprite_script(&self) -> Cow<'_, str> {
+        self.script("mouseUp")
+    }
+
+    /// The Lingo source of the script, as it is stored in the action list.
+    #[must_use]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn has_handler(&self) -> bool {
+        self.text.split(|c| c == '\r' || c == '\n').any(|line| {
+            let mut words = line.split_whitespace();
+            matches!(words.next(), Some(word) if word.eq_ignore_ascii_case("on"))
+                && words.next().is_some()
+        })
+    }
+
+    fn script(&self, handler: &str) -> Cow<'_, str> {
+        if self.has_handler() {
+            Cow::Borrowed(&self.text)
+        } else {
+            Cow::Owned(format!("on {}\r{}\rend {}\r", handler, self.text.trim_end_matches('\r'), handler))
+        }
+    }
+}
+
+/// The frame and sprite scripts of a score, from a `VWAC` resource.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ActionList(BTreeMap<ActionNum, Action>);
+
+impl ActionList {
+    /// Returns the script with the given number.
+    #[must_use]
+    pub fn get(&self, num: ActionNum) -> Option<&Action> {
+        self.0.get(&num)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ActionNum, &Action)> {
+        self.0.iter().map(|(&num, action)| (num, action))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Resource for ActionList {
+    type Context = (DecoderRef, );
+
+    fn load(input: &mut Input<impl Reader>, size: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
+        const ENTRY_SIZE: u32 = 4;
+
+        let count = input.read_u16().context("Can’t read action count")?;
+        let header_size = 2 + (u32::from(count) + 1) * ENTRY_SIZE;
+        ensure_sample!(header_size <= size, "Action count {} is too large for resource size {}", count, size);
+
+        let mut entries = Vec::with_capacity(usize::from(count) + 1);
+        for index in 0..=count {
+            let num = input.read_u8().with_context(|| format!("Can’t read action {} number", index))?;
+            let field_1 = input.read_u8().with_context(|| format!("Can’t read action {} field_1", index))?;
+            let offset = input.read_u16().with_context(|| format!("Can’t read action {} offset", index))?;
+            entries.push((ActionNum(num), field_1, u32::from(offset)));
+        }
+
+        let mut text = Vec::with_capacity((size - header_size).try_into().unwrap());
+        input.take((size - header_size).into()).read_to_end(&mut text).context("Can’t read action text")?;
+
+        let mut actions = BTreeMap::new();
+        // The last entry only marks the end of the text of the last action
+        for (index, window) in entries.windows(2).enumerate() {
+            let (num, field_1, start) = window[0];
+            let end = window[1].2;
+            ensure_sample!(
+                start <= end && end as usize <= text.len(),
+                "Invalid action {} text range {}..{}", index, start, end
+            );
+            actions.insert(num, Action {
+                field_1,
+                text: context.0.decode(&text[start as usize..end as usize]),
+            });
+        }
+
+        Ok(Self(actions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::encodings::MAC_ROMAN;
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn load() {
+        let mut data = vec![ 0, 2, 1, 0, 0, 0, 3, 0, 0, 8, 0, 0, 0, 17 ];
+        data.extend(b"go loop");
+        data.extend(b"\rbeep\rgo 1");
+        let size = data.len() as u32;
+        let mut input = ByteOrdered::new(Cursor::new(data), Endianness::Big);
+        let actions = ActionList::load(&mut input, size, &(MAC_ROMAN, )).unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions.get(ActionNum(1)).unwrap().text(), "go loop\r");
+        assert_eq!(actions.get(ActionNum(3)).unwrap().text(), "beep\rgo 1");
+        assert!(actions.get(ActionNum(2)).is_none());
+    }
+
+    #[test]
+    fn implicit_handler() {
+        let action = Action { field_1: 0, text: "go loop\r".to_string() };
+        assert_eq!(action.frame_script(), "on exitFrame\rgo loop\rend exitFrame\r");
+        assert_eq!(action.sprite_script(), "on mouseUp\rgo loop\rend mouseUp\r");
+    }
+
+    #[test]
+    fn explicit_handler() {
+        let action = Action { field_1: 0, text: "-- comment\rON enterFrame\r  beep\rend".to_string() };
+        assert_eq!(action.frame_script(), action.text());
+        assert_eq!(action.sprite_script(), action.text());
+    }
+}
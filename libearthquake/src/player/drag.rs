@@ -0,0 +1,262 @@
+//! Dragging moveable sprites with the mouse.
+//!
+//! When the user presses the mouse button on a moveable sprite, the sprite
+//! follows the mouse until the button is released, and then the sprite
+//! receives `mouseUp`. The drag advances by one step each time the player
+//! polls the event manager, so the stage keeps updating during the drag.
+
+use anyhow::{Context, Result as AResult};
+use libmactoolbox::{EventManager, Point, Rect};
+use std::convert::TryFrom;
+use super::{
+    event::{Dispatcher, ScriptHost},
+    hit_test::{SpriteMembers, SpriteRect},
+    score::{ChannelNum, Score},
+    sprite_property::SpriteProperty,
+};
+
+/// The state of the mouse button and pointer.
+pub trait MouseTracker {
+    /// The position of the mouse on the stage, like `GetMouse`.
+    fn mouse(&self) -> AResult<Point>;
+
+    /// Returns whether the mouse button is still down, like `StillDown`.
+    fn still_down(&self) -> bool;
+
+    /// Removes the mouse-up event from the event queue, like `WaitMouseUp`.
+    fn wait_mouse_up(&mut self) -> bool;
+}
+
+impl MouseTracker for EventManager {
+    fn mouse(&self) -> AResult<Point> {
+        EventManager::mouse(self)
+    }
+
+    fn still_down(&self) -> bool {
+        EventManager::still_down(self)
+    }
+
+    fn wait_mouse_up(&mut self) -> bool {
+        EventManager::wait_mouse_up(self)
+    }
+}
+
+/// A sprite which is being dragged by the user.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Drag {
+    channel: ChannelNum,
+    /// The distance from the mouse to the origin of the sprite.
+    offset: Point,
+}
+
+impl Drag {
+    /// Starts dragging the sprite in the given channel, if it is moveable,
+    /// from the point where the mouse button was pressed.
+    #[must_use]
+    pub fn begin(score: &Score, channel: ChannelNum, mouse: Point) -> Option<Self> {
+        if channel.0 < 1 || !score.moveable_sprites().contains_sprite(channel) {
+            return None;
+        }
+
+        let origin = score.current_frame().sprites.get(usize::try_from(channel.0 - 1).ok()?)?.origin();
+        Some(Self {
+            channel,
+            offset: Point { x: origin.x.saturating_sub(mouse.x), y: origin.y.saturating_sub(mouse.y) },
+        })
+    }
+
+    /// The channel of the dragged sprite.
+    #[must_use]
+    pub fn channel(&self) -> ChannelNum {
+        self.channel
+    }
+
+    /// Advances the drag using the current state of the mouse. Returns
+    /// `false` once the mouse button is released, after the sprite receives
+    /// `mouseUp`.
+    pub fn step(
+        &self,
+        score: &mut Score,
+        members: &impl SpriteMembers,
+        mouse: &mut impl MouseTracker,
+        dispatcher: &mut Dispatcher,
+        host: &mut impl ScriptHost,
+    ) -> AResult<bool> {
+        let still_down = mouse.still_down();
+        self.track(score, members, mouse.mouse()?)?;
+        if still_down {
+            Ok(true)
+        } else {
+            mouse.wait_mouse_up();
+            dispatcher.dispatch_mouse_up(host, score.current_frame())?;
+            Ok(false)
+        }
+    }
+
+    /// Moves the sprite to follow the mouse, keeping it inside the sprite
+    /// given by its `constraint` property. Returns the new origin of the
+    /// sprite.
+    pub fn track(&self, score: &mut Score, members: &impl SpriteMembers, mouse: Point) -> AResult<Point> {
+        let mut origin = Point { x: mouse.x.saturating_add(self.offset.x), y: mouse.y.saturating_add(self.offset.y) };
+        let constraint = ChannelNum(score.sprite_property(self.channel, SpriteProperty::Constraint)?.to_integer_in()?);
+        if constraint.0 != 0 {
+            let rect = sprite_rect(score, members, constraint)?;
+            origin.x = constrain(origin.x, rect.left, rect.right);
+            origin.y = constrain(origin.y, rect.top, rect.bottom);
+        }
+
+        score.move_sprite(self.channel, origin)?;
+        Ok(origin)
+    }
+}
+
+/// Limits a horizontal position to the left and right edges of the sprite in
+/// the given channel, like the Lingo function `constrainH`.
+pub fn constrain_h(score: &Score, members: &impl SpriteMembers, channel: ChannelNum, h: i16) -> AResult<i16> {
+    let rect = sprite_rect(score, members, channel)?;
+    Ok(constrain(h, rect.left, rect.right))
+}
+
+/// Limits a vertical position to the top and bottom edges of the sprite in
+/// the given channel, like the Lingo function `constrainV`.
+pub fn constrain_v(score: &Score, members: &impl SpriteMembers, channel: ChannelNum, v: i16) -> AResult<i16> {
+    let rect = sprite_rect(score, members, channel)?;
+    Ok(constrain(v, rect.top, rect.bottom))
+}
+
+fn constrain(value: i16, min: i16, max: i16) -> i16 {
+    value.max(min).min(max)
+}
+
+fn sprite_rect(score: &Score, members: &impl SpriteMembers, channel: ChannelNum) -> AResult<Rect> {
+    let sprite = usize::try_from(channel.0 - 1).ok()
+        .and_then(|index| score.current_frame().sprites.get(index))
+        .with_context(|| format!("Invalid sprite channel {}", channel.0))?;
+    Ok(SpriteRect::new(sprite, members.member_bounds(channel, sprite)).rect())
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use crate::resources::config::Version as ConfigVersion;
+    use libcommon::Resource;
+    use libmactoolbox::quickdraw::PixMap;
+    use std::io::Cursor;
+    use super::*;
+    use super::super::{event::{Message, Outcome, Script}, score::Sprite, sprite_property::Value};
+
+    struct Members;
+
+    impl SpriteMembers for Members {
+        fn member_bounds(&self, _: ChannelNum, _: &Sprite) -> Option<Rect> {
+            None
+        }
+
+        fn member_image(&self, _: ChannelNum, _: &Sprite) -> Option<&PixMap> {
+            None
+        }
+    }
+
+    #[derive(Default)]
+    struct Host(Vec<(Message, Option<ChannelNum>)>);
+
+    impl ScriptHost for Host {
+        fn call(&mut self, script: Script<'_>, message: Message) -> AResult<Outcome> {
+            if let Script::Member(channel, _) = script {
+                self.0.push((message, Some(channel)));
+            }
+            Ok(Outcome::NotHandled)
+        }
+    }
+
+    struct Mouse {
+        position: Point,
+        down: bool,
+    }
+
+    impl MouseTracker for Mouse {
+        fn mouse(&self) -> AResult<Point> {
+            Ok(self.position)
+        }
+
+        fn still_down(&self) -> bool {
+            self.down
+        }
+
+        fn wait_mouse_up(&mut self) -> bool {
+            !self.down
+        }
+    }
+
+    /// A score with one frame, where a 100×100 sprite in channel 1 contains
+    /// a 10×10 moveable sprite in channel 2 at (20, 20).
+    fn score() -> Score {
+        let sprite = |member: u8, position: u8, size: u8, flags: u8| vec![
+            1, 0, 0, 1, 0, member, 0, 0, 0, 0, 0, 0,
+            0, position, 0, position, 0, size, 0, size,
+            flags, 0, 0, 0,
+        ];
+
+        let mut frame = vec![ 0, 54, 0, 48, 0, 48 ];
+        frame.extend(sprite(1, 0, 100, 0));
+        frame.extend(sprite(2, 20, 10, 0x80));
+
+        let size = (20 + frame.len()) as u8;
+        let mut data = vec![ 0, 0, 0, size, 0, 0, 0, 20, 0, 0, 0, 1, 0, 5, 0, 24, 0, 50, 0, 0 ];
+        data.extend(frame);
+
+        let mut input = ByteOrdered::new(Cursor::new(data), Endianness::Big);
+        let mut score = Score::load(&mut input, size.into(), &(ConfigVersion::V1214, )).unwrap();
+        score.next().unwrap().unwrap();
+        score
+    }
+
+    #[test]
+    fn drag() {
+        let mut score = score();
+        let mut dispatcher = Dispatcher::new();
+        let mut host = Host::default();
+        let hidden = score.hidden_sprites();
+        let frame = score.current_frame().clone();
+        dispatcher.dispatch_mouse_down(&mut host, &Members, Point { x: 25, y: 25 }, &frame, &hidden).unwrap();
+        let channel = dispatcher.mouse_down_sprite().unwrap();
+        assert_eq!(channel, ChannelNum(2));
+        assert!(Drag::begin(&score, ChannelNum(1), Point { x: 5, y: 5 }).is_none());
+
+        let drag = Drag::begin(&score, channel, Point { x: 25, y: 25 }).unwrap();
+        score.set_sprite_property(channel, SpriteProperty::Constraint, Value::Integer(1)).unwrap();
+
+        let mut mouse = Mouse { position: Point { x: 55, y: 35 }, down: true };
+        assert!(drag.step(&mut score, &Members, &mut mouse, &mut dispatcher, &mut host).unwrap());
+        assert_eq!(score.current_frame().sprites[1].origin(), Point { x: 50, y: 30 });
+
+        mouse.position = Point { x: 300, y: -300 };
+        assert!(drag.step(&mut score, &Members, &mut mouse, &mut dispatcher, &mut host).unwrap());
+        assert_eq!(score.current_frame().sprites[1].origin(), Point { x: 100, y: 0 });
+
+        mouse.position = Point { x: 45, y: 45 };
+        mouse.down = false;
+        assert!(!drag.step(&mut score, &Members, &mut mouse, &mut dispatcher, &mut host).unwrap());
+        assert_eq!(score.current_frame().sprites[1].origin(), Point { x: 40, y: 40 });
+        assert_eq!(host.0, vec![ (Message::MouseDown, Some(channel)), (Message::MouseUp, Some(channel)) ]);
+    }
+
+    #[test]
+    fn drag_to_limit() {
+        let mut score = score();
+        let drag = Drag::begin(&score, ChannelNum(2), Point { x: 25, y: 15 }).unwrap();
+        let origin = drag.track(&mut score, &Members, Point { x: i16::MIN, y: i16::MAX }).unwrap();
+        assert_eq!(origin, Point { x: i16::MIN, y: i16::MAX });
+        assert_eq!(score.current_frame().sprites[1].origin(), origin);
+    }
+
+    #[test]
+    fn constrain_to_sprite() {
+        let score = score();
+        assert_eq!(constrain_h(&score, &Members, ChannelNum(2), 5).unwrap(), 20);
+        assert_eq!(constrain_h(&score, &Members, ChannelNum(2), 25).unwrap(), 25);
+        assert_eq!(constrain_v(&score, &Members, ChannelNum(2), 50).unwrap(), 30);
+        assert!(constrain_h(&score, &Members, ChannelNum(0), 0).is_err());
+    }
+}
@@ -0,0 +1,589 @@
+//! Routing of events through Director’s message hierarchy.
+//!
+//! A user event first goes to its primary event handler, like
+//! `the mouseDownScript`, then to the script of the sprite which received the
+//! event, the script of that sprite’s cast member, the frame script, and
+//! finally the movie scripts. The first script with a handler for the
+//! message stops the message unless the handler uses `pass`.
+
+use anyhow::{Context, Result as AResult};
+use crate::resources::cast::MemberId;
+use libmactoolbox::{EventKind, EventRecord, Point, Rect, quickdraw::Pen};
+use std::convert::TryFrom;
+use super::score::{ActionNum, ChannelNum, Frame, Sprite, SpriteBitmask, SpriteKind};
+
+/// A message which is sent to Lingo handlers.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Message {
+    MouseDown,
+    MouseUp,
+    RightMouseDown,
+    RightMouseUp,
+    MouseEnter,
+    MouseLeave,
+    MouseWithin,
+    KeyDown,
+    KeyUp,
+    PrepareFrame,
+    EnterFrame,
+    ExitFrame,
+    PrepareMovie,
+    StartMovie,
+    StopMovie,
+    Idle,
+    Timeout,
+    ActivateWindow,
+    DeactivateWindow,
+}
+
+impl Message {
+    /// Returns the message for a Toolbox event.
+    #[must_use]
+    pub fn from_event_kind(kind: EventKind) -> Option<Self> {
+        match kind {
+            EventKind::MouseDown => Some(Self::MouseDown),
+            EventKind::MouseUp => Some(Self::MouseUp),
+            EventKind::KeyDown | EventKind::AutoKey => Some(Self::KeyDown),
+            EventKind::KeyUp => Some(Self::KeyUp),
+            EventKind::Null => Some(Self::Idle),
+            _ => None,
+        }
+    }
+
+    /// The name of the Lingo handler which receives the message.
+    #[must_use]
+    pub fn handler_name(self) -> &'static str {
+        match self {
+            Self::MouseDown => "mouseDown",
+            Self::MouseUp => "mouseUp",
+            Self::RightMouseDown => "rightMouseDown",
+            Self::RightMouseUp => "rightMouseUp",
+            Self::MouseEnter => "mouseEnter",
+            Self::MouseLeave => "mouseLeave",
+            Self::MouseWithin => "mouseWithin",
+            Self::KeyDown => "keyDown",
+            Self::KeyUp => "keyUp",
+            Self::PrepareFrame => "prepareFrame",
+            Self::EnterFrame => "enterFrame",
+            Self::ExitFrame => "exitFrame",
+            Self::PrepareMovie => "prepareMovie",
+            Self::StartMovie => "startMovie",
+            Self::StopMovie => "stopMovie",
+            Self::Idle => "idle",
+            Self::Timeout => "timeout",
+            Self::ActivateWindow => "activateWindow",
+            Self::DeactivateWindow => "deactivateWindow",
+        }
+    }
+
+    /// Returns whether the message is sent to the movie scripts at the end
+    /// of the message path.
+    fn reaches_movie(self) -> bool {
+        !matches!(self, Self::MouseEnter | Self::MouseLeave | Self::MouseWithin)
+    }
+
+    /// Returns whether the message is sent to the frame script.
+    fn reaches_frame(self) -> bool {
+        matches!(self,
+            Self::MouseDown
+            | Self::MouseUp
+            | Self::RightMouseDown
+            | Self::RightMouseUp
+            | Self::KeyDown
+            | Self::KeyUp
+            | Self::PrepareFrame
+            | Self::EnterFrame
+            | Self::ExitFrame
+        )
+    }
+
+    /// Returns whether the message is sent to a sprite and its cast member.
+    fn reaches_sprite(self) -> bool {
+        matches!(self,
+            Self::MouseDown
+            | Self::MouseUp
+            | Self::RightMouseDown
+            | Self::RightMouseUp
+            | Self::MouseEnter
+            | Self::MouseLeave
+            | Self::MouseWithin
+            | Self::KeyDown
+            | Self::KeyUp
+        )
+    }
+}
+
+/// A primary event handler, which is set from Lingo by a movie property like
+/// `the mouseDownScript`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PrimaryHandler {
+    MouseDown,
+    MouseUp,
+    KeyDown,
+    KeyUp,
+    Timeout,
+}
+
+impl PrimaryHandler {
+    /// Returns the primary event handler which receives the given message
+    /// before any other script.
+    #[must_use]
+    pub fn for_message(message: Message) -> Option<Self> {
+        match message {
+            Message::MouseDown => Some(Self::MouseDown),
+            Message::MouseUp => Some(Self::MouseUp),
+            Message::KeyDown => Some(Self::KeyDown),
+            Message::KeyUp => Some(Self::KeyUp),
+            Message::Timeout => Some(Self::Timeout),
+            _ => None,
+        }
+    }
+
+    /// The name of the Lingo movie property which holds the handler.
+    #[must_use]
+    pub fn property_name(self) -> &'static str {
+        match self {
+            Self::MouseDown => "mouseDownScript",
+            Self::MouseUp => "mouseUpScript",
+            Self::KeyDown => "keyDownScript",
+            Self::KeyUp => "keyUpScript",
+            Self::Timeout => "timeoutScript",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A script which may receive a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Script<'a> {
+    /// The text of a primary event handler.
+    Primary(PrimaryHandler, &'a str),
+    /// The script attached to a sprite in the score.
+    Sprite(ChannelNum, MemberId),
+    /// The script attached to a sprite in a Director 3 score, from the
+    /// action list.
+    SpriteAction(ChannelNum, ActionNum),
+    /// The script of the cast member used by a sprite.
+    Member(ChannelNum, MemberId),
+    /// The script of the current frame.
+    Frame(MemberId),
+    /// The script of the current frame in a Director 3 score, from the
+    /// action list.
+    FrameAction(ActionNum),
+    /// The movie scripts.
+    Movie,
+}
+
+/// The result of sending a message to a script.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The script has no handler for the message.
+    NotHandled,
+    /// The script handled the message. Primary event handlers pass the
+    /// message on after handling it; other scripts stop it.
+    Handled,
+    /// The handler used `pass` to send the message further along the
+    /// message path.
+    Pass,
+    /// The handler used `dontPassEvent` or `stopEvent` to stop the message.
+    Stop,
+}
+
+/// Runs Lingo handlers for the dispatcher.
+pub trait ScriptHost {
+    /// Sends the message to the given script, running its handler for the
+    /// message, if it has one.
+    fn call(&mut self, script: Script<'_>, message: Message) -> AResult<Outcome>;
+}
+
+/// Provides the geometry of sprites on the stage for hit testing.
+pub trait SpriteGeometry {
+    /// The bounding rectangle of the sprite on the stage.
+    fn sprite_rect(&self, channel: ChannelNum, sprite: &Sprite) -> Rect;
+
+    /// Returns whether the given point is inside the matte of the cast
+    /// member of a sprite which is drawn with the matte ink.
+    fn matte_contains(&self, channel: ChannelNum, sprite: &Sprite, point: Point) -> bool;
+
+    /// Returns whether the pixel of the cast member under the given point is
+    /// drawn by a sprite which uses the background transparent ink.
+    fn transparent_contains(&self, _: ChannelNum, _: &Sprite, _: Point) -> bool {
+        true
+    }
+}
+
+/// The Director matte ink, which makes the white pixels surrounding a bitmap
+/// transparent, is stored in the score as `patCopy`.
+const MATTE_INK: Pen = Pen::PatCopy;
+
+/// The Director background transparent ink, which makes every pixel in the
+/// background colour transparent, is stored in the score as `transparent`.
+const BACKGROUND_TRANSPARENT_INK: Pen = Pen::Transparent;
+
+/// Returns the topmost visible sprite at the given point on the stage.
+#[must_use]
+pub fn sprite_at(frame: &Frame, hidden_sprites: &SpriteBitmask, geometry: &impl SpriteGeometry, point: Point) -> Option<ChannelNum> {
+    frame.sprites.iter().enumerate().rev().find_map(|(index, sprite)| {
+        let channel = ChannelNum(index as i16 + 1);
+        if sprite.kind() == SpriteKind::None || hidden_sprites.contains_sprite(channel) {
+            return None;
+        }
+
+        let hit = geometry.sprite_rect(channel, sprite).contains(point) && match sprite.ink() {
+            MATTE_INK => geometry.matte_contains(channel, sprite, point),
+            BACKGROUND_TRANSPARENT_INK => geometry.transparent_contains(channel, sprite, point),
+            _ => true,
+        };
+        if hit { Some(channel) } else { None }
+    })
+}
+
+/// Sends messages along Director’s message path.
+#[derive(Clone, Debug, Default)]
+pub struct Dispatcher {
+    primary_handlers: [ String; 5 ],
+    mouse_down_sprite: Option<ChannelNum>,
+    hover_sprite: Option<ChannelNum>,
+}
+
+impl Dispatcher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends a message to every script on its message path, starting with
+    /// the given sprite, until a handler stops it. Returns whether any script
+    /// handled the message.
+    pub fn dispatch(&self, host: &mut impl ScriptHost, message: Message, frame: &Frame, sprite: Option<ChannelNum>) -> AResult<bool> {
+        let mut handled = false;
+
+        if let Some(primary) = PrimaryHandler::for_message(message) {
+            let text = self.primary_handler(primary);
+            if !text.is_empty() {
+                match call(host, Script::Primary(primary, text), message)? {
+                    Outcome::NotHandled => {},
+                    Outcome::Handled | Outcome::Pass => handled = true,
+                    Outcome::Stop => return Ok(true),
+                }
+            }
+        }
+
+        let mut path = Vec::with_capacity(4);
+        if message.reaches_sprite() {
+            if let Some(channel) = sprite {
+                let sprite = usize::try_from(channel.0 - 1).ok()
+                    .and_then(|index| frame.sprites.get(index))
+                    .with_context(|| format!("Invalid sprite channel {}", channel.0))?;
+                if sprite.script() != MemberId::default() {
+                    path.push(Script::Sprite(channel, sprite.script()));
+                } else if sprite.action() != ActionNum::default() {
+                    path.push(Script::SpriteAction(channel, sprite.action()));
+                }
+                path.push(Script::Member(channel, sprite.id()));
+            }
+        }
+        if message.reaches_frame() {
+            if frame.script != MemberId::default() {
+                path.push(Script::Frame(frame.script));
+            } else if frame.action != ActionNum::default() {
+                path.push(Script::FrameAction(frame.action));
+            }
+        }
+        if message.reaches_movie() {
+            path.push(Script::Movie);
+        }
+
+        for script in path {
+            match call(host, script, message)? {
+                Outcome::NotHandled => {},
+                Outcome::Pass => handled = true,
+                Outcome::Handled | Outcome::Stop => return Ok(true),
+            }
+        }
+
+        Ok(handled)
+    }
+
+    /// Sends the message for a Toolbox event to the sprite which should
+    /// receive it. Mouse-down events go to the sprite under the mouse,
+    /// mouse-up events go to the sprite which received the mouse-down event,
+    /// and key events go to the editable sprite with keyboard focus.
+    pub fn dispatch_event(
+        &mut self,
+        host: &mut impl ScriptHost,
+        geometry: &impl SpriteGeometry,
+        event: &EventRecord,
+        frame: &Frame,
+        hidden_sprites: &SpriteBitmask,
+        focus: Option<ChannelNum>,
+    ) -> AResult<bool> {
+        let message = match Message::from_event_kind(event.kind()) {
+            Some(message) => message,
+            None => return Ok(false),
+        };
+
+        match (message, event.mouse()) {
+            (Message::MouseDown, Some(point)) => self.dispatch_mouse_down(host, geometry, point, frame, hidden_sprites),
+            (Message::MouseUp, _) => self.dispatch_mouse_up(host, frame),
+            _ => {
+                let sprite = if matches!(message, Message::KeyDown | Message::KeyUp) { focus } else { None };
+                self.dispatch(host, message, frame, sprite)
+            },
+        }
+    }
+
+    /// Sends `mouseDown` to the sprite under the given point.
+    pub fn dispatch_mouse_down(
+        &mut self,
+        host: &mut impl ScriptHost,
+        geometry: &impl SpriteGeometry,
+        point: Point,
+        frame: &Frame,
+        hidden_sprites: &SpriteBitmask,
+    ) -> AResult<bool> {
+        self.mouse_down_sprite = sprite_at(frame, hidden_sprites, geometry, point);
+        self.dispatch(host, Message::MouseDown, frame, self.mouse_down_sprite)
+    }
+
+    /// Sends `mouseUp` to the sprite which received the last mouse-down
+    /// event.
+    pub fn dispatch_mouse_up(&mut self, host: &mut impl ScriptHost, frame: &Frame) -> AResult<bool> {
+        let sprite = self.mouse_down_sprite.take();
+        self.dispatch(host, Message::MouseUp, frame, sprite)
+    }
+
+    /// Sends `mouseLeave`, `mouseEnter`, and `mouseWithin` messages as the
+    /// mouse moves over sprites.
+    pub fn dispatch_mouse_move(
+        &mut self,
+        host: &mut impl ScriptHost,
+        geometry: &impl SpriteGeometry,
+        point: Point,
+        frame: &Frame,
+        hidden_sprites: &SpriteBitmask,
+    ) -> AResult<()> {
+        let sprite = sprite_at(frame, hidden_sprites, geometry, point);
+        if sprite != self.hover_sprite {
+            if let Some(old_sprite) = self.hover_sprite {
+                self.dispatch(host, Message::MouseLeave, frame, Some(old_sprite))?;
+            }
+            self.hover_sprite = sprite;
+            if sprite.is_some() {
+                self.dispatch(host, Message::MouseEnter, frame, sprite)?;
+            }
+        }
+
+        if sprite.is_some() {
+            self.dispatch(host, Message::MouseWithin, frame, sprite)?;
+        }
+
+        Ok(())
+    }
+
+    /// The sprite which received the last mouse-down event, like
+    /// `the clickOn`.
+    #[must_use]
+    pub fn mouse_down_sprite(&self) -> Option<ChannelNum> {
+        self.mouse_down_sprite
+    }
+
+    /// The text of a primary event handler.
+    #[must_use]
+    pub fn primary_handler(&self, handler: PrimaryHandler) -> &str {
+        &self.primary_handlers[handler.index()]
+    }
+
+    /// Sets the text of a primary event handler. An empty string removes
+    /// the handler.
+    pub fn set_primary_handler(&mut self, handler: PrimaryHandler, text: impl Into<String>) {
+        self.primary_handlers[handler.index()] = text.into();
+    }
+}
+
+fn call(host: &mut impl ScriptHost, script: Script<'_>, message: Message) -> AResult<Outcome> {
+    host.call(script, message)
+        .with_context(|| format!("Can’t send {} to {:?}", message.handler_name(), script))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::Resource;
+    use std::io::Cursor;
+    use super::*;
+    use super::super::score::Version;
+
+    #[derive(Default)]
+    struct Host {
+        handlers: Vec<(String, Message, Outcome)>,
+        calls: Vec<String>,
+    }
+
+    impl Host {
+        fn on(mut self, script: &str, message: Message, outcome: Outcome) -> Self {
+            self.handlers.push((script.to_string(), message, outcome));
+            self
+        }
+    }
+
+    fn script_name(script: Script<'_>) -> String {
+        match script {
+            Script::Primary(handler, _) => handler.property_name().to_string(),
+            Script::Sprite(channel, _) => format!("sprite {}", channel.0),
+            Script::SpriteAction(channel, action) => format!("sprite {} action {}", channel.0, action.0),
+            Script::Member(_, member) => format!("member {}", member.num().0),
+            Script::Frame(_) => "frame".to_string(),
+            Script::FrameAction(action) => format!("frame action {}", action.0),
+            Script::Movie => "movie".to_string(),
+        }
+    }
+
+    impl ScriptHost for Host {
+        fn call(&mut self, script: Script<'_>, message: Message) -> AResult<Outcome> {
+            let name = script_name(script);
+            self.calls.push(name.clone());
+            Ok(self.handlers.iter()
+                .find(|(handler_script, handler_message, _)| *handler_script == name && *handler_message == message)
+                .map_or(Outcome::NotHandled, |&(_, _, outcome)| outcome))
+        }
+    }
+
+    struct Geometry;
+
+    impl SpriteGeometry for Geometry {
+        fn sprite_rect(&self, _: ChannelNum, sprite: &Sprite) -> Rect {
+            let origin = sprite.origin();
+            Rect { top: origin.y, left: origin.x, bottom: origin.y + sprite.height(), right: origin.x + sprite.width() }
+        }
+
+        fn matte_contains(&self, _: ChannelNum, _: &Sprite, point: Point) -> bool {
+            point.x > 15
+        }
+
+        fn transparent_contains(&self, _: ChannelNum, _: &Sprite, point: Point) -> bool {
+            point.x < 25
+        }
+    }
+
+    fn sprite(member: u8, script: u8, ink: u8, x: u8) -> Sprite {
+        let data = [
+            1, ink, 0, 0, 0, member, 0, 0, 0, script,
+            0, 0, 0, x, 0, 0, 0, 20, 0, 20,
+            0, 0, 0, 0,
+        ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        Sprite::load(&mut input, data.len() as u32, &(Version::V5, )).unwrap()
+    }
+
+    fn frame() -> Frame {
+        let mut frame = Frame {
+            script: MemberId::new(0_i16, 10_i16),
+            ..Frame::default()
+        };
+        frame.sprites[0] = sprite(1, 0, 0, 0);
+        frame.sprites[1] = sprite(2, 3, MATTE_INK as u8, 10);
+        frame
+    }
+
+    #[test]
+    fn message_path() {
+        let dispatcher = Dispatcher::new();
+        let mut host = Host::default();
+        assert!(!dispatcher.dispatch(&mut host, Message::MouseDown, &frame(), Some(ChannelNum(2))).unwrap());
+        assert_eq!(host.calls, vec![ "sprite 2", "member 2", "frame", "movie" ]);
+
+        let mut host = Host::default().on("member 2", Message::MouseUp, Outcome::Handled);
+        assert!(dispatcher.dispatch(&mut host, Message::MouseUp, &frame(), Some(ChannelNum(2))).unwrap());
+        assert_eq!(host.calls, vec![ "sprite 2", "member 2" ]);
+
+        let mut host = Host::default().on("sprite 2", Message::MouseUp, Outcome::Pass);
+        assert!(dispatcher.dispatch(&mut host, Message::MouseUp, &frame(), Some(ChannelNum(2))).unwrap());
+        assert_eq!(host.calls, vec![ "sprite 2", "member 2", "frame", "movie" ]);
+
+        let mut host = Host::default();
+        dispatcher.dispatch(&mut host, Message::ExitFrame, &frame(), None).unwrap();
+        assert_eq!(host.calls, vec![ "frame", "movie" ]);
+
+        let mut host = Host::default();
+        dispatcher.dispatch(&mut host, Message::StartMovie, &frame(), None).unwrap();
+        assert_eq!(host.calls, vec![ "movie" ]);
+    }
+
+    #[test]
+    fn invalid_sprite() {
+        let dispatcher = Dispatcher::new();
+        let mut host = Host::default();
+        assert!(dispatcher.dispatch(&mut host, Message::MouseDown, &frame(), Some(ChannelNum(0))).is_err());
+        assert!(dispatcher.dispatch(&mut host, Message::MouseDown, &frame(), Some(ChannelNum(i16::MAX))).is_err());
+        assert!(host.calls.is_empty());
+    }
+
+    #[test]
+    fn action_message_path() {
+        let data = [ 2, 1, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 20, 0, 20 ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        let mut frame = Frame {
+            action: ActionNum(4),
+            ..Frame::default()
+        };
+        frame.sprites[0] = Sprite::load(&mut input, data.len() as u32, &(Version::V3, )).unwrap();
+
+        let dispatcher = Dispatcher::new();
+        let mut host = Host::default();
+        dispatcher.dispatch(&mut host, Message::MouseUp, &frame, Some(ChannelNum(1))).unwrap();
+        assert_eq!(host.calls, vec![ "sprite 1 action 2", "member 5", "frame action 4", "movie" ]);
+    }
+
+    #[test]
+    fn primary_handlers() {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.set_primary_handler(PrimaryHandler::KeyDown, "beep");
+        assert_eq!(dispatcher.primary_handler(PrimaryHandler::KeyDown), "beep");
+
+        let mut host = Host::default().on("keyDownScript", Message::KeyDown, Outcome::Handled);
+        dispatcher.dispatch(&mut host, Message::KeyDown, &frame(), None).unwrap();
+        assert_eq!(host.calls, vec![ "keyDownScript", "frame", "movie" ]);
+
+        let mut host = Host::default().on("keyDownScript", Message::KeyDown, Outcome::Stop);
+        assert!(dispatcher.dispatch(&mut host, Message::KeyDown, &frame(), None).unwrap());
+        assert_eq!(host.calls, vec![ "keyDownScript" ]);
+    }
+
+    #[test]
+    fn hit_test() {
+        let mut frame = frame();
+        let hidden = SpriteBitmask::empty();
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 5, y: 5 }), Some(ChannelNum(1)));
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 12, y: 5 }), Some(ChannelNum(1)));
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 18, y: 5 }), Some(ChannelNum(2)));
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 25, y: 5 }), Some(ChannelNum(2)));
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 35, y: 5 }), None);
+
+        frame.sprites[1] = sprite(2, 3, BACKGROUND_TRANSPARENT_INK as u8, 10);
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 12, y: 5 }), Some(ChannelNum(2)));
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 25, y: 5 }), None);
+
+        let mut hidden = SpriteBitmask::empty();
+        hidden.set_sprite(ChannelNum(2));
+        assert_eq!(sprite_at(&frame, &hidden, &Geometry, Point { x: 12, y: 5 }), Some(ChannelNum(1)));
+    }
+
+    #[test]
+    fn hover() {
+        let mut dispatcher = Dispatcher::new();
+        let mut host = Host::default();
+        let frame = frame();
+        let hidden = SpriteBitmask::empty();
+        dispatcher.dispatch_mouse_move(&mut host, &Geometry, Point { x: 5, y: 5 }, &frame, &hidden).unwrap();
+        dispatcher.dispatch_mouse_move(&mut host, &Geometry, Point { x: 25, y: 5 }, &frame, &hidden).unwrap();
+        assert_eq!(host.calls, vec![
+            "member 1", "member 1",
+            "member 1", "sprite 2", "member 2", "sprite 2", "member 2",
+        ]);
+    }
+}
@@ -0,0 +1,708 @@
+use anyhow::{Context, Result as AResult};
+use crate::resources::{
+    field::{Alignment, ButtonKind, Flags, Frame, Meta},
+    styled_text::StyledText,
+};
+use libmactoolbox::{
+    Point,
+    quickdraw::{CGrafPort, Font, FontInfo, GrafVerb, Region, RGBColor},
+    Rect,
+    ResourceManager,
+    TEHandle,
+};
+use std::ops::Range;
+use super::fonts::MovieFonts;
+
+/// The font measurements needed to lay out text.
+pub trait TextMetrics {
+    fn char_width(&self, c: u8) -> i16;
+    fn info(&self) -> FontInfo;
+}
+
+impl TextMetrics for Font {
+    fn char_width(&self, c: u8) -> i16 {
+        Font::char_width(self, c)
+    }
+
+    fn info(&self) -> FontInfo {
+        Font::info(self)
+    }
+}
+
+/// A part of a line which is drawn with a single style run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub range: Range<usize>,
+    /// The index of the style run of the segment.
+    pub style_index: usize,
+    /// The horizontal position of the segment, relative to the start of the
+    /// line.
+    pub left: i16,
+}
+
+/// A single line of laid out text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Line {
+    /// The characters of the line, excluding any line break.
+    pub range: Range<usize>,
+    /// The top of the line, relative to the top of the text.
+    pub top: i16,
+    pub ascent: i16,
+    pub height: i16,
+    /// The horizontal position of the line after alignment.
+    pub left: i16,
+    /// The width of the line, excluding trailing spaces.
+    pub width: i16,
+    pub segments: Vec<Segment>,
+}
+
+/// Styled text broken into lines, like a `TextEdit` record.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TextLayout {
+    lines: Vec<Line>,
+    height: i16,
+}
+
+impl TextLayout {
+    /// Lays out text in a column of the given width. `fonts` holds the font
+    /// of each style run of the text. Lines are broken at carriage returns
+    /// and, if `word_wrap` is set, after the last space which fits in the
+    /// column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of fonts is not the same as the number of style
+    /// runs.
+    #[must_use]
+    pub fn new(text: &StyledText, fonts: &[impl TextMetrics], width: i16, alignment: Alignment, word_wrap: bool) -> Self {
+        assert_eq!(fonts.len(), text.styles().len());
+
+        let bytes = text.text();
+        let char_width = |index: usize, _| fonts[text.style_index_at(index)].char_width(bytes[index]);
+
+        let mut lines = Vec::new();
+        let mut top = 0;
+        let mut start = 0;
+        loop {
+            let paragraph_end = bytes[start..].iter()
+                .position(|&c| c == b'\r')
+                .map_or(bytes.len(), |end| start + end);
+
+            let mut line_start = start;
+            loop {
+                let line_end = if word_wrap {
+                    break_line(bytes, line_start..paragraph_end, width, char_width)
+                } else {
+                    paragraph_end
+                };
+
+                let line = Self::make_line(text, fonts, line_start..line_end, top, width, alignment);
+                top += line.height;
+                lines.push(line);
+
+                line_start = line_end;
+                if line_start >= paragraph_end {
+                    break;
+                }
+            }
+
+            if paragraph_end == bytes.len() {
+                break;
+            }
+            start = paragraph_end + 1;
+        }
+
+        Self { lines, height: top }
+    }
+
+    pub(super) fn from_lines(lines: Vec<Line>, height: i16) -> Self {
+        Self { lines, height }
+    }
+
+    /// Removes the lines which do not fit completely within the given
+    /// height. The first line is always kept.
+    pub fn truncate_to_height(&mut self, height: i16) {
+        let count = self.lines.iter()
+            .position(|line| line.top + line.height > height)
+            .unwrap_or_else(|| self.lines.len())
+            .max(1);
+        self.lines.truncate(count);
+        self.height = self.lines.last().map_or(0, |line| line.top + line.height);
+    }
+
+    /// The total height of the text.
+    #[must_use]
+    pub fn height(&self) -> i16 {
+        self.height
+    }
+
+    /// Returns the index of the line at the given vertical position, relative
+    /// to the top of the text.
+    #[must_use]
+    pub fn line_at(&self, v: i16) -> Option<usize> {
+        if v < 0 {
+            return None;
+        }
+
+        self.lines.iter().position(|line| v < line.top + line.height)
+    }
+
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns the height of the line with the given index, like Lingo
+    /// `lineHeight`.
+    #[must_use]
+    pub fn line_height(&self, index: usize) -> Option<i16> {
+        self.lines.get(index).map(|line| line.height)
+    }
+
+    #[must_use]
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    fn make_line(text: &StyledText, fonts: &[impl TextMetrics], range: Range<usize>, top: i16, width: i16, alignment: Alignment) -> Line {
+        let bytes = text.text();
+
+        let mut segments = Vec::new();
+        let mut ascent = 0;
+        let mut descent = 0;
+        let mut left = 0;
+        let mut line_width = 0;
+        let mut index = range.start;
+        loop {
+            let style_index = text.style_index_at(index);
+            let font = &fonts[style_index];
+            let info = font.info();
+            ascent = ascent.max(info.ascent);
+            descent = descent.max(info.descent + info.leading);
+
+            let end = text.styles().get(style_index + 1)
+                .map_or(range.end, |next| (next.start as usize).min(range.end));
+            if index == end {
+                break;
+            }
+
+            segments.push(Segment { range: index..end, style_index, left });
+            for &c in &bytes[index..end] {
+                left += font.char_width(c);
+                if c != b' ' {
+                    line_width = left;
+                }
+            }
+
+            index = end;
+            if index == range.end {
+                break;
+            }
+        }
+
+        let left = match alignment {
+            Alignment::Left => 0,
+            Alignment::Center => (width - line_width) / 2,
+            Alignment::Right => width - line_width,
+        };
+
+        Line {
+            range,
+            top,
+            ascent,
+            height: ascent + descent,
+            left,
+            width: line_width,
+            segments,
+        }
+    }
+}
+
+/// A field or button cast member with laid out text.
+#[derive(Clone, Debug)]
+pub struct Field {
+    meta: Meta,
+    layout: TextLayout,
+    scroll_top: i16,
+}
+
+impl Field {
+    /// The size of the check box and radio button marks.
+    const MARK_SIZE: i16 = 12;
+
+    /// The space between a check box or radio button mark and its text.
+    const MARK_GAP: i16 = 4;
+
+    /// The corner size of a push button.
+    const PUSH_BUTTON_OVAL_SIZE: i16 = 16;
+
+    /// The width of the scroll bar of a scrolling field.
+    const SCROLL_BAR_WIDTH: i16 = 16;
+
+    /// Lays out the text of a field or button. `fonts` holds the font of each
+    /// style run of the text.
+    #[must_use]
+    pub fn new(meta: Meta, text: &StyledText, fonts: &[impl TextMetrics]) -> Self {
+        let mut layout = TextLayout::new(
+            text,
+            fonts,
+            Self::text_rect_of(&meta).width(),
+            meta.alignment(),
+            !meta.flags().contains(Flags::NO_WORD_WRAP)
+        );
+
+        // Fields which are limited to their size only keep the lines which fit
+        // completely inside the field
+        if meta.frame() == Frame::LimitToFieldSize {
+            layout.truncate_to_height(meta.bounds().height());
+        }
+
+        let mut field = Self { meta, layout, scroll_top: 0 };
+        field.set_scroll_top(meta.scroll_top());
+        field
+    }
+
+    /// Finds the font of each style run of the text of a field through the
+    /// font tables of the movie. Runs whose font is missing are drawn with
+    /// the application font.
+    pub fn find_fonts(resources: &ResourceManager<'_>, text: &StyledText, movie_fonts: &MovieFonts) -> AResult<Vec<Font>> {
+        text.styles().iter().map(|style| {
+            if let Some(font) = movie_fonts.find_by_id(resources, style.font_id, style.size, style.style)? {
+                Ok(font)
+            } else {
+                Font::find(resources, Font::APPLICATION_FONT, style.size, style.style)?
+                    .with_context(|| format!("Can’t find a font for style font {}", style.font_id))
+            }
+        }).collect()
+    }
+
+    /// Converts the text of a field from the character set of the movie to
+    /// the Mac character set used by the fonts from
+    /// [`find_fonts`](Self::find_fonts).
+    #[must_use]
+    pub fn map_text(text: &StyledText, movie_fonts: &MovieFonts) -> StyledText {
+        let mut bytes = text.text().to_vec();
+        let len = bytes.len();
+        for (index, style) in text.styles().iter().enumerate() {
+            let name = movie_fonts.font_name(style.font_id).unwrap_or_default();
+            let start = (style.start as usize).min(len);
+            let end = text.styles().get(index + 1).map_or(len, |next| (next.start as usize).min(len));
+            if start < end {
+                movie_fonts.map_text(name, &mut bytes[start..end]);
+            }
+        }
+        StyledText::new(bytes, text.styles().to_vec())
+    }
+
+    /// Draws the field with its bounds moved by the given offset. For check
+    /// boxes and radio buttons, `hilite` draws the mark as checked; for push
+    /// buttons, it draws the button as pressed.
+    pub fn draw(&self, port: &mut CGrafPort, offset: Point, text: &StyledText, fonts: &[Font], hilite: bool) {
+        let view_rect = self.view_rect().offset(offset.x, offset.y);
+        let margin = i16::from(self.meta.margin_size());
+        let margin_rect = view_rect.inset(-margin, -margin);
+        let border = i16::from(self.meta.border_size());
+        let frame_rect = margin_rect.inset(-border, -border);
+
+        port.rgb_fore_color(RGBColor::BLACK);
+
+        let box_shadow = i16::from(self.meta.box_shadow_size());
+        if box_shadow != 0 {
+            port.std_rect(GrafVerb::Paint, frame_rect.offset(box_shadow, box_shadow));
+        }
+
+        port.rgb_back_color(self.meta.back_color());
+        port.std_rect(GrafVerb::Erase, margin_rect);
+        port.rgb_back_color(RGBColor::WHITE);
+
+        if border != 0 {
+            port.pen_size(border, border);
+            port.std_rect(GrafVerb::Frame, frame_rect);
+            port.pen_size(1, 1);
+        }
+
+        match self.meta.button_kind() {
+            ButtonKind::None => {},
+            ButtonKind::Button => {
+                port.std_rrect(GrafVerb::Frame, margin_rect, Self::PUSH_BUTTON_OVAL_SIZE, Self::PUSH_BUTTON_OVAL_SIZE);
+            },
+            ButtonKind::Radio | ButtonKind::CheckBox => {
+                let first_line_height = self.layout.line_height(0).unwrap_or(Self::MARK_SIZE);
+                let top = view_rect.top + ((first_line_height - Self::MARK_SIZE) / 2).max(0);
+                let mark_rect = Rect {
+                    top,
+                    left: view_rect.left,
+                    bottom: top + Self::MARK_SIZE,
+                    right: view_rect.left + Self::MARK_SIZE,
+                };
+                Self::draw_mark(port, self.meta.button_kind(), mark_rect, hilite);
+            },
+        }
+
+        if self.meta.frame() == Frame::Scroll {
+            self.draw_scroll_bar(port, view_rect);
+        }
+
+        let old_clip = port.clip_rgn().clone();
+        port.set_clip(old_clip.intersect(&Region::from(view_rect)));
+
+        let text_rect = self.text_rect().offset(offset.x, offset.y);
+        let text_shadow = i16::from(self.meta.text_shadow_size());
+        for line in self.layout.lines() {
+            let top = text_rect.top + line.top - self.scroll_top;
+            if top + line.height <= view_rect.top {
+                continue;
+            }
+            if top >= view_rect.bottom {
+                break;
+            }
+
+            let baseline = top + line.ascent;
+            for segment in &line.segments {
+                let left = text_rect.left + line.left + segment.left;
+                let font = &fonts[segment.style_index];
+                let chars = &text.text()[segment.range.clone()];
+                if text_shadow != 0 {
+                    port.rgb_fore_color(RGBColor::BLACK);
+                    port.move_to(left + text_shadow, baseline + text_shadow);
+                    port.draw_text(font, chars);
+                }
+                port.rgb_fore_color(text.styles()[segment.style_index].color);
+                port.move_to(left, baseline);
+                port.draw_text(font, chars);
+            }
+        }
+
+        port.set_clip(old_clip);
+        port.rgb_fore_color(RGBColor::BLACK);
+
+        if hilite && self.meta.button_kind() == ButtonKind::Button {
+            port.std_rrect(GrafVerb::Invert, margin_rect, Self::PUSH_BUTTON_OVAL_SIZE, Self::PUSH_BUTTON_OVAL_SIZE);
+        }
+    }
+
+    #[must_use]
+    pub fn layout(&self) -> &TextLayout {
+        &self.layout
+    }
+
+    /// Returns the number of lines of laid out text.
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.layout.line_count()
+    }
+
+    /// Returns the height of the line with the given index, like Lingo
+    /// `lineHeight`.
+    #[must_use]
+    pub fn line_height(&self, index: usize) -> Option<i16> {
+        self.layout.line_height(index)
+    }
+
+    /// The largest possible scroll position of the field.
+    #[must_use]
+    pub fn max_scroll_top(&self) -> i16 {
+        if self.meta.frame() == Frame::Fit {
+            0
+        } else {
+            (self.layout.height() - self.meta.bounds().height()).max(0)
+        }
+    }
+
+    #[must_use]
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    /// Scrolls the field so that the line which is the given number of lines
+    /// away from the current top line is at the top of the field, like Lingo
+    /// `scrollByLine`.
+    pub fn scroll_by_lines(&mut self, count: i16) {
+        let lines = self.layout.lines();
+        let current = self.layout.line_at(self.scroll_top).unwrap_or_else(|| lines.len() - 1);
+        let target = (current as isize + isize::from(count)).max(0) as usize;
+        self.set_scroll_top(lines[target.min(lines.len() - 1)].top);
+    }
+
+    /// Scrolls the field by the given number of pages, like Lingo
+    /// `scrollByPage`.
+    pub fn scroll_by_pages(&mut self, count: i16) {
+        let page_height = self.meta.bounds().height();
+        self.set_scroll_top(self.scroll_top.saturating_add(count.saturating_mul(page_height)));
+    }
+
+    #[must_use]
+    pub fn scroll_top(&self) -> i16 {
+        self.scroll_top
+    }
+
+    /// Sets the scroll position of the field, like Lingo `scrollTop`.
+    pub fn set_scroll_top(&mut self, scroll_top: i16) {
+        self.scroll_top = scroll_top.max(0).min(self.max_scroll_top());
+    }
+
+    /// Creates an editor for the text of the field with its bounds moved by
+    /// the given offset. The editor uses a single font for the whole text.
+    #[must_use]
+    pub fn text_edit(&self, offset: Point, text: &[u8], font: Font) -> TEHandle {
+        let dest_rect = self.text_rect().offset(offset.x, offset.y - self.scroll_top);
+        let view_rect = self.view_rect().offset(offset.x, offset.y);
+        let mut te = TEHandle::new(dest_rect, view_rect, font);
+        te.set_cr_only(self.meta.flags().contains(Flags::NO_WORD_WRAP));
+        te.set_text(text);
+        te
+    }
+
+    /// The area where the text is drawn, excluding button marks and scroll
+    /// bars.
+    #[must_use]
+    pub fn text_rect(&self) -> Rect {
+        let mut rect = Self::text_rect_of(&self.meta);
+        rect.bottom = self.view_rect().bottom;
+        rect
+    }
+
+    /// The visible area of the field, excluding decorations. Fields which
+    /// fit their text grow to the height of the text.
+    #[must_use]
+    pub fn view_rect(&self) -> Rect {
+        let mut rect = self.meta.bounds();
+        if self.meta.frame() == Frame::Fit {
+            rect.bottom = rect.top + self.layout.height();
+        }
+        rect
+    }
+
+    fn draw_mark(port: &mut CGrafPort, kind: ButtonKind, rect: Rect, hilite: bool) {
+        if kind == ButtonKind::Radio {
+            port.std_oval(GrafVerb::Frame, rect);
+            if hilite {
+                port.std_oval(GrafVerb::Paint, rect.inset(3, 3));
+            }
+        } else {
+            port.std_rect(GrafVerb::Frame, rect);
+            if hilite {
+                port.move_to(rect.left, rect.top);
+                port.std_line(Point { x: rect.right - 1, y: rect.bottom - 1 });
+                port.move_to(rect.right - 1, rect.top);
+                port.std_line(Point { x: rect.left, y: rect.bottom - 1 });
+            }
+        }
+    }
+
+    fn draw_scroll_bar(&self, port: &mut CGrafPort, view_rect: Rect) {
+        let bar_rect = Rect { left: view_rect.right - Self::SCROLL_BAR_WIDTH, ..view_rect };
+        port.std_rect(GrafVerb::Erase, bar_rect);
+        port.std_rect(GrafVerb::Frame, bar_rect);
+
+        let arrow_rect = Rect { bottom: bar_rect.top + Self::SCROLL_BAR_WIDTH, ..bar_rect };
+        port.std_rect(GrafVerb::Frame, arrow_rect);
+        port.std_rect(GrafVerb::Frame, arrow_rect.offset(0, bar_rect.height() - Self::SCROLL_BAR_WIDTH));
+
+        let max_scroll_top = self.max_scroll_top();
+        let track_height = bar_rect.height() - Self::SCROLL_BAR_WIDTH * 3;
+        if max_scroll_top != 0 && track_height > 0 {
+            let thumb_top = i32::from(track_height) * i32::from(self.scroll_top) / i32::from(max_scroll_top);
+            port.std_rect(GrafVerb::Frame, arrow_rect.offset(0, Self::SCROLL_BAR_WIDTH + thumb_top as i16));
+        }
+    }
+
+    fn text_rect_of(meta: &Meta) -> Rect {
+        let mut rect = meta.bounds();
+        if matches!(meta.button_kind(), ButtonKind::Radio | ButtonKind::CheckBox) {
+            rect.left += Self::MARK_SIZE + Self::MARK_GAP;
+        }
+        if meta.frame() == Frame::Scroll {
+            rect.right -= Self::SCROLL_BAR_WIDTH;
+        }
+        rect
+    }
+}
+
+/// Returns the end of the first line of the given text which fits in the
+/// given width. `advance` returns the advance width of the character at the
+/// given index when it is drawn at the given horizontal position. Spaces at
+/// the end of a line hang past the edge of the column, and words which are
+/// wider than the column are broken between characters.
+pub(super) fn break_line(bytes: &[u8], range: Range<usize>, width: i16, advance: impl Fn(usize, i16) -> i16) -> usize {
+    let mut x = 0;
+    let mut last_break = range.start;
+    for index in range.clone() {
+        let char_advance = advance(index, x);
+        if bytes[index] == b' ' {
+            last_break = index + 1;
+        } else if index > range.start && x + char_advance > width {
+            return if last_break > range.start { last_break } else { index };
+        }
+        x += char_advance;
+    }
+    range.end
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use crate::resources::{config::Platform, font_map::FontMap, styled_text::TextStyle};
+    use libcommon::Resource;
+    use std::io::Cursor;
+    use super::*;
+
+    struct FixedFont {
+        width: i16,
+        ascent: i16,
+        descent: i16,
+    }
+
+    impl TextMetrics for FixedFont {
+        fn char_width(&self, _: u8) -> i16 {
+            self.width
+        }
+
+        fn info(&self) -> FontInfo {
+            FontInfo {
+                ascent: self.ascent,
+                descent: self.descent,
+                wid_max: self.width,
+                leading: 0,
+            }
+        }
+    }
+
+    const FONT: FixedFont = FixedFont { width: 1, ascent: 8, descent: 2 };
+
+    fn plain(text: &[u8]) -> StyledText {
+        StyledText::new(text.to_vec(), vec![ TextStyle::default() ])
+    }
+
+    fn meta(frame: Frame, button_kind: ButtonKind, bounds: Rect) -> Meta {
+        let mut data = vec![ 0, 0, 0, frame as u8, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0 ];
+        for value in &[ bounds.top, bounds.left, bounds.bottom, bounds.right, bounds.height() ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(&[ 0, 0, 0, 0, 0, button_kind as u8 ]);
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        Meta::load(&mut input, data.len() as u32, &()).unwrap()
+    }
+
+    fn line_ranges(layout: &TextLayout) -> Vec<Range<usize>> {
+        layout.lines().iter().map(|line| line.range.clone()).collect()
+    }
+
+    #[test]
+    fn word_wrap() {
+        let layout = TextLayout::new(&plain(b"The quick brown fox"), &[ FONT ], 10, Alignment::Left, true);
+        assert_eq!(line_ranges(&layout), vec![ 0..10, 10..19 ]);
+        assert_eq!(layout.lines()[0].width, 9);
+        assert_eq!(layout.lines()[1].top, 10);
+        assert_eq!(layout.height(), 20);
+
+        let layout = TextLayout::new(&plain(b"abcdefghijkl"), &[ FONT ], 5, Alignment::Left, true);
+        assert_eq!(line_ranges(&layout), vec![ 0..5, 5..10, 10..12 ]);
+
+        let layout = TextLayout::new(&plain(b"The quick brown fox"), &[ FONT ], 10, Alignment::Left, false);
+        assert_eq!(line_ranges(&layout), vec![ 0..19 ]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        let layout = TextLayout::new(&plain(b"a\r\rb"), &[ FONT ], 10, Alignment::Left, true);
+        assert_eq!(line_ranges(&layout), vec![ 0..1, 2..2, 3..4 ]);
+
+        let layout = TextLayout::new(&plain(b"a\r"), &[ FONT ], 10, Alignment::Left, true);
+        assert_eq!(line_ranges(&layout), vec![ 0..1, 2..2 ]);
+
+        let layout = TextLayout::new(&plain(b""), &[ FONT ], 10, Alignment::Left, true);
+        assert_eq!(layout.line_count(), 1);
+        assert_eq!(layout.line_height(0), Some(10));
+        assert_eq!(layout.line_height(1), None);
+    }
+
+    #[test]
+    fn alignment() {
+        let layout = TextLayout::new(&plain(b"abcd "), &[ FONT ], 10, Alignment::Center, true);
+        assert_eq!(layout.lines()[0].left, 3);
+        let layout = TextLayout::new(&plain(b"abcd "), &[ FONT ], 10, Alignment::Right, true);
+        assert_eq!(layout.lines()[0].left, 6);
+    }
+
+    #[test]
+    fn style_runs() {
+        let text = StyledText::new(b"abcdef".to_vec(), vec![
+            TextStyle::default(),
+            TextStyle { start: 3, ..TextStyle::default() },
+        ]);
+        let fonts = [ FONT, FixedFont { width: 2, ascent: 12, descent: 1 } ];
+        let layout = TextLayout::new(&text, &fonts, 20, Alignment::Left, true);
+        let line = &layout.lines()[0];
+        assert_eq!(line.segments, vec![
+            Segment { range: 0..3, style_index: 0, left: 0 },
+            Segment { range: 3..6, style_index: 1, left: 3 },
+        ]);
+        assert_eq!(line.width, 9);
+        assert_eq!(line.ascent, 12);
+        assert_eq!(line.height, 14);
+        assert_eq!(layout.line_at(13), Some(0));
+        assert_eq!(layout.line_at(14), None);
+    }
+
+    #[test]
+    fn scroll() {
+        let bounds = Rect { top: 0, left: 0, bottom: 20, right: 26 };
+        let text = plain(b"1\r2\r3\r4\r5");
+        let mut field = Field::new(meta(Frame::Scroll, ButtonKind::None, bounds), &text, &[ FONT ]);
+        assert_eq!(field.text_rect().width(), 10);
+        assert_eq!(field.line_count(), 5);
+        assert_eq!(field.max_scroll_top(), 30);
+        field.set_scroll_top(100);
+        assert_eq!(field.scroll_top(), 30);
+        field.scroll_by_lines(-1);
+        assert_eq!(field.scroll_top(), 20);
+        field.scroll_by_pages(-5);
+        assert_eq!(field.scroll_top(), 0);
+        field.scroll_by_lines(2);
+        assert_eq!(field.scroll_top(), 20);
+
+        let field = Field::new(meta(Frame::Fit, ButtonKind::None, bounds), &text, &[ FONT ]);
+        assert_eq!(field.view_rect().height(), 50);
+        assert_eq!(field.max_scroll_top(), 0);
+    }
+
+    #[test]
+    fn limit_to_field_size() {
+        let bounds = Rect { top: 0, left: 0, bottom: 25, right: 10 };
+        let field = Field::new(meta(Frame::LimitToFieldSize, ButtonKind::None, bounds), &plain(b"1\r2\r3\r4"), &[ FONT ]);
+        assert_eq!(field.line_count(), 2);
+        assert_eq!(field.layout().height(), 20);
+        assert_eq!(field.max_scroll_top(), 0);
+    }
+
+    #[test]
+    fn map_text() {
+        let movie_fonts = MovieFonts::new(FontMap::default(), None, Platform::Win);
+        let text = Field::map_text(&plain(b"caf\xe9"), &movie_fonts);
+        assert_eq!(text.text(), b"caf\x8e");
+
+        let movie_fonts = MovieFonts::new(FontMap::default(), None, Platform::Mac);
+        let text = Field::map_text(&plain(b"caf\x8e"), &movie_fonts);
+        assert_eq!(text.text(), b"caf\x8e");
+    }
+
+    #[test]
+    fn check_box() {
+        let bounds = Rect { top: 0, left: 0, bottom: 12, right: 40 };
+        let field = Field::new(meta(Frame::Fixed, ButtonKind::CheckBox, bounds), &plain(b""), &[ FONT ]);
+        assert_eq!(field.text_rect().left, 16);
+
+        let mut port = CGrafPort::new(Rect { top: 0, left: 0, bottom: 14, right: 42 });
+        field.draw(&mut port, Point { x: 1, y: 1 }, &plain(b""), &[], true);
+        let pix_map = port.pix_map().borrow();
+        let is_black = |x, y| pix_map.pixel(x, y) == Some(RGBColor::BLACK);
+        assert!(is_black(1, 1));
+        assert!(is_black(12, 12));
+        assert!(is_black(6, 6));
+        assert!(is_black(7, 6));
+        assert!(!is_black(2, 6));
+        assert!(!is_black(13, 1));
+    }
+}
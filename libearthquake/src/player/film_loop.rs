@@ -0,0 +1,301 @@
+use anyhow::{Context, Result as AResult};
+use crate::resources::{cast::MemberId, film_loop::{Flags, Meta}};
+use libmactoolbox::{Point, quickdraw::{CGrafPort, Region}, Rect};
+use super::score::{ChannelNum, Frame, Score, Sprite, SpriteKind};
+
+/// Maps coordinates on the stage of a film loop to coordinates on the stage
+/// of the movie which contains it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Mapping {
+    src: Rect,
+    dst: Rect,
+    clip: Rect,
+}
+
+impl Mapping {
+    /// Creates a mapping from a film loop stage with the given bounds to the
+    /// given sprite rectangle. The film loop is scaled to fit the sprite if
+    /// the `SCALE` flag is set; otherwise, it is drawn at its original size
+    /// and cropped to the sprite, aligned to either the top-left corner or
+    /// the centre.
+    #[must_use]
+    pub fn new(bounds: Rect, flags: Flags, sprite_rect: Rect) -> Self {
+        let dst = if flags.contains(Flags::SCALE) {
+            sprite_rect
+        } else {
+            let (left, top) = if flags.contains(Flags::CROP_FROM_CENTER) {
+                (
+                    sprite_rect.left + (sprite_rect.width() - bounds.width()) / 2,
+                    sprite_rect.top + (sprite_rect.height() - bounds.height()) / 2,
+                )
+            } else {
+                (sprite_rect.left, sprite_rect.top)
+            };
+
+            Rect {
+                top,
+                left,
+                bottom: top + bounds.height(),
+                right: left + bounds.width(),
+            }
+        };
+
+        Self { src: bounds, dst, clip: sprite_rect }
+    }
+
+    /// The area of the movie stage which the film loop may draw into.
+    #[must_use]
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// Maps a point from the film loop stage to the movie stage.
+    #[must_use]
+    pub fn map_point(&self, point: Point) -> Point {
+        let map = |value: i16, src_start: i16, src_size: i16, dst_start: i16, dst_size: i16| {
+            let offset = i32::from(value) - i32::from(src_start);
+            let offset = if src_size == 0 {
+                offset
+            } else {
+                offset * i32::from(dst_size) / i32::from(src_size)
+            };
+            (i32::from(dst_start) + offset) as i16
+        };
+
+        Point {
+            x: map(point.x, self.src.left, self.src.width(), self.dst.left, self.dst.width()),
+            y: map(point.y, self.src.top, self.src.height(), self.dst.top, self.dst.height()),
+        }
+    }
+
+    /// Maps a rectangle from the film loop stage to the movie stage.
+    #[must_use]
+    pub fn map_rect(&self, rect: Rect) -> Rect {
+        let top_left = self.map_point(Point { x: rect.left, y: rect.top });
+        let bottom_right = self.map_point(Point { x: rect.right, y: rect.bottom });
+        Rect {
+            top: top_left.y,
+            left: top_left.x,
+            bottom: bottom_right.y,
+            right: bottom_right.x,
+        }
+    }
+}
+
+/// The playback state of a film loop or movie cast member. The score of the
+/// member plays inside the rectangle of the sprite which uses it.
+#[derive(Clone, Debug)]
+pub struct FilmLoop {
+    meta: Meta,
+    is_movie: bool,
+    frames: Vec<Frame>,
+    frame_index: usize,
+    finished: bool,
+}
+
+impl FilmLoop {
+    /// Creates a new player for the given frames of a film loop. If
+    /// `is_movie` is set, the member is a movie cast member, which may run
+    /// its own scripts.
+    #[must_use]
+    pub fn new(meta: Meta, is_movie: bool, frames: Vec<Frame>) -> Self {
+        Self {
+            meta,
+            is_movie,
+            frames,
+            frame_index: 0,
+            finished: false,
+        }
+    }
+
+    /// Creates a new player from the score of a film loop.
+    pub fn from_score(meta: Meta, is_movie: bool, score: Score) -> AResult<Self> {
+        let frames = score.collect::<AResult<Vec<_>>>().context("Can’t read film loop score")?;
+        Ok(Self::new(meta, is_movie, frames))
+    }
+
+    /// Moves to the next frame of the score. After the last frame, playback
+    /// returns to the first frame, unless looping is disabled, in which case
+    /// playback stops on the last frame. Returns whether the current frame
+    /// changed.
+    pub fn advance(&mut self) -> bool {
+        if self.finished || self.frames.is_empty() {
+            return false;
+        }
+
+        if self.frame_index + 1 < self.frames.len() {
+            self.frame_index += 1;
+            true
+        } else if self.meta.flags().contains(Flags::NO_LOOP) {
+            self.finished = true;
+            false
+        } else {
+            let changed = self.frame_index != 0;
+            self.frame_index = 0;
+            changed
+        }
+    }
+
+    #[must_use]
+    pub fn current_frame(&self) -> Option<&Frame> {
+        self.frames.get(self.frame_index)
+    }
+
+    /// Draws the current frame of the film loop into the given sprite
+    /// rectangle. Each sprite in the frame is drawn by `draw_sprite`, which
+    /// uses the given mapping to convert the position of the sprite from the
+    /// film loop stage to the movie stage. Drawing is clipped to the sprite
+    /// rectangle.
+    pub fn draw(&self, port: &mut CGrafPort, sprite_rect: Rect, mut draw_sprite: impl FnMut(&mut CGrafPort, ChannelNum, &Sprite, &Mapping)) {
+        let frame = if let Some(frame) = self.current_frame() {
+            frame
+        } else {
+            return;
+        };
+
+        let mapping = self.mapping(sprite_rect);
+        let old_clip = port.clip_rgn().clone();
+        port.set_clip(old_clip.intersect(&Region::from(mapping.clip())));
+
+        for (index, sprite) in frame.sprites.iter().enumerate() {
+            if sprite.kind() != SpriteKind::None {
+                draw_sprite(port, ChannelNum(index as i16 + 1), sprite, &mapping);
+            }
+        }
+
+        port.set_clip(old_clip);
+    }
+
+    /// The number of frames in the score of the film loop.
+    #[must_use]
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The zero-based index of the current frame.
+    #[must_use]
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// Returns the frame script of the current frame, if the member is
+    /// allowed to run scripts.
+    #[must_use]
+    pub fn frame_script(&self) -> Option<MemberId> {
+        if self.scripts_enabled() {
+            self.current_frame()
+                .map(|frame| frame.script)
+                .filter(|&script| script != MemberId::default())
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether playback stopped at the end of a film loop which does
+    /// not loop.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns the mapping from the film loop stage to the given sprite
+    /// rectangle.
+    #[must_use]
+    pub fn mapping(&self, sprite_rect: Rect) -> Mapping {
+        Mapping::new(self.meta.bounds(), self.meta.flags(), sprite_rect)
+    }
+
+    #[must_use]
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    /// Returns playback to the first frame.
+    pub fn rewind(&mut self) {
+        self.frame_index = 0;
+        self.finished = false;
+    }
+
+    /// Returns whether scripts in the score of the member should run. Only
+    /// movie cast members run scripts, and only if they are enabled.
+    #[must_use]
+    pub fn scripts_enabled(&self) -> bool {
+        self.is_movie && self.meta.flags().contains(Flags::ENABLE_SCRIPTS)
+    }
+
+    /// Returns whether sounds in the score of the member should play.
+    #[must_use]
+    pub fn sound_enabled(&self) -> bool {
+        self.meta.flags().contains(Flags::SOUND_ENABLED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::Resource;
+    use std::io::Cursor;
+    use super::*;
+
+    fn meta(flags: u8) -> Meta {
+        let data = [ 0, 10, 0, 20, 0, 50, 0, 60, 0, 0, 0, flags, 0, 0 ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        Meta::load(&mut input, data.len() as u32, &()).unwrap()
+    }
+
+    fn frames(count: usize) -> Vec<Frame> {
+        vec![ Frame::default(); count ]
+    }
+
+    #[test]
+    fn crop() {
+        let sprite_rect = Rect { top: 100, left: 100, bottom: 120, right: 120 };
+        let mapping = Mapping::new(meta(0).bounds(), Flags::empty(), sprite_rect);
+        assert_eq!(mapping.clip(), sprite_rect);
+        assert_eq!(mapping.map_point(Point { x: 20, y: 10 }), Point { x: 100, y: 100 });
+        assert_eq!(mapping.map_point(Point { x: 30, y: 15 }), Point { x: 110, y: 105 });
+
+        let mapping = Mapping::new(meta(0).bounds(), Flags::CROP_FROM_CENTER, sprite_rect);
+        assert_eq!(mapping.map_point(Point { x: 40, y: 30 }), Point { x: 110, y: 110 });
+    }
+
+    #[test]
+    fn scale() {
+        let sprite_rect = Rect { top: 0, left: 0, bottom: 20, right: 80 };
+        let film_loop = FilmLoop::new(meta(2), false, frames(1));
+        let mapping = film_loop.mapping(sprite_rect);
+        assert_eq!(mapping.map_rect(Rect { top: 10, left: 20, bottom: 30, right: 40 }), Rect { top: 0, left: 0, bottom: 10, right: 40 });
+        assert_eq!(mapping.map_rect(film_loop.meta().bounds()), sprite_rect);
+    }
+
+    #[test]
+    fn playback() {
+        let mut film_loop = FilmLoop::new(meta(0), false, frames(3));
+        assert_eq!(film_loop.frame_count(), 3);
+        assert!(film_loop.advance());
+        assert!(film_loop.advance());
+        assert_eq!(film_loop.frame_index(), 2);
+        assert!(film_loop.advance());
+        assert_eq!(film_loop.frame_index(), 0);
+        assert!(!film_loop.is_finished());
+
+        let mut film_loop = FilmLoop::new(meta(0x20), false, frames(2));
+        assert!(film_loop.advance());
+        assert!(!film_loop.advance());
+        assert_eq!(film_loop.frame_index(), 1);
+        assert!(film_loop.is_finished());
+        film_loop.rewind();
+        assert_eq!(film_loop.frame_index(), 0);
+        assert!(!film_loop.is_finished());
+    }
+
+    #[test]
+    fn scripts() {
+        let mut frames = frames(1);
+        frames[0].script = MemberId::new(0_i16, 5_i16);
+        assert_eq!(FilmLoop::new(meta(0x10), false, frames.clone()).frame_script(), None);
+        assert_eq!(FilmLoop::new(meta(0), true, frames.clone()).frame_script(), None);
+        assert_eq!(FilmLoop::new(meta(0x10), true, frames).frame_script(), Some(MemberId::new(0_i16, 5_i16)));
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::Result as AResult;
+use crate::resources::{
+    config::Platform as ConfigPlatform,
+    font_map::{CrossPlatformFontMap, FontMap, Platform},
+};
+use libcommon::encodings::{Encoder, MAC_ROMAN, Unmappable};
+use libmactoolbox::{quickdraw::{Font, Style}, ResourceManager};
+use std::convert::TryFrom;
+
+/// The font tables of a movie, which are used to find the Mac fonts that draw
+/// the text of its cast members.
+#[derive(Clone, Debug)]
+pub struct MovieFonts {
+    font_map: FontMap,
+    substitutions: CrossPlatformFontMap,
+    platform: Platform,
+}
+
+impl MovieFonts {
+    /// Creates font tables from the `VWFM` font map and the `FXmp`
+    /// substitutions of a movie authored on the given platform. Rules which
+    /// are missing from the substitutions are taken from the default Director
+    /// font map.
+    #[must_use]
+    pub fn new(font_map: FontMap, substitutions: Option<CrossPlatformFontMap>, platform: ConfigPlatform) -> Self {
+        let default_map = CrossPlatformFontMap::default();
+        Self {
+            font_map,
+            substitutions: match substitutions {
+                Some(substitutions) => substitutions.with_fallback(&default_map),
+                None => default_map,
+            },
+            platform: if platform == ConfigPlatform::Win { Platform::Win } else { Platform::Mac },
+        }
+    }
+
+    /// Returns the name of the font with the given movie font number, if it
+    /// is in the movie font map.
+    #[must_use]
+    pub fn font_name(&self, font_id: i16) -> Option<&str> {
+        self.font_map.get(font_id)
+    }
+
+    /// Finds the Mac font which draws text in the font with the given name
+    /// and size from the movie.
+    pub fn find(&self, resources: &ResourceManager<'_>, name: &str, size: i16, style: Style) -> AResult<Option<Font>> {
+        let (family, size) = self.mac_family(resources, name, size)?;
+        Font::find(resources, family, size, style)
+    }
+
+    /// Finds the Mac font which draws text in the font with the given movie
+    /// font number. Font numbers which are not in the movie font map are
+    /// treated as Mac font numbers.
+    pub fn find_by_id(&self, resources: &ResourceManager<'_>, font_id: i16, size: i16, style: Style) -> AResult<Option<Font>> {
+        if let Some(name) = self.font_name(font_id) {
+            self.find(resources, name, size, style)
+        } else {
+            Font::find(resources, font_id, size, style)
+        }
+    }
+
+    /// Returns the Mac font number and size which replace the given font and
+    /// size from the movie. Fonts which are not installed are replaced by
+    /// the application font.
+    pub fn mac_family(&self, resources: &ResourceManager<'_>, name: &str, size: i16) -> AResult<(i16, i16)> {
+        let (name, size) = self.mac_font(name, size);
+        let name = MAC_ROMAN.encode(name, Unmappable::Replace)?;
+        let family = Font::get_font_number(resources, name)?.unwrap_or(Font::APPLICATION_FONT);
+        Ok((family, size))
+    }
+
+    /// Returns the Mac font name and size which replace the given font and
+    /// size from the movie.
+    #[must_use]
+    pub fn mac_font<'a>(&'a self, name: &'a str, size: i16) -> (&'a str, i16) {
+        match u16::try_from(size) {
+            Ok(size) => {
+                let (name, size) = self.substitutions.mac_font(self.platform, name, size);
+                (name, i16::try_from(size).unwrap_or(i16::MAX))
+            },
+            Err(_) => (name, size),
+        }
+    }
+
+    /// Converts text in the given font from the character set of the movie to
+    /// the Mac character set, unless the font substitution says that the font
+    /// should not be mapped, like a symbol font.
+    pub fn map_text(&self, name: &str, text: &mut [u8]) {
+        if self.platform == Platform::Mac
+            || self.substitutions.find(self.platform, name).map_or(false, |font| !font.map_chars) {
+            return;
+        }
+
+        for c in text {
+            *c = self.substitutions.map_char(self.platform, Platform::Mac, *c);
+        }
+    }
+}
+
+impl Default for MovieFonts {
+    fn default() -> Self {
+        Self::new(FontMap::default(), None, ConfigPlatform::Mac)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    #[test]
+    fn win_movie() {
+        let fonts = MovieFonts::new(FontMap::default(), None, ConfigPlatform::Win);
+        assert_eq!(fonts.mac_font("Arial", 12), ("Helvetica", 12));
+        assert_eq!(fonts.mac_font("Times New Roman", 12), ("Times", 14));
+        assert_eq!(fonts.mac_font("Unknown", 9), ("Unknown", 9));
+
+        let mut text = *b"caf\xe9";
+        fonts.map_text("Arial", &mut text);
+        assert_eq!(&text, b"caf\x8e");
+
+        let mut text = *b"\xe9";
+        fonts.map_text("Symbol", &mut text);
+        assert_eq!(&text, b"\xe9");
+    }
+
+    #[test]
+    fn mac_movie() {
+        let substitutions = CrossPlatformFontMap::parse("Mac:Geneva => Win:Arial 12=>10").unwrap();
+        let fonts = MovieFonts::new(FontMap::default(), Some(substitutions), ConfigPlatform::Mac);
+        assert_eq!(fonts.mac_font("Geneva", 12), ("Geneva", 12));
+        let mut text = *b"caf\x8e";
+        fonts.map_text("Geneva", &mut text);
+        assert_eq!(&text, b"caf\x8e");
+    }
+}
@@ -0,0 +1,183 @@
+//! Named markers on frames of the score.
+
+use anyhow::{Context, Result as AResult};
+use crate::ensure_sample;
+use libcommon::{encodings::DecoderRef, Reader, Resource, resource::Input};
+use std::{convert::TryInto, io::Read};
+use super::score::FrameNum;
+
+/// A named marker on a frame of the score.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrameLabel {
+    frame: FrameNum,
+    name: String,
+    comment: String,
+}
+
+impl FrameLabel {
+    /// The text which follows the first line of the label in the score
+    /// window.
+    #[must_use]
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    #[must_use]
+    pub fn frame(&self) -> FrameNum {
+        self.frame
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The frame labels of a score, from a `VWLB` resource, sorted by frame.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FrameLabels(Vec<FrameLabel>);
+
+impl FrameLabels {
+    /// Returns the frame with the given label, like `go to frame "name"`.
+    /// Labels are not case-sensitive.
+    #[must_use]
+    pub fn frame(&self, name: &str) -> Option<FrameNum> {
+        self.0.iter()
+            .find(|label| label.name.eq_ignore_ascii_case(name))
+            .map(FrameLabel::frame)
+    }
+
+    /// Returns the label of the given frame.
+    #[must_use]
+    pub fn get(&self, frame: FrameNum) -> Option<&FrameLabel> {
+        self.0.iter().find(|label| label.frame == frame)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FrameLabel> {
+        self.0.iter()
+    }
+
+    /// Returns the names of all labels, each followed by a carriage return,
+    /// like `the labelList`.
+    #[must_use]
+    pub fn label_list(&self) -> String {
+        self.0.iter().fold(String::new(), |mut list, label| {
+            list.push_str(&label.name);
+            list.push('\r');
+            list
+        })
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns the frame of the marker which is `offset` markers away from
+    /// the last marker at or before the given frame, like `marker(offset)`.
+    /// An offset which goes past the first or last marker gives that marker.
+    /// Returns `None` if there are no markers.
+    #[must_use]
+    pub fn marker(&self, frame: FrameNum, offset: i16) -> Option<FrameNum> {
+        let last = self.0.len().checked_sub(1)?;
+        let current = self.0.iter().rposition(|label| label.frame <= frame);
+        let index = match current {
+            Some(current) => current as i32 + i32::from(offset),
+            // Before the first marker, the next marker is the first one
+            None if offset > 0 => i32::from(offset) - 1,
+            None => 0,
+        };
+        Some(self.0[index.max(0).min(last as i32) as usize].frame)
+    }
+}
+
+impl Resource for FrameLabels {
+    type Context = (DecoderRef, );
+
+    fn load(input: &mut Input<impl Reader>, size: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
+        const ENTRY_SIZE: u32 = 4;
+
+        let count = input.read_u16().context("Can’t read frame label count")?;
+        let header_size = 2 + (u32::from(count) + 1) * ENTRY_SIZE;
+        ensure_sample!(header_size <= size, "Frame label count {} is too large for resource size {}", count, size);
+
+        let mut entries = Vec::with_capacity(usize::from(count) + 1);
+        for index in 0..=count {
+            let frame = input.read_i16().with_context(|| format!("Can’t read frame label {} frame", index))?;
+            let offset = input.read_u16().with_context(|| format!("Can’t read frame label {} offset", index))?;
+            entries.push((FrameNum(frame), u32::from(offset)));
+        }
+
+        let mut text = Vec::with_capacity((size - header_size).try_into().unwrap());
+        input.take((size - header_size).into()).read_to_end(&mut text).context("Can’t read frame label text")?;
+
+        let mut labels = Vec::with_capacity(count.into());
+        // The last entry only marks the end of the text of the last label
+        for (index, window) in entries.windows(2).enumerate() {
+            let (frame, start) = window[0];
+            let end = window[1].1;
+            ensure_sample!(
+                start <= end && end as usize <= text.len(),
+                "Invalid frame label {} text range {}..{}", index, start, end
+            );
+            let data = context.0.decode(&text[start as usize..end as usize]);
+            let mut lines = data.splitn(2, '\r');
+            labels.push(FrameLabel {
+                frame,
+                name: lines.next().unwrap_or_default().to_string(),
+                comment: lines.next().unwrap_or_default().to_string(),
+            });
+        }
+
+        labels.sort_by_key(FrameLabel::frame);
+        Ok(Self(labels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::encodings::MAC_ROMAN;
+    use std::io::Cursor;
+    use super::*;
+
+    fn labels() -> FrameLabels {
+        let mut data = vec![ 0, 3, 0, 2, 0, 0, 0, 5, 0, 5, 0, 15, 0, 14, 0, 0, 0, 18 ];
+        data.extend(b"Intromain\rLoop");
+        data.extend(b"End");
+        data.extend(&[ 0x8e ]);
+        let size = data.len() as u32;
+        let mut input = ByteOrdered::new(Cursor::new(data), Endianness::Big);
+        FrameLabels::load(&mut input, size, &(MAC_ROMAN, )).unwrap()
+    }
+
+    #[test]
+    fn load() {
+        let labels = labels();
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels.get(FrameNum(5)).unwrap().name(), "main");
+        assert_eq!(labels.get(FrameNum(5)).unwrap().comment(), "Loop");
+        assert_eq!(labels.get(FrameNum(15)).unwrap().name(), "Endé");
+        assert_eq!(labels.frame("INTRO"), Some(FrameNum(2)));
+        assert_eq!(labels.frame("Missing"), None);
+        assert_eq!(labels.label_list(), "Intro\rmain\rEndé\r");
+    }
+
+    #[test]
+    fn marker() {
+        let labels = labels();
+        assert_eq!(labels.marker(FrameNum(7), 0), Some(FrameNum(5)));
+        assert_eq!(labels.marker(FrameNum(7), 1), Some(FrameNum(15)));
+        assert_eq!(labels.marker(FrameNum(7), -1), Some(FrameNum(2)));
+        assert_eq!(labels.marker(FrameNum(7), 5), Some(FrameNum(15)));
+        assert_eq!(labels.marker(FrameNum(1), 0), Some(FrameNum(2)));
+        assert_eq!(labels.marker(FrameNum(1), 1), Some(FrameNum(2)));
+        assert_eq!(FrameLabels::default().marker(FrameNum(1), 0), None);
+    }
+}
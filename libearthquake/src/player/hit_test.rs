@@ -0,0 +1,410 @@
+//! The geometry of sprites on the stage.
+//!
+//! Any [`SpriteMembers`] is a [`SpriteGeometry`], so the cast members of a
+//! movie can be used with [`sprite_at`](super::event::sprite_at) to find the
+//! sprite under a point for `rollOver`, `the mouseCast`, and `the clickOn`.
+//! A sprite is only hit if the pixel under the point is drawn opaquely by its
+//! ink.
+
+use crate::resources::field::Flags as FieldFlags;
+use libmactoolbox::{Point, quickdraw::{PixMap, Region, RGBColor}, Rect};
+use std::borrow::Cow;
+use super::{event::SpriteGeometry, score::{ChannelNum, Sprite}};
+
+/// Provides the cast members used by sprites for hit testing and text
+/// editing.
+pub trait SpriteMembers {
+    /// The bounds of the cast member used by the sprite, with the
+    /// registration point of the member at the origin. Returns `None` if the
+    /// member has no intrinsic size, in which case the sprite origin is the
+    /// top-left corner of the sprite.
+    fn member_bounds(&self, channel: ChannelNum, sprite: &Sprite) -> Option<Rect>;
+
+    /// The image of the cast member used by the sprite, in the coordinate
+    /// system of [`member_bounds`](Self::member_bounds), if the member is a
+    /// bitmap.
+    fn member_image(&self, channel: ChannelNum, sprite: &Sprite) -> Option<&PixMap>;
+
+    /// The matte of the image of the cast member used by the sprite, which
+    /// is used to draw and hit test sprites with the matte ink. The default
+    /// calculates the matte from [`member_image`](Self::member_image) every
+    /// time, so casts should cache it with the bitmap member instead.
+    fn member_matte(&self, channel: ChannelNum, sprite: &Sprite) -> Option<Cow<'_, Matte>> {
+        self.member_image(channel, sprite).map(|image| Cow::Owned(Matte::new(image)))
+    }
+
+    /// The colour which is transparent when a sprite is drawn with the
+    /// background transparent ink.
+    fn back_color(&self, _: &Sprite) -> RGBColor {
+        RGBColor::WHITE
+    }
+
+    /// The flags of the cast member used by the sprite, if the member is a
+    /// field.
+    fn field_flags(&self, _: ChannelNum, _: &Sprite) -> Option<FieldFlags> {
+        None
+    }
+}
+
+/// The position of a sprite on the stage, taking into account its
+/// registration point, stretching, and flipping.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SpriteRect {
+    rect: Rect,
+    member_bounds: Rect,
+    flip_h: bool,
+    flip_v: bool,
+}
+
+impl SpriteRect {
+    /// Calculates the position of a sprite which uses a member with the
+    /// given bounds. A stretched sprite is scaled to its width and height.
+    /// A sprite is flipped by its flip flags or by a negative width or
+    /// height.
+    #[must_use]
+    pub fn new(sprite: &Sprite, member_bounds: Option<Rect>) -> Self {
+        let origin = sprite.origin();
+        let (member_bounds, width, height) = match member_bounds {
+            Some(bounds) if !sprite.stretch() => (bounds, bounds.width(), bounds.height()),
+            Some(bounds) => (bounds, sprite.width(), sprite.height()),
+            None => (
+                Rect { top: 0, left: 0, bottom: sprite.height().abs(), right: sprite.width().abs() },
+                sprite.width(),
+                sprite.height(),
+            ),
+        };
+
+        let axis = |origin: i16, member_start: i16, member_size: i16, size: i16| {
+            let offset = if member_size == 0 {
+                0
+            } else {
+                i32::from(member_start) * i32::from(size.abs()) / i32::from(member_size)
+            };
+            let start = i32::from(origin) + offset;
+            (start as i16, (start + i32::from(size.abs())) as i16)
+        };
+
+        let (left, right) = axis(origin.x, member_bounds.left, member_bounds.width(), width);
+        let (top, bottom) = axis(origin.y, member_bounds.top, member_bounds.height(), height);
+
+        Self {
+            rect: Rect { top, left, bottom, right },
+            member_bounds,
+            flip_h: sprite.flip_h() || width < 0,
+            flip_v: sprite.flip_v() || height < 0,
+        }
+    }
+
+    /// Returns whether the sprite is mirrored horizontally.
+    #[must_use]
+    pub fn flip_h(&self) -> bool {
+        self.flip_h
+    }
+
+    /// Returns whether the sprite is mirrored vertically.
+    #[must_use]
+    pub fn flip_v(&self) -> bool {
+        self.flip_v
+    }
+
+    /// Maps a point on the stage to the nearest point in the coordinate
+    /// system of the cast member. Returns `None` if the point is outside the
+    /// sprite.
+    #[must_use]
+    pub fn map_to_member(&self, point: Point) -> Option<Point> {
+        if !self.rect.contains(point) {
+            return None;
+        }
+
+        let map = |value: i16, start: i16, size: i16, flip: bool, member_start: i16, member_size: i16| {
+            let mut offset = i32::from(value) - i32::from(start);
+            if flip {
+                offset = i32::from(size) - 1 - offset;
+            }
+            (i32::from(member_start) + offset * i32::from(member_size) / i32::from(size)) as i16
+        };
+
+        Some(Point {
+            x: map(point.x, self.rect.left, self.rect.width(), self.flip_h, self.member_bounds.left, self.member_bounds.width()),
+            y: map(point.y, self.rect.top, self.rect.height(), self.flip_v, self.member_bounds.top, self.member_bounds.height()),
+        })
+    }
+
+    /// The bounding rectangle of the sprite on the stage.
+    #[must_use]
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+}
+
+/// The pixels of a bitmap which are drawn by the matte ink, which is
+/// everything except white pixels connected to the edge of the image by
+/// other white pixels, like the mask from `CalcCMask`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Matte {
+    bounds: Rect,
+    holes: Vec<bool>,
+}
+
+impl Matte {
+    /// Calculates the matte of an image.
+    #[must_use]
+    pub fn new(image: &PixMap) -> Self {
+        let bounds = image.bounds();
+        let mut matte = Self {
+            bounds,
+            holes: vec![ false; image.pixels().len() ],
+        };
+        let is_white = |point: Point| image.pixel(point.x, point.y) == Some(RGBColor::WHITE);
+
+        let mut stack = Vec::new();
+        for y in bounds.top..bounds.bottom {
+            for x in bounds.left..bounds.right {
+                let point = Point { x, y };
+                let is_edge = x == bounds.left || y == bounds.top || x == bounds.right - 1 || y == bounds.bottom - 1;
+                if is_edge && is_white(point) && !matte.holes[matte.index(point)] {
+                    let index = matte.index(point);
+                    matte.holes[index] = true;
+                    stack.push(point);
+                }
+            }
+        }
+
+        while let Some(point) = stack.pop() {
+            for &(dx, dy) in &[ (-1, 0), (1, 0), (0, -1), (0, 1) ] {
+                let next = Point { x: point.x + dx, y: point.y + dy };
+                if bounds.contains(next) && !matte.holes[matte.index(next)] && is_white(next) {
+                    let index = matte.index(next);
+                    matte.holes[index] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        matte
+    }
+
+    /// Returns whether the matte ink draws the pixel of the image at the
+    /// given point.
+    #[must_use]
+    pub fn contains(&self, point: Point) -> bool {
+        self.bounds.contains(point) && !self.holes[self.index(point)]
+    }
+
+    /// Returns the matte as a region in the coordinate system of the image,
+    /// mirrored horizontally and/or vertically within the image bounds.
+    #[must_use]
+    pub fn to_region(&self, flip_h: bool, flip_v: bool) -> Region {
+        let bounds = self.bounds;
+        let is_drawn = |x: i16, y: i16| {
+            let x = if flip_h { bounds.right - 1 - (x - bounds.left) } else { x };
+            let y = if flip_v { bounds.bottom - 1 - (y - bounds.top) } else { y };
+            self.contains(Point { x, y })
+        };
+
+        let mut region = Region::default();
+        for y in bounds.top..bounds.bottom {
+            let mut row = Region::default();
+            let mut x = bounds.left;
+            while x < bounds.right {
+                if !is_drawn(x, y) {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < bounds.right && is_drawn(x, y) {
+                    x += 1;
+                }
+                row = row.union(&Region::from(Rect { top: y, left: start, bottom: y + 1, right: x }));
+            }
+            region = region.union(&row);
+        }
+        region
+    }
+
+    fn index(&self, point: Point) -> usize {
+        let width = self.bounds.width() as usize;
+        (i32::from(point.y) - i32::from(self.bounds.top)) as usize * width
+            + (i32::from(point.x) - i32::from(self.bounds.left)) as usize
+    }
+}
+
+impl<T: SpriteMembers> SpriteGeometry for T {
+    fn sprite_rect(&self, channel: ChannelNum, sprite: &Sprite) -> Rect {
+        SpriteRect::new(sprite, self.member_bounds(channel, sprite)).rect()
+    }
+
+    fn matte_contains(&self, channel: ChannelNum, sprite: &Sprite, point: Point) -> bool {
+        match self.member_matte(channel, sprite) {
+            Some(matte) => member_point(self, channel, sprite, point).map_or(false, |point| matte.contains(point)),
+            None => true,
+        }
+    }
+
+    fn transparent_contains(&self, channel: ChannelNum, sprite: &Sprite, point: Point) -> bool {
+        match self.member_image(channel, sprite) {
+            Some(image) => member_point(self, channel, sprite, point)
+                .map_or(false, |point| image.pixel(point.x, point.y) != Some(self.back_color(sprite))),
+            None => true,
+        }
+    }
+}
+
+/// Maps a point on the stage to the coordinate system of the cast member
+/// used by a sprite.
+fn member_point(members: &impl SpriteMembers, channel: ChannelNum, sprite: &Sprite, point: Point) -> Option<Point> {
+    SpriteRect::new(sprite, members.member_bounds(channel, sprite)).map_to_member(point)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::Resource;
+    use std::io::Cursor;
+    use libmactoolbox::quickdraw::Pen;
+    use super::*;
+    use super::super::{event::sprite_at, score::{Frame, SpriteBitmask, SpriteLineSize, Version}};
+
+    struct Members {
+        ring: PixMap,
+    }
+
+    impl Members {
+        /// A 20×20 black ring around a white hole, on a white background
+        fn new() -> Self {
+            let mut ring = PixMap::new(Rect { top: -10, left: -10, bottom: 10, right: 10 }, RGBColor::WHITE);
+            for y in -8..8 {
+                for x in -8..8 {
+                    if !(-4..4).contains(&x) || !(-4..4).contains(&y) {
+                        ring.set_pixel(x, y, RGBColor::BLACK);
+                    }
+                }
+            }
+            Self { ring }
+        }
+    }
+
+    impl SpriteMembers for Members {
+        fn member_bounds(&self, _: ChannelNum, sprite: &Sprite) -> Option<Rect> {
+            if sprite.id().num().0 == 1 {
+                None
+            } else {
+                Some(self.ring.bounds())
+            }
+        }
+
+        fn member_image(&self, _: ChannelNum, sprite: &Sprite) -> Option<&PixMap> {
+            if sprite.id().num().0 == 1 {
+                None
+            } else {
+                Some(&self.ring)
+            }
+        }
+    }
+
+    fn sprite(member: u8, ink: u8, x: i16, width: i16) -> Sprite {
+        flipped_sprite(member, ink, x, width, 0)
+    }
+
+    fn flipped_sprite(member: u8, ink: u8, x: i16, width: i16, flags: u8) -> Sprite {
+        let x = x.to_be_bytes();
+        let width = width.to_be_bytes();
+        let data = [
+            1, ink, 0, 0, 0, member, 0, 0, 0, 0,
+            0, 0, x[0], x[1], 0, 20, 0, 20, width[0], width[1],
+            0, 0, flags, 0,
+        ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        Sprite::load(&mut input, data.len() as u32, &(Version::V5, )).unwrap()
+    }
+
+    #[test]
+    fn sprite_rect() {
+        let members = Members::new();
+        let rect = SpriteRect::new(&sprite(2, 0, 50, 0), members.member_bounds(ChannelNum(1), &sprite(2, 0, 0, 0)));
+        assert_eq!(rect.rect(), Rect { top: 10, left: 40, bottom: 30, right: 60 });
+        assert_eq!(rect.map_to_member(Point { x: 40, y: 10 }), Some(Point { x: -10, y: -10 }));
+        assert_eq!(rect.map_to_member(Point { x: 60, y: 10 }), None);
+
+        let plain = SpriteRect::new(&sprite(1, 0, 50, 30), None);
+        assert_eq!(plain.rect(), Rect { top: 20, left: 50, bottom: 40, right: 80 });
+    }
+
+    #[test]
+    fn sprite_rect_stretched_and_flipped() {
+        let bounds = Some(Rect { top: -10, left: -10, bottom: 10, right: 10 });
+        let mut stretched = sprite(2, 0x80, 50, 40);
+        let rect = SpriteRect::new(&stretched, bounds);
+        assert_eq!(rect.rect(), Rect { top: 10, left: 30, bottom: 30, right: 70 });
+        assert_eq!(rect.map_to_member(Point { x: 69, y: 10 }), Some(Point { x: 9, y: -10 }));
+
+        stretched = sprite(2, 0x80, 50, -40);
+        let rect = SpriteRect::new(&stretched, bounds);
+        assert!(rect.flip_h());
+        assert!(!rect.flip_v());
+        assert_eq!(rect.rect(), Rect { top: 10, left: 30, bottom: 30, right: 70 });
+        assert_eq!(rect.map_to_member(Point { x: 69, y: 10 }), Some(Point { x: -10, y: -10 }));
+    }
+
+    #[test]
+    fn sprite_rect_flipped_by_flags() {
+        let members = Members::new();
+        let bounds = members.member_bounds(ChannelNum(1), &sprite(2, 0, 0, 0));
+        let rect = SpriteRect::new(&flipped_sprite(2, 0, 50, 0, SpriteLineSize::FLIP_H.bits()), bounds);
+        assert!(rect.flip_h());
+        assert!(!rect.flip_v());
+        assert_eq!(rect.rect(), Rect { top: 10, left: 40, bottom: 30, right: 60 });
+        assert_eq!(rect.map_to_member(Point { x: 40, y: 10 }), Some(Point { x: 9, y: -10 }));
+
+        let rect = SpriteRect::new(&flipped_sprite(2, 0, 50, 0, SpriteLineSize::FLIP_V.bits()), bounds);
+        assert!(!rect.flip_h());
+        assert!(rect.flip_v());
+        assert_eq!(rect.map_to_member(Point { x: 40, y: 10 }), Some(Point { x: -10, y: 9 }));
+    }
+
+    #[test]
+    fn matte() {
+        let matte = Matte::new(&Members::new().ring);
+        assert!(matte.contains(Point { x: 0, y: 0 }));
+        assert!(matte.contains(Point { x: -8, y: -8 }));
+        assert!(!matte.contains(Point { x: -10, y: 0 }));
+        assert!(!matte.contains(Point { x: 10, y: 0 }));
+
+        let region = matte.to_region(false, false);
+        assert_eq!(region.bounds(), Rect { top: -8, left: -8, bottom: 8, right: 8 });
+        assert!(region.contains(Point { x: 0, y: 0 }));
+        assert!(!region.contains(Point { x: -9, y: 0 }));
+
+        // A black pixel on the left of a white row
+        let mut image = PixMap::new(Rect { top: 0, left: 0, bottom: 1, right: 3 }, RGBColor::WHITE);
+        image.set_pixel(0, 0, RGBColor::BLACK);
+        let matte = Matte::new(&image);
+        assert_eq!(matte.to_region(false, false).bounds(), Rect { top: 0, left: 0, bottom: 1, right: 1 });
+        assert_eq!(matte.to_region(true, false).bounds(), Rect { top: 0, left: 2, bottom: 1, right: 3 });
+    }
+
+    #[test]
+    fn ink_rules() {
+        let members = Members::new();
+        let mut frame = Frame::default();
+        frame.sprites[0] = sprite(1, 0, 0, 100);
+        frame.sprites[1] = sprite(2, Pen::PatCopy as u8, 50, 0);
+        let hidden = SpriteBitmask::empty();
+
+        // Matte: the hole inside the ring is opaque, the surrounding white is not
+        assert_eq!(sprite_at(&frame, &hidden, &members, Point { x: 50, y: 20 }), Some(ChannelNum(2)));
+        assert_eq!(sprite_at(&frame, &hidden, &members, Point { x: 45, y: 20 }), Some(ChannelNum(2)));
+        assert_eq!(sprite_at(&frame, &hidden, &members, Point { x: 41, y: 20 }), Some(ChannelNum(1)));
+
+        // Background transparent: every white pixel is transparent
+        frame.sprites[1] = sprite(2, Pen::Transparent as u8, 50, 0);
+        assert_eq!(sprite_at(&frame, &hidden, &members, Point { x: 50, y: 20 }), Some(ChannelNum(1)));
+        assert_eq!(sprite_at(&frame, &hidden, &members, Point { x: 45, y: 20 }), Some(ChannelNum(2)));
+
+        // Copy: the whole rectangle is opaque
+        frame.sprites[1] = sprite(2, 0, 50, 0);
+        assert_eq!(sprite_at(&frame, &hidden, &members, Point { x: 41, y: 20 }), Some(ChannelNum(2)));
+        assert_eq!(sprite_at(&frame, &hidden, &members, Point { x: 150, y: 20 }), None);
+    }
+}
@@ -1,17 +1,33 @@
 // TODO: You know, finish this file and then remove these overrides
 #![allow(dead_code)]
 
+pub mod action_list;
+pub mod drag;
+pub mod event;
+pub mod field;
+pub mod film_loop;
+pub mod fonts;
+pub mod frame_labels;
+pub mod hit_test;
 pub mod movie;
+pub mod palette;
+pub mod rich_text;
 pub mod score;
+pub mod shape;
+pub mod sprite_property;
+pub mod stage;
+pub mod text_editor;
+pub mod transition;
+pub mod video;
 
 use anyhow::Result as AResult;
 use crate::detection::{detect, FileType};
-use libmactoolbox::{Point, Rect};
+use libmactoolbox::{Point, quickdraw::Region};
 use std::{path::{Path, PathBuf}, time::Instant};
 use libcommon::vfs::VirtualFileSystem;
 
 pub struct Player {
-    gray_rgn: Rect,
+    gray_rgn: Region,
     last_mouse_down: Instant,
     last_key_down: Instant,
     last_mouse_move: Instant,
@@ -27,7 +43,7 @@ impl Player {
         let file_type = detect(fs, &path)?.info;
 
         Ok(Self {
-            gray_rgn: Rect::default(),
+            gray_rgn: Region::default(),
             file_type,
             path: path.as_ref().to_path_buf(),
             last_mouse_down: now,
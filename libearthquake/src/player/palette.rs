@@ -0,0 +1,529 @@
+//! Executing the palette channel of the score.
+//!
+//! A palette cell either switches the active palette, optionally fading the
+//! screen to black or white and back while it does so, or cycles a range of
+//! colours in the active palette. An effect happens between frames unless the
+//! cell spans frames, in which case it advances by one step on each frame.
+
+use anyhow::{anyhow, Context, Result as AResult};
+use crate::resources::cast::MemberId;
+use libmactoolbox::quickdraw::{ColorTable, PixMap, RGBColor};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use super::score::{Palette, PaletteFlags};
+
+/// The fastest rate of a palette transition, in steps per second.
+const MAX_RATE: i16 = 30;
+
+/// The palettes which can be chosen from the palette channel.
+pub trait PaletteMembers {
+    /// Returns the colours of the palette cast member with the given ID.
+    fn palette(&self, id: MemberId) -> Option<&ColorTable>;
+}
+
+/// The palettes which are built into Director.
+#[derive(Clone, Copy, Debug, Eq, FromPrimitive, PartialEq)]
+pub enum SystemPalette {
+    SystemMac = -1,
+    Rainbow = -2,
+    Grayscale = -3,
+    Pastels = -4,
+    Vivid = -5,
+    Ntsc = -6,
+    Metallic = -7,
+    Web216 = -8,
+    SystemWin = -101,
+    SystemWinDir4 = -102,
+}
+
+impl SystemPalette {
+    /// Returns the colours of the palette, or `None` if the palette is not
+    /// supported yet.
+    #[must_use]
+    pub fn color_table(self) -> Option<ColorTable> {
+        match self {
+            Self::SystemMac => Some(system_mac()),
+            Self::Grayscale => Some(ColorTable::new((0..=255_u16).map(|index| {
+                let level = 0xffff - index * 0x101;
+                (index, RGBColor { r: level, g: level, b: level })
+            }).collect())),
+            _ => None,
+        }
+    }
+}
+
+/// The standard 8-bit colour table of the Macintosh, which is a 6×6×6 colour
+/// cube followed by ramps of red, green, blue, and grey.
+fn system_mac() -> ColorTable {
+    const RAMP: [ u16; 10 ] = [ 0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11 ];
+    let mut entries = Vec::with_capacity(256);
+    for index in 0..215 {
+        entries.push((index, RGBColor {
+            r: (5 - index / 36) * 0x3333,
+            g: (5 - index / 6 % 6) * 0x3333,
+            b: (5 - index % 6) * 0x3333,
+        }));
+    }
+    let ramps: [ fn(u16) -> RGBColor; 4 ] = [
+        |level| RGBColor { r: level, g: 0, b: 0 },
+        |level| RGBColor { r: 0, g: level, b: 0 },
+        |level| RGBColor { r: 0, g: 0, b: level },
+        |level| RGBColor { r: level, g: level, b: level },
+    ];
+    for (ramp, make_color) in ramps.iter().enumerate() {
+        for (index, &level) in RAMP.iter().enumerate() {
+            entries.push(((215 + ramp * RAMP.len() + index) as u16, make_color(level * 0x101)));
+        }
+    }
+    entries.push((255, RGBColor::BLACK));
+    ColorTable::new(entries)
+}
+
+/// A running palette transition or colour cycle.
+#[derive(Clone, Debug)]
+enum Effect {
+    Fade {
+        from: ColorTable,
+        to: ColorTable,
+        /// The colour to fade through, if the transition fades to black or
+        /// white instead of directly to the new palette.
+        through: Option<RGBColor>,
+        step: u16,
+        /// The total number of steps. A fade through a colour spends the
+        /// first half of them fading to that colour.
+        steps: u16,
+    },
+    Cycle {
+        start: u8,
+        end: u8,
+        auto_reverse: bool,
+        step: u32,
+        steps: u32,
+    },
+}
+
+/// The state of the palette channel.
+#[derive(Clone, Debug)]
+pub struct PaletteChannel {
+    /// The palette which was last chosen by the score.
+    id: MemberId,
+    /// The colours of the palette which was last chosen by the score.
+    table: ColorTable,
+    /// The colours which are currently on the screen.
+    colors: ColorTable,
+    /// The palette cell which started the current effect.
+    cell: Option<Palette>,
+    effect: Option<Effect>,
+    spans_frames: bool,
+}
+
+impl PaletteChannel {
+    /// Creates a palette channel with the given active palette.
+    #[must_use]
+    pub fn new(id: MemberId, table: ColorTable) -> Self {
+        Self {
+            id,
+            colors: table.clone(),
+            table,
+            cell: None,
+            effect: None,
+            spans_frames: false,
+        }
+    }
+
+    /// Starts executing the palette cell of a new frame.
+    pub fn begin_frame(&mut self, palette: &Palette, members: &impl PaletteMembers) -> AResult<()> {
+        let is_empty = palette.id().num().0 == 0 && !palette.flags().contains(PaletteFlags::ACTION_CYCLE);
+        if is_empty {
+            self.cell = None;
+            return Ok(());
+        }
+
+        // A cell which spans frames is repeated in each frame of the span,
+        // and only the first one starts the effect
+        if self.cell.map_or(false, |cell| is_same_cell(&cell, palette)) {
+            return Ok(());
+        }
+
+        self.cell = Some(*palette);
+        self.spans_frames = palette.flags().contains(PaletteFlags::SPAN_FRAMES);
+
+        if palette.flags().contains(PaletteFlags::ACTION_CYCLE) {
+            let (start, end) = (palette.cycle_start_color(), palette.cycle_end_color());
+            let len = u32::from(end.saturating_sub(start)) + 1;
+            let auto_reverse = palette.flags().contains(PaletteFlags::CYCLE_AUTO_REVERSE);
+            let steps = len * u32::from(palette.num_cycles().max(1) as u16) * if auto_reverse { 2 } else { 1 };
+            self.effect = if start < end {
+                Some(Effect::Cycle { start, end, auto_reverse, step: 0, steps })
+            } else {
+                None
+            };
+            return Ok(());
+        }
+
+        if palette.id() == self.id {
+            return Ok(());
+        }
+
+        let table = resolve(palette.id(), members)?;
+        let through = if palette.flags().contains(PaletteFlags::FADE_REVERSE) {
+            Some(RGBColor::BLACK)
+        } else if palette.flags().contains(PaletteFlags::FADE) {
+            Some(RGBColor::WHITE)
+        } else {
+            None
+        };
+
+        self.id = palette.id();
+        if through.is_some() || self.spans_frames {
+            // Each half of a fade between frames lasts one second at the
+            // rate of the cell
+            let steps = if self.spans_frames {
+                palette.num_frames().max(1) as u16
+            } else {
+                palette.rate().0.clamp(1, MAX_RATE) as u16 * 2
+            };
+            self.effect = Some(Effect::Fade {
+                from: self.colors.clone(),
+                to: table.clone(),
+                through,
+                step: 0,
+                steps,
+            });
+        } else {
+            self.colors = table.clone();
+            self.effect = None;
+        }
+        self.table = table;
+
+        Ok(())
+    }
+
+    /// The colours which are currently on the screen.
+    #[must_use]
+    pub fn colors(&self) -> &ColorTable {
+        &self.colors
+    }
+
+    /// The palette which was last chosen by the score.
+    #[must_use]
+    pub fn id(&self) -> MemberId {
+        self.id
+    }
+
+    /// The colours of the palette which was last chosen by the score.
+    #[must_use]
+    pub fn table(&self) -> &ColorTable {
+        &self.table
+    }
+
+    /// Returns whether a palette transition or colour cycle is running.
+    #[must_use]
+    pub fn is_busy(&self) -> bool {
+        self.effect.is_some()
+    }
+
+    /// Returns whether the running effect advances once per frame instead
+    /// of running to completion before the next frame.
+    #[must_use]
+    pub fn spans_frames(&self) -> bool {
+        self.spans_frames
+    }
+
+    /// Advances the running effect by one step. Returns `true` if the
+    /// colours on the screen changed.
+    pub fn step(&mut self) -> bool {
+        let (colors, done) = match &mut self.effect {
+            None => return false,
+            Some(Effect::Fade { from, to, through, step, steps }) => {
+                *step += 1;
+                let colors = match through {
+                    Some(through) => {
+                        let half = (*steps + 1) / 2;
+                        if *step <= half {
+                            fade(from, |_| Some(*through), *step, half)
+                        } else {
+                            let through = from.entries().iter().map(|&(value, _)| (value, *through)).collect();
+                            fade(&ColorTable::new(through), |value| to.get(value), *step - half, *steps - half)
+                        }
+                    },
+                    None => fade(from, |value| to.get(value), *step, *steps),
+                };
+                (colors, *step >= *steps)
+            },
+            Some(Effect::Cycle { start, end, auto_reverse, step, steps }) => {
+                let len = u32::from(*end - *start) + 1;
+                let forward = !*auto_reverse || (*step / len) % 2 == 0;
+                *step += 1;
+                (cycle(&self.colors, *start, *end, forward), *step >= *steps)
+            },
+        };
+
+        if done {
+            if let Some(Effect::Fade { .. }) = self.effect {
+                self.colors = self.table.clone();
+            } else {
+                self.colors = colors;
+            }
+            self.effect = None;
+        } else {
+            self.colors = colors;
+        }
+        true
+    }
+}
+
+impl Default for PaletteChannel {
+    fn default() -> Self {
+        Self::new(MemberId::new(0, SystemPalette::SystemMac as i16), system_mac())
+    }
+}
+
+/// Returns a copy of the image which uses the colours of a new palette. With
+/// palette mapping, each pixel becomes the closest colour in the new
+/// palette; otherwise, each pixel keeps its index from the old palette, so
+/// its colour changes to whatever the new palette has at that index.
+#[must_use]
+pub fn remap(image: &PixMap, from: &ColorTable, to: &ColorTable, palette_mapping: bool) -> PixMap {
+    let bounds = image.bounds();
+    let mut remapped = image.clone();
+    for y in bounds.top..bounds.bottom {
+        for x in bounds.left..bounds.right {
+            let new_color = image.pixel(x, y).and_then(|color| if palette_mapping {
+                to.color_to_index(color)
+            } else {
+                from.color_to_index(color)
+            }).and_then(|index| to.get(index));
+            if let Some(new_color) = new_color {
+                remapped.set_pixel(x, y, new_color);
+            }
+        }
+    }
+    remapped
+}
+
+/// Returns the colours of the palette with the given ID.
+pub fn resolve(id: MemberId, members: &impl PaletteMembers) -> AResult<ColorTable> {
+    if id.num().0 < 0 {
+        let palette = SystemPalette::from_i16(id.num().0)
+            .with_context(|| format!("Unknown built-in palette {}", id.num().0))?;
+        palette.color_table()
+            .ok_or_else(|| anyhow!("Built-in palette {:?} is not supported", palette))
+    } else {
+        members.palette(id)
+            .cloned()
+            .with_context(|| format!("Can’t find palette {}", id))
+    }
+}
+
+/// Rotates the colours in the range `start..=end` by one entry.
+fn cycle(colors: &ColorTable, start: u8, end: u8, forward: bool) -> ColorTable {
+    let mut entries = colors.entries().to_vec();
+    let range = usize::from(start)..=usize::from(end).min(entries.len().saturating_sub(1));
+    if let Some(slice) = entries.get_mut(range) {
+        let mut values = slice.iter().map(|&(_, color)| color).collect::<Vec<_>>();
+        if forward {
+            values.rotate_right(1);
+        } else {
+            values.rotate_left(1);
+        }
+        for (entry, color) in slice.iter_mut().zip(values) {
+            entry.1 = color;
+        }
+    }
+    ColorTable::new(entries)
+}
+
+/// Blends each colour of `from` toward its target colour by `step / steps`.
+fn fade(from: &ColorTable, target: impl Fn(u16) -> Option<RGBColor>, step: u16, steps: u16) -> ColorTable {
+    let mix = |a: u16, b: u16| {
+        let (a, b) = (i64::from(a), i64::from(b));
+        (a + (b - a) * i64::from(step) / i64::from(steps.max(1))) as u16
+    };
+
+    ColorTable::new(from.entries().iter().map(|&(value, color)| {
+        let target = target(value).unwrap_or(color);
+        (value, RGBColor {
+            r: mix(color.r, target.r),
+            g: mix(color.g, target.g),
+            b: mix(color.b, target.b),
+        })
+    }).collect())
+}
+
+fn is_same_cell(a: &Palette, b: &Palette) -> bool {
+    a.id() == b.id()
+        && a.flags() == b.flags()
+        && a.rate() == b.rate()
+        && a.cycle_start_color() == b.cycle_start_color()
+        && a.cycle_end_color() == b.cycle_end_color()
+        && a.num_frames() == b.num_frames()
+        && a.num_cycles() == b.num_cycles()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use binread::BinRead;
+    use std::io::Cursor;
+    use super::*;
+
+    const RED: RGBColor = RGBColor { r: 0xffff, g: 0, b: 0 };
+    const GREEN: RGBColor = RGBColor { r: 0, g: 0xffff, b: 0 };
+    const BLUE: RGBColor = RGBColor { r: 0, g: 0, b: 0xffff };
+
+    struct Members(ColorTable);
+
+    impl PaletteMembers for Members {
+        fn palette(&self, id: MemberId) -> Option<&ColorTable> {
+            if id == MemberId::new(1, 10) {
+                Some(&self.0)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn members() -> Members {
+        Members(ColorTable::new(vec![ (0, RED), (1, GREEN), (2, BLUE) ]))
+    }
+
+    fn palette(num: i16, flags: PaletteFlags, start: u8, end: u8, num_frames: i16, num_cycles: i16) -> Palette {
+        palette_at_rate(num, flags, 30, start, end, num_frames, num_cycles)
+    }
+
+    fn palette_at_rate(num: i16, flags: PaletteFlags, rate: u8, start: u8, end: u8, num_frames: i16, num_cycles: i16) -> Palette {
+        let num = num.to_be_bytes();
+        let num_frames = num_frames.to_be_bytes();
+        let num_cycles = num_cycles.to_be_bytes();
+        let data = [
+            0, 1, num[0], num[1], rate, flags.bits(), start ^ 0x80, end ^ 0x80,
+            num_frames[0], num_frames[1], num_cycles[0], num_cycles[1], 0, 0, 0, 0,
+        ];
+        Palette::read(&mut Cursor::new(&data[..])).unwrap()
+    }
+
+    fn white() -> ColorTable {
+        ColorTable::new(vec![ (0, RGBColor::WHITE), (1, RGBColor::WHITE), (2, RGBColor::WHITE) ])
+    }
+
+    #[test]
+    fn system_palettes() {
+        let mac = SystemPalette::SystemMac.color_table().unwrap();
+        assert_eq!(mac.len(), 256);
+        assert_eq!(mac.get(0), Some(RGBColor::WHITE));
+        assert_eq!(mac.get(35), Some(RGBColor { r: 0xffff, g: 0, b: 0 }));
+        assert_eq!(mac.get(215), Some(RGBColor { r: 0xeeee, g: 0, b: 0 }));
+        assert_eq!(mac.get(254), Some(RGBColor { r: 0x1111, g: 0x1111, b: 0x1111 }));
+        assert_eq!(mac.get(255), Some(RGBColor::BLACK));
+        assert!(resolve(MemberId::new(0, -3), &members()).is_ok());
+        assert!(resolve(MemberId::new(0, -50), &members()).is_err());
+        assert!(resolve(MemberId::new(1, 11), &members()).is_err());
+    }
+
+    #[test]
+    fn switch() {
+        let mut channel = PaletteChannel::new(MemberId::new(0, -1), white());
+        let cell = palette(10, PaletteFlags::empty(), 0, 0, 0, 0);
+        channel.begin_frame(&cell, &members()).unwrap();
+        assert!(!channel.is_busy());
+        assert_eq!(channel.id(), MemberId::new(1, 10));
+        assert_eq!(channel.colors(), &members().0);
+    }
+
+    #[test]
+    fn fade_to_black() {
+        let mut channel = PaletteChannel::new(MemberId::new(0, -1), white());
+        let cell = palette(10, PaletteFlags::FADE_REVERSE | PaletteFlags::SPAN_FRAMES, 0, 0, 4, 0);
+        channel.begin_frame(&cell, &members()).unwrap();
+        assert!(channel.is_busy());
+        assert!(channel.spans_frames());
+
+        assert!(channel.step());
+        assert_eq!(channel.colors().get(0), Some(RGBColor { r: 0x8000, g: 0x8000, b: 0x8000 }));
+        assert!(channel.step());
+        assert_eq!(channel.colors().get(2), Some(RGBColor::BLACK));
+
+        // The same cell in the next frame does not restart the fade
+        channel.begin_frame(&cell, &members()).unwrap();
+        assert!(channel.step());
+        assert_eq!(channel.colors().get(0), Some(RGBColor { r: 0x7fff, g: 0, b: 0 }));
+        assert!(channel.step());
+        assert!(!channel.is_busy());
+        assert_eq!(channel.colors(), &members().0);
+        assert!(!channel.step());
+
+        // The frames of the span are split between the two halves
+        let mut channel = PaletteChannel::new(MemberId::new(0, -1), white());
+        let cell = palette(10, PaletteFlags::FADE | PaletteFlags::SPAN_FRAMES, 0, 0, 2, 0);
+        channel.begin_frame(&cell, &members()).unwrap();
+        assert!(channel.step());
+        assert_eq!(channel.colors(), &white());
+        assert!(channel.step());
+        assert!(!channel.is_busy());
+        assert_eq!(channel.colors(), &members().0);
+    }
+
+    #[test]
+    fn fade_between_frames() {
+        let mut channel = PaletteChannel::new(MemberId::new(0, -1), white());
+        let cell = palette_at_rate(10, PaletteFlags::FADE_REVERSE, 2, 0, 0, 0, 0);
+        channel.begin_frame(&cell, &members()).unwrap();
+        assert!(!channel.spans_frames());
+        let mut steps = Vec::new();
+        while channel.step() {
+            steps.push(channel.colors().get(0).unwrap());
+        }
+        assert_eq!(steps, vec![
+            RGBColor { r: 0x8000, g: 0x8000, b: 0x8000 },
+            RGBColor::BLACK,
+            RGBColor { r: 0x7fff, g: 0, b: 0 },
+            RED,
+        ]);
+
+        // Rates beyond the fastest rate fade at the fastest rate
+        let mut channel = PaletteChannel::new(MemberId::new(0, -1), white());
+        let cell = palette_at_rate(10, PaletteFlags::FADE, 100, 0, 0, 0, 0);
+        channel.begin_frame(&cell, &members()).unwrap();
+        let mut count = 0;
+        while channel.step() {
+            count += 1;
+        }
+        assert_eq!(count, MAX_RATE * 2);
+    }
+
+    #[test]
+    fn color_cycling() {
+        let mut channel = PaletteChannel::new(MemberId::new(1, 10), members().0);
+        let cell = palette(0, PaletteFlags::ACTION_CYCLE | PaletteFlags::CYCLE_AUTO_REVERSE, 1, 2, 0, 1);
+        channel.begin_frame(&cell, &members()).unwrap();
+
+        let colors = |channel: &PaletteChannel| channel.colors().entries().iter().map(|&(_, color)| color).collect::<Vec<_>>();
+        assert!(channel.step());
+        assert_eq!(colors(&channel), vec![ RED, BLUE, GREEN ]);
+        assert!(channel.step());
+        assert_eq!(colors(&channel), vec![ RED, GREEN, BLUE ]);
+        assert!(channel.step());
+        assert_eq!(colors(&channel), vec![ RED, BLUE, GREEN ]);
+        assert!(channel.step());
+        assert_eq!(colors(&channel), vec![ RED, GREEN, BLUE ]);
+        assert!(!channel.is_busy());
+    }
+
+    #[test]
+    fn remap_bitmap() {
+        let bounds = libmactoolbox::Rect { top: 0, left: 0, bottom: 1, right: 2 };
+        let mut image = PixMap::new(bounds, RED);
+        image.set_pixel(1, 0, RGBColor { r: 0, g: 0xeeee, b: 0 });
+        let from = ColorTable::new(vec![ (0, RED), (1, RGBColor { r: 0, g: 0xeeee, b: 0 }) ]);
+        let to = ColorTable::new(vec![ (0, GREEN), (1, RED) ]);
+
+        let mapped = remap(&image, &from, &to, true);
+        assert_eq!(mapped.pixel(0, 0), Some(RED));
+        assert_eq!(mapped.pixel(1, 0), Some(GREEN));
+
+        let unmapped = remap(&image, &from, &to, false);
+        assert_eq!(unmapped.pixel(0, 0), Some(GREEN));
+        assert_eq!(unmapped.pixel(1, 0), Some(RED));
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::Result as AResult;
+use crate::resources::{
+    field::Alignment,
+    text::{Meta, RichText, RunStyle, TabKind},
+};
+use libmactoolbox::{
+    Point,
+    quickdraw::{CGrafPort, Font, FontInfo, RGBColor},
+    ResourceManager,
+};
+use std::ops::Range;
+use super::{field::{break_line, Line, Segment, TextLayout, TextMetrics}, fonts::MovieFonts};
+
+/// The fonts used to draw a run of rich text.
+#[derive(Clone, Debug)]
+pub struct RunFonts {
+    pub font: Font,
+    /// A font which is twice the size of `font`, used to draw anti-aliased
+    /// text.
+    pub smooth_font: Option<Font>,
+}
+
+impl RunFonts {
+    /// Finds the fonts for a run of rich text in the open resource files,
+    /// using the font tables of the movie to replace fonts from other
+    /// platforms. The run is anti-aliased if the text member asks for it and
+    /// a font which is twice the size of the run exists.
+    pub fn find(resources: &ResourceManager<'_>, run: &RunStyle, meta: &Meta, movie_fonts: &MovieFonts) -> AResult<Option<Self>> {
+        let (family, size) = movie_fonts.mac_family(resources, &run.font_name, run.size)?;
+        let font = if let Some(font) = Font::find(resources, family, size, run.style)? {
+            font
+        } else {
+            return Ok(None);
+        };
+
+        // `Font::find` falls back to the nearest size, so the larger font is
+        // only usable if it really is twice as large
+        let smooth_font = if meta.should_anti_alias(run.size) {
+            Font::find(resources, family, size.saturating_mul(2), run.style)?.filter(|smooth_font| {
+                let (info, smooth_info) = (font.info(), smooth_font.info());
+                smooth_info.ascent + smooth_info.descent >= (info.ascent + info.descent) * 2 - 1
+            })
+        } else {
+            None
+        };
+
+        Ok(Some(Self { font, smooth_font }))
+    }
+}
+
+impl TextMetrics for RunFonts {
+    fn char_width(&self, c: u8) -> i16 {
+        self.font.char_width(c)
+    }
+
+    fn info(&self) -> FontInfo {
+        self.font.info()
+    }
+}
+
+/// Converts rich text from the character set of the movie to the Mac
+/// character set used by the fonts from [`RunFonts::find`].
+#[must_use]
+pub fn map_text(text: &RichText, movie_fonts: &MovieFonts) -> RichText {
+    let mut bytes = text.text().to_vec();
+    let len = bytes.len();
+    for (index, run) in text.runs().iter().enumerate() {
+        let start = (run.start as usize).min(len);
+        let end = text.runs().get(index + 1).map_or(len, |next| (next.start as usize).min(len));
+        if start < end {
+            movie_fonts.map_text(&run.font_name, &mut bytes[start..end]);
+        }
+    }
+    RichText::new(bytes, text.paragraphs().to_vec(), text.runs().to_vec())
+}
+
+/// Lays out rich text in a column of the given width. `fonts` holds the font
+/// of each run of the text. Lines are broken at carriage returns and, if
+/// `word_wrap` is set, after the last space which fits between the indents
+/// of the paragraph.
+///
+/// # Panics
+///
+/// Panics if the number of fonts is not the same as the number of runs.
+#[must_use]
+pub fn layout(text: &RichText, fonts: &[impl TextMetrics], width: i16, word_wrap: bool) -> TextLayout {
+    assert_eq!(fonts.len(), text.runs().len());
+
+    let bytes = text.text();
+    let mut lines = Vec::new();
+    let mut top = 0;
+    let mut start = 0;
+    loop {
+        let paragraph_end = bytes[start..].iter()
+            .position(|&c| c == b'\r')
+            .map_or(bytes.len(), |end| start + end);
+        let paragraph = &text.paragraphs()[text.paragraph_index_at(start)];
+        top += paragraph.space_before;
+
+        let mut line_start = start;
+        loop {
+            let indent = paragraph.left_indent + if line_start == start { paragraph.first_indent } else { 0 };
+            let column_width = width - indent - paragraph.right_indent;
+            let line_end = if word_wrap {
+                break_line(bytes, line_start..paragraph_end, column_width, |index, x| {
+                    if bytes[index] == b'\t' {
+                        paragraph.next_tab_stop(indent + x).position - indent - x
+                    } else {
+                        fonts[text.run_index_at(index)].char_width(bytes[index])
+                    }
+                })
+            } else {
+                paragraph_end
+            };
+
+            let line = make_line(text, fonts, line_start..line_end, top, indent, column_width);
+            top += line.height;
+            lines.push(line);
+
+            line_start = line_end;
+            if line_start >= paragraph_end {
+                break;
+            }
+        }
+
+        top += paragraph.space_after;
+
+        if paragraph_end == bytes.len() {
+            break;
+        }
+        start = paragraph_end + 1;
+    }
+
+    TextLayout::from_lines(lines, top)
+}
+
+/// Draws laid out rich text with the top-left corner of the text at the
+/// given point.
+pub fn draw(layout: &TextLayout, port: &mut CGrafPort, origin: Point, text: &RichText, fonts: &[RunFonts]) {
+    for line in layout.lines() {
+        let baseline = origin.y + line.top + line.ascent;
+        for segment in &line.segments {
+            let chars = &text.text()[segment.range.clone()];
+            let fonts = &fonts[segment.style_index];
+            port.rgb_fore_color(text.runs()[segment.style_index].color);
+            port.move_to(origin.x + line.left + segment.left, baseline);
+            if let Some(smooth_font) = &fonts.smooth_font {
+                port.draw_text_smooth(smooth_font, chars, 2);
+            } else {
+                port.draw_text(&fonts.font, chars);
+            }
+        }
+    }
+    port.rgb_fore_color(RGBColor::BLACK);
+}
+
+fn make_line(text: &RichText, fonts: &[impl TextMetrics], range: Range<usize>, top: i16, indent: i16, width: i16) -> Line {
+    let bytes = text.text();
+    let paragraph = &text.paragraphs()[text.paragraph_index_at(range.start)];
+
+    // Empty lines take their height from the run where they start
+    let (mut ascent, mut descent) = if range.is_empty() {
+        let info = fonts[text.run_index_at(range.start)].info();
+        (info.ascent, info.descent + info.leading)
+    } else {
+        (0, 0)
+    };
+    let mut measure = |index: usize| {
+        let font = &fonts[text.run_index_at(index)];
+        let info = font.info();
+        ascent = ascent.max(info.ascent);
+        descent = descent.max(info.descent + info.leading);
+        font.char_width(bytes[index])
+    };
+
+    // Each tab starts a new field of text which is aligned to the next tab
+    // stop
+    let mut segments = Vec::new();
+    let mut x = 0;
+    let mut line_width = 0;
+    let mut field_start = range.start;
+    while field_start < range.end {
+        let field_end = bytes[field_start..range.end].iter()
+            .position(|&c| c == b'\t')
+            .map_or(range.end, |end| field_start + end);
+
+        if field_start != range.start {
+            let stop = paragraph.next_tab_stop(indent + x);
+            let field_width = |end: usize| (field_start..end).map(|index| fonts[text.run_index_at(index)].char_width(bytes[index])).sum::<i16>();
+            let offset = match stop.kind {
+                TabKind::Left => 0,
+                TabKind::Center => field_width(field_end) / 2,
+                TabKind::Right => field_width(field_end),
+                TabKind::Decimal => {
+                    let point = bytes[field_start..field_end].iter()
+                        .position(|&c| c == b'.')
+                        .map_or(field_end, |point| field_start + point);
+                    field_width(point)
+                },
+            };
+            x = x.max(stop.position - indent - offset);
+        }
+
+        let mut index = field_start;
+        while index < field_end {
+            let run_index = text.run_index_at(index);
+            let end = text.runs().get(run_index + 1)
+                .map_or(field_end, |next| (next.start as usize).min(field_end));
+            segments.push(Segment { range: index..end, style_index: run_index, left: x });
+            for char_index in index..end {
+                x += measure(char_index);
+                if bytes[char_index] != b' ' {
+                    line_width = x;
+                }
+            }
+            index = end;
+        }
+
+        field_start = field_end + 1;
+    }
+
+    let left = indent + match paragraph.alignment {
+        Alignment::Left => 0,
+        Alignment::Center => (width - line_width) / 2,
+        Alignment::Right => width - line_width,
+    };
+
+    Line {
+        range,
+        top,
+        ascent,
+        height: ascent + descent,
+        left,
+        width: line_width,
+        segments,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use crate::resources::{config::Platform, font_map::FontMap, text::{ParagraphStyle, TabStop}};
+    use libmactoolbox::quickdraw::Style;
+    use super::*;
+
+    struct FixedFont(i16);
+
+    impl TextMetrics for FixedFont {
+        fn char_width(&self, _: u8) -> i16 {
+            self.0
+        }
+
+        fn info(&self) -> FontInfo {
+            FontInfo {
+                ascent: self.0 * 4,
+                descent: self.0,
+                wid_max: self.0,
+                leading: 0,
+            }
+        }
+    }
+
+    fn run(start: u32) -> RunStyle {
+        RunStyle {
+            start,
+            font_name: "Geneva".to_string(),
+            size: 12,
+            style: Style::empty(),
+            color: RGBColor::BLACK,
+        }
+    }
+
+    #[test]
+    fn paragraphs() {
+        let text = RichText::new(b"abcdef\rxyz".to_vec(), vec![
+            ParagraphStyle { first_indent: 2, right_indent: 1, space_after: 3, ..ParagraphStyle::default() },
+            ParagraphStyle { start: 7, alignment: Alignment::Right, space_before: 1, ..ParagraphStyle::default() },
+        ], vec![ run(0), run(8) ]);
+        let layout = layout(&text, &[ FixedFont(1), FixedFont(2) ], 7, true);
+        let lines = layout.lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[0].range.clone(), lines[0].left, lines[0].top), (0..4, 2, 0));
+        assert_eq!((lines[1].range.clone(), lines[1].left, lines[1].top), (4..6, 0, 5));
+        assert_eq!((lines[2].range.clone(), lines[2].top, lines[2].height), (7..10, 14, 10));
+        assert_eq!(lines[2].width, 5);
+        assert_eq!(lines[2].left, 2);
+        assert_eq!(layout.height(), 24);
+    }
+
+    #[test]
+    fn map_win_text() {
+        let text = RichText::new(b"\xe9\xe9".to_vec(), vec![ ParagraphStyle::default() ], vec![
+            RunStyle { font_name: "Arial".to_string(), ..run(0) },
+            RunStyle { font_name: "Symbol".to_string(), ..run(1) },
+        ]);
+        let movie_fonts = MovieFonts::new(FontMap::default(), None, Platform::Win);
+        assert_eq!(map_text(&text, &movie_fonts).text(), b"\x8e\xe9");
+    }
+
+    #[test]
+    fn tab_stops() {
+        let stop = |position, kind| TabStop { position, kind };
+        let text = RichText::new(b"a\tb\tcc\td.dd\te".to_vec(), vec![
+            ParagraphStyle {
+                tab_stops: vec![
+                    stop(10, TabKind::Left),
+                    stop(20, TabKind::Right),
+                    stop(30, TabKind::Decimal),
+                ],
+                ..ParagraphStyle::default()
+            },
+        ], vec![ run(0) ]);
+        let layout = layout(&text, &[ FixedFont(1) ], 100, true);
+        let lefts = layout.lines()[0].segments.iter()
+            .map(|segment| (segment.range.clone(), segment.left))
+            .collect::<Vec<_>>();
+        assert_eq!(lefts, vec![ (0..1, 0), (2..3, 10), (4..6, 18), (7..11, 29), (12..13, 36) ]);
+    }
+}
@@ -2,18 +2,19 @@
 #![allow(clippy::struct_excessive_bools)]
 #![allow(dead_code)]
 
-use anyhow::{bail, Context, Result as AResult};
+use anyhow::{bail, ensure, Context, Result as AResult};
 use binread::{BinRead, ReadOptions};
 use bitflags::bitflags;
 use byteordered::{ByteOrdered, Endianness};
-use crate::{ensure_sample, resources::{transition::{Kind as TransitionKind, QuarterSeconds}, cast::{MemberId, MemberKind}}};
+use crate::{ensure_sample, resources::{config::{Config, Flags as ConfigFlags}, transition::{Kind as TransitionKind, QuarterSeconds}, cast::{MemberId, MemberKind}}};
 use derive_more::{Add, AddAssign, Display};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use libcommon::{Reader, Resource, Unk16, Unk32, Unk8, UnkPtr, binread_enum, binread_flags, resource::Input};
-use libmactoolbox::{quickdraw::Pen, Point, Rect, TEHandle};
+use libcommon::{encodings::DecoderRef, Reader, Resource, Unk16, Unk32, Unk8, UnkPtr, binread_enum, binread_flags, resource::Input};
+use libmactoolbox::{quickdraw::{ColorTable, Pen, PixMap}, Point, Rect, ResourceId, ResourceSource};
 use smart_default::SmartDefault;
 use std::{convert::{TryFrom, TryInto}, io::{Cursor, Read}, io::SeekFrom, iter::Rev, io::Seek};
+use super::{action_list::ActionList, frame_labels::FrameLabels, palette::{remap, PaletteChannel, PaletteMembers}, sprite_property::{SpriteProperty, Value}, text_editor::TextEditor, transition::Params as TransitionParams};
 
 bitflags! {
     #[derive(Default)]
@@ -34,6 +35,10 @@ bitflags! {
 
 binread_flags!(Flags, u16);
 
+/// The number of a script in the action list of a Director 3 score.
+#[derive(BinRead, Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct ActionNum(pub u8);
+
 #[derive(Add, AddAssign, Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
 pub struct FrameNum(pub i16);
 
@@ -165,6 +170,12 @@ impl SpriteBitmask {
         self.0[bit / 8] & (1 << (bit % 8)) != 0
     }
 
+    /// Returns whether the bit for the given sprite channel is set.
+    #[must_use]
+    pub fn contains_sprite(&self, channel: ChannelNum) -> bool {
+        self.contains(Self::sprite_bit(channel))
+    }
+
     #[must_use]
     pub fn empty() -> Self {
         SpriteBitmask::default()
@@ -195,11 +206,35 @@ impl SpriteBitmask {
         self
     }
 
+    /// Clears the bit for the given sprite channel.
+    pub fn remove_sprite(&mut self, channel: ChannelNum) -> &mut Self {
+        self.remove(Self::sprite_bit(channel))
+    }
+
     pub fn set(&mut self, bit: usize) -> &mut Self {
         assert!(bit < Self::NUM_CHANNELS);
         self.0[bit / 8] |= 1 << (bit % 8);
         self
     }
+
+    /// Sets the bit for the given sprite channel.
+    pub fn set_sprite(&mut self, channel: ChannelNum) -> &mut Self {
+        self.set(Self::sprite_bit(channel))
+    }
+
+    /// Sets or clears the bit for the given sprite channel.
+    pub fn toggle_sprite(&mut self, channel: ChannelNum, value: bool) -> &mut Self {
+        if value {
+            self.set_sprite(channel)
+        } else {
+            self.remove_sprite(channel)
+        }
+    }
+
+    fn sprite_bit(channel: ChannelNum) -> usize {
+        assert!(channel.0 > 0);
+        Self::MIN_SPRITE + usize::try_from(channel.0 - 1).unwrap()
+    }
 }
 
 impl std::ops::BitAnd for SpriteBitmask {
@@ -272,6 +307,7 @@ impl std::ops::SubAssign for SpriteBitmask {
 #[cfg(test)]
 mod tests {
     #![allow(clippy::wildcard_imports)]
+    use crate::resources::config::Version as ConfigVersion;
     use super::*;
 
     #[test]
@@ -308,6 +344,14 @@ mod tests {
         assert!(bitmask.contains(8));
     }
 
+    #[test]
+    fn sprite_bitmask_contains_sprite() {
+        let mut bitmask = SpriteBitmask::empty();
+        bitmask.set(SpriteBitmask::MIN_SPRITE + 2);
+        assert!(bitmask.contains_sprite(ChannelNum(3)));
+        assert!(!bitmask.contains_sprite(ChannelNum(1)));
+    }
+
     #[test]
     #[should_panic]
     fn sprite_bitmask_clear_invalid() {
@@ -329,6 +373,117 @@ mod tests {
         let mut bitmask = SpriteBitmask::default();
         bitmask.set(SpriteBitmask::NUM_CHANNELS);
     }
+
+    #[test]
+    fn v5_frame_sprites_follow_header() {
+        // One compressed V5 frame which changes the palette channel and the
+        // first sprite channel
+        let mut data = vec![ 0, 20 ];
+        data.extend_from_slice(&[ 0, 4, 0, 24, 0, 0, 0, 7 ]);
+        data.extend_from_slice(&[ 0, 6, 0, 48, 1, 0, 0, 0, 0, 3 ]);
+        let size = data.len() as u32;
+        let input = ByteOrdered::new(Cursor::new(data), Endianness::Big);
+        let mut stream = ScoreStream::new(input, 0, size, Version::V5);
+        let frame = stream.next(SpriteBitmask::empty()).unwrap().unwrap();
+        assert_eq!(frame.palette.id, MemberId::new(0_i16, 7_i16));
+        assert_eq!(frame.sprites[0].id(), MemberId::new(0_i16, 3_i16));
+        assert_eq!(frame.sprites[1].id(), MemberId::default());
+        assert!(stream.next(SpriteBitmask::empty()).unwrap().is_none());
+    }
+
+    #[test]
+    fn v8_frame_palette() {
+        // The V8 palette channel fills its whole cell, so the sprites still
+        // start after the frame header
+        let mut frame = vec![ 0, 40, 0, 24, 0, 24 ];
+        frame.extend_from_slice(&[ 0, 1, 0, 10, 15, PaletteFlags::FADE.bits(), 0x81, 0x82, 0, 3, 0, 2, 0, 0, 0, 0 ]);
+        frame.extend_from_slice(&[ 0xff; 8 ]);
+        frame.extend_from_slice(&[ 0, 6, 0, 48, 1, 0, 0, 0, 0, 3 ]);
+
+        let size = (20 + frame.len()) as u8;
+        let mut data = vec![ 0, 0, 0, size, 0, 0, 0, 20, 0, 0, 0, 1, 0, 8, 0, 24, 0, 50, 0, 0 ];
+        data.extend(frame);
+
+        let mut input = ByteOrdered::new(Cursor::new(data), Endianness::Big);
+        let mut score = Score::load(&mut input, size.into(), &(ConfigVersion::V1217, )).unwrap();
+        assert!(score.advance().unwrap());
+        let palette = score.palette();
+        assert_eq!(palette.id(), MemberId::new(1_i16, 10_i16));
+        assert_eq!(palette.rate(), FPS(15));
+        assert_eq!(palette.flags(), PaletteFlags::FADE);
+        assert_eq!(palette.cycle_start_color(), 1);
+        assert_eq!(palette.cycle_end_color(), 2);
+        assert_eq!(palette.num_frames(), 3);
+        assert_eq!(palette.num_cycles(), 2);
+        assert_eq!(score.current_frame().sprites[0].id(), MemberId::new(0_i16, 3_i16));
+        assert_eq!(score.current_frame().sprites[1].id(), MemberId::default());
+        assert!(!score.advance().unwrap());
+    }
+
+    /// A V5 score where the first frame puts a sprite in channel 1 at (10, 0)
+    /// and the second frame moves it to (50, 0).
+    fn two_frame_score() -> Score {
+        let mut data = vec![ 0, 30, 0, 24, 0, 48, 1, 0, 0, 1, 0, 1 ];
+        data.extend_from_slice(&[ 0; 6 ]);
+        data.extend_from_slice(&[ 0, 10 ]);
+        data.extend_from_slice(&[ 0; 10 ]);
+        data.extend_from_slice(&[ 0, 8, 0, 2, 0, 60, 0, 50 ]);
+        let size = data.len() as u32;
+        let input = ByteOrdered::new(Cursor::new(data), Endianness::Big);
+        Score {
+            vwsc: ScoreStream::new(input, 0, size, Version::V5),
+            ..Score::default()
+        }
+    }
+
+    #[test]
+    fn sprite_properties() {
+        let mut score = two_frame_score();
+        score.next().unwrap().unwrap();
+        let channel = ChannelNum(1);
+        assert_eq!(score.sprite_property(channel, SpriteProperty::LocH).unwrap(), Value::Integer(10));
+        assert_eq!(score.sprite_property(channel, SpriteProperty::CastNum).unwrap(), Value::Integer(1));
+        assert_eq!(score.sprite_property(channel, SpriteProperty::Blend).unwrap(), Value::Integer(100));
+        assert_eq!(score.sprite_property(channel, SpriteProperty::Visible).unwrap(), Value::Integer(1));
+
+        score.set_sprite_property(channel, SpriteProperty::Ink, Value::Integer(36)).unwrap();
+        assert_eq!(score.current_frame().sprites[0].ink(), Pen::Transparent);
+        score.set_sprite_property(channel, SpriteProperty::Blend, Value::Integer(50)).unwrap();
+        assert_eq!(score.sprite_property(channel, SpriteProperty::Blend).unwrap(), Value::Integer(50));
+        score.set_sprite_property(channel, SpriteProperty::Member, MemberId::new(2_i16, 3_i16).into()).unwrap();
+        assert_eq!(score.current_frame().sprites[0].id(), MemberId::new(2_i16, 3_i16));
+        score.set_sprite_property(channel, SpriteProperty::CastNum, Value::Integer(4)).unwrap();
+        assert_eq!(score.current_frame().sprites[0].id(), MemberId::new(2_i16, 4_i16));
+        score.set_sprite_property(channel, SpriteProperty::Visible, Value::Integer(0)).unwrap();
+        assert!(score.hidden_sprites().contains_sprite(channel));
+        score.set_sprite_property(channel, SpriteProperty::MoveableSprite, Value::Integer(1)).unwrap();
+        assert!(score.moveable_sprites.contains_sprite(channel));
+
+        assert!(score.set_sprite_property(channel, SpriteProperty::Ink, Value::Integer(20)).is_err());
+        assert!(score.set_sprite_property(channel, SpriteProperty::Blend, Value::Integer(101)).is_err());
+        assert!(score.set_sprite_property(channel, SpriteProperty::LocH, MemberId::new(1_i16, 1_i16).into()).is_err());
+        assert!(score.set_sprite_property(ChannelNum(0), SpriteProperty::LocH, Value::Integer(0)).is_err());
+        assert_eq!(SpriteProperty::from_name("moveablesprite"), Some(SpriteProperty::MoveableSprite));
+    }
+
+    #[test]
+    fn puppet_sprites_persist() {
+        let channel = ChannelNum(1);
+
+        let mut score = two_frame_score();
+        score.next().unwrap().unwrap();
+        score.set_sprite_property(channel, SpriteProperty::LocH, Value::Integer(99)).unwrap();
+        score.next().unwrap().unwrap();
+        assert_eq!(score.sprite_property(channel, SpriteProperty::LocH).unwrap(), Value::Integer(50));
+
+        let mut score = two_frame_score();
+        score.next().unwrap().unwrap();
+        score.set_puppet(channel, true).unwrap();
+        assert!(score.is_puppet(channel));
+        score.set_sprite_property(channel, SpriteProperty::LocH, Value::Integer(99)).unwrap();
+        score.next().unwrap().unwrap();
+        assert_eq!(score.sprite_property(channel, SpriteProperty::LocH).unwrap(), Value::Integer(99));
+    }
 }
 
 impl std::fmt::Debug for SpriteBitmask {
@@ -365,6 +520,22 @@ pub enum Transition {
 }
 
 impl Transition {
+    /// Returns the parameters of a transition which is stored in the score.
+    /// Cast member transitions get their parameters from the cast member
+    /// instead.
+    #[must_use]
+    pub fn params(&self) -> Option<TransitionParams> {
+        match *self {
+            Self::Legacy { chunk_size, which_transition, time, change_area, .. } => Some(TransitionParams {
+                kind: which_transition,
+                chunk_size,
+                duration: time.into(),
+                entire_stage: !change_area,
+            }),
+            Self::None | Self::Cast(..) | Self::LegacyTempo(..) => None,
+        }
+    }
+
     fn tempo(&self) -> Tempo {
         match self {
             Self::Legacy { tempo, .. } | Self::LegacyTempo(tempo) => *tempo,
@@ -486,7 +657,7 @@ impl ScoreStream {
         let mut new_frame = match self.version {
             Version::V3 => FrameV3::read_args(cursor, args).map(Frame::from),
             Version::V4 => FrameV4::read_args(cursor, args).map(Frame::from),
-            Version::V5 | Version::V6 | Version::V7 => Frame::read_args(cursor, args),
+            Version::V5 | Version::V6 | Version::V7 | Version::V8 => Frame::read_args(cursor, args),
             Version::Unknown => bail!("Unknown score version"),
         }.context("Can’t read frame")?;
 
@@ -525,6 +696,7 @@ impl ScoreStream {
         }
 
         self.raw_last_frame = new_data;
+        self.last_frame = new_frame.clone();
 
         Ok(Some(new_frame))
     }
@@ -560,8 +732,10 @@ pub struct Score {
     score_sprites: SpriteBitmask,
     sprites_to_paint0: SpriteBitmask,
     sprites_to_paint1: SpriteBitmask,
-    #[default([ Point { x: -0x8000, y: 0 }; NUM_SPRITES ])]
+    #[default([ Self::UNMOVED; NUM_SPRITES ])]
     sprite_origins: [ Point; NUM_SPRITES ],
+    #[default([ ChannelNum(0); NUM_SPRITES ])]
+    sprite_constraints: [ ChannelNum; NUM_SPRITES ],
     moveable_sprites: SpriteBitmask,
     immediate_sprites: SpriteBitmask,
     interactive_sprites: SpriteBitmask,
@@ -594,6 +768,7 @@ pub struct Score {
     current_tempo: Tempo,
     flags: Flags,
     palette_mapping: bool,
+    palette_channel: PaletteChannel,
     maybe_has_current_frame: bool,
     maybe_wrote_frame_delta: bool,
     maybe_error_writing_delta: bool,
@@ -604,6 +779,8 @@ pub struct Score {
     should_loop: bool,
     maybe_rewind_to_first_frame: bool,
     maybe_has_moveable_sprites: bool,
+    labels: FrameLabels,
+    actions: ActionList,
 }
 
 // TODO: This is just for debugging
@@ -611,13 +788,9 @@ impl Iterator for Score {
     type Item = AResult<Frame>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.vwsc.next(self.puppet_sprites) {
-            Ok(Some(frame)) => {
-                self.current_frame.frame = frame.clone();
-                self.current_frame_num += FrameNum(1);
-                Some(Ok(frame))
-            },
-            Ok(None) => None,
+        match self.advance() {
+            Ok(true) => Some(Ok(self.current_frame.frame.clone())),
+            Ok(false) => None,
             Err(e) => Some(Err(e)),
         }
     }
@@ -625,6 +798,335 @@ impl Iterator for Score {
 
 impl Score {
     const V5_HEADER_SIZE: u8 = 20;
+    /// The value of `sprite_origins` for a sprite which has not been dragged.
+    const UNMOVED: Point = Point { x: -0x8000, y: 0 };
+
+    /// Loads the score with the given number from a movie, along with its
+    /// frame labels and action list, using the settings of the movie
+    /// configuration. Text is decoded with the encoding of the movie.
+    pub fn load_movie(source: &impl ResourceSource, num: i16, config: &Config, decoder: DecoderRef) -> AResult<Self> {
+        let mut score = (*source.load::<Self>(ResourceId::new(b"VWSC", num), &(config.version(), ))
+            .with_context(|| format!("Can’t load score {}", num))?).clone();
+        score.set_palette_mapping(config.flags().contains(ConfigFlags::PALETTE_MAPPING));
+
+        let labels_id = ResourceId::new(b"VWLB", num);
+        if source.contains(labels_id) {
+            let labels = source.load::<FrameLabels>(labels_id, &(decoder, ))
+                .with_context(|| format!("Can’t load frame labels {}", num))?;
+            score.set_labels((*labels).clone());
+        }
+
+        let actions_id = ResourceId::new(b"VWAC", num);
+        if source.contains(actions_id) {
+            let actions = source.load::<ActionList>(actions_id, &(decoder, ))
+                .with_context(|| format!("Can’t load action list {}", num))?;
+            score.set_actions((*actions).clone());
+        }
+
+        Ok(score)
+    }
+
+    /// Reads the next frame of the score and makes it the current frame,
+    /// keeping puppet sprites and dragged sprite positions. Returns `false`
+    /// at the end of the score.
+    pub fn advance(&mut self) -> AResult<bool> {
+        if let Some(frame) = self.vwsc.next(self.puppet_sprites)? {
+            self.current_frame.frame = frame;
+            self.current_frame_palette = self.current_frame.frame.palette;
+            self.current_frame_num += FrameNum(1);
+            self.update_sprite_flags();
+            self.apply_sprite_origins();
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// The frame and sprite scripts of a Director 3 score.
+    #[must_use]
+    pub fn actions(&self) -> &ActionList {
+        &self.actions
+    }
+
+    /// The frame which is currently on the stage, including any changes made
+    /// to its sprites from Lingo.
+    #[must_use]
+    pub fn current_frame(&self) -> &Frame {
+        &self.current_frame.frame
+    }
+
+    /// The number of the frame which is currently on the stage, like `the
+    /// frame`.
+    #[must_use]
+    pub fn current_frame_num(&self) -> FrameNum {
+        self.current_frame_num
+    }
+
+    /// The label of the current frame, like `the frameLabel`.
+    #[must_use]
+    pub fn frame_label(&self) -> Option<&str> {
+        self.labels.get(self.current_frame_num).map(|label| label.name())
+    }
+
+    /// The frame labels of the score, which are used by `go to frame
+    /// "name"` and `the labelList`.
+    #[must_use]
+    pub fn labels(&self) -> &FrameLabels {
+        &self.labels
+    }
+
+    /// Returns the frame of the marker which is `offset` markers away from
+    /// the current marker, like `marker(offset)`.
+    #[must_use]
+    pub fn marker(&self, offset: i16) -> Option<FrameNum> {
+        self.labels.marker(self.current_frame_num, offset)
+    }
+
+    /// Sets the frame and sprite scripts of a Director 3 score, from the
+    /// `VWAC` resource with the same number as the score.
+    pub fn set_actions(&mut self, actions: ActionList) {
+        self.actions = actions;
+    }
+
+    /// Sets the frame labels of the score, from the `VWLB` resource with the
+    /// same number as the score.
+    pub fn set_labels(&mut self, labels: FrameLabels) {
+        self.labels = labels;
+    }
+
+    /// The sprite channels whose text the user can edit because of the
+    /// `editableText` sprite property.
+    #[must_use]
+    pub fn editable_sprites(&self) -> SpriteBitmask {
+        self.editable_sprites
+    }
+
+    /// The sprite channels which are hidden by the `visible` sprite
+    /// property.
+    #[must_use]
+    pub fn hidden_sprites(&self) -> SpriteBitmask {
+        self.hidden_sprites
+    }
+
+    /// The sprite channels which the user can drag.
+    #[must_use]
+    pub fn moveable_sprites(&self) -> SpriteBitmask {
+        self.moveable_sprites
+    }
+
+    /// Moves the sprite in the given channel after the user drags it. The
+    /// sprite stays where it was dropped in later frames for as long as it
+    /// remains moveable.
+    pub fn move_sprite(&mut self, channel: ChannelNum, origin: Point) -> AResult<()> {
+        let index = Self::sprite_index(channel)?;
+        self.current_frame.frame.sprites[index].origin = origin;
+        self.vwsc.last_frame.sprites[index].origin = origin;
+        self.sprite_origins[index] = origin;
+        Ok(())
+    }
+
+    /// The palette channel of the current frame.
+    #[must_use]
+    pub fn palette(&self) -> &Palette {
+        &self.current_frame_palette
+    }
+
+    /// Returns whether cast bitmaps are remapped to the closest colours of
+    /// the active palette instead of being drawn with their colour indexes.
+    #[must_use]
+    pub fn palette_mapping(&self) -> bool {
+        self.palette_mapping
+    }
+
+    /// Sets whether cast bitmaps are remapped to the active palette, from
+    /// the palette mapping flag of the movie configuration.
+    pub fn set_palette_mapping(&mut self, palette_mapping: bool) {
+        self.palette_mapping = palette_mapping;
+    }
+
+    /// The state of the palette channel.
+    #[must_use]
+    pub fn palette_channel(&self) -> &PaletteChannel {
+        &self.palette_channel
+    }
+
+    /// Advances to the next frame and runs its palette channel. An effect
+    /// which happens between frames runs to completion, and `show_colors` is
+    /// called with the colours of each step, which are shown at the rate of
+    /// the palette cell; an effect which spans frames advances by one step.
+    /// Returns `false` at the end of the score.
+    pub fn next_frame(&mut self, members: &impl PaletteMembers, mut show_colors: impl FnMut(&ColorTable)) -> AResult<bool> {
+        if !self.advance()? {
+            return Ok(false);
+        }
+
+        self.palette_channel.begin_frame(&self.current_frame_palette, members)
+            .context("Can’t run palette channel")?;
+        if self.palette_channel.spans_frames() {
+            if self.palette_channel.step() {
+                show_colors(self.palette_channel.colors());
+            }
+        } else {
+            while self.palette_channel.step() {
+                show_colors(self.palette_channel.colors());
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Returns a copy of a bitmap with the given palette which uses the
+    /// active palette, remapping its colours if the movie uses palette
+    /// mapping.
+    #[must_use]
+    pub fn remap_bitmap(&self, image: &PixMap, palette: &ColorTable) -> PixMap {
+        remap(image, palette, self.palette_channel.table(), self.palette_mapping)
+    }
+
+    /// The editor of the text sprite which has keyboard focus.
+    #[must_use]
+    pub fn text_editor(&self) -> &TextEditor {
+        &self.editable_sprite
+    }
+
+    #[must_use]
+    pub fn text_editor_mut(&mut self) -> &mut TextEditor {
+        &mut self.editable_sprite
+    }
+
+    /// Returns whether the sprite in the given channel is controlled by Lingo
+    /// instead of the score, like `the puppet of sprite`.
+    #[must_use]
+    pub fn is_puppet(&self, channel: ChannelNum) -> bool {
+        self.puppet_sprites.contains_sprite(channel)
+    }
+
+    /// Gives control of the sprite in the given channel to Lingo or returns
+    /// it to the score, like `puppetSprite`. A puppet sprite keeps its
+    /// current properties when the score moves to another frame.
+    pub fn set_puppet(&mut self, channel: ChannelNum, puppet: bool) -> AResult<()> {
+        Self::sprite_index(channel)?;
+        self.puppet_sprites.toggle_sprite(channel, puppet);
+        Ok(())
+    }
+
+    /// Returns the value of a property of the sprite in the given channel.
+    pub fn sprite_property(&self, channel: ChannelNum, property: SpriteProperty) -> AResult<Value> {
+        let sprite = &self.current_frame.frame.sprites[Self::sprite_index(channel)?];
+        Ok(match property {
+            SpriteProperty::LocH => sprite.origin().x.into(),
+            SpriteProperty::LocV => sprite.origin().y.into(),
+            SpriteProperty::CastNum => sprite.id().num().0.into(),
+            SpriteProperty::Member => sprite.id().into(),
+            SpriteProperty::Ink => (sprite.ink() as i32).into(),
+            SpriteProperty::Blend => if sprite.blend() { sprite.blend_amount() } else { 100 }.into(),
+            SpriteProperty::ForeColor => sprite.fore_color_index().into(),
+            SpriteProperty::BackColor => sprite.back_color_index().into(),
+            SpriteProperty::Width => sprite.width().into(),
+            SpriteProperty::Height => sprite.height().into(),
+            SpriteProperty::Stretch => sprite.stretch().into(),
+            SpriteProperty::Visible => (!self.hidden_sprites.contains_sprite(channel)).into(),
+            SpriteProperty::MoveableSprite => sprite.moveable().into(),
+            SpriteProperty::EditableText => sprite.editable().into(),
+            SpriteProperty::Trails => sprite.trails().into(),
+            SpriteProperty::Constraint => self.sprite_constraints[Self::sprite_index(channel)?].0.into(),
+        })
+    }
+
+    /// Sets a property of the sprite in the given channel. The change lasts
+    /// until the score changes the sprite, unless the sprite is a puppet.
+    pub fn set_sprite_property(&mut self, channel: ChannelNum, property: SpriteProperty, value: Value) -> AResult<()> {
+        let index = Self::sprite_index(channel)?;
+        let mut sprite = self.current_frame.frame.sprites[index];
+        self.write_sprite_property(channel, &mut sprite, property, value)
+            .with_context(|| format!("Can’t set the {} of sprite {}", property.name(), channel.0))?;
+
+        // The score stream copies puppet sprites forward from the last frame
+        // it read, so the change has to be made there too
+        self.current_frame.frame.sprites[index] = sprite;
+        self.vwsc.last_frame.sprites[index] = sprite;
+        self.update_sprite_flags();
+        Ok(())
+    }
+
+    fn write_sprite_property(&mut self, channel: ChannelNum, sprite: &mut Sprite, property: SpriteProperty, value: Value) -> AResult<()> {
+        match property {
+            SpriteProperty::LocH => sprite.origin.x = value.to_integer_in()?,
+            SpriteProperty::LocV => sprite.origin.y = value.to_integer_in()?,
+            SpriteProperty::CastNum => {
+                let lib_num = if sprite.id.lib().0 == 0 { 1 } else { sprite.id.lib().0 };
+                sprite.id = MemberId::new(lib_num, value.to_integer_in::<i16>()?);
+            },
+            SpriteProperty::Member => sprite.id = value.to_member()?,
+            SpriteProperty::Ink => {
+                let ink = value.to_integer_in::<u8>()?;
+                let pen = Pen::from_u8(ink)
+                    .filter(|_| ink <= SpriteInk::INK_KIND.bits())
+                    .with_context(|| format!("Invalid sprite ink {}", ink))?;
+                sprite.set_ink(pen);
+            },
+            SpriteProperty::Blend => {
+                let amount = value.to_integer_in::<u8>()?;
+                ensure!(amount <= 100, "Blend {} is out of range", amount);
+                sprite.blend_amount = amount;
+                sprite.line_size_and_flags.set(SpriteLineSize::BLEND, amount != 100);
+            },
+            SpriteProperty::ForeColor => sprite.fore_color_index = value.to_integer_in()?,
+            SpriteProperty::BackColor => sprite.back_color_index = value.to_integer_in()?,
+            SpriteProperty::Width => sprite.width = value.to_integer_in()?,
+            SpriteProperty::Height => sprite.height = value.to_integer_in()?,
+            SpriteProperty::Stretch => sprite.ink_and_flags.set(SpriteInk::STRETCH, value.to_bool()?),
+            SpriteProperty::Visible => {
+                self.hidden_sprites.toggle_sprite(channel, !value.to_bool()?);
+            },
+            SpriteProperty::MoveableSprite => sprite.score_color_and_flags.set(SpriteScoreColor::MOVEABLE, value.to_bool()?),
+            SpriteProperty::EditableText => sprite.score_color_and_flags.set(SpriteScoreColor::EDITABLE, value.to_bool()?),
+            SpriteProperty::Trails => sprite.ink_and_flags.set(SpriteInk::TRAILS, value.to_bool()?),
+            SpriteProperty::Constraint => {
+                let constraint = ChannelNum(value.to_integer_in()?);
+                if constraint.0 != 0 {
+                    Self::sprite_index(constraint)?;
+                }
+                self.sprite_constraints[Self::sprite_index(channel)?] = constraint;
+            },
+        }
+        Ok(())
+    }
+
+    /// Puts dragged sprites back where the user dropped them, and forgets
+    /// the positions of sprites which are no longer moveable.
+    fn apply_sprite_origins(&mut self) {
+        for (index, origin) in self.sprite_origins.iter_mut().enumerate() {
+            if *origin == Self::UNMOVED {
+                continue;
+            }
+
+            let sprite = &mut self.current_frame.frame.sprites[index];
+            if sprite.kind != SpriteKind::None && sprite.moveable() {
+                sprite.origin = *origin;
+            } else {
+                *origin = Self::UNMOVED;
+            }
+        }
+    }
+
+    fn sprite_index(channel: ChannelNum) -> AResult<usize> {
+        usize::try_from(channel.0 - 1).ok()
+            .filter(|&index| index < NUM_SPRITES)
+            .with_context(|| format!("Invalid sprite channel {}", channel.0))
+    }
+
+    /// Updates the channel masks which mirror the flags of sprites in the
+    /// current frame.
+    fn update_sprite_flags(&mut self) {
+        for (index, sprite) in self.current_frame.frame.sprites.iter().enumerate() {
+            let channel = ChannelNum(index as i16 + 1);
+            let in_use = sprite.kind != SpriteKind::None;
+            self.moveable_sprites.toggle_sprite(channel, in_use && sprite.moveable());
+            self.editable_sprites.toggle_sprite(channel, in_use && sprite.editable());
+        }
+        self.maybe_has_moveable_sprites = !self.moveable_sprites.is_empty();
+    }
 }
 
 impl Resource for Score {
@@ -655,7 +1157,7 @@ impl Resource for Score {
             dbg!(own_size, header_size, num_frames, version);
 
             match version {
-                Version::V4 | Version::V5 | Version::V6 | Version::V7 => {
+                Version::V4 | Version::V5 | Version::V6 | Version::V7 | Version::V8 => {
                     let (expect_sprite_size, expect_num_sprites) = if version < Version::V5 {
                         (Sprite::V0_SIZE, Frame::V0_SIZE_IN_CELLS)
                     } else {
@@ -743,6 +1245,56 @@ pub struct Palette {
     field_f: Unk8,
 }
 
+impl Palette {
+    /// The palette to switch to, or a null ID to keep the current palette.
+    /// Negative member numbers are built-in palettes.
+    #[must_use]
+    pub fn id(&self) -> MemberId {
+        self.id
+    }
+
+    /// The last palette index of the range of colours to cycle.
+    #[must_use]
+    pub fn cycle_end_color(&self) -> u8 {
+        Self::color_index(self.cycle_end_color)
+    }
+
+    /// The first palette index of the range of colours to cycle.
+    #[must_use]
+    pub fn cycle_start_color(&self) -> u8 {
+        Self::color_index(self.cycle_start_color)
+    }
+
+    #[must_use]
+    pub fn flags(&self) -> PaletteFlags {
+        self.flags
+    }
+
+    /// The number of times to cycle through the colour range.
+    #[must_use]
+    pub fn num_cycles(&self) -> i16 {
+        self.num_cycles
+    }
+
+    /// The number of frames which a transition spanning frames lasts.
+    #[must_use]
+    pub fn num_frames(&self) -> i16 {
+        self.num_frames
+    }
+
+    /// The speed of the transition or colour cycling, in steps per second.
+    #[must_use]
+    pub fn rate(&self) -> FPS {
+        self.rate
+    }
+
+    // Colour indexes are stored as signed offsets from the middle of the
+    // palette
+    fn color_index(value: i8) -> u8 {
+        (i16::from(value) + 0x80) as u8
+    }
+}
+
 #[derive(BinRead, Clone, Copy, Debug, Default)]
 #[br(big, import(version: Version))]
 struct PaletteV4 {
@@ -761,6 +1313,20 @@ struct PaletteV4 {
     field_f: Unk8,
 }
 
+#[derive(BinRead, Clone, Copy, Debug, Default)]
+#[br(big)]
+struct PaletteV8 {
+    palette: Palette,
+    field_10: Unk32,
+    field_14: Unk32,
+}
+
+impl From<PaletteV8> for Palette {
+    fn from(new: PaletteV8) -> Self {
+        new.palette
+    }
+}
+
 impl From<PaletteV4> for Palette {
     fn from(old: PaletteV4) -> Self {
         Self {
@@ -784,7 +1350,7 @@ impl Resource for Palette {
 
     fn load(input: &mut Input<impl Reader>, _: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
         if context.0 > Version::V7 {
-            todo!("Score palette version 8 parsing")
+            PaletteV8::read(input).map(Self::from)
         } else if context.0 >= Version::V5 {
             Self::read(input)
         } else {
@@ -803,15 +1369,6 @@ struct Score1494 {
     cast_member_kind: MemberKind,
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-struct TextEditor {
-    te: TEHandle,
-    rect: Rect,
-    sprite_num: ChannelNum,
-    id: MemberId,
-    is_editing: bool,
-}
-
 #[derive(Clone, Debug, SmartDefault)]
 struct SpriteFrame {
     frame: Frame,
@@ -823,6 +1380,9 @@ struct SpriteFrame {
 #[br(big, import(version: Version))]
 pub struct Frame {
     pub script: MemberId,
+    /// The frame script of a Director 3 score, which is not a cast member.
+    #[br(default)]
+    pub action: ActionNum,
     pub sound_1: MemberId,
     pub sound_2: MemberId,
     #[br(args(version))]
@@ -834,7 +1394,10 @@ pub struct Frame {
     pub transition_related: Unk8,
     #[br(args(version, transition), parse_with = Self::parse_tempo)]
     pub tempo: Tempo,
-    #[br(align_before(24))]
+    // The palette channel is in the second cell of the frame header. The
+    // header is two `sizeof(Sprite)`s long even though the palette only
+    // uses 16 bytes of its cell, so the first sprite starts at offset 48.
+    #[br(align_before(24), align_after(48), args(version), parse_with = Self::parse_palette)]
     pub palette: Palette,
     #[default([ Sprite::default(); NUM_SPRITES ])]
     #[br(args(version), parse_with = parse_sprites::<Sprite, _>)]
@@ -842,6 +1405,15 @@ pub struct Frame {
 }
 
 impl Frame {
+    fn parse_palette<R>(reader: &mut R, options: &ReadOptions, args: (Version, )) -> binread::BinResult<Palette>
+    where R: binread::io::Read + binread::io::Seek {
+        if args.0 > Version::V7 {
+            PaletteV8::read_options(reader, options, ()).map(Palette::from)
+        } else {
+            Palette::read_options(reader, options, ())
+        }
+    }
+
     fn parse_tempo<R>(reader: &mut R, options: &ReadOptions, args: (Version, Transition)) -> binread::BinResult<Tempo>
     where R: binread::io::Read + binread::io::Seek {
         let (version, transition) = args;
@@ -873,11 +1445,8 @@ where
 #[derive(BinRead, Clone, Copy, Debug)]
 #[br(big, import(version: Version))]
 struct FrameV3 {
-    // TODO: This is an index into VWAC resource. There is no way to convert
-    // this to D5, so either script field needs to be an enum (probably) or
-    // extra fields should be added to Frame to store it. Also, it turns out
-    // this is not the script-related field from D4.
-    script: u8,
+    // This is not the script-related field from D4
+    action: ActionNum,
     sound_1_kind_maybe: Unk8,
     #[br(args(version))]
     transition: Transition,
@@ -893,6 +1462,7 @@ struct FrameV3 {
 impl From<FrameV3> for Frame {
     fn from(old: FrameV3) -> Self {
         Self {
+            action: old.action,
             sound_1: old.sound_1.into(),
             sound_2: old.sound_2.into(),
             transition: old.transition,
@@ -938,6 +1508,7 @@ impl From<FrameV4> for Frame {
     fn from(old: FrameV4) -> Self {
         Self {
             script: old.script.into(),
+            action: ActionNum::default(),
             sound_1: old.sound_1.into(),
             sound_2: old.sound_2.into(),
             transition: old.transition,
@@ -977,6 +1548,7 @@ pub enum Version {
     V5,
     V6,
     V7,
+    V8,
 }
 
 #[derive(Copy, Clone, Debug, Eq, FromPrimitive, PartialEq, SmartDefault)]
@@ -1010,8 +1582,10 @@ bitflags! {
     pub struct SpriteLineSize: u8 {
         const LINE_SIZE = 0xf;
         const BLEND     = 0x10;
-        const FLAG_20   = 0x20;
-        const FLAG_40   = 0x40;
+        /// The sprite is mirrored horizontally.
+        const FLIP_H    = 0x20;
+        /// The sprite is mirrored vertically.
+        const FLIP_V    = 0x40;
         // TODO: What is this? Exists in data converted from D4 to D5
         const FLAG_80   = 0x80;
     }
@@ -1060,14 +1634,16 @@ fn fix_v0_v6_sprite_kind(kind: SpriteKind) -> SpriteKind {
     }
 }
 
-#[derive(BinRead, Clone, Copy, Default)]
+#[derive(BinRead, Clone, Copy, Default, Eq, PartialEq)]
 #[br(big, import(version: Version))]
 pub struct Sprite {
-    #[br(map = |kind: SpriteKind| if version == Version::V7 { kind } else { fix_v0_v6_sprite_kind(kind) })]
+    #[br(map = |kind: SpriteKind| if version >= Version::V7 { kind } else { fix_v0_v6_sprite_kind(kind) })]
     kind: SpriteKind,
     ink_and_flags: SpriteInk,
     id: MemberId,
     script: MemberId,
+    #[br(default)]
+    action: ActionNum,
     fore_color_index: u8,
     back_color_index: u8,
     origin: Point,
@@ -1082,11 +1658,8 @@ pub struct Sprite {
 #[derive(BinRead, Clone, Copy, Debug, Default)]
 #[br(big, import(version: Version))]
 struct SpriteV3 {
-    // TODO: This is an index into VWAC resource. There is no way to convert
-    // this to D5, so either script field needs to be an enum (probably) or
-    // extra fields should be added to Frame to store it. Also, it turns out
-    // this is not the script-related field from D4.
-    script: u8,
+    // This is not the script-related field from D4
+    action: ActionNum,
     kind: SpriteKind,
     fore_color_index: u8,
     back_color_index: u8,
@@ -1105,6 +1678,7 @@ impl From<SpriteV3> for Sprite {
             kind: fix_v0_v6_sprite_kind(old.kind),
             ink_and_flags: old.ink_and_flags,
             id: old.id.into(),
+            action: old.action,
             fore_color_index: old.fore_color_index,
             back_color_index: old.back_color_index,
             origin: old.origin,
@@ -1141,6 +1715,7 @@ impl From<SpriteV4> for Sprite {
             ink_and_flags: old.ink_and_flags,
             id: old.id.into(),
             script: old.script.into(),
+            action: ActionNum::default(),
             fore_color_index: old.fore_color_index,
             back_color_index: old.back_color_index,
             origin: old.origin,
@@ -1157,6 +1732,12 @@ impl Sprite {
     const V0_SIZE: u16 = 20;
     const V5_SIZE: u16 = 24;
 
+    /// The sprite script of a Director 3 score, which is not a cast member.
+    #[must_use]
+    pub fn action(&self) -> ActionNum {
+        self.action
+    }
+
     #[must_use]
     pub fn back_color_index(&self) -> u8 {
         self.back_color_index
@@ -1177,6 +1758,16 @@ impl Sprite {
         self.score_color_and_flags.contains(SpriteScoreColor::EDITABLE)
     }
 
+    #[must_use]
+    pub fn flip_h(&self) -> bool {
+        self.line_size_and_flags.contains(SpriteLineSize::FLIP_H)
+    }
+
+    #[must_use]
+    pub fn flip_v(&self) -> bool {
+        self.line_size_and_flags.contains(SpriteLineSize::FLIP_V)
+    }
+
     #[must_use]
     pub fn fore_color_index(&self) -> u8 {
         self.fore_color_index
@@ -1217,6 +1808,11 @@ impl Sprite {
         self.origin
     }
 
+    fn set_ink(&mut self, ink: Pen) {
+        self.ink_and_flags.remove(SpriteInk::INK_KIND);
+        self.ink_and_flags |= SpriteInk::from_bits_truncate(ink as u8);
+    }
+
     #[must_use]
     pub fn score_color(&self) -> u8 {
         (self.score_color_and_flags & SpriteScoreColor::COLOR).bits()
@@ -1250,7 +1846,7 @@ impl Resource for Sprite {
         match context.0 {
             Version::V3 => SpriteV3::read_args(input, *context).map(Self::from),
             Version::V4 => SpriteV4::read_args(input, *context).map(Self::from),
-            Version::V5 | Version::V6 | Version::V7 => Self::read_args(input, *context),
+            Version::V5 | Version::V6 | Version::V7 | Version::V8 => Self::read_args(input, *context),
             Version::Unknown => bail!("Unknown score version"),
         }.context("Can’t read sprite")
     }
@@ -1259,10 +1855,13 @@ impl Resource for Sprite {
 impl std::fmt::Debug for Sprite {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct(std::any::type_name::<Self>())
+            .field("action", &self.action())
             .field("back_color_index", &self.back_color_index())
             .field("blend", &self.blend())
             .field("blend_amount", &self.blend_amount())
             .field("editable", &self.editable())
+            .field("flip_h", &self.flip_h())
+            .field("flip_v", &self.flip_v())
             .field("fore_color_index", &self.fore_color_index())
             .field("height", &self.height())
             .field("id", &self.id())
@@ -0,0 +1,150 @@
+use crate::resources::shape::{Kind, LineDirection, Meta};
+use libmactoolbox::{
+    Point,
+    quickdraw::{CGrafPort, GrafVerb, Pattern, PatternList, PixPat, RGBColor},
+    Rect,
+};
+
+/// The corner oval size of round rectangle shapes.
+const ROUND_RECT_OVAL_SIZE: i16 = 16;
+
+/// Draws a shape cast member into the given rectangle, which is normally the
+/// rectangle of the sprite. The shape pattern is looked up in the system
+/// pattern list; shapes with patterns which are not in the list are drawn
+/// solid.
+///
+/// The pen, pattern, and colour state of the port is reset afterwards.
+pub fn draw(meta: &Meta, port: &mut CGrafPort, rect: Rect, fore_color: RGBColor, back_color: RGBColor, patterns: Option<&PatternList>) {
+    let pattern = patterns
+        .and_then(|patterns| patterns.get(meta.pattern() as i16))
+        .unwrap_or(Pattern::BLACK);
+    let pen_size = meta.pen_size();
+
+    port.rgb_fore_color(fore_color);
+    port.rgb_back_color(back_color);
+    port.fill_pix_pat(pattern.into());
+    port.pen_pix_pat(pattern.into());
+    port.pen_size(pen_size, pen_size);
+
+    match meta.kind() {
+        Kind::Line => {
+            // Lines are drawn by the top-left corner of the pen, so the end
+            // points are pulled in to keep the whole line inside the rect
+            if pen_size > 0 {
+                let right = rect.right - pen_size;
+                let bottom = rect.bottom - pen_size;
+                let (start, end) = match meta.line_direction() {
+                    LineDirection::TopToBottom => (
+                        Point { x: rect.left, y: rect.top },
+                        Point { x: right, y: bottom },
+                    ),
+                    LineDirection::BottomToTop => (
+                        Point { x: rect.left, y: bottom },
+                        Point { x: right, y: rect.top },
+                    ),
+                };
+                port.move_to(start.x, start.y);
+                port.std_line(end);
+            }
+        },
+        kind => {
+            let (oval_width, oval_height) = match kind {
+                Kind::Rect | Kind::Line => (0, 0),
+                Kind::RoundRect => (ROUND_RECT_OVAL_SIZE, ROUND_RECT_OVAL_SIZE),
+                Kind::Oval => (rect.width(), rect.height()),
+            };
+
+            if meta.filled() {
+                port.std_rrect(GrafVerb::Fill, rect, oval_width, oval_height);
+            }
+
+            if pen_size > 0 {
+                port.std_rrect(GrafVerb::Frame, rect, oval_width, oval_height);
+            }
+        },
+    }
+
+    port.pen_size(1, 1);
+    port.pen_pix_pat(PixPat::from(Pattern::BLACK));
+    port.fill_pix_pat(PixPat::from(Pattern::BLACK));
+    port.rgb_fore_color(RGBColor::BLACK);
+    port.rgb_back_color(RGBColor::WHITE);
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::Resource;
+    use std::io::Cursor;
+    use super::*;
+
+    const RED: RGBColor = RGBColor { r: 0xffff, g: 0, b: 0 };
+
+    fn meta(kind: u8, pattern: u8, filled: bool, line_size: u8, line_direction: u8) -> Meta {
+        let data = [
+            0, kind,
+            0, 0, 0, 0, 0, 10, 0, 10,
+            0, pattern,
+            0xff, 0,
+            u8::from(filled),
+            line_size,
+            line_direction,
+        ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        Meta::load(&mut input, data.len() as u32, &()).unwrap()
+    }
+
+    fn draw_shape(meta: &Meta, patterns: Option<&PatternList>) -> CGrafPort {
+        let mut port = CGrafPort::new(Rect { top: 0, left: 0, bottom: 24, right: 24 });
+        let rect = Rect { top: 2, left: 2, bottom: 22, right: 22 };
+        draw(meta, &mut port, rect, RED, RGBColor::WHITE, patterns);
+        port
+    }
+
+    #[test]
+    fn pen_size() {
+        assert_eq!(meta(1, 1, false, 0, 5).pen_size(), 0);
+        assert_eq!(meta(1, 1, false, 1, 5).pen_size(), 0);
+        assert_eq!(meta(1, 1, false, 3, 5).pen_size(), 2);
+        assert_eq!(meta(1, 1, false, 0x13, 5).pen_size(), 2);
+    }
+
+    #[test]
+    fn filled_rect() {
+        let patterns = PatternList::load(
+            &mut ByteOrdered::new(Cursor::new(&[ 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa ][..]), Endianness::Big),
+            18,
+            &()
+        ).unwrap();
+        let port = draw_shape(&meta(1, 2, true, 1, 5), Some(&patterns));
+        let pix_map = port.pix_map().borrow();
+        assert_eq!(pix_map.pixel(1, 2), Some(RGBColor::WHITE));
+        assert_eq!(pix_map.pixel(2, 2), Some(RED));
+        assert_eq!(pix_map.pixel(3, 2), Some(RGBColor::WHITE));
+        assert_eq!(pix_map.pixel(20, 21), Some(RED));
+        assert_eq!(pix_map.pixel(22, 21), Some(RGBColor::WHITE));
+    }
+
+    #[test]
+    fn framed_oval() {
+        let port = draw_shape(&meta(3, 1, false, 3, 5), None);
+        let pix_map = port.pix_map().borrow();
+        assert_eq!(pix_map.pixel(2, 2), Some(RGBColor::WHITE));
+        assert_eq!(pix_map.pixel(2, 12), Some(RED));
+        assert_eq!(pix_map.pixel(3, 12), Some(RED));
+        assert_eq!(pix_map.pixel(4, 12), Some(RGBColor::WHITE));
+        assert_eq!(pix_map.pixel(12, 12), Some(RGBColor::WHITE));
+    }
+
+    #[test]
+    fn line() {
+        let port = draw_shape(&meta(4, 1, false, 2, 6), None);
+        let pix_map = port.pix_map().borrow();
+        assert_eq!(pix_map.pixel(2, 21), Some(RED));
+        assert_eq!(pix_map.pixel(21, 2), Some(RED));
+        assert_eq!(pix_map.pixel(12, 11), Some(RED));
+        assert_eq!(pix_map.pixel(2, 2), Some(RGBColor::WHITE));
+        assert_eq!(pix_map.pixel(21, 21), Some(RGBColor::WHITE));
+    }
+}
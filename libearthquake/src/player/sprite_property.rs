@@ -0,0 +1,208 @@
+//! Lingo sprite properties, like `the locH of sprite 1`.
+
+use anyhow::{anyhow, Result as AResult};
+use crate::resources::cast::MemberId;
+use std::convert::TryFrom;
+
+/// A property of a sprite which can be read and written from Lingo.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpriteProperty {
+    /// The horizontal position of the registration point of the sprite.
+    LocH,
+    /// The vertical position of the registration point of the sprite.
+    LocV,
+    /// The number of the cast member used by the sprite.
+    CastNum,
+    /// The cast member used by the sprite.
+    Member,
+    /// The ink used to draw the sprite.
+    Ink,
+    /// The opacity of the sprite, from 0 to 100.
+    Blend,
+    /// The palette index of the foreground colour.
+    ForeColor,
+    /// The palette index of the background colour.
+    BackColor,
+    Width,
+    Height,
+    /// Whether the sprite is scaled to its width and height.
+    Stretch,
+    /// Whether the sprite is drawn at all.
+    Visible,
+    /// Whether the user can drag the sprite.
+    MoveableSprite,
+    /// Whether the user can edit the text of the sprite.
+    EditableText,
+    /// Whether the sprite leaves a trail when it moves.
+    Trails,
+    /// The channel of the sprite whose rectangle limits where the sprite can
+    /// be dragged, or 0 if it is not limited.
+    Constraint,
+}
+
+impl SpriteProperty {
+    /// Returns the property with the given Lingo name. Lingo names are not
+    /// case sensitive.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "loch" => Self::LocH,
+            "locv" => Self::LocV,
+            "castnum" => Self::CastNum,
+            "member" => Self::Member,
+            "ink" => Self::Ink,
+            "blend" => Self::Blend,
+            "forecolor" => Self::ForeColor,
+            "backcolor" => Self::BackColor,
+            "width" => Self::Width,
+            "height" => Self::Height,
+            "stretch" => Self::Stretch,
+            "visible" => Self::Visible,
+            "moveablesprite" => Self::MoveableSprite,
+            "editabletext" => Self::EditableText,
+            "trails" => Self::Trails,
+            "constraint" => Self::Constraint,
+            _ => return None,
+        })
+    }
+
+    /// The Lingo name of the property.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LocH => "locH",
+            Self::LocV => "locV",
+            Self::CastNum => "castNum",
+            Self::Member => "member",
+            Self::Ink => "ink",
+            Self::Blend => "blend",
+            Self::ForeColor => "foreColor",
+            Self::BackColor => "backColor",
+            Self::Width => "width",
+            Self::Height => "height",
+            Self::Stretch => "stretch",
+            Self::Visible => "visible",
+            Self::MoveableSprite => "moveableSprite",
+            Self::EditableText => "editableText",
+            Self::Trails => "trails",
+            Self::Constraint => "constraint",
+        }
+    }
+}
+
+/// The value of a sprite property.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Value {
+    /// An integer. Lingo booleans are integers where zero is false.
+    Integer(i32),
+    /// A reference to a cast member.
+    Member(MemberId),
+}
+
+impl Value {
+    /// Returns the value as a boolean.
+    pub fn to_bool(self) -> AResult<bool> {
+        self.to_integer().map(|value| value != 0)
+    }
+
+    /// Returns the value as an integer.
+    pub fn to_integer(self) -> AResult<i32> {
+        match self {
+            Self::Integer(value) => Ok(value),
+            Self::Member(_) => Err(anyhow!("Expected an integer, got {:?}", self)),
+        }
+    }
+
+    /// Returns the value as an integer in the range of `T`.
+    pub fn to_integer_in<T: TryFrom<i32>>(self) -> AResult<T> {
+        let value = self.to_integer()?;
+        T::try_from(value).map_err(|_| anyhow!("Integer {} is out of range", value))
+    }
+
+    /// Returns the value as a cast member. Integers are member numbers in the
+    /// first cast library.
+    pub fn to_member(self) -> AResult<MemberId> {
+        match self {
+            Self::Integer(_) => self.to_integer_in::<i16>().map(MemberId::from),
+            Self::Member(id) => Ok(id),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<i16> for Value {
+    fn from(value: i16) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(value: u8) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<MemberId> for Value {
+    fn from(value: MemberId) -> Self {
+        Self::Member(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    #[test]
+    fn property_names() {
+        for property in &[
+            SpriteProperty::LocH,
+            SpriteProperty::LocV,
+            SpriteProperty::CastNum,
+            SpriteProperty::Member,
+            SpriteProperty::Ink,
+            SpriteProperty::Blend,
+            SpriteProperty::ForeColor,
+            SpriteProperty::BackColor,
+            SpriteProperty::Width,
+            SpriteProperty::Height,
+            SpriteProperty::Stretch,
+            SpriteProperty::Visible,
+            SpriteProperty::MoveableSprite,
+            SpriteProperty::EditableText,
+            SpriteProperty::Trails,
+            SpriteProperty::Constraint,
+        ] {
+            assert_eq!(SpriteProperty::from_name(property.name()), Some(*property));
+        }
+        assert_eq!(SpriteProperty::from_name("EDITABLETEXT"), Some(SpriteProperty::EditableText));
+        assert_eq!(SpriteProperty::from_name("rect"), None);
+    }
+
+    #[test]
+    fn value_conversions() {
+        assert!(Value::Integer(2).to_bool().unwrap());
+        assert!(!Value::from(false).to_bool().unwrap());
+        assert_eq!(Value::Integer(255).to_integer_in::<u8>().unwrap(), 255);
+        assert!(Value::Integer(256).to_integer_in::<u8>().is_err());
+        assert!(Value::Integer(-1).to_integer_in::<u8>().is_err());
+
+        let member = MemberId::new(2_i16, 3_i16);
+        assert_eq!(Value::from(member).to_member().unwrap(), member);
+        assert_eq!(Value::Integer(4).to_member().unwrap(), MemberId::from(4_i16));
+        assert!(Value::Integer(40_000).to_member().is_err());
+        assert!(Value::from(member).to_integer().is_err());
+        assert!(Value::from(member).to_bool().is_err());
+    }
+}
@@ -0,0 +1,327 @@
+//! Drawing the sprites of the score onto the stage.
+//!
+//! Only the parts of the stage which changed since the last frame are
+//! repainted. A sprite with trails is never erased from its old position, so
+//! it leaves a copy of itself behind. Trail copies are kept when other
+//! sprites are repainted over them, until a sprite without trails moves away
+//! from them.
+
+use libmactoolbox::{quickdraw::{CGrafPort, GrafVerb, Pen, PixMap, Region, RGBColor}, Rect};
+use super::{hit_test::{Matte, SpriteMembers, SpriteRect}, score::{ChannelNum, Frame, Sprite, SpriteBitmask, SpriteKind}};
+
+/// The stage of a movie.
+pub struct Stage {
+    port: CGrafPort,
+    /// The frame which was last drawn.
+    frame: Frame,
+    /// The rectangle of each sprite when it was last drawn, or an empty
+    /// rectangle if it was not drawn.
+    sprite_rects: Vec<Rect>,
+    /// The area which must be repainted even if no sprite changed.
+    invalid: Region,
+    /// The area covered by copies of sprites with trails.
+    trails: Region,
+}
+
+impl Stage {
+    /// Creates a stage with the given bounds, filled with the given colour.
+    #[must_use]
+    pub fn new(bounds: Rect, color: RGBColor) -> Self {
+        let mut port = CGrafPort::new(bounds);
+        port.rgb_back_color(color);
+        port.std_rect(GrafVerb::Erase, bounds);
+        let frame = Frame::default();
+        let sprite_rects = vec![ Rect::default(); frame.sprites.len() ];
+        Self { port, frame, sprite_rects, invalid: Region::default(), trails: Region::default() }
+    }
+
+    /// Marks an area of the stage to be repainted by the next update, like
+    /// `InvalRect`.
+    pub fn invalidate(&mut self, rect: Rect) {
+        self.invalid = self.invalid.union(&Region::from(rect));
+    }
+
+    /// The graphics port which holds the pixels of the stage.
+    #[must_use]
+    pub fn port(&self) -> &CGrafPort {
+        &self.port
+    }
+
+    /// Draws the given frame. Bitmap sprites are drawn by the stage; all
+    /// other sprites are drawn by `draw_sprite`, which is given the clipped
+    /// port, the channel and sprite, and the sprite rectangle. Returns the
+    /// area of the stage which was repainted.
+    pub fn update(
+        &mut self,
+        frame: &Frame,
+        hidden_sprites: &SpriteBitmask,
+        members: &impl SpriteMembers,
+        mut draw_sprite: impl FnMut(&mut CGrafPort, ChannelNum, &Sprite, Rect),
+    ) -> Region {
+        let rects = frame.sprites.iter().enumerate().map(|(index, sprite)| {
+            let channel = ChannelNum(index as i16 + 1);
+            if sprite.kind() == SpriteKind::None || hidden_sprites.contains_sprite(channel) {
+                None
+            } else {
+                Some(SpriteRect::new(sprite, members.member_bounds(channel, sprite)))
+            }
+        }).collect::<Vec<_>>();
+
+        let mut dirty = std::mem::take(&mut self.invalid);
+        for (index, sprite) in frame.sprites.iter().enumerate() {
+            let old_sprite = &self.frame.sprites[index];
+            let old_rect = self.sprite_rects[index];
+            let new_rect = rects[index].map_or_else(Rect::default, |rect| rect.rect());
+            if old_sprite == sprite && old_rect == new_rect {
+                continue;
+            }
+
+            if !old_rect.is_empty() {
+                if old_sprite.trails() {
+                    self.trails = self.trails.union(&Region::from(old_rect));
+                } else {
+                    self.trails = self.trails.difference(&Region::from(old_rect));
+                    dirty = dirty.union(&Region::from(old_rect));
+                }
+            }
+            if !new_rect.is_empty() {
+                dirty = dirty.union(&Region::from(new_rect));
+            }
+        }
+
+        self.frame = frame.clone();
+        for (sprite_rect, rect) in self.sprite_rects.iter_mut().zip(&rects) {
+            *sprite_rect = rect.map_or_else(Rect::default, |rect| rect.rect());
+        }
+
+        let dirty = dirty.intersect(&Region::from(self.port.port_rect()));
+        if dirty.is_empty() {
+            return dirty;
+        }
+
+        let old_clip = self.port.clip_rgn().clone();
+        self.port.set_clip(dirty.clone());
+        self.port.std_rgn(GrafVerb::Erase, &dirty.difference(&self.trails));
+
+        for (index, sprite) in frame.sprites.iter().enumerate() {
+            let rect = match rects[index] {
+                Some(rect) if dirty.intersects_rect(rect.rect()) => rect,
+                _ => continue,
+            };
+
+            let channel = ChannelNum(index as i16 + 1);
+            match members.member_image(channel, sprite) {
+                Some(image) => {
+                    let back_color = members.back_color(sprite);
+                    // Director stores the matte ink as `patCopy`
+                    let matte = if sprite.ink() == Pen::PatCopy {
+                        members.member_matte(channel, sprite)
+                    } else {
+                        None
+                    };
+                    draw_bitmap(&mut self.port, image, matte.as_deref(), sprite, &rect, back_color);
+                },
+                None => draw_sprite(&mut self.port, channel, sprite, rect.rect()),
+            }
+        }
+
+        self.port.set_clip(old_clip);
+        dirty
+    }
+}
+
+/// Draws a bitmap sprite, scaling the image to the sprite rectangle using
+/// nearest-neighbour sampling and mirroring it if the sprite is flipped. The
+/// matte of the image is given for sprites drawn with the matte ink.
+fn draw_bitmap(port: &mut CGrafPort, image: &PixMap, matte: Option<&Matte>, sprite: &Sprite, rect: &SpriteRect, back_color: RGBColor) {
+    let flipped;
+    let image = if rect.flip_h() || rect.flip_v() {
+        flipped = flip(image, rect.flip_h(), rect.flip_v());
+        &flipped
+    } else {
+        image
+    };
+
+    let bounds = image.bounds();
+    let (mode, mask) = match sprite.ink() {
+        Pen::PatCopy => (
+            Pen::SrcCopy as u16,
+            matte.map(|matte| matte.to_region(rect.flip_h(), rect.flip_v()).map(bounds, rect.rect())),
+        ),
+        ink => (ink as u16, None),
+    };
+
+    port.rgb_back_color(back_color);
+    port.std_bits(image, bounds, rect.rect(), mode, mask.as_ref());
+    port.rgb_back_color(RGBColor::WHITE);
+}
+
+/// Returns a copy of the image mirrored horizontally and/or vertically.
+fn flip(image: &PixMap, flip_h: bool, flip_v: bool) -> PixMap {
+    let bounds = image.bounds();
+    let mut flipped = PixMap::new(bounds, RGBColor::WHITE);
+    for y in bounds.top..bounds.bottom {
+        for x in bounds.left..bounds.right {
+            let src_x = if flip_h { bounds.right - 1 - (x - bounds.left) } else { x };
+            let src_y = if flip_v { bounds.bottom - 1 - (y - bounds.top) } else { y };
+            if let Some(color) = image.pixel(src_x, src_y) {
+                flipped.set_pixel(x, y, color);
+            }
+        }
+    }
+    flipped
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::Resource;
+    use std::io::Cursor;
+    use super::*;
+    use super::super::score::{SpriteLineSize, Version};
+
+    const RED: RGBColor = RGBColor { r: 0xffff, g: 0, b: 0 };
+
+    struct Members {
+        /// A 2×1 image which is black on the left and red on the right.
+        image: PixMap,
+        /// A 2×1 image which is white on the left and black on the right.
+        half: PixMap,
+    }
+
+    impl Members {
+        fn new() -> Self {
+            let mut image = PixMap::new(Rect { top: 0, left: 0, bottom: 1, right: 2 }, RED);
+            image.set_pixel(0, 0, RGBColor::BLACK);
+            let mut half = PixMap::new(Rect { top: 0, left: 0, bottom: 1, right: 2 }, RGBColor::WHITE);
+            half.set_pixel(1, 0, RGBColor::BLACK);
+            Self { image, half }
+        }
+
+        fn image(&self, sprite: &Sprite) -> Option<&PixMap> {
+            match sprite.id().num().0 {
+                1 => Some(&self.image),
+                2 => Some(&self.half),
+                _ => None,
+            }
+        }
+    }
+
+    impl SpriteMembers for Members {
+        fn member_bounds(&self, _: ChannelNum, sprite: &Sprite) -> Option<Rect> {
+            self.image(sprite).map(PixMap::bounds)
+        }
+
+        fn member_image(&self, _: ChannelNum, sprite: &Sprite) -> Option<&PixMap> {
+            self.image(sprite)
+        }
+    }
+
+    fn sprite(member: u8, ink: u8, x: i16, width: i16) -> Sprite {
+        flipped_sprite(member, ink, x, width, 0)
+    }
+
+    fn flipped_sprite(member: u8, ink: u8, x: i16, width: i16, flags: u8) -> Sprite {
+        let x = x.to_be_bytes();
+        let width = width.to_be_bytes();
+        let data = [
+            1, ink, 0, 1, 0, member, 0, 0, 0, 0,
+            0, 0, x[0], x[1], 0, 0, 0, 1, width[0], width[1],
+            0, 0, flags, 0,
+        ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        Sprite::load(&mut input, data.len() as u32, &(Version::V5, )).unwrap()
+    }
+
+    fn row(stage: &Stage, y: i16, width: i16) -> Vec<RGBColor> {
+        let pix_map = stage.port().pix_map().borrow();
+        (0..width).map(|x| pix_map.pixel(x, y).unwrap()).collect()
+    }
+
+    fn no_other_sprites(_: &mut CGrafPort, _: ChannelNum, _: &Sprite, _: Rect) {
+        panic!("Unexpected non-bitmap sprite");
+    }
+
+    #[test]
+    fn stretch_and_flip() {
+        const W: RGBColor = RGBColor::WHITE;
+        const B: RGBColor = RGBColor::BLACK;
+        let members = Members::new();
+        let hidden = SpriteBitmask::empty();
+        let mut stage = Stage::new(Rect { top: 0, left: 0, bottom: 1, right: 6 }, W);
+        let mut frame = Frame::default();
+
+        frame.sprites[0] = sprite(1, 0, 1, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ W, B, RED, W, W, W ]);
+
+        frame.sprites[0] = sprite(1, 0x80, 1, 4);
+        let dirty = stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(dirty.bounds(), Rect { top: 0, left: 1, bottom: 1, right: 5 });
+        assert_eq!(row(&stage, 0, 6), vec![ W, B, B, RED, RED, W ]);
+
+        frame.sprites[0] = sprite(1, 0x80, 1, -4);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ W, RED, RED, B, B, W ]);
+
+        frame.sprites[0] = flipped_sprite(1, 0, 1, 2, SpriteLineSize::FLIP_H.bits());
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ W, RED, B, W, W, W ]);
+    }
+
+    #[test]
+    fn trails() {
+        const W: RGBColor = RGBColor::WHITE;
+        const B: RGBColor = RGBColor::BLACK;
+        let members = Members::new();
+        let hidden = SpriteBitmask::empty();
+        let mut stage = Stage::new(Rect { top: 0, left: 0, bottom: 1, right: 6 }, W);
+        let mut frame = Frame::default();
+
+        frame.sprites[0] = sprite(1, 0x40, 0, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        frame.sprites[0] = sprite(1, 0x40, 3, 2);
+        let dirty = stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(dirty.bounds(), Rect { top: 0, left: 3, bottom: 1, right: 5 });
+        assert_eq!(row(&stage, 0, 6), vec![ B, RED, W, B, RED, W ]);
+
+        // Turning off trails only affects where the sprite moves next
+        frame.sprites[0] = sprite(1, 0, 4, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ B, RED, W, B, B, RED ]);
+        frame.sprites[0] = sprite(1, 0, 0, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ B, RED, W, B, W, W ]);
+
+        // Nothing changed, so nothing is repainted
+        assert!(stage.update(&frame, &hidden, &members, no_other_sprites).is_empty());
+    }
+
+    #[test]
+    fn trails_under_transparent_sprites() {
+        const W: RGBColor = RGBColor::WHITE;
+        const B: RGBColor = RGBColor::BLACK;
+        let members = Members::new();
+        let hidden = SpriteBitmask::empty();
+        let mut stage = Stage::new(Rect { top: 0, left: 0, bottom: 1, right: 6 }, W);
+        let mut frame = Frame::default();
+        let ink = Pen::Transparent as u8 | 0x40;
+
+        frame.sprites[0] = sprite(2, ink, 0, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ W, B, W, W, W, W ]);
+
+        // The old copy shows through the transparent part of the new one
+        frame.sprites[0] = sprite(2, ink, 1, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ W, B, B, W, W, W ]);
+
+        // A sprite without trails which moves away erases what it covered
+        frame.sprites[0] = sprite(1, 0, 1, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        frame.sprites[0] = sprite(1, 0, 4, 2);
+        stage.update(&frame, &hidden, &members, no_other_sprites);
+        assert_eq!(row(&stage, 0, 6), vec![ W, W, W, W, B, RED ]);
+    }
+}
@@ -0,0 +1,349 @@
+//! Editing the text of field sprites.
+//!
+//! One text sprite at a time has keyboard focus. Its text is edited by a
+//! `TextEdit` record until focus moves to another sprite, at which point the
+//! edited text must be written back to the field cast member.
+
+use anyhow::Result as AResult;
+use crate::resources::{cast::MemberId, field::Flags as FieldFlags};
+use libcommon::encodings::{Decoder, Encoder, Unmappable};
+use libmactoolbox::{EventKind, EventModifiers, EventRecord, Point, Rect, TEHandle};
+use std::convert::TryFrom;
+use super::{hit_test::SpriteMembers, score::{ChannelNum, Frame, SpriteBitmask, SpriteKind}};
+
+/// What happened to a key which was sent to the text editor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyOutcome {
+    /// No text sprite has keyboard focus, or the key is a command key which
+    /// the editor does not use.
+    NotHandled,
+    /// The key changed the text or the selection.
+    Edited,
+    /// The key was tab, so keyboard focus should move to the next tabbable
+    /// sprite, or to the previous one if `backward` is set.
+    Tab { backward: bool },
+}
+
+/// The editor of the text sprite which has keyboard focus.
+#[derive(Clone, Debug, Default)]
+pub struct TextEditor {
+    te: Option<TEHandle>,
+    rect: Rect,
+    sprite_num: ChannelNum,
+    id: MemberId,
+}
+
+impl TextEditor {
+    /// The character code of the tab key.
+    const TAB: char = '\t';
+
+    /// Gives keyboard focus to the text sprite in the given channel, which
+    /// uses the given field cast member. Returns the cast member and edited
+    /// text of the sprite which previously had focus.
+    pub fn begin(&mut self, channel: ChannelNum, id: MemberId, mut te: TEHandle) -> Option<(MemberId, Vec<u8>)> {
+        let previous = self.end();
+        te.activate();
+        self.rect = te.view_rect();
+        self.sprite_num = channel;
+        self.id = id;
+        self.te = Some(te);
+        previous
+    }
+
+    /// The channel of the text sprite which has keyboard focus.
+    #[must_use]
+    pub fn channel(&self) -> Option<ChannelNum> {
+        self.te.as_ref().map(|_| self.sprite_num)
+    }
+
+    /// Moves the insertion point to the given point, or extends the
+    /// selection to it if the shift key is down. Returns `false` if the point
+    /// is outside the text sprite which has focus.
+    pub fn click(&mut self, point: Point, modifiers: EventModifiers) -> bool {
+        match &mut self.te {
+            Some(te) if self.rect.contains(point) => {
+                te.click(point, modifiers.contains(EventModifiers::SHIFT_KEY));
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Removes keyboard focus from the text sprite. Returns its cast member
+    /// and edited text.
+    pub fn end(&mut self) -> Option<(MemberId, Vec<u8>)> {
+        let id = self.id;
+        self.te.take().map(|te| (id, te.text().to_vec()))
+    }
+
+    /// Blinks the caret.
+    pub fn idle(&mut self) {
+        if let Some(te) = &mut self.te {
+            te.idle();
+        }
+    }
+
+    /// Handles a key typed by the user, inserting it into the text using the
+    /// encoder of the movie. Command-X, Command-C, and Command-V cut, copy,
+    /// and paste using the given scrap, and Command-A selects all the text.
+    pub fn key(&mut self, char_code: char, modifiers: EventModifiers, scrap: &mut Vec<u8>, encoder: &dyn Encoder) -> AResult<KeyOutcome> {
+        let te = match &mut self.te {
+            Some(te) => te,
+            None => return Ok(KeyOutcome::NotHandled),
+        };
+
+        if char_code == Self::TAB {
+            return Ok(KeyOutcome::Tab { backward: modifiers.contains(EventModifiers::SHIFT_KEY) });
+        }
+
+        if modifiers.contains(EventModifiers::COMMAND_KEY) {
+            match char_code.to_ascii_lowercase() {
+                'a' => te.set_select(0, te.text().len()),
+                'c' => te.copy(scrap),
+                'v' => te.paste(scrap),
+                'x' => te.cut(scrap),
+                _ => return Ok(KeyOutcome::NotHandled),
+            }
+        } else {
+            for byte in encoder.encode(char_code.encode_utf8(&mut [ 0; 4 ]), Unmappable::Ignore)? {
+                te.key(byte);
+            }
+        }
+
+        Ok(KeyOutcome::Edited)
+    }
+
+    /// Handles a key-down event from the event manager.
+    pub fn key_event(&mut self, event: &EventRecord, scrap: &mut Vec<u8>, encoder: &dyn Encoder) -> AResult<KeyOutcome> {
+        match event.kind() {
+            EventKind::KeyDown | EventKind::AutoKey => match event.char_code() {
+                Some(char_code) => self.key(char_code, event.modifiers(), scrap, encoder),
+                None => Ok(KeyOutcome::NotHandled),
+            },
+            _ => Ok(KeyOutcome::NotHandled),
+        }
+    }
+
+    /// The distance from the top of the text to the top of the sprite.
+    #[must_use]
+    pub fn scroll_top(&self) -> i16 {
+        self.te.as_ref().map_or(0, TEHandle::scroll_top)
+    }
+
+    /// The offset of the end of the selection, like Lingo `the selEnd`.
+    #[must_use]
+    pub fn sel_end(&self) -> i32 {
+        self.te.as_ref().map_or(0, |te| te.sel_end() as i32)
+    }
+
+    /// The offset of the start of the selection, like Lingo `the selStart`.
+    #[must_use]
+    pub fn sel_start(&self) -> i32 {
+        self.te.as_ref().map_or(0, |te| te.sel_start() as i32)
+    }
+
+    /// The selected text, like Lingo `the selection`, decoded using the
+    /// decoder of the movie.
+    #[must_use]
+    pub fn selection(&self, decoder: &dyn Decoder) -> String {
+        self.te.as_ref().map_or_else(String::new, |te| decoder.decode(te.selection()))
+    }
+
+    /// Moves the end of the selection, like Lingo `set the selEnd`. The start
+    /// of the selection moves too if it would be after the end.
+    pub fn set_sel_end(&mut self, end: i32) {
+        if let Some(te) = &mut self.te {
+            let end = usize::try_from(end).unwrap_or(0);
+            te.set_select(te.sel_start().min(end), end);
+        }
+    }
+
+    /// Moves the start of the selection, like Lingo `set the selStart`. The
+    /// end of the selection moves too if it would be before the start.
+    pub fn set_sel_start(&mut self, start: i32) {
+        if let Some(te) = &mut self.te {
+            let start = usize::try_from(start).unwrap_or(0);
+            te.set_select(start, te.sel_end().max(start));
+        }
+    }
+
+    #[must_use]
+    pub fn te(&self) -> Option<&TEHandle> {
+        self.te.as_ref()
+    }
+
+    #[must_use]
+    pub fn te_mut(&mut self) -> Option<&mut TEHandle> {
+        self.te.as_mut()
+    }
+}
+
+/// Returns whether the user can edit the text of the sprite in the given
+/// channel, either because of its `editableText` sprite property or because
+/// its field cast member is editable.
+#[must_use]
+pub fn is_editable(frame: &Frame, editable_sprites: &SpriteBitmask, members: &impl SpriteMembers, channel: ChannelNum) -> bool {
+    let sprite = match usize::try_from(channel.0 - 1).ok().and_then(|index| frame.sprites.get(index)) {
+        Some(sprite) if sprite.kind() != SpriteKind::None => sprite,
+        _ => return false,
+    };
+
+    match members.field_flags(channel, sprite) {
+        Some(flags) => editable_sprites.contains_sprite(channel) || flags.contains(FieldFlags::EDITABLE),
+        None => false,
+    }
+}
+
+/// Returns the editable sprite with a tabbable field which receives keyboard
+/// focus when the user presses tab in the sprite in the given channel. Tab
+/// moves through sprites in channel order, wrapping around at the end.
+#[must_use]
+pub fn next_tab_sprite(
+    frame: &Frame,
+    editable_sprites: &SpriteBitmask,
+    members: &impl SpriteMembers,
+    current: Option<ChannelNum>,
+    backward: bool,
+) -> Option<ChannelNum> {
+    let tabbable = frame.sprites.iter().enumerate().filter_map(|(index, sprite)| {
+        let channel = ChannelNum(index as i16 + 1);
+        let is_tabbable = members.field_flags(channel, sprite).map_or(false, |flags| flags.contains(FieldFlags::TABBABLE));
+        if is_tabbable && is_editable(frame, editable_sprites, members, channel) {
+            Some(channel)
+        } else {
+            None
+        }
+    }).collect::<Vec<_>>();
+
+    if backward {
+        let current = current.unwrap_or(ChannelNum(i16::MAX));
+        tabbable.iter().rev().find(|&&channel| channel < current).or_else(|| tabbable.last()).copied()
+    } else {
+        let current = current.unwrap_or(ChannelNum(0));
+        tabbable.iter().find(|&&channel| channel > current).or_else(|| tabbable.first()).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use libcommon::{encodings::{MAC_GREEK, MAC_ROMAN}, Resource};
+    use libmactoolbox::quickdraw::{BitmapFont, Font, PixMap, Style};
+    use std::{io::Cursor, rc::Rc};
+    use super::*;
+    use super::super::score::{Sprite, Version};
+
+    struct Members;
+
+    impl SpriteMembers for Members {
+        fn member_bounds(&self, _: ChannelNum, _: &Sprite) -> Option<Rect> {
+            None
+        }
+
+        fn member_image(&self, _: ChannelNum, _: &Sprite) -> Option<&PixMap> {
+            None
+        }
+
+        /// The member number of each sprite is its field flags.
+        fn field_flags(&self, _: ChannelNum, sprite: &Sprite) -> Option<FieldFlags> {
+            FieldFlags::from_bits(sprite.id().num().0 as u8)
+        }
+    }
+
+    /// Builds a font with no glyph images where every character is 6 pixels
+    /// wide.
+    fn font() -> Font {
+        let mut data = Vec::new();
+        for value in &[ 0x9000_u16, 0x20, 0x7e, 6, 0, 0xfffe, 6, 0, 0, 8, 2, 2, 0 ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        for value in [ 0_u16; 97 ].iter().chain(&[ 6; 97 ]) {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut input = ByteOrdered::new(Cursor::new(&data), Endianness::Big);
+        let strike = BitmapFont::load(&mut input, data.len() as u32, &()).unwrap();
+        Font::new(Rc::new(strike), Style::empty())
+    }
+
+    fn sprite(flags: FieldFlags) -> Sprite {
+        let data = [
+            7, 0, 0, 1, 0, flags.bits(), 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 20, 0, 100,
+            0, 0, 0, 0,
+        ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        Sprite::load(&mut input, data.len() as u32, &(Version::V5, )).unwrap()
+    }
+
+    #[test]
+    fn tab_order() {
+        let mut frame = Frame::default();
+        frame.sprites[0] = sprite(FieldFlags::EDITABLE | FieldFlags::TABBABLE);
+        frame.sprites[1] = sprite(FieldFlags::TABBABLE);
+        frame.sprites[2] = sprite(FieldFlags::EDITABLE);
+        frame.sprites[3] = sprite(FieldFlags::EDITABLE | FieldFlags::TABBABLE);
+        let mut editable = SpriteBitmask::empty();
+
+        assert!(is_editable(&frame, &editable, &Members, ChannelNum(3)));
+        assert!(!is_editable(&frame, &editable, &Members, ChannelNum(2)));
+        assert!(!is_editable(&frame, &editable, &Members, ChannelNum(5)));
+        assert_eq!(next_tab_sprite(&frame, &editable, &Members, None, false), Some(ChannelNum(1)));
+        assert_eq!(next_tab_sprite(&frame, &editable, &Members, Some(ChannelNum(1)), false), Some(ChannelNum(4)));
+        assert_eq!(next_tab_sprite(&frame, &editable, &Members, Some(ChannelNum(4)), false), Some(ChannelNum(1)));
+        assert_eq!(next_tab_sprite(&frame, &editable, &Members, Some(ChannelNum(1)), true), Some(ChannelNum(4)));
+
+        editable.set_sprite(ChannelNum(2));
+        assert_eq!(next_tab_sprite(&frame, &editable, &Members, Some(ChannelNum(1)), false), Some(ChannelNum(2)));
+    }
+
+    #[test]
+    fn editing() {
+        let rect = Rect { top: 0, left: 0, bottom: 20, right: 100 };
+        let mut te = TEHandle::new(rect, rect, font());
+        te.set_text(b"ab");
+
+        let mut editor = TextEditor::default();
+        let mut scrap = Vec::new();
+        assert_eq!(editor.key('x', EventModifiers::empty(), &mut scrap, MAC_ROMAN).unwrap(), KeyOutcome::NotHandled);
+        assert!(editor.begin(ChannelNum(3), MemberId::new(1, 5), te).is_none());
+        assert_eq!(editor.channel(), Some(ChannelNum(3)));
+
+        assert_eq!(editor.key('é', EventModifiers::empty(), &mut scrap, MAC_ROMAN).unwrap(), KeyOutcome::Edited);
+        assert_eq!(editor.te().unwrap().text(), b"ab\x8e");
+        assert_eq!(editor.key('\t', EventModifiers::SHIFT_KEY, &mut scrap, MAC_ROMAN).unwrap(), KeyOutcome::Tab { backward: true });
+        assert_eq!(editor.key('q', EventModifiers::COMMAND_KEY, &mut scrap, MAC_ROMAN).unwrap(), KeyOutcome::NotHandled);
+
+        editor.set_sel_start(1);
+        assert_eq!((editor.sel_start(), editor.sel_end()), (1, 3));
+        assert_eq!(editor.selection(MAC_ROMAN), "bé");
+        editor.key('c', EventModifiers::COMMAND_KEY, &mut scrap, MAC_ROMAN).unwrap();
+        editor.set_sel_end(0);
+        assert_eq!((editor.sel_start(), editor.sel_end()), (0, 0));
+        editor.key('v', EventModifiers::COMMAND_KEY, &mut scrap, MAC_ROMAN).unwrap();
+        assert_eq!(editor.te().unwrap().text(), b"b\x8eab\x8e");
+
+        assert!(editor.click(Point { x: 7, y: 5 }, EventModifiers::empty()));
+        assert_eq!(editor.sel_start(), 1);
+        assert!(editor.click(Point { x: 19, y: 5 }, EventModifiers::SHIFT_KEY));
+        assert_eq!(editor.selection(MAC_ROMAN), "éa");
+        assert!(!editor.click(Point { x: 200, y: 5 }, EventModifiers::empty()));
+
+        assert_eq!(editor.end(), Some((MemberId::new(1, 5), b"b\x8eab\x8e".to_vec())));
+        assert_eq!(editor.channel(), None);
+    }
+
+    #[test]
+    fn movie_encoding() {
+        let rect = Rect { top: 0, left: 0, bottom: 20, right: 100 };
+        let mut editor = TextEditor::default();
+        let mut scrap = Vec::new();
+        editor.begin(ChannelNum(1), MemberId::new(1, 1), TEHandle::new(rect, rect, font()));
+
+        assert_eq!(editor.key('Ω', EventModifiers::empty(), &mut scrap, MAC_GREEK).unwrap(), KeyOutcome::Edited);
+        assert_eq!(editor.te().unwrap().text(), b"\xbf");
+        editor.key('a', EventModifiers::COMMAND_KEY, &mut scrap, MAC_GREEK).unwrap();
+        assert_eq!(editor.selection(MAC_GREEK), "Ω");
+        assert_eq!(editor.selection(MAC_ROMAN), "ø");
+    }
+}
@@ -0,0 +1,471 @@
+use crate::resources::transition::{Flags, Kind, Milliseconds, StandardMeta};
+use libmactoolbox::{Point, quickdraw::PixMap, Rect};
+use std::{convert::TryFrom, time::Duration};
+
+/// The size of each slat of the blinds transitions.
+const BLIND_SIZE: i16 = 16;
+/// The size of each square of the checkerboard transition.
+const CHECKER_SIZE: i16 = 16;
+/// The number of steps of the slower dissolve transitions.
+const DISSOLVE_STEPS: u32 = 64;
+/// The number of steps of the fast dissolve transitions.
+const DISSOLVE_FAST_STEPS: u32 = 16;
+/// The number of steps of the pattern dissolve, which is one step for each
+/// entry of the dither matrix.
+const DITHER_STEPS: u32 = 64;
+/// The ordered dither matrix used by the pattern dissolve.
+const DITHER_MATRIX: [ [ u8; 8 ]; 8 ] = [
+    [  0, 32,  8, 40,  2, 34, 10, 42 ],
+    [ 48, 16, 56, 24, 50, 18, 58, 26 ],
+    [ 12, 44,  4, 36, 14, 46,  6, 38 ],
+    [ 60, 28, 52, 20, 62, 30, 54, 22 ],
+    [  3, 35, 11, 43,  1, 33,  9, 41 ],
+    [ 51, 19, 59, 27, 49, 17, 57, 25 ],
+    [ 15, 47,  7, 39, 13, 45,  5, 37 ],
+    [ 63, 31, 55, 23, 61, 29, 53, 21 ],
+];
+/// The seed of the random order of the random transitions. It is fixed so
+/// that transitions are always drawn the same way.
+const RANDOM_SEED: u32 = 0x2545_f491;
+
+/// The parameters of a transition.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Params {
+    pub kind: Kind,
+    /// The number of pixels which change in each step of the transition.
+    pub chunk_size: u8,
+    pub duration: Milliseconds,
+    /// Whether the transition applies to the whole stage instead of only the
+    /// area which changed between frames.
+    pub entire_stage: bool,
+}
+
+impl From<&StandardMeta> for Params {
+    fn from(meta: &StandardMeta) -> Self {
+        Self {
+            kind: meta.kind(),
+            chunk_size: meta.chunk_size(),
+            duration: meta.duration(),
+            entire_stage: meta.flags().contains(Flags::ENTIRE_STAGE),
+        }
+    }
+}
+
+/// Where the colour of a pixel comes from during a transition.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Source {
+    From(i16, i16),
+    To(i16, i16),
+}
+
+/// A transition between two images of the stage.
+///
+/// A transition is split into a fixed number of steps. Each step is drawn
+/// from only the two images and the step number, so drawing is
+/// deterministic and any step can be drawn at any time.
+#[derive(Clone, Debug)]
+pub struct Transition {
+    params: Params,
+    rect: Rect,
+    step_count: u32,
+    /// The size of each cell of the random transitions.
+    cell_size: Point,
+    /// The step at which each cell of the random transitions changes, in
+    /// rows from top to bottom.
+    cell_steps: Vec<u32>,
+}
+
+impl Transition {
+    /// Creates a transition for a stage with the given bounds. `changed_rect`
+    /// is the area of the stage which changed between the two frames, which
+    /// is the only area that is transitioned unless the transition applies
+    /// to the entire stage.
+    #[must_use]
+    pub fn new(params: Params, stage_rect: Rect, changed_rect: Rect) -> Self {
+        let rect = if params.entire_stage {
+            stage_rect
+        } else {
+            stage_rect.intersect(changed_rect)
+        };
+
+        let chunk_size = i16::from(params.chunk_size.max(1));
+        let (width, height) = if rect.is_empty() {
+            (0, 0)
+        } else {
+            (rect.width(), rect.height())
+        };
+        let steps = |size: i16| u32::try_from((size + chunk_size - 1) / chunk_size).unwrap_or(0).max(1);
+
+        let mut cell_size = Point { x: 1, y: 1 };
+        let mut random_cells = None;
+        let step_count = match params.kind {
+            Kind::Xtra => 1,
+            Kind::WipeRight | Kind::WipeLeft | Kind::PushLeft | Kind::PushRight
+            | Kind::RevealRight | Kind::RevealLeft | Kind::CoverLeft | Kind::CoverRight => steps(width),
+            Kind::WipeDown | Kind::WipeUp | Kind::PushDown | Kind::PushUp
+            | Kind::RevealUp | Kind::RevealDown | Kind::CoverDown | Kind::CoverUp => steps(height),
+            Kind::RevealUpRight | Kind::RevealDownRight | Kind::RevealDownLeft | Kind::RevealUpLeft
+            | Kind::CoverDownLeft | Kind::CoverDownRight | Kind::CoverUpLeft | Kind::CoverUpRight => steps(width.max(height)),
+            Kind::CenterOutHoriz | Kind::EdgesInHoriz => steps((width + 1) / 2),
+            Kind::CenterOutVert | Kind::EdgesInVert => steps((height + 1) / 2),
+            Kind::CenterOutSquare | Kind::ZoomOpen | Kind::ZoomClose => steps((width.max(height) + 1) / 2),
+            Kind::EdgesInSquare => steps((width.min(height) + 1) / 2),
+            Kind::VenetianBlinds | Kind::VerticalBlinds => steps(BLIND_SIZE),
+            Kind::Checkerboard => steps(CHECKER_SIZE) * 2,
+            Kind::StripsBottomBuildLeft | Kind::StripsBottomBuildRight
+            | Kind::StripsLeftBuildDown | Kind::StripsLeftBuildUp
+            | Kind::StripsRightBuildDown | Kind::StripsRightBuildUp
+            | Kind::StripsTopBuildLeft | Kind::StripsTopBuildRight => steps(width) + steps(height) - 1,
+            Kind::DissolvePatterns => DITHER_STEPS,
+            Kind::DissolvePixelsFast | Kind::DissolveBitsFast => {
+                random_cells = Some(DISSOLVE_FAST_STEPS);
+                DISSOLVE_FAST_STEPS
+            },
+            Kind::DissolvePixels | Kind::DissolveBits => {
+                random_cells = Some(DISSOLVE_STEPS);
+                DISSOLVE_STEPS
+            },
+            Kind::DissolveBoxyRects => {
+                cell_size = Point { x: chunk_size * 2, y: chunk_size };
+                random_cells = Some(DISSOLVE_STEPS);
+                DISSOLVE_STEPS
+            },
+            Kind::DissolveBoxySquares => {
+                cell_size = Point { x: chunk_size, y: chunk_size };
+                random_cells = Some(DISSOLVE_STEPS);
+                DISSOLVE_STEPS
+            },
+            Kind::RandomRows => {
+                cell_size = Point { x: width.max(1), y: chunk_size };
+                random_cells = Some(steps(height));
+                steps(height)
+            },
+            Kind::RandomColumns => {
+                cell_size = Point { x: chunk_size, y: height.max(1) };
+                random_cells = Some(steps(width));
+                steps(width)
+            },
+        };
+
+        let cell_steps = random_cells.map_or_else(Vec::new, |step_count| {
+            let columns = (i32::from(width) + i32::from(cell_size.x) - 1) / i32::from(cell_size.x);
+            let rows = (i32::from(height) + i32::from(cell_size.y) - 1) / i32::from(cell_size.y);
+            random_steps((columns * rows) as usize, step_count)
+        });
+
+        Self {
+            params,
+            rect,
+            step_count,
+            cell_size,
+            cell_steps,
+        }
+    }
+
+    /// Returns whether the transition has finished after the given amount
+    /// of time.
+    #[must_use]
+    pub fn is_done(&self, elapsed: Duration) -> bool {
+        self.step_at(elapsed) == self.step_count
+    }
+
+    #[must_use]
+    pub fn params(&self) -> Params {
+        self.params
+    }
+
+    /// The area of the stage which is transitioned.
+    #[must_use]
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Returns the step which should be drawn after the given amount of time
+    /// since the start of the transition.
+    #[must_use]
+    pub fn step_at(&self, elapsed: Duration) -> u32 {
+        // Transitions without a duration finish immediately
+        let duration = u128::try_from(self.params.duration.0).unwrap_or(0);
+        (elapsed.as_millis() * u128::from(self.step_count)).checked_div(duration)
+            .map_or(self.step_count, |step| u32::try_from(step).unwrap_or(u32::MAX).min(self.step_count))
+    }
+
+    /// The number of steps in the transition. Drawing step 0 draws only the
+    /// old image and drawing the last step draws only the new image.
+    #[must_use]
+    pub fn step_count(&self) -> u32 {
+        self.step_count
+    }
+
+    /// Draws the given step of the transition from the image `from` to the
+    /// image `to` into `dst`. All images must have the same bounds as the
+    /// stage. Pixels outside the transitioned area are copied from `to`.
+    pub fn draw(&self, step: u32, from: &PixMap, to: &PixMap, dst: &mut PixMap) {
+        let step = step.min(self.step_count);
+        let bounds = dst.bounds();
+        for y in bounds.top..bounds.bottom {
+            for x in bounds.left..bounds.right {
+                let source = if self.rect.contains(Point { x, y }) {
+                    self.source(step, x, y)
+                } else {
+                    Source::To(x, y)
+                };
+
+                let color = match source {
+                    Source::From(x, y) => from.pixel(x, y),
+                    Source::To(x, y) => to.pixel(x, y),
+                };
+
+                if let Some(color) = color {
+                    dst.set_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Returns where the pixel at the given stage coordinate comes from in
+    /// the given step.
+    fn source(&self, step: u32, x: i16, y: i16) -> Source {
+        let rect = self.rect;
+        let chunk_size = i32::from(self.params.chunk_size.max(1));
+        let (width, height) = (i32::from(rect.width()), i32::from(rect.height()));
+        // Distances from each edge of the transitioned area
+        let left = i32::from(x) - i32::from(rect.left);
+        let top = i32::from(y) - i32::from(rect.top);
+        let right = width - 1 - left;
+        let bottom = height - 1 - top;
+        let center_x = (2 * left + 1 - width).abs() / 2;
+        let center_y = (2 * top + 1 - height).abs() / 2;
+
+        // The step after which the pixel shows the new image
+        let threshold = |distance: i32| u32::try_from(distance / chunk_size).unwrap_or(0);
+        let strip = |index: i32, along: i32| threshold(index) + threshold(along);
+
+        let pixel_step = match self.params.kind {
+            Kind::Xtra => 0,
+            Kind::WipeRight => threshold(left),
+            Kind::WipeLeft => threshold(right),
+            Kind::WipeDown => threshold(top),
+            Kind::WipeUp => threshold(bottom),
+            Kind::CenterOutHoriz => threshold(center_x),
+            Kind::EdgesInHoriz => threshold(left.min(right)),
+            Kind::CenterOutVert => threshold(center_y),
+            Kind::EdgesInVert => threshold(top.min(bottom)),
+            Kind::CenterOutSquare => threshold(center_x.max(center_y)),
+            Kind::EdgesInSquare => threshold(left.min(right).min(top).min(bottom)),
+            Kind::ZoomOpen | Kind::ZoomClose => {
+                let steps = i64::from(self.step_count);
+                let scaled = |distance: i32, size: i32| i64::from(distance) * 2 * steps / i64::from(size).max(1);
+                let distance = scaled(center_x, width).max(scaled(center_y, height)).min(steps - 1);
+                let distance = if self.params.kind == Kind::ZoomOpen {
+                    distance
+                } else {
+                    steps - 1 - distance
+                };
+                u32::try_from(distance).unwrap_or(0)
+            },
+            Kind::VenetianBlinds => threshold(top % i32::from(BLIND_SIZE)),
+            Kind::VerticalBlinds => threshold(left % i32::from(BLIND_SIZE)),
+            Kind::Checkerboard => {
+                let size = i32::from(CHECKER_SIZE);
+                let parity = ((left / size + top / size) % 2) as u32;
+                parity * (self.step_count / 2) + threshold(top % size)
+            },
+            Kind::StripsBottomBuildLeft => strip(right, bottom),
+            Kind::StripsBottomBuildRight => strip(left, bottom),
+            Kind::StripsTopBuildLeft => strip(right, top),
+            Kind::StripsTopBuildRight => strip(left, top),
+            Kind::StripsLeftBuildDown => strip(top, left),
+            Kind::StripsLeftBuildUp => strip(bottom, left),
+            Kind::StripsRightBuildDown => strip(top, right),
+            Kind::StripsRightBuildUp => strip(bottom, right),
+            Kind::DissolvePatterns => {
+                u32::from(DITHER_MATRIX[(top & 7) as usize][(left & 7) as usize])
+            },
+            Kind::DissolvePixelsFast | Kind::DissolveBitsFast | Kind::DissolvePixels | Kind::DissolveBits
+            | Kind::DissolveBoxyRects | Kind::DissolveBoxySquares | Kind::RandomRows | Kind::RandomColumns => {
+                let (cell_width, cell_height) = (i32::from(self.cell_size.x), i32::from(self.cell_size.y));
+                let columns = (width + cell_width - 1) / cell_width;
+                let index = (top / cell_height) * columns + left / cell_width;
+                self.cell_steps[index as usize]
+            },
+            Kind::PushLeft => return self.slide(step, x, y, (-1, 0), Slide::Push),
+            Kind::PushRight => return self.slide(step, x, y, (1, 0), Slide::Push),
+            Kind::PushDown => return self.slide(step, x, y, (0, 1), Slide::Push),
+            Kind::PushUp => return self.slide(step, x, y, (0, -1), Slide::Push),
+            Kind::RevealUp => return self.slide(step, x, y, (0, -1), Slide::Reveal),
+            Kind::RevealUpRight => return self.slide(step, x, y, (1, -1), Slide::Reveal),
+            Kind::RevealRight => return self.slide(step, x, y, (1, 0), Slide::Reveal),
+            Kind::RevealDownRight => return self.slide(step, x, y, (1, 1), Slide::Reveal),
+            Kind::RevealDown => return self.slide(step, x, y, (0, 1), Slide::Reveal),
+            Kind::RevealDownLeft => return self.slide(step, x, y, (-1, 1), Slide::Reveal),
+            Kind::RevealLeft => return self.slide(step, x, y, (-1, 0), Slide::Reveal),
+            Kind::RevealUpLeft => return self.slide(step, x, y, (-1, -1), Slide::Reveal),
+            Kind::CoverDown => return self.slide(step, x, y, (0, 1), Slide::Cover),
+            Kind::CoverDownLeft => return self.slide(step, x, y, (-1, 1), Slide::Cover),
+            Kind::CoverDownRight => return self.slide(step, x, y, (1, 1), Slide::Cover),
+            Kind::CoverLeft => return self.slide(step, x, y, (-1, 0), Slide::Cover),
+            Kind::CoverRight => return self.slide(step, x, y, (1, 0), Slide::Cover),
+            Kind::CoverUp => return self.slide(step, x, y, (0, -1), Slide::Cover),
+            Kind::CoverUpLeft => return self.slide(step, x, y, (-1, -1), Slide::Cover),
+            Kind::CoverUpRight => return self.slide(step, x, y, (1, -1), Slide::Cover),
+        };
+
+        if pixel_step < step {
+            Source::To(x, y)
+        } else {
+            Source::From(x, y)
+        }
+    }
+
+    /// Returns where a pixel comes from in a transition where one or both
+    /// images move in the given direction.
+    fn slide(&self, step: u32, x: i16, y: i16, (dx, dy): (i32, i32), slide: Slide) -> Source {
+        let rect = self.rect;
+        let (width, height) = (i64::from(rect.width()), i64::from(rect.height()));
+        let offset_x = (width * i64::from(step) / i64::from(self.step_count)) as i32;
+        let offset_y = (height * i64::from(step) / i64::from(self.step_count)) as i32;
+        let (x, y) = (i32::from(x), i32::from(y));
+
+        // The old image moves by the offset, and the new image moves in
+        // behind it from the opposite edge
+        let old = (x - dx * offset_x, y - dy * offset_y);
+        let new = (x + dx * (width as i32 - offset_x), y + dy * (height as i32 - offset_y));
+        let contains = |(x, y): (i32, i32)| {
+            x >= i32::from(rect.left) && x < i32::from(rect.right)
+            && y >= i32::from(rect.top) && y < i32::from(rect.bottom)
+        };
+        let from = |(x, y): (i32, i32)| Source::From(x as i16, y as i16);
+        let to = |(x, y): (i32, i32)| Source::To(x as i16, y as i16);
+
+        match slide {
+            Slide::Push => if contains(new) { to(new) } else { from(old) },
+            Slide::Cover => if contains(new) { to(new) } else { from((x, y)) },
+            Slide::Reveal => if contains(old) { from(old) } else { to((x, y)) },
+        }
+    }
+}
+
+/// The ways that images move in sliding transitions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Slide {
+    /// The new image pushes the old image out.
+    Push,
+    /// The new image moves in over the old image.
+    Cover,
+    /// The old image moves out to show the new image.
+    Reveal,
+}
+
+/// Returns the step at which each of `count` cells changes, in a fixed
+/// pseudo-random order which spreads the cells evenly over the steps.
+fn random_steps(count: usize, step_count: u32) -> Vec<u32> {
+    let mut order = (0..count).collect::<Vec<_>>();
+    let mut state = RANDOM_SEED;
+    for index in (1..count).rev() {
+        // xorshift32
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        order.swap(index, state as usize % (index + 1));
+    }
+
+    let mut steps = vec![ 0; count ];
+    for (rank, &cell) in order.iter().enumerate() {
+        steps[cell] = (rank as u64 * u64::from(step_count) / count as u64) as u32;
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use libmactoolbox::quickdraw::RGBColor;
+    use num_traits::FromPrimitive;
+    use super::*;
+
+    const STAGE: Rect = Rect { top: 0, left: 0, bottom: 8, right: 12 };
+
+    fn params(kind: Kind, chunk_size: u8) -> Params {
+        Params { kind, chunk_size, duration: Milliseconds(1000), entire_stage: true }
+    }
+
+    /// Draws a step of a transition from black to white and returns a map of
+    /// the result, with `#` for pixels which are still black.
+    fn draw(transition: &Transition, step: u32) -> Vec<String> {
+        let from = PixMap::new(STAGE, RGBColor::BLACK);
+        let to = PixMap::new(STAGE, RGBColor::WHITE);
+        let mut dst = PixMap::new(STAGE, RGBColor { r: 0x8000, g: 0x8000, b: 0x8000 });
+        transition.draw(step, &from, &to, &mut dst);
+        (STAGE.top..STAGE.bottom).map(|y| {
+            (STAGE.left..STAGE.right).map(|x| {
+                if dst.pixel(x, y) == Some(RGBColor::BLACK) { '#' } else { '.' }
+            }).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn all_kinds() {
+        for value in 1..=52 {
+            let kind = Kind::from_u8(value).unwrap();
+            let transition = Transition::new(params(kind, 2), STAGE, Rect::default());
+            let step_count = transition.step_count();
+            assert!(step_count > 0);
+            assert!(draw(&transition, 0).iter().all(|row| row == "############"), "{:?} at start", kind);
+            assert!(draw(&transition, step_count).iter().all(|row| row == "............"), "{:?} at end", kind);
+            assert_eq!(draw(&transition, step_count / 2), draw(&transition, step_count / 2), "{:?} is not deterministic", kind);
+        }
+    }
+
+    #[test]
+    fn wipe() {
+        let transition = Transition::new(params(Kind::WipeRight, 4), STAGE, Rect::default());
+        assert_eq!(transition.step_count(), 3);
+        assert_eq!(draw(&transition, 1)[0], "....########");
+        assert_eq!(draw(&transition, 2)[7], "........####");
+    }
+
+    #[test]
+    fn push() {
+        let from = PixMap::new(STAGE, RGBColor::BLACK);
+        let mut to = PixMap::new(STAGE, RGBColor::WHITE);
+        to.set_pixel(0, 7, RGBColor::BLACK);
+        let mut dst = PixMap::new(STAGE, RGBColor::WHITE);
+        let transition = Transition::new(params(Kind::PushDown, 2), STAGE, Rect::default());
+        assert_eq!(transition.step_count(), 4);
+        transition.draw(1, &from, &to, &mut dst);
+        // The bottom of the new image is pushed in above the old image
+        assert_eq!(dst.pixel(0, 0), Some(RGBColor::WHITE));
+        assert_eq!(dst.pixel(0, 1), Some(RGBColor::BLACK));
+        assert_eq!(dst.pixel(1, 1), Some(RGBColor::WHITE));
+        assert_eq!(dst.pixel(1, 2), Some(RGBColor::BLACK));
+        assert_eq!(dst.pixel(1, 7), Some(RGBColor::BLACK));
+    }
+
+    #[test]
+    fn changing_area() {
+        let mut params = params(Kind::CenterOutHoriz, 1);
+        params.entire_stage = false;
+        let changed_rect = Rect { top: 2, left: 2, bottom: 6, right: 6 };
+        let transition = Transition::new(params, STAGE, changed_rect);
+        assert_eq!(transition.rect(), changed_rect);
+        assert_eq!(transition.step_count(), 2);
+        let map = draw(&transition, 1);
+        assert_eq!(map[0], "............");
+        assert_eq!(map[3], "..#..#......");
+    }
+
+    #[test]
+    fn timing() {
+        let transition = Transition::new(params(Kind::WipeDown, 1), STAGE, Rect::default());
+        assert_eq!(transition.step_count(), 8);
+        assert_eq!(transition.step_at(Duration::from_millis(0)), 0);
+        assert_eq!(transition.step_at(Duration::from_millis(500)), 4);
+        assert_eq!(transition.step_at(Duration::from_secs(5)), 8);
+        assert!(!transition.is_done(Duration::from_millis(999)));
+        assert!(transition.is_done(Duration::from_secs(1)));
+
+        let cut = Transition::new(Params { duration: Milliseconds(0), ..params(Kind::WipeDown, 1) }, STAGE, Rect::default());
+        assert!(cut.is_done(Duration::from_millis(0)));
+    }
+}
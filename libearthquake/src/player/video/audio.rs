@@ -0,0 +1,245 @@
+//! Decoders for uncompressed and IMA ADPCM audio.
+
+use anyhow::{bail, ensure, Result as AResult};
+use super::{AudioDecoder, AudioFormat};
+
+const INDEX_TABLE: [ i8; 16 ] = [
+    -1, -1, -1, -1, 2, 4, 6, 8,
+    -1, -1, -1, -1, 2, 4, 6, 8,
+];
+
+const STEP_TABLE: [ i32; 89 ] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17,
+    19, 21, 23, 25, 28, 31, 34, 37, 41, 45,
+    50, 55, 60, 66, 73, 80, 88, 97, 107, 118,
+    130, 143, 157, 173, 190, 209, 230, 253, 279, 307,
+    337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066,
+    2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358,
+    5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899,
+    15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+/// The state of one channel of an IMA ADPCM stream.
+#[derive(Clone, Copy, Debug, Default)]
+struct ImaChannel {
+    predictor: i32,
+    index: i32,
+}
+
+impl ImaChannel {
+    fn new(predictor: i16, index: u8) -> Self {
+        Self { predictor: predictor.into(), index: i32::from(index).min(88) }
+    }
+
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = STEP_TABLE[self.index as usize];
+        let mut diff = step >> 3;
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 8 != 0 {
+            self.predictor -= diff;
+        } else {
+            self.predictor += diff;
+        }
+        self.predictor = self.predictor.max(i16::MIN.into()).min(i16::MAX.into());
+        self.index = (self.index + i32::from(INDEX_TABLE[usize::from(nibble & 0xf)])).max(0).min(88);
+        self.predictor as i16
+    }
+}
+
+/// A decoder for uncompressed 8- or 16-bit audio.
+#[derive(Debug)]
+pub(super) struct PcmDecoder {
+    bits_per_sample: u16,
+    signed: bool,
+    big_endian: bool,
+}
+
+impl PcmDecoder {
+    pub(super) fn new(format: &AudioFormat, signed: bool, big_endian: bool) -> AResult<Self> {
+        ensure!(
+            format.bits_per_sample == 8 || format.bits_per_sample == 16,
+            "Unsupported PCM sample size {}",
+            format.bits_per_sample
+        );
+        Ok(Self { bits_per_sample: format.bits_per_sample, signed, big_endian })
+    }
+}
+
+impl AudioDecoder for PcmDecoder {
+    fn decode(&mut self, data: &[u8], output: &mut Vec<i16>) -> AResult<()> {
+        if self.bits_per_sample == 8 {
+            output.extend(data.iter().map(|&sample| {
+                let sample = if self.signed { sample } else { sample ^ 0x80 };
+                i16::from(sample as i8) << 8
+            }));
+        } else {
+            output.extend(data.chunks_exact(2).map(|sample| {
+                let sample = [ sample[0], sample[1] ];
+                if self.big_endian {
+                    i16::from_be_bytes(sample)
+                } else {
+                    i16::from_le_bytes(sample)
+                }
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// A decoder for `QuickTime` IMA 4:1 audio. Each channel is stored in
+/// separate 34-byte packets of 64 samples.
+#[derive(Debug)]
+pub(super) struct QuickTimeImaDecoder {
+    channels: usize,
+}
+
+impl QuickTimeImaDecoder {
+    const PACKET_SIZE: usize = 34;
+    const SAMPLES_PER_PACKET: usize = 64;
+
+    pub(super) fn new(format: &AudioFormat) -> Self {
+        Self { channels: usize::from(format.channels.max(1)) }
+    }
+}
+
+impl AudioDecoder for QuickTimeImaDecoder {
+    fn decode(&mut self, data: &[u8], output: &mut Vec<i16>) -> AResult<()> {
+        let frame_size = Self::PACKET_SIZE * self.channels;
+        ensure!(data.len() % frame_size == 0, "IMA 4:1 data size {} is not a multiple of {}", data.len(), frame_size);
+
+        let mut samples = vec![ 0; Self::SAMPLES_PER_PACKET * self.channels ];
+        for frame in data.chunks_exact(frame_size) {
+            for (channel, packet) in frame.chunks_exact(Self::PACKET_SIZE).enumerate() {
+                let header = u16::from_be_bytes([ packet[0], packet[1] ]);
+                let mut state = ImaChannel::new((header & 0xff80) as i16, (header & 0x7f) as u8);
+                for (index, &byte) in packet[2..].iter().enumerate() {
+                    samples[(index * 2) * self.channels + channel] = state.decode(byte & 0xf);
+                    samples[(index * 2 + 1) * self.channels + channel] = state.decode(byte >> 4);
+                }
+            }
+            output.extend_from_slice(&samples);
+        }
+        Ok(())
+    }
+}
+
+/// A decoder for Microsoft IMA ADPCM audio. Each block starts with a header
+/// for each channel, followed by groups of eight samples from each channel
+/// in turn.
+#[derive(Debug)]
+pub(super) struct MsImaDecoder {
+    channels: usize,
+    block_align: usize,
+    samples_per_block: usize,
+}
+
+impl MsImaDecoder {
+    pub(super) fn new(format: &AudioFormat, samples_per_block: u16) -> Self {
+        Self {
+            channels: usize::from(format.channels.max(1)),
+            block_align: usize::from(format.block_align),
+            samples_per_block: usize::from(samples_per_block),
+        }
+    }
+
+    fn decode_block(&self, block: &[u8], output: &mut Vec<i16>) -> AResult<()> {
+        let header_size = 4 * self.channels;
+        if block.len() < header_size {
+            bail!("IMA ADPCM block is too small ({} bytes)", block.len());
+        }
+
+        let mut states = block[..header_size].chunks_exact(4)
+            .map(|header| ImaChannel::new(i16::from_le_bytes([ header[0], header[1] ]), header[2]))
+            .collect::<Vec<_>>();
+
+        let samples_per_block = self.samples_per_block.max(1)
+            .min((block.len() - header_size) * 2 / self.channels + 1);
+        let start = output.len();
+        output.resize(start + samples_per_block * self.channels, 0);
+        for (channel, state) in states.iter().enumerate() {
+            output[start + channel] = state.predictor as i16;
+        }
+
+        // Each group holds 4 bytes (8 samples) from each channel in turn
+        for (group_index, group) in block[header_size..].chunks(4 * self.channels).enumerate() {
+            for (channel, bytes) in group.chunks(4).enumerate() {
+                let state = &mut states[channel];
+                for (byte_index, &byte) in bytes.iter().enumerate() {
+                    for (nibble_index, &nibble) in [ byte & 0xf, byte >> 4 ].iter().enumerate() {
+                        let sample_index = 1 + group_index * 8 + byte_index * 2 + nibble_index;
+                        if sample_index < samples_per_block {
+                            output[start + sample_index * self.channels + channel] = state.decode(nibble);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl AudioDecoder for MsImaDecoder {
+    fn decode(&mut self, data: &[u8], output: &mut Vec<i16>) -> AResult<()> {
+        let block_align = if self.block_align == 0 { data.len() } else { self.block_align };
+        for block in data.chunks(block_align.max(1)) {
+            self.decode_block(block, output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::super::AudioCodec;
+    use super::*;
+
+    fn format(codec: AudioCodec, channels: u16, bits_per_sample: u16, block_align: u16) -> AudioFormat {
+        AudioFormat { codec, channels, sample_rate: 22050, bits_per_sample, block_align }
+    }
+
+    #[test]
+    fn pcm() {
+        let mut output = Vec::new();
+        PcmDecoder::new(&format(AudioCodec::Pcm { signed: false, big_endian: false }, 1, 8, 1), false, false).unwrap()
+            .decode(&[ 0x80, 0xff, 0 ], &mut output).unwrap();
+        assert_eq!(output, vec![ 0, 0x7f00, -0x8000 ]);
+
+        output.clear();
+        PcmDecoder::new(&format(AudioCodec::Pcm { signed: true, big_endian: true }, 1, 16, 2), true, true).unwrap()
+            .decode(&[ 0x12, 0x34, 0xff, 0xfe ], &mut output).unwrap();
+        assert_eq!(output, vec![ 0x1234, -2 ]);
+    }
+
+    #[test]
+    fn quicktime_ima() {
+        let mut packet = [ 0; 34 ];
+        packet[0] = 0x01;
+        packet[2] = 0x07;
+        let mut output = Vec::new();
+        QuickTimeImaDecoder::new(&format(AudioCodec::QuickTimeIma, 1, 16, 34))
+            .decode(&packet, &mut output).unwrap();
+        assert_eq!(output.len(), 64);
+        assert_eq!(&output[..3], &[ 267, 269, 270 ]);
+    }
+
+    #[test]
+    fn ms_ima() {
+        let block = [ 0x10, 0, 0, 0, 0x17, 0, 0, 0 ];
+        let mut output = Vec::new();
+        MsImaDecoder::new(&format(AudioCodec::MsIma { samples_per_block: 9 }, 1, 4, 8), 9)
+            .decode(&block, &mut output).unwrap();
+        assert_eq!(output.len(), 9);
+        assert_eq!(&output[..3], &[ 16, 27, 33 ]);
+    }
+}
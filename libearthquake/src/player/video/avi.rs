@@ -0,0 +1,342 @@
+//! A reader for AVI files.
+//!
+//! An AVI file is a little-endian RIFF file. The `hdrl` list describes each
+//! stream, the `movi` list contains the media data of every stream
+//! interleaved in chunks, and the optional `idx1` chunk marks key frames.
+
+use anyhow::{bail, Context, ensure, Result as AResult};
+use byteorder::{LittleEndian, ReadBytesExt};
+use libcommon::Reader;
+use libmactoolbox::{OSType, quickdraw::{ColorTable, RGBColor}};
+use std::{convert::TryFrom, io::{Cursor, Read, SeekFrom}};
+use super::{AudioCodec, AudioFormat, Container, Sample, Track, VideoCodec, VideoFormat};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IMA_ADPCM: u16 = 0x11;
+const KEY_FRAME: u32 = 0x10;
+
+#[derive(Clone, Copy, Debug)]
+struct Chunk {
+    id: [u8; 4],
+    /// The type of a `LIST` chunk.
+    list_kind: Option<[u8; 4]>,
+    /// The position of the data of the chunk, after its header.
+    offset: u64,
+    size: u64,
+}
+
+impl Chunk {
+    fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+
+    fn is_list(&self, kind: &[u8]) -> bool {
+        self.list_kind.as_ref().map_or(false, |list_kind| list_kind[..] == *kind)
+    }
+}
+
+/// Reads the headers of the chunks between `start` and `end`.
+fn read_chunks(input: &mut impl Reader, start: u64, end: u64) -> AResult<Vec<Chunk>> {
+    let mut chunks = Vec::new();
+    let mut pos = start;
+    while pos + 8 <= end {
+        input.seek(SeekFrom::Start(pos))?;
+        let mut id = [ 0; 4 ];
+        input.read_exact(&mut id)?;
+        let size = u64::from(input.read_u32::<LittleEndian>()?);
+        let (list_kind, offset, size) = if &id == b"LIST" && size >= 4 {
+            let mut kind = [ 0; 4 ];
+            input.read_exact(&mut kind)?;
+            (Some(kind), pos + 12, size - 4)
+        } else {
+            (None, pos + 8, size)
+        };
+        // Truncated files are common, so the last chunk is allowed to
+        // extend past the end of the file
+        let size = size.min(end.saturating_sub(offset));
+        chunks.push(Chunk { id, list_kind, offset, size });
+        // Chunks are padded to an even size
+        pos = offset + size + (size & 1);
+    }
+    Ok(chunks)
+}
+
+fn read_data(input: &mut impl Reader, chunk: Chunk) -> AResult<Cursor<Vec<u8>>> {
+    input.seek(SeekFrom::Start(chunk.offset))?;
+    let mut data = vec![ 0; usize::try_from(chunk.size)? ];
+    input.read_exact(&mut data)
+        .with_context(|| format!("Can’t read {} chunk data", String::from_utf8_lossy(&chunk.id)))?;
+    Ok(Cursor::new(data))
+}
+
+#[derive(Clone, Debug)]
+enum StreamFormat {
+    Video(VideoFormat),
+    Audio(AudioFormat),
+    Other,
+}
+
+#[derive(Clone, Debug)]
+struct Stream {
+    format: StreamFormat,
+    scale: u32,
+    rate: u32,
+    chunks: Vec<(u64, u32)>,
+}
+
+fn read_video_format(data: &mut Cursor<Vec<u8>>) -> AResult<VideoFormat> {
+    let header_size = data.read_u32::<LittleEndian>()?;
+    let width = data.read_i32::<LittleEndian>()?;
+    let height = data.read_i32::<LittleEndian>()?;
+    // Planes
+    data.read_u16::<LittleEndian>()?;
+    let depth = data.read_u16::<LittleEndian>()?;
+    let mut compression = [ 0; 4 ];
+    data.read_exact(&mut compression)?;
+    // Image size, horizontal resolution, vertical resolution
+    data.set_position(data.position() + 12);
+    let colors_used = data.read_u32::<LittleEndian>()?;
+    data.set_position(u64::from(header_size));
+
+    let palette = if depth <= 8 {
+        let count = if colors_used == 0 { 1 << depth } else { colors_used.min(256) };
+        let mut entries = Vec::with_capacity(count as usize);
+        for value in 0..count {
+            let mut quad = [ 0; 4 ];
+            if data.read_exact(&mut quad).is_err() {
+                break;
+            }
+            let expand = |channel: u8| u16::from(channel) * 0x101;
+            entries.push((value as u16, RGBColor { r: expand(quad[2]), g: expand(quad[1]), b: expand(quad[0]) }));
+        }
+        Some(ColorTable::new(entries))
+    } else {
+        None
+    };
+
+    let codec = match &compression {
+        [ 0, 0, 0, 0 ] | b"DIB " => VideoCodec::Raw { bottom_up: height > 0, little_endian: true },
+        b"cvid" | b"CVID" => VideoCodec::Cinepak,
+        b"CRAM" | b"cram" | b"MSVC" | b"msvc" | b"WHAM" | b"wham" => VideoCodec::MsVideo1,
+        _ => VideoCodec::Unknown(OSType::new(compression)),
+    };
+
+    Ok(VideoFormat {
+        codec,
+        width: i16::try_from(width).context("Video width is out of range")?,
+        height: i16::try_from(height.abs()).context("Video height is out of range")?,
+        depth,
+        palette,
+    })
+}
+
+fn read_audio_format(data: &mut Cursor<Vec<u8>>) -> AResult<AudioFormat> {
+    let tag = data.read_u16::<LittleEndian>()?;
+    let channels = data.read_u16::<LittleEndian>()?;
+    let sample_rate = data.read_u32::<LittleEndian>()?;
+    // Average bytes per second
+    data.read_u32::<LittleEndian>()?;
+    let block_align = data.read_u16::<LittleEndian>()?;
+    let bits_per_sample = data.read_u16::<LittleEndian>()?;
+
+    let codec = match tag {
+        WAVE_FORMAT_PCM => AudioCodec::Pcm { signed: bits_per_sample > 8, big_endian: false },
+        WAVE_FORMAT_IMA_ADPCM => {
+            // Size of extra data
+            data.read_u16::<LittleEndian>()?;
+            AudioCodec::MsIma { samples_per_block: data.read_u16::<LittleEndian>()? }
+        },
+        tag => AudioCodec::Unknown(tag.into()),
+    };
+
+    Ok(AudioFormat { codec, channels, sample_rate, bits_per_sample, block_align })
+}
+
+fn read_stream(input: &mut impl Reader, strl: Chunk) -> AResult<Stream> {
+    let chunks = read_chunks(input, strl.offset, strl.end())?;
+    let header_chunk = chunks.iter().find(|chunk| &chunk.id == b"strh").context("Missing stream header")?;
+    let format_chunk = chunks.iter().find(|chunk| &chunk.id == b"strf").context("Missing stream format")?;
+
+    let mut header = read_data(input, *header_chunk)?;
+    let mut kind = [ 0; 4 ];
+    header.read_exact(&mut kind)?;
+    // Handler, flags, priority, language, initial frames
+    header.set_position(header.position() + 4 + 4 + 2 + 2 + 4);
+    let scale = header.read_u32::<LittleEndian>()?;
+    let rate = header.read_u32::<LittleEndian>()?;
+
+    let mut data = read_data(input, *format_chunk)?;
+    let format = match &kind {
+        b"vids" => StreamFormat::Video(read_video_format(&mut data).context("Can’t read video format")?),
+        b"auds" => StreamFormat::Audio(read_audio_format(&mut data).context("Can’t read audio format")?),
+        _ => StreamFormat::Other,
+    };
+
+    Ok(Stream { format, scale, rate, chunks: Vec::new() })
+}
+
+/// Returns the stream number of a media chunk in the `movi` list.
+fn stream_number(id: [u8; 4]) -> Option<usize> {
+    let digit = |c: u8| (c as char).to_digit(16);
+    Some((digit(id[0])? * 16 + digit(id[1])?) as usize)
+}
+
+/// Adds every media chunk in a `movi` list to its stream.
+fn read_movie_data(input: &mut impl Reader, list: Chunk, streams: &mut [Stream]) -> AResult<()> {
+    for chunk in read_chunks(input, list.offset, list.end())? {
+        if chunk.is_list(b"rec ") {
+            read_movie_data(input, chunk, streams)?;
+        } else if let Some(stream) = stream_number(chunk.id).and_then(|number| streams.get_mut(number)) {
+            stream.chunks.push((chunk.offset, chunk.size as u32));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the key frame flags of the chunks of each stream, in order.
+fn read_index(input: &mut impl Reader, idx1: Chunk, stream_count: usize) -> AResult<Vec<Vec<bool>>> {
+    let mut key_frames = vec![ Vec::new(); stream_count ];
+    let mut data = read_data(input, idx1)?;
+    for _ in 0..idx1.size / 16 {
+        let mut id = [ 0; 4 ];
+        data.read_exact(&mut id)?;
+        let flags = data.read_u32::<LittleEndian>()?;
+        // Offset, size
+        data.read_u64::<LittleEndian>()?;
+        if let Some(stream) = stream_number(id).and_then(|number| key_frames.get_mut(number)) {
+            stream.push(flags & KEY_FRAME != 0);
+        }
+    }
+    Ok(key_frames)
+}
+
+pub(super) fn read(input: &mut impl Reader) -> AResult<Container> {
+    let end = input.len()?;
+    input.seek(SeekFrom::Start(0))?;
+    let mut header = [ 0; 12 ];
+    input.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"AVI " {
+        bail!("Not an AVI file");
+    }
+
+    let chunks = read_chunks(input, 12, end)?;
+    let hdrl = chunks.iter().find(|chunk| chunk.is_list(b"hdrl")).context("Missing header list")?;
+    let mut streams = Vec::new();
+    for (index, strl) in read_chunks(input, hdrl.offset, hdrl.end())?.into_iter().filter(|chunk| chunk.is_list(b"strl")).enumerate() {
+        streams.push(read_stream(input, strl).with_context(|| format!("Can’t read stream {}", index))?);
+    }
+
+    let movi = chunks.iter().find(|chunk| chunk.is_list(b"movi")).context("Missing movie list")?;
+    read_movie_data(input, *movi, &mut streams)?;
+
+    let key_frames = match chunks.iter().find(|chunk| &chunk.id == b"idx1") {
+        Some(idx1) => Some(read_index(input, *idx1, streams.len()).context("Can’t read index")?),
+        None => None,
+    };
+
+    let mut container = Container::default();
+    for (number, stream) in streams.into_iter().enumerate() {
+        let is_key_frame = |index: usize| key_frames.as_ref()
+            .and_then(|key_frames| key_frames[number].get(index).copied())
+            .unwrap_or(true);
+
+        match stream.format {
+            StreamFormat::Video(format) if container.video.is_none() => {
+                let scale = stream.scale.max(1);
+                let samples = stream.chunks.iter().enumerate().map(|(index, &(offset, size))| Sample {
+                    offset,
+                    size,
+                    time: index as u64 * u64::from(scale),
+                    duration: scale,
+                    key_frame: is_key_frame(index),
+                }).collect();
+                container.video = Some(Track { format, time_scale: stream.rate, samples });
+            },
+            StreamFormat::Audio(format) if container.audio.is_none() => {
+                ensure!(format.block_align != 0, "Invalid audio block size");
+                let block_align = u32::from(format.block_align);
+                let samples_per_block = match format.codec {
+                    AudioCodec::MsIma { samples_per_block } => u32::from(samples_per_block),
+                    _ => 1,
+                };
+                let mut time = 0;
+                let samples = stream.chunks.iter().map(|&(offset, size)| {
+                    let duration = size / block_align * samples_per_block;
+                    let sample = Sample { offset, size, time, duration, key_frame: true };
+                    time += u64::from(duration);
+                    sample
+                }).collect();
+                container.audio = Some(Track { format, time_scale: format.sample_rate, samples });
+            },
+            _ => {},
+        }
+    }
+
+    Ok(container)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() & 1 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn list(kind: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        chunk(b"LIST", &[ kind, &children.concat() ].concat())
+    }
+
+    fn le(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn read_streams() {
+        let video_header = [ &b"vids"[..], &[ 0; 16 ], &le(&[ 1, 15, 0, 2, 0, 0, 0 ]), &[ 0; 8 ] ].concat();
+        let video_format = [ le(&[ 40, 2, 0xffff_ffff ]), vec![ 1, 0, 24, 0 ], le(&[ 0, 0, 0, 0, 0, 0 ]) ].concat();
+        let audio_header = [ &b"auds"[..], &[ 0; 16 ], &le(&[ 1, 22050, 0, 0, 0, 0, 1 ]), &[ 0; 8 ] ].concat();
+        let audio_format = [ vec![ 1, 0, 1, 0 ], le(&[ 22050, 22050 ]), vec![ 1, 0, 8, 0 ] ].concat();
+
+        let file = chunk(b"RIFF", &[
+            &b"AVI "[..],
+            &list(b"hdrl", &[
+                chunk(b"avih", &[ 0; 56 ]),
+                list(b"strl", &[ chunk(b"strh", &video_header), chunk(b"strf", &video_format) ]),
+                list(b"strl", &[ chunk(b"strh", &audio_header), chunk(b"strf", &audio_format) ]),
+            ]),
+            &list(b"movi", &[
+                chunk(b"00db", &[ 1, 2, 3, 4, 5, 6, 0, 0 ]),
+                chunk(b"01wb", &[ 0x80; 5 ]),
+                list(b"rec ", &[ chunk(b"00dc", &[ 0; 8 ]) ]),
+            ]),
+            &chunk(b"idx1", &[
+                &b"00db"[..], &le(&[ 0x10, 0, 8 ]),
+                &b"01wb"[..], &le(&[ 0, 0, 5 ]),
+                &b"00dc"[..], &le(&[ 0, 0, 8 ]),
+            ].concat()),
+        ].concat());
+
+        let container = read(&mut Cursor::new(file)).unwrap();
+        let video = container.video.unwrap();
+        assert_eq!(video.format.codec, VideoCodec::Raw { bottom_up: false, little_endian: true });
+        assert_eq!((video.format.width, video.format.height, video.format.depth), (2, 1, 24));
+        assert_eq!(video.time_scale, 15);
+        assert_eq!(video.samples.len(), 2);
+        assert!(video.samples[0].key_frame);
+        assert!(!video.samples[1].key_frame);
+        assert_eq!(video.samples[1].time, 1);
+        assert_eq!(video.to_duration(video.duration()).as_millis(), 133);
+
+        let audio = container.audio.unwrap();
+        assert_eq!(audio.format.codec, AudioCodec::Pcm { signed: false, big_endian: false });
+        assert_eq!(audio.samples, vec![ Sample { offset: 348, size: 5, time: 0, duration: 5, key_frame: true } ]);
+    }
+}
@@ -0,0 +1,268 @@
+//! A decoder for Cinepak (`cvid`).
+//!
+//! Frames are divided into horizontal strips, each with two codebooks of
+//! 2x2 pixel blocks. Each 4x4 block of a strip is either skipped, drawn by
+//! scaling up one V1 codebook entry, or drawn from four V4 codebook entries.
+
+use anyhow::{bail, ensure, Result as AResult};
+use libmactoolbox::quickdraw::{PixMap, RGBColor};
+use super::{rgb888, VideoDecoder};
+
+/// The colours of a 2x2 block, in the order top-left, top-right, bottom-left,
+/// bottom-right.
+type Entry = [ RGBColor; 4 ];
+
+#[derive(Clone, Debug)]
+struct Strip {
+    v1: Vec<Entry>,
+    v4: Vec<Entry>,
+}
+
+impl Default for Strip {
+    fn default() -> Self {
+        Self {
+            v1: vec![ [ RGBColor::BLACK; 4 ]; 256 ],
+            v4: vec![ [ RGBColor::BLACK; 4 ]; 256 ],
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Decoder {
+    strips: Vec<Strip>,
+}
+
+impl Decoder {
+    const MAX_STRIPS: usize = 32;
+    const FRAME_HEADER_SIZE: usize = 10;
+    const STRIP_HEADER_SIZE: usize = 12;
+    const KEEP_CODEBOOKS: u8 = 1;
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([ data[offset], data[offset + 1] ])
+}
+
+fn read_u24(data: &[u8], offset: usize) -> usize {
+    (usize::from(data[offset]) << 16) | usize::from(read_u16(data, offset + 1))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    (u32::from(read_u16(data, offset)) << 16) | u32::from(read_u16(data, offset + 2))
+}
+
+/// Reads the flags which select the codebook entries or blocks to update, if
+/// the chunk is a partial update.
+struct Mask {
+    enabled: bool,
+    flags: u32,
+    bit: u32,
+}
+
+impl Mask {
+    fn new(enabled: bool) -> Self {
+        Self { enabled, flags: 0, bit: 0 }
+    }
+
+    /// Moves to the next flag, reading a new set of flags from the data if
+    /// required. Returns `None` if there is no more data.
+    fn next(&mut self, data: &[u8], pos: &mut usize) -> Option<()> {
+        self.bit >>= 1;
+        if self.bit == 0 {
+            if *pos + 4 > data.len() {
+                return None;
+            }
+            self.flags = read_u32(data, *pos);
+            self.bit = 0x8000_0000;
+            *pos += 4;
+        }
+        Some(())
+    }
+
+    fn is_set(&self) -> bool {
+        self.flags & self.bit != 0
+    }
+}
+
+fn decode_codebook(codebook: &mut [Entry], chunk_id: u8, data: &[u8]) {
+    let entry_size = if chunk_id & 4 == 0 { 6 } else { 4 };
+    let mut mask = Mask::new(chunk_id & 1 != 0);
+    let mut pos = 0;
+
+    for entry in codebook.iter_mut() {
+        if mask.enabled && mask.next(data, &mut pos).is_none() {
+            break;
+        }
+
+        if !mask.enabled || mask.is_set() {
+            if pos + entry_size > data.len() {
+                break;
+            }
+
+            let (u, v) = if entry_size == 6 {
+                (i32::from(data[pos + 4] as i8), i32::from(data[pos + 5] as i8))
+            } else {
+                (0, 0)
+            };
+
+            let clamp = |value: i32| value.max(0).min(255) as u8;
+            for (color, &y) in entry.iter_mut().zip(&data[pos..pos + 4]) {
+                let y = i32::from(y);
+                *color = rgb888(clamp(y + v * 2), clamp(y - u / 2 - v), clamp(y + u * 2));
+            }
+
+            pos += entry_size;
+        }
+    }
+}
+
+fn decode_vectors(frame: &mut PixMap, strip: &Strip, area: (i16, i16, i16, i16), chunk_id: u8, data: &[u8]) -> AResult<()> {
+    let (top, left, bottom, right) = area;
+    let mut mask = Mask::new(chunk_id & 1 != 0);
+    let mut pos = 0;
+
+    let mut fill = |x: i16, y: i16, size: i16, entry: &Entry| {
+        for (index, &color) in entry.iter().enumerate() {
+            let block_x = x + (index as i16 & 1) * size;
+            let block_y = y + (index as i16 >> 1) * size;
+            for dy in 0..size {
+                for dx in 0..size {
+                    frame.set_pixel(block_x + dx, block_y + dy, color);
+                }
+            }
+        }
+    };
+
+    for y in (top..bottom).step_by(4) {
+        for x in (left..right).step_by(4) {
+            if mask.enabled && mask.next(data, &mut pos).is_none() {
+                bail!("Cinepak vector flags are truncated");
+            }
+
+            if !mask.enabled || mask.is_set() {
+                // Unless every block uses the V1 codebook, another flag
+                // selects the codebook
+                let all_v1 = chunk_id & 2 != 0;
+                if !all_v1 && mask.next(data, &mut pos).is_none() {
+                    bail!("Cinepak vector flags are truncated");
+                }
+
+                if all_v1 || !mask.is_set() {
+                    ensure!(pos < data.len(), "Cinepak V1 vector is truncated");
+                    fill(x, y, 2, &strip.v1[usize::from(data[pos])]);
+                    pos += 1;
+                } else {
+                    ensure!(pos + 4 <= data.len(), "Cinepak V4 vector is truncated");
+                    for (index, &entry) in data[pos..pos + 4].iter().enumerate() {
+                        let index = index as i16;
+                        fill(x + (index & 1) * 2, y + (index >> 1) * 2, 1, &strip.v4[usize::from(entry)]);
+                    }
+                    pos += 4;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl VideoDecoder for Decoder {
+    fn decode(&mut self, data: &[u8], frame: &mut PixMap) -> AResult<()> {
+        ensure!(data.len() >= Self::FRAME_HEADER_SIZE, "Cinepak frame is too small ({} bytes)", data.len());
+        let flags = data[0];
+        let end = data.len().min(read_u24(data, 1).max(Self::FRAME_HEADER_SIZE));
+        let strip_count = usize::from(read_u16(data, 8)).min(Self::MAX_STRIPS);
+        if self.strips.len() < strip_count {
+            self.strips.resize(strip_count, Strip::default());
+        }
+
+        let bounds = frame.bounds();
+        let mut pos = Self::FRAME_HEADER_SIZE;
+        let mut y0 = 0;
+        for index in 0..strip_count {
+            ensure!(pos + Self::STRIP_HEADER_SIZE <= end, "Cinepak strip {} is truncated", index);
+            let header = &data[pos..pos + Self::STRIP_HEADER_SIZE];
+            let y1 = read_u16(header, 4) as i16;
+            let (y1, y2) = if y1 == 0 {
+                (y0, y0 + read_u16(header, 8) as i16)
+            } else {
+                (y1, read_u16(header, 8) as i16)
+            };
+            let x1 = read_u16(header, 6) as i16;
+            let x2 = read_u16(header, 10) as i16;
+            let strip_size = read_u24(header, 1).saturating_sub(Self::STRIP_HEADER_SIZE);
+            pos += Self::STRIP_HEADER_SIZE;
+            let strip_end = end.min(pos + strip_size);
+
+            if index > 0 && flags & Self::KEEP_CODEBOOKS == 0 {
+                self.strips[index] = self.strips[index - 1].clone();
+            }
+
+            let area = (
+                bounds.top + y1,
+                bounds.left + x1,
+                bounds.top + y2.min(bounds.height()),
+                bounds.left + x2.min(bounds.width()),
+            );
+            let strip = &mut self.strips[index];
+            let mut chunk_pos = pos;
+            while chunk_pos + 4 <= strip_end {
+                let chunk_id = data[chunk_pos];
+                let chunk_size = read_u24(data, chunk_pos + 1).saturating_sub(4);
+                chunk_pos += 4;
+                let chunk = &data[chunk_pos..strip_end.min(chunk_pos + chunk_size)];
+                match chunk_id {
+                    0x20 | 0x21 | 0x24 | 0x25 => decode_codebook(&mut strip.v4, chunk_id, chunk),
+                    0x22 | 0x23 | 0x26 | 0x27 => decode_codebook(&mut strip.v1, chunk_id, chunk),
+                    0x30 | 0x31 | 0x32 => {
+                        decode_vectors(frame, strip, area, chunk_id, chunk)?;
+                        break;
+                    },
+                    _ => {},
+                }
+                chunk_pos += chunk_size;
+            }
+
+            pos = strip_end;
+            y0 = y2;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use libmactoolbox::Rect;
+    use super::*;
+
+    #[test]
+    fn v1_and_v4() {
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 4, right: 8 }, RGBColor::WHITE);
+        let data = [
+            // Frame header
+            0, 0, 0, 55, 0, 8, 0, 4, 0, 1,
+            // Strip header
+            0x10, 0, 0, 45, 0, 0, 0, 0, 0, 4, 0, 8,
+            // Greyscale V1 codebook with two entries
+            0x26, 0, 0, 12, 10, 20, 30, 40, 50, 50, 50, 50,
+            // Greyscale V4 codebook with one entry
+            0x24, 0, 0, 8, 60, 70, 80, 90,
+            // One V1 block, then one V4 block
+            0x30, 0, 0, 13, 0x40, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        Decoder::default().decode(&data, &mut frame).unwrap();
+        let grey = |value: u16| Some(RGBColor { r: value * 0x101, g: value * 0x101, b: value * 0x101 });
+        assert_eq!(frame.pixel(0, 0), grey(10));
+        assert_eq!(frame.pixel(1, 1), grey(10));
+        assert_eq!(frame.pixel(2, 0), grey(20));
+        assert_eq!(frame.pixel(0, 2), grey(30));
+        assert_eq!(frame.pixel(3, 3), grey(40));
+        assert_eq!(frame.pixel(4, 0), grey(60));
+        assert_eq!(frame.pixel(5, 0), grey(70));
+        assert_eq!(frame.pixel(5, 1), grey(90));
+        assert_eq!(frame.pixel(6, 0), grey(60));
+        assert_eq!(frame.pixel(7, 3), grey(90));
+    }
+}
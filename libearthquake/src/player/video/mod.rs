@@ -0,0 +1,534 @@
+//! Digital video playback for `QuickTime` and AVI cast members.
+
+mod audio;
+mod avi;
+mod cinepak;
+mod ms_video_1;
+mod quicktime;
+mod raw;
+mod rle;
+mod rpza;
+
+use anyhow::{bail, Context, Result as AResult};
+use crate::resources::video::{Flags, Meta};
+use libcommon::Reader;
+use libmactoolbox::{
+    OSType,
+    quickdraw::{CGrafPort, ColorTable, Pen, PixMap, Region, RGBColor},
+    Rect,
+};
+use std::{convert::TryFrom, io::SeekFrom, time::Duration};
+
+/// The compression format of a video track.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VideoCodec {
+    /// Uncompressed pixels. `QuickTime` stores rows top to bottom with
+    /// big-endian pixels; AVI stores rows bottom to top with little-endian
+    /// pixels in BGR order.
+    Raw { bottom_up: bool, little_endian: bool },
+    /// Apple Animation (`rle `).
+    AppleAnimation,
+    /// Apple Video (`rpza`).
+    AppleVideo,
+    /// Cinepak (`cvid`).
+    Cinepak,
+    /// Microsoft Video 1 (`CRAM`).
+    MsVideo1,
+    Unknown(OSType),
+}
+
+/// The compression format of an audio track.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AudioCodec {
+    /// Uncompressed samples. 8-bit samples are unsigned unless `signed` is
+    /// set.
+    Pcm { signed: bool, big_endian: bool },
+    /// `QuickTime` IMA 4:1 (`ima4`).
+    QuickTimeIma,
+    /// Microsoft IMA ADPCM, with the given number of samples per channel in
+    /// each block.
+    MsIma { samples_per_block: u16 },
+    Unknown(u32),
+}
+
+/// The format of a video track.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VideoFormat {
+    pub codec: VideoCodec,
+    pub width: i16,
+    pub height: i16,
+    /// The number of bits per pixel.
+    pub depth: u16,
+    /// The colour table of indexed video.
+    pub palette: Option<ColorTable>,
+}
+
+/// The format of an audio track.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AudioFormat {
+    pub codec: AudioCodec,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    /// The size of each independently decodable block of compressed audio,
+    /// in bytes.
+    pub block_align: u16,
+}
+
+/// A chunk of media data in a container file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Sample {
+    /// The position of the data in the file.
+    pub offset: u64,
+    pub size: u32,
+    /// The presentation time of the sample, in track time units.
+    pub time: u64,
+    /// The duration of the sample, in track time units.
+    pub duration: u32,
+    /// Whether the sample can be decoded without any previous samples.
+    pub key_frame: bool,
+}
+
+/// A stream of media in a container file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Track<F> {
+    pub format: F,
+    /// The number of track time units per second.
+    pub time_scale: u32,
+    pub samples: Vec<Sample>,
+}
+
+impl<F> Track<F> {
+    /// The total duration of the track, in track time units.
+    #[must_use]
+    pub fn duration(&self) -> u64 {
+        self.samples.last().map_or(0, |sample| sample.time + u64::from(sample.duration))
+    }
+
+    /// Converts a time in track time units to a duration.
+    #[must_use]
+    pub fn to_duration(&self, time: u64) -> Duration {
+        if self.time_scale == 0 {
+            Duration::default()
+        } else {
+            let scale = u64::from(self.time_scale);
+            Duration::from_secs(time / scale) + Duration::from_nanos((time % scale) * 1_000_000_000 / scale)
+        }
+    }
+}
+
+/// The tracks of a `QuickTime` or AVI file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Container {
+    pub video: Option<Track<VideoFormat>>,
+    pub audio: Option<Track<AudioFormat>>,
+}
+
+impl Container {
+    /// Reads the tracks of an AVI file.
+    pub fn read_avi(input: &mut impl Reader) -> AResult<Self> {
+        avi::read(input).context("Can’t read AVI file")
+    }
+
+    /// Reads the tracks of a `QuickTime` movie file.
+    pub fn read_quicktime(input: &mut impl Reader) -> AResult<Self> {
+        quicktime::read(input).context("Can’t read QuickTime movie")
+    }
+}
+
+/// A video decoder.
+pub trait VideoDecoder: std::fmt::Debug {
+    /// Decodes one sample of video into the given image, which contains the
+    /// previously decoded frame.
+    fn decode(&mut self, data: &[u8], frame: &mut PixMap) -> AResult<()>;
+}
+
+/// An audio decoder.
+pub trait AudioDecoder: std::fmt::Debug {
+    /// Decodes one sample of audio, appending interleaved signed 16-bit PCM
+    /// to `output`.
+    fn decode(&mut self, data: &[u8], output: &mut Vec<i16>) -> AResult<()>;
+}
+
+/// Creates a decoder for the given video format.
+pub fn video_decoder(format: &VideoFormat) -> AResult<Box<dyn VideoDecoder>> {
+    Ok(match format.codec {
+        VideoCodec::Raw { bottom_up, little_endian } => Box::new(raw::Decoder::new(format, bottom_up, little_endian)?),
+        VideoCodec::AppleAnimation => Box::new(rle::Decoder::new(format)?),
+        VideoCodec::AppleVideo => Box::new(rpza::Decoder),
+        VideoCodec::Cinepak => Box::new(cinepak::Decoder::default()),
+        VideoCodec::MsVideo1 => Box::new(ms_video_1::Decoder::new(format)?),
+        VideoCodec::Unknown(codec) => bail!("Unsupported video codec {}", codec),
+    })
+}
+
+/// Creates a decoder for the given audio format.
+pub fn audio_decoder(format: &AudioFormat) -> AResult<Box<dyn AudioDecoder>> {
+    Ok(match format.codec {
+        AudioCodec::Pcm { signed, big_endian } => Box::new(audio::PcmDecoder::new(format, signed, big_endian)?),
+        AudioCodec::QuickTimeIma => Box::new(audio::QuickTimeImaDecoder::new(format)),
+        AudioCodec::MsIma { samples_per_block } => Box::new(audio::MsImaDecoder::new(format, samples_per_block)),
+        AudioCodec::Unknown(codec) => bail!("Unsupported audio codec 0x{:x}", codec),
+    })
+}
+
+/// Converts a 16-bit pixel with five bits per channel into a colour.
+#[must_use]
+fn rgb555(value: u16) -> RGBColor {
+    let expand = |channel: u16| {
+        let channel = channel & 0x1f;
+        (channel << 11) | (channel << 6) | (channel << 1) | (channel >> 4)
+    };
+    RGBColor { r: expand(value >> 10), g: expand(value >> 5), b: expand(value) }
+}
+
+/// Converts a pixel with eight bits per channel into a colour.
+#[must_use]
+fn rgb888(r: u8, g: u8, b: u8) -> RGBColor {
+    RGBColor { r: u16::from(r) * 0x101, g: u16::from(g) * 0x101, b: u16::from(b) * 0x101 }
+}
+
+/// Returns the colour of a palette index, or black if the index is not in
+/// the palette.
+#[must_use]
+fn palette_color(palette: &ColorTable, index: u8) -> RGBColor {
+    palette.get(index.into()).unwrap_or(RGBColor::BLACK)
+}
+
+/// The playback state of a digital video cast member.
+#[derive(Debug)]
+pub struct Video<R: Reader> {
+    input: R,
+    container: Container,
+    flags: Flags,
+    frame_rate: u8,
+    video_decoder: Option<Box<dyn VideoDecoder>>,
+    audio_decoder: Option<Box<dyn AudioDecoder>>,
+    frame: PixMap,
+    /// The index of the next video sample to decode.
+    next_frame: usize,
+    /// The index of the next audio sample to decode.
+    next_audio: usize,
+    time: Duration,
+    playing: bool,
+    audio: Vec<i16>,
+}
+
+impl<R: Reader> Video<R> {
+    /// Opens the linked media file of a digital video cast member.
+    pub fn new(meta: &Meta, mut input: R) -> AResult<Self> {
+        let container = if meta.flags().contains(Flags::VIDEO_KIND_AVI) {
+            Container::read_avi(&mut input)?
+        } else {
+            Container::read_quicktime(&mut input)?
+        };
+        Self::with_container(meta, container, input)
+    }
+
+    /// Creates a player for tracks which have already been read from the
+    /// given file.
+    pub fn with_container(meta: &Meta, container: Container, input: R) -> AResult<Self> {
+        let flags = meta.flags();
+        let video_decoder = match &container.video {
+            Some(track) if !flags.contains(Flags::HIDE_VIDEO) => Some(video_decoder(&track.format)?),
+            _ => None,
+        };
+        let audio_decoder = match &container.audio {
+            Some(track) if flags.contains(Flags::SOUND_ENABLED) && !flags.contains(Flags::PLAY_EVERY_FRAME) => {
+                Some(audio_decoder(&track.format)?)
+            },
+            _ => None,
+        };
+        let bounds = container.video.as_ref().map_or_else(Rect::default, |track| Rect {
+            top: 0,
+            left: 0,
+            bottom: track.format.height,
+            right: track.format.width,
+        });
+
+        Ok(Self {
+            input,
+            container,
+            flags,
+            frame_rate: meta.frame_rate(),
+            video_decoder,
+            audio_decoder,
+            frame: PixMap::new(bounds, RGBColor::BLACK),
+            next_frame: 0,
+            next_audio: 0,
+            time: Duration::default(),
+            playing: !flags.contains(Flags::PAUSED_AT_START),
+            audio: Vec::new(),
+        })
+    }
+
+    /// Advances playback by the given amount of time and decodes any media
+    /// which became due. Returns whether the visible frame changed.
+    pub fn advance(&mut self, elapsed: Duration) -> AResult<bool> {
+        if !self.playing {
+            return Ok(false);
+        }
+
+        self.time += elapsed;
+
+        // Videos which show every frame loop as soon as the last frame has
+        // been shown; other videos loop once the last frame has been shown
+        // for its full duration
+        if self.flags.contains(Flags::LOOP) && self.is_at_end() {
+            let duration = self.duration();
+            if self.time >= duration || self.flags.intersects(Flags::PLAY_EVERY_FRAME | Flags::FRAME_RATE_MAXIMUM) {
+                self.time = if self.time >= duration && duration != Duration::default() {
+                    Duration::from_nanos((self.time.as_nanos() % duration.as_nanos()) as u64)
+                } else {
+                    Duration::default()
+                };
+                self.next_frame = 0;
+                self.next_audio = 0;
+            }
+        }
+
+        self.decode_audio()?;
+        self.decode_video()
+    }
+
+    /// Takes the audio which has been decoded so far, as interleaved signed
+    /// 16-bit samples in the format of the audio track.
+    pub fn take_audio(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.audio)
+    }
+
+    #[must_use]
+    pub fn audio_format(&self) -> Option<&AudioFormat> {
+        self.container.audio.as_ref().map(|track| &track.format)
+    }
+
+    /// Returns whether the video draws directly to the screen over all other
+    /// sprites instead of being composited.
+    #[must_use]
+    pub fn direct_to_stage(&self) -> bool {
+        self.flags.contains(Flags::DIRECT_TO_STAGE)
+    }
+
+    /// Draws the current frame into the given sprite rectangle. The video is
+    /// scaled to fit the sprite if the `SCALE` flag is set; otherwise, it is
+    /// drawn at its original size and cropped to the sprite, aligned to
+    /// either the top-left corner or the centre.
+    pub fn draw(&self, port: &mut CGrafPort, sprite_rect: Rect) {
+        if self.video_decoder.is_none() {
+            return;
+        }
+
+        let bounds = self.frame.bounds();
+        let dst_rect = if self.flags.contains(Flags::SCALE) {
+            sprite_rect
+        } else {
+            let (left, top) = if self.flags.contains(Flags::CROP_FROM_CENTER) {
+                (
+                    sprite_rect.left + (sprite_rect.width() - bounds.width()) / 2,
+                    sprite_rect.top + (sprite_rect.height() - bounds.height()) / 2,
+                )
+            } else {
+                (sprite_rect.left, sprite_rect.top)
+            };
+            bounds.offset(left, top)
+        };
+
+        let old_clip = port.clip_rgn().clone();
+        port.set_clip(old_clip.intersect(&Region::from(sprite_rect)));
+        port.std_bits(&self.frame, bounds, dst_rect, Pen::SrcCopy as u16, None);
+        port.set_clip(old_clip);
+    }
+
+    /// The total duration of the video.
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        let video = self.container.video.as_ref().map_or_else(Duration::default, |track| {
+            if self.flags.contains(Flags::FRAME_RATE_FIXED) {
+                fixed_frame_time(self.frame_rate, track.samples.len())
+            } else {
+                track.to_duration(track.duration())
+            }
+        });
+        let audio = self.container.audio.as_ref()
+            .map_or_else(Duration::default, |track| track.to_duration(track.duration()));
+        video.max(audio)
+    }
+
+    /// The most recently decoded frame.
+    #[must_use]
+    pub fn frame(&self) -> &PixMap {
+        &self.frame
+    }
+
+    /// The index of the most recently decoded frame.
+    #[must_use]
+    pub fn frame_index(&self) -> Option<usize> {
+        self.next_frame.checked_sub(1)
+    }
+
+    /// Returns whether playback reached the end of a video which does not
+    /// loop.
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        !self.flags.contains(Flags::LOOP) && self.is_at_end()
+    }
+
+    #[must_use]
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    /// Returns playback to the start of the video.
+    pub fn rewind(&mut self) {
+        self.time = Duration::default();
+        self.next_frame = 0;
+        self.next_audio = 0;
+        self.audio.clear();
+    }
+
+    /// The current playback time.
+    #[must_use]
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    fn decode_audio(&mut self) -> AResult<()> {
+        let (track, decoder) = match (&self.container.audio, &mut self.audio_decoder) {
+            (Some(track), Some(decoder)) => (track, decoder),
+            _ => return Ok(()),
+        };
+
+        while let Some(sample) = track.samples.get(self.next_audio) {
+            if track.to_duration(sample.time) > self.time {
+                break;
+            }
+            let (index, data) = (self.next_audio, read_sample(&mut self.input, sample)?);
+            decoder.decode(&data, &mut self.audio)
+                .with_context(|| format!("Can’t decode audio sample {}", index))?;
+            self.next_audio += 1;
+        }
+
+        Ok(())
+    }
+
+    fn decode_video(&mut self) -> AResult<bool> {
+        let (track, decoder) = match (&self.container.video, &mut self.video_decoder) {
+            (Some(track), Some(decoder)) => (track, decoder),
+            _ => return Ok(false),
+        };
+
+        let (flags, frame_rate) = (self.flags, self.frame_rate);
+        let frame_time = |index: usize| if flags.contains(Flags::FRAME_RATE_FIXED) {
+            fixed_frame_time(frame_rate, index)
+        } else {
+            track.samples.get(index).map_or_else(Duration::default, |sample| track.to_duration(sample.time))
+        };
+
+        // Playing every frame and playing at maximum speed both show at
+        // most one new frame at a time, without skipping
+        let last_frame = if flags.contains(Flags::FRAME_RATE_MAXIMUM) {
+            self.next_frame
+        } else if flags.contains(Flags::PLAY_EVERY_FRAME) {
+            if self.next_frame < track.samples.len() && frame_time(self.next_frame) <= self.time {
+                self.time = frame_time(self.next_frame);
+                self.next_frame
+            } else {
+                return Ok(false);
+            }
+        } else {
+            let time = self.time;
+            match (0..track.samples.len()).rev().find(|&index| frame_time(index) <= time) {
+                Some(index) if index >= self.next_frame => index,
+                _ => return Ok(false),
+            }
+        };
+
+        let last_frame = last_frame.min(track.samples.len());
+        let mut changed = false;
+        while self.next_frame <= last_frame && self.next_frame < track.samples.len() {
+            let sample = &track.samples[self.next_frame];
+            if sample.size != 0 {
+                let (index, data) = (self.next_frame, read_sample(&mut self.input, sample)?);
+                decoder.decode(&data, &mut self.frame)
+                    .with_context(|| format!("Can’t decode video frame {}", index))?;
+                changed = true;
+            }
+            self.next_frame += 1;
+        }
+
+        Ok(changed)
+    }
+
+    fn is_at_end(&self) -> bool {
+        let video_done = self.video_decoder.is_none()
+            || self.container.video.as_ref().map_or(true, |track| self.next_frame >= track.samples.len());
+        let audio_done = self.audio_decoder.is_none()
+            || self.container.audio.as_ref().map_or(true, |track| self.next_audio >= track.samples.len());
+        video_done && audio_done
+    }
+}
+
+/// Returns the time of the given frame at a fixed frame rate.
+fn fixed_frame_time(frame_rate: u8, index: usize) -> Duration {
+    if frame_rate == 0 {
+        Duration::default()
+    } else {
+        Duration::from_nanos(index as u64 * 1_000_000_000 / u64::from(frame_rate))
+    }
+}
+
+fn read_sample(input: &mut impl Reader, sample: &Sample) -> AResult<Vec<u8>> {
+    let len = input.len().context("Can’t get media file size")?;
+    if sample.offset.checked_add(u64::from(sample.size)).map_or(true, |end| end > len) {
+        bail!("{} byte media sample at {} is past the end of the file", sample.size, sample.offset);
+    }
+
+    input.seek(SeekFrom::Start(sample.offset))
+        .with_context(|| format!("Can’t seek to media sample at {}", sample.offset))?;
+    let mut data = vec![ 0; usize::try_from(sample.size).context("Media sample is too large")? ];
+    input.read_exact(&mut data)
+        .with_context(|| format!("Can’t read {} byte media sample at {}", sample.size, sample.offset))?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use std::io::Cursor;
+    use super::*;
+
+    fn sample(offset: u64, size: u32) -> Sample {
+        Sample { offset, size, time: 0, duration: 1, key_frame: true }
+    }
+
+    #[test]
+    fn read_sample_checks_size() {
+        let mut input = Cursor::new(vec![ 1, 2, 3, 4 ]);
+        assert_eq!(read_sample(&mut input, &sample(1, 3)).unwrap(), vec![ 2, 3, 4 ]);
+        assert!(read_sample(&mut input, &sample(1, 4)).is_err());
+        assert!(read_sample(&mut input, &sample(0, u32::MAX)).is_err());
+        assert!(read_sample(&mut input, &sample(u64::MAX, 1)).is_err());
+    }
+
+    #[test]
+    fn track_duration() {
+        let track = Track {
+            format: (),
+            time_scale: 600,
+            samples: vec![ Sample { time: 0, duration: 300, ..sample(0, 0) }, Sample { time: 300, duration: 600, ..sample(0, 0) } ],
+        };
+        assert_eq!(track.duration(), 900);
+        assert_eq!(track.to_duration(900), Duration::from_millis(1500));
+        assert_eq!(Track { time_scale: 0, ..track }.to_duration(900), Duration::default());
+        assert_eq!(fixed_frame_time(10, 5), Duration::from_millis(500));
+        assert_eq!(fixed_frame_time(0, 5), Duration::default());
+    }
+}
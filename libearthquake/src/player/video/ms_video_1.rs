@@ -0,0 +1,177 @@
+//! A decoder for Microsoft Video 1 (`CRAM`).
+//!
+//! Frames are divided into 4x4 blocks, starting from the bottom-left corner.
+//! Each block is either skipped, filled with one colour, or drawn using two
+//! colours, or two colours for each quadrant, selected by a bit mask.
+
+use anyhow::{bail, Result as AResult};
+use byteorder::{LittleEndian, ReadBytesExt};
+use libmactoolbox::quickdraw::{ColorTable, PixMap, RGBColor};
+use std::io::Cursor;
+use super::{palette_color, rgb555, VideoDecoder, VideoFormat};
+
+#[derive(Debug)]
+pub(super) struct Decoder {
+    depth: u16,
+    palette: ColorTable,
+}
+
+impl Decoder {
+    pub(super) fn new(format: &VideoFormat) -> AResult<Self> {
+        match format.depth {
+            8 | 16 => {},
+            depth => bail!("Unsupported Microsoft Video 1 depth {}", depth),
+        }
+
+        Ok(Self {
+            depth: format.depth,
+            palette: format.palette.clone().unwrap_or_default(),
+        })
+    }
+
+    fn read_color(&self, input: &mut Cursor<&[u8]>) -> AResult<RGBColor> {
+        Ok(if self.depth == 8 {
+            palette_color(&self.palette, input.read_u8()?)
+        } else {
+            rgb555(input.read_u16::<LittleEndian>()?)
+        })
+    }
+
+    fn read_colors(&self, input: &mut Cursor<&[u8]>, colors: &mut [RGBColor]) -> AResult<()> {
+        for color in colors {
+            *color = self.read_color(input)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the index of the colour for a pixel in a block with two colours
+/// for each quadrant.
+fn quadrant_color(x: i16, y: i16, bit: u16) -> usize {
+    (((y & 2) << 1) + (x & 2)) as usize + usize::from(bit ^ 1)
+}
+
+impl VideoDecoder for Decoder {
+    fn decode(&mut self, data: &[u8], frame: &mut PixMap) -> AResult<()> {
+        let bounds = frame.bounds();
+        let blocks_wide = bounds.width() / 4;
+        let blocks_high = bounds.height() / 4;
+        let mut blocks_left = i32::from(blocks_wide) * i32::from(blocks_high);
+        let mut skip_blocks = 0_u16;
+        let mut input = Cursor::new(data);
+
+        for block_y in (0..blocks_high).rev() {
+            for block_x in 0..blocks_wide {
+                if skip_blocks != 0 {
+                    skip_blocks -= 1;
+                    blocks_left -= 1;
+                    continue;
+                }
+
+                let left = bounds.left + block_x * 4;
+                // Rows within each block are also stored from bottom to top
+                let bottom = bounds.top + block_y * 4 + 3;
+                let mut draw = |color_at: &dyn Fn(i16, i16, u16) -> RGBColor, flags: u16| {
+                    let mut flags = flags;
+                    for pixel_y in 0..4 {
+                        for pixel_x in 0..4 {
+                            frame.set_pixel(left + pixel_x, bottom - pixel_y, color_at(pixel_x, pixel_y, flags & 1));
+                            flags >>= 1;
+                        }
+                    }
+                };
+
+                let byte_a = input.read_u8()?;
+                let byte_b = input.read_u8()?;
+                let flags = u16::from_le_bytes([ byte_a, byte_b ]);
+
+                if byte_a == 0 && byte_b == 0 && blocks_left == 0 {
+                    return Ok(());
+                } else if byte_b & 0xfc == 0x84 {
+                    skip_blocks = (((u16::from(byte_b) - 0x84) << 8) + u16::from(byte_a)).saturating_sub(1);
+                } else if byte_b < 0x80 {
+                    let mut colors = [ RGBColor::BLACK; 8 ];
+                    // In 16-bit video, the high bit of the first colour
+                    // selects a block with two colours for each quadrant
+                    let eight_colors = if self.depth == 16 {
+                        let first = input.read_u16::<LittleEndian>()?;
+                        colors[0] = rgb555(first);
+                        first & 0x8000 != 0
+                    } else {
+                        colors[0] = self.read_color(&mut input)?;
+                        false
+                    };
+                    colors[1] = self.read_color(&mut input)?;
+                    if eight_colors {
+                        self.read_colors(&mut input, &mut colors[2..])?;
+                        draw(&|x, y, bit| colors[quadrant_color(x, y, bit)], flags);
+                    } else {
+                        draw(&|_, _, bit| colors[usize::from(bit ^ 1)], flags);
+                    }
+                } else if self.depth == 8 && byte_b >= 0x90 {
+                    let mut colors = [ RGBColor::BLACK; 8 ];
+                    self.read_colors(&mut input, &mut colors)?;
+                    draw(&|x, y, bit| colors[quadrant_color(x, y, bit)], flags);
+                } else {
+                    let color = if self.depth == 8 {
+                        palette_color(&self.palette, byte_a)
+                    } else {
+                        rgb555(flags)
+                    };
+                    draw(&|_, _, _| color, 0);
+                }
+
+                blocks_left -= 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use libmactoolbox::Rect;
+    use super::super::VideoCodec;
+    use super::*;
+
+    const RED: RGBColor = RGBColor { r: 0xffff, g: 0, b: 0 };
+    const BLUE: RGBColor = RGBColor { r: 0, g: 0, b: 0xffff };
+
+    fn decode(data: &[u8]) -> PixMap {
+        let format = VideoFormat { codec: VideoCodec::MsVideo1, width: 4, height: 4, depth: 16, palette: None };
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 4, right: 4 }, RGBColor::WHITE);
+        Decoder::new(&format).unwrap().decode(data, &mut frame).unwrap();
+        frame
+    }
+
+    #[test]
+    fn fill() {
+        let frame = decode(&[ 0x00, 0xfc ]);
+        assert_eq!(frame.pixel(0, 0), Some(RED));
+        assert_eq!(frame.pixel(3, 3), Some(RED));
+    }
+
+    #[test]
+    fn two_colors() {
+        // The first four flag bits are the bottom row of the block
+        let frame = decode(&[ 0x0f, 0x00, 0x00, 0x7c, 0x1f, 0x00 ]);
+        assert_eq!(frame.pixel(0, 3), Some(RED));
+        assert_eq!(frame.pixel(3, 3), Some(RED));
+        assert_eq!(frame.pixel(0, 0), Some(BLUE));
+        assert_eq!(frame.pixel(2, 2), Some(BLUE));
+    }
+
+    #[test]
+    fn skip() {
+        let frame = decode(&[ 0x01, 0x84 ]);
+        assert_eq!(frame.pixel(0, 0), Some(RGBColor::WHITE));
+    }
+
+    #[test]
+    fn unsupported_depth() {
+        let format = VideoFormat { codec: VideoCodec::MsVideo1, width: 4, height: 4, depth: 24, palette: None };
+        assert!(Decoder::new(&format).is_err());
+    }
+}
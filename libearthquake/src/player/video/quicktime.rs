@@ -0,0 +1,490 @@
+//! A reader for `QuickTime` movie files.
+//!
+//! A movie file is a tree of atoms. The `moov` atom describes each track,
+//! including a sample table which gives the location and timing of every
+//! sample of media in the file.
+
+use anyhow::{bail, Context, ensure, Result as AResult};
+use byteorder::{BigEndian, ReadBytesExt};
+use libcommon::Reader;
+use libmactoolbox::{OSType, OSTypeReadExt, quickdraw::{ColorTable, RGBColor}};
+use std::{convert::TryFrom, io::{Cursor, Read, SeekFrom}};
+use super::{AudioCodec, AudioFormat, Container, Sample, Track, VideoCodec, VideoFormat};
+
+#[derive(Clone, Copy, Debug)]
+struct Atom {
+    kind: OSType,
+    /// The position of the data of the atom, after its header.
+    offset: u64,
+    /// The size of the data of the atom, excluding its header.
+    size: u64,
+}
+
+impl Atom {
+    fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+}
+
+/// Reads the headers of the atoms between `start` and `end`.
+fn read_atoms(input: &mut impl Reader, start: u64, end: u64) -> AResult<Vec<Atom>> {
+    let mut atoms = Vec::new();
+    let mut pos = start;
+    while pos + 8 <= end {
+        input.seek(SeekFrom::Start(pos))?;
+        let size = input.read_u32::<BigEndian>()?;
+        let kind = input.read_os_type::<BigEndian>()?;
+        let (header_size, size) = match size {
+            0 => (8, end - pos),
+            1 => (16, input.read_u64::<BigEndian>()?),
+            size => (8, u64::from(size)),
+        };
+        ensure!(size >= header_size && pos + size <= end, "Invalid size {} for atom {} at {}", size, kind, pos);
+        atoms.push(Atom { kind, offset: pos + header_size, size: size - header_size });
+        pos += size;
+    }
+    Ok(atoms)
+}
+
+/// Reads the headers of the atoms inside the given atom.
+fn read_children(input: &mut impl Reader, atom: Atom) -> AResult<Vec<Atom>> {
+    read_atoms(input, atom.offset, atom.end())
+        .with_context(|| format!("Can’t read children of atom {}", atom.kind))
+}
+
+fn find(atoms: &[Atom], kind: &[u8]) -> Option<Atom> {
+    atoms.iter().copied().find(|atom| atom.kind.as_bytes()[..] == *kind)
+}
+
+fn read_data(input: &mut impl Reader, atom: Atom) -> AResult<Cursor<Vec<u8>>> {
+    input.seek(SeekFrom::Start(atom.offset))?;
+    let mut data = vec![ 0; usize::try_from(atom.size)? ];
+    input.read_exact(&mut data)
+        .with_context(|| format!("Can’t read {} atom data", atom.kind))?;
+    Ok(Cursor::new(data))
+}
+
+/// The sample table of a track.
+#[derive(Debug, Default)]
+struct SampleTable {
+    /// The first sample description of the track.
+    description: Vec<u8>,
+    /// Runs of samples with the same duration, as (count, duration).
+    time_to_sample: Vec<(u32, u32)>,
+    /// The 1-based numbers of samples which are key frames, or `None` if
+    /// every sample is a key frame.
+    sync_samples: Option<Vec<u32>>,
+    /// Runs of chunks with the same number of samples, as (1-based first
+    /// chunk, samples per chunk).
+    sample_to_chunk: Vec<(u32, u32)>,
+    /// The size of every sample, or zero if samples have different sizes.
+    sample_size: u32,
+    sample_sizes: Vec<u32>,
+    sample_count: u32,
+    chunk_offsets: Vec<u64>,
+}
+
+impl SampleTable {
+    fn read(input: &mut impl Reader, stbl: Atom) -> AResult<Self> {
+        let mut table = Self::default();
+        for atom in read_children(input, stbl)? {
+            let mut data = match atom.kind.as_bytes() {
+                b"stsd" | b"stts" | b"stss" | b"stsc" | b"stsz" | b"stco" | b"co64" => read_data(input, atom)?,
+                _ => continue,
+            };
+
+            // Version and flags
+            data.read_u32::<BigEndian>()?;
+
+            match atom.kind.as_bytes() {
+                b"stsd" => {
+                    if data.read_u32::<BigEndian>()? != 0 {
+                        let size = data.read_u32::<BigEndian>()?;
+                        let start = usize::try_from(data.position())? - 4;
+                        let end = data.get_ref().len().min(start + usize::try_from(size)?);
+                        table.description = data.get_ref()[start..end].to_vec();
+                    }
+                },
+                b"stts" => {
+                    let count = data.read_u32::<BigEndian>()?;
+                    for _ in 0..count {
+                        table.time_to_sample.push((data.read_u32::<BigEndian>()?, data.read_u32::<BigEndian>()?));
+                    }
+                },
+                b"stss" => {
+                    let count = data.read_u32::<BigEndian>()?;
+                    let mut sync_samples = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        sync_samples.push(data.read_u32::<BigEndian>()?);
+                    }
+                    table.sync_samples = Some(sync_samples);
+                },
+                b"stsc" => {
+                    let count = data.read_u32::<BigEndian>()?;
+                    for _ in 0..count {
+                        let first_chunk = data.read_u32::<BigEndian>()?;
+                        let samples_per_chunk = data.read_u32::<BigEndian>()?;
+                        // Sample description ID
+                        data.read_u32::<BigEndian>()?;
+                        table.sample_to_chunk.push((first_chunk, samples_per_chunk));
+                    }
+                },
+                b"stsz" => {
+                    table.sample_size = data.read_u32::<BigEndian>()?;
+                    table.sample_count = data.read_u32::<BigEndian>()?;
+                    if table.sample_size == 0 {
+                        for _ in 0..table.sample_count {
+                            table.sample_sizes.push(data.read_u32::<BigEndian>()?);
+                        }
+                    }
+                },
+                b"stco" => {
+                    let count = data.read_u32::<BigEndian>()?;
+                    for _ in 0..count {
+                        table.chunk_offsets.push(data.read_u32::<BigEndian>()?.into());
+                    }
+                },
+                _ => {
+                    let count = data.read_u32::<BigEndian>()?;
+                    for _ in 0..count {
+                        table.chunk_offsets.push(data.read_u64::<BigEndian>()?);
+                    }
+                },
+            }
+        }
+
+        ensure!(!table.description.is_empty(), "Missing sample description");
+        Ok(table)
+    }
+
+    /// Returns the number of samples in each chunk.
+    fn chunk_sample_counts(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.chunk_offsets.len()).map(move |index| {
+            let chunk = index as u32 + 1;
+            self.sample_to_chunk.iter()
+                .rev()
+                .find(|&&(first_chunk, _)| first_chunk <= chunk)
+                .map_or(0, |&(_, count)| count)
+        })
+    }
+
+    /// Returns the duration of each sample.
+    fn durations(&self) -> impl Iterator<Item = u32> + '_ {
+        self.time_to_sample.iter().flat_map(|&(count, duration)| std::iter::repeat(duration).take(count as usize))
+    }
+
+    /// Builds the list of samples of a video track.
+    fn video_samples(&self) -> Vec<Sample> {
+        let mut samples = Vec::with_capacity(self.sample_count as usize);
+        for (&chunk_offset, count) in self.chunk_offsets.iter().zip(self.chunk_sample_counts()) {
+            let mut offset = chunk_offset;
+            for _ in 0..count {
+                let index = samples.len();
+                if index >= self.sample_count as usize {
+                    break;
+                }
+                let size = if self.sample_size == 0 { self.sample_sizes[index] } else { self.sample_size };
+                samples.push(Sample { offset, size, time: 0, duration: 0, key_frame: true });
+                offset += u64::from(size);
+            }
+        }
+
+        let mut time = 0;
+        for (sample, duration) in samples.iter_mut().zip(self.durations()) {
+            sample.time = time;
+            sample.duration = duration;
+            time += u64::from(duration);
+        }
+
+        if let Some(sync_samples) = &self.sync_samples {
+            for (index, sample) in samples.iter_mut().enumerate() {
+                sample.key_frame = sync_samples.contains(&(index as u32 + 1));
+            }
+        }
+
+        samples
+    }
+
+    /// Builds the list of samples of a sound track. Each chunk of sound
+    /// becomes one sample.
+    fn audio_samples(&self, bytes_for_samples: impl Fn(u32) -> u32) -> Vec<Sample> {
+        let sample_duration = self.time_to_sample.first().map_or(1, |&(_, duration)| duration);
+        let mut time = 0;
+        self.chunk_offsets.iter().zip(self.chunk_sample_counts()).map(|(&offset, count)| {
+            let duration = count * sample_duration;
+            let sample = Sample { offset, size: bytes_for_samples(count), time, duration, key_frame: true };
+            time += u64::from(duration);
+            sample
+        }).collect()
+    }
+}
+
+/// The levels of the red, green, blue, and grey ramps of the default 8-bit
+/// colour table.
+const RAMP: [ u16; 10 ] = [ 0xeeee, 0xdddd, 0xbbbb, 0xaaaa, 0x8888, 0x7777, 0x5555, 0x4444, 0x2222, 0x1111 ];
+
+/// Returns the default colour table for 8-bit video, which is the standard
+/// Macintosh 256-colour palette.
+fn default_palette() -> ColorTable {
+    let mut entries = Vec::with_capacity(256);
+    for r in 0..6 {
+        for g in 0..6 {
+            for b in 0..6 {
+                let level = |value: u16| 0xffff - value * 0x3333;
+                entries.push(RGBColor { r: level(r), g: level(g), b: level(b) });
+            }
+        }
+    }
+    // The last colour of the cube is black, which goes at the end instead
+    entries.pop();
+
+    entries.extend(RAMP.iter().map(|&r| RGBColor { r, g: 0, b: 0 }));
+    entries.extend(RAMP.iter().map(|&g| RGBColor { r: 0, g, b: 0 }));
+    entries.extend(RAMP.iter().map(|&b| RGBColor { r: 0, g: 0, b }));
+    entries.extend(RAMP.iter().map(|&value| RGBColor { r: value, g: value, b: value }));
+    entries.push(RGBColor::BLACK);
+
+    ColorTable::new(entries.into_iter().enumerate().map(|(index, color)| (index as u16, color)).collect())
+}
+
+/// Reads the format of a video sample description.
+fn read_video_format(description: &[u8]) -> AResult<VideoFormat> {
+    let mut data = Cursor::new(description);
+    data.read_u32::<BigEndian>()?;
+    let codec = data.read_os_type::<BigEndian>()?;
+    // Reserved, data reference index, version, revision, vendor, temporal
+    // quality, spatial quality
+    data.set_position(data.position() + 6 + 2 + 2 + 2 + 4 + 4 + 4);
+    let width = data.read_i16::<BigEndian>()?;
+    let height = data.read_i16::<BigEndian>()?;
+    // Horizontal resolution, vertical resolution, data size, frame count,
+    // compressor name
+    data.set_position(data.position() + 4 + 4 + 4 + 2 + 32);
+    let depth = data.read_u16::<BigEndian>()?;
+    let color_table_id = data.read_i16::<BigEndian>()?;
+
+    // Depths above 32 are greyscale
+    let depth = if depth > 32 { depth - 32 } else { depth };
+
+    let palette = if color_table_id == 0 {
+        // Seed, flags
+        data.read_u32::<BigEndian>()?;
+        data.read_u16::<BigEndian>()?;
+        let count = u32::from(data.read_u16::<BigEndian>()?) + 1;
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let value = data.read_u16::<BigEndian>()?;
+            let r = data.read_u16::<BigEndian>()?;
+            let g = data.read_u16::<BigEndian>()?;
+            let b = data.read_u16::<BigEndian>()?;
+            entries.push((value, RGBColor { r, g, b }));
+        }
+        Some(ColorTable::new(entries))
+    } else if depth == 8 {
+        Some(default_palette())
+    } else {
+        None
+    };
+
+    let codec = match codec.as_bytes() {
+        b"raw " => VideoCodec::Raw { bottom_up: false, little_endian: false },
+        b"rle " => VideoCodec::AppleAnimation,
+        b"rpza" => VideoCodec::AppleVideo,
+        b"cvid" => VideoCodec::Cinepak,
+        _ => VideoCodec::Unknown(codec),
+    };
+
+    Ok(VideoFormat { codec, width, height, depth, palette })
+}
+
+/// The size of the compressed data for a number of sound samples.
+#[derive(Clone, Copy, Debug)]
+struct SoundPacking {
+    samples_per_packet: u32,
+    bytes_per_frame: u32,
+}
+
+/// Reads the format of a sound sample description.
+fn read_audio_format(description: &[u8]) -> AResult<(AudioFormat, SoundPacking)> {
+    let mut data = Cursor::new(description);
+    data.read_u32::<BigEndian>()?;
+    let codec = data.read_os_type::<BigEndian>()?;
+    // Reserved, data reference index
+    data.set_position(data.position() + 6 + 2);
+    let version = data.read_u16::<BigEndian>()?;
+    // Revision, vendor
+    data.set_position(data.position() + 2 + 4);
+    let channels = data.read_u16::<BigEndian>()?;
+    let bits_per_sample = data.read_u16::<BigEndian>()?;
+    // Compression ID, packet size
+    data.set_position(data.position() + 2 + 2);
+    let sample_rate = data.read_u32::<BigEndian>()? >> 16;
+
+    let (codec, block_align, default_packing) = match codec.as_bytes() {
+        b"ima4" => (AudioCodec::QuickTimeIma, 34 * channels, SoundPacking { samples_per_packet: 64, bytes_per_frame: 34 * u32::from(channels) }),
+        b"twos" | b"sowt" | b"raw " => {
+            let codec = match codec.as_bytes() {
+                b"twos" => AudioCodec::Pcm { signed: true, big_endian: true },
+                b"sowt" => AudioCodec::Pcm { signed: true, big_endian: false },
+                _ => AudioCodec::Pcm { signed: false, big_endian: true },
+            };
+            let frame_size = channels * bits_per_sample / 8;
+            (codec, frame_size, SoundPacking { samples_per_packet: 1, bytes_per_frame: frame_size.into() })
+        },
+        _ => (AudioCodec::Unknown(u32::from_be_bytes(*codec.as_bytes())), 0, SoundPacking { samples_per_packet: 1, bytes_per_frame: 0 }),
+    };
+
+    let packing = if version == 1 {
+        let samples_per_packet = data.read_u32::<BigEndian>()?;
+        // Bytes per packet
+        data.read_u32::<BigEndian>()?;
+        let bytes_per_frame = data.read_u32::<BigEndian>()?;
+        if samples_per_packet == 0 || bytes_per_frame == 0 {
+            default_packing
+        } else {
+            SoundPacking { samples_per_packet, bytes_per_frame }
+        }
+    } else {
+        default_packing
+    };
+
+    Ok((AudioFormat { codec, channels, sample_rate, bits_per_sample, block_align }, packing))
+}
+
+/// Reads the media handler type, time scale, and sample table of a track.
+fn read_media(input: &mut impl Reader, trak: Atom) -> AResult<Option<([u8; 4], u32, SampleTable)>> {
+    let mdia = match find(&read_children(input, trak)?, b"mdia") {
+        Some(mdia) => mdia,
+        None => return Ok(None),
+    };
+    let children = read_children(input, mdia)?;
+
+    let mut mdhd = read_data(input, find(&children, b"mdhd").context("Missing media header")?)?;
+    let version = mdhd.read_u8()?;
+    // Flags, creation time, modification time
+    let times_size = if version == 1 { 16 } else { 8 };
+    mdhd.set_position(mdhd.position() + 3 + times_size);
+    let time_scale = mdhd.read_u32::<BigEndian>()?;
+
+    let mut hdlr = read_data(input, find(&children, b"hdlr").context("Missing media handler")?)?;
+    // Version, flags, component type
+    hdlr.set_position(8);
+    let mut handler = [ 0; 4 ];
+    hdlr.read_exact(&mut handler)?;
+
+    let minf = find(&children, b"minf").context("Missing media information")?;
+    let stbl = find(&read_children(input, minf)?, b"stbl").context("Missing sample table")?;
+    let table = SampleTable::read(input, stbl).context("Can’t read sample table")?;
+
+    Ok(Some((handler, time_scale, table)))
+}
+
+pub(super) fn read(input: &mut impl Reader) -> AResult<Container> {
+    let end = input.len()?;
+    let atoms = read_atoms(input, 0, end)?;
+    let moov = match find(&atoms, b"moov") {
+        Some(moov) => moov,
+        None => bail!("Missing movie atom"),
+    };
+
+    let mut container = Container::default();
+    for (index, trak) in read_children(input, moov)?.into_iter().filter(|atom| atom.kind.as_bytes() == b"trak").enumerate() {
+        let (handler, time_scale, table) = match read_media(input, trak).with_context(|| format!("Can’t read track {}", index))? {
+            Some(media) => media,
+            None => continue,
+        };
+
+        match &handler {
+            b"vide" if container.video.is_none() => {
+                let format = read_video_format(&table.description).context("Can’t read video format")?;
+                container.video = Some(Track { format, time_scale, samples: table.video_samples() });
+            },
+            b"soun" if container.audio.is_none() => {
+                let (format, packing) = read_audio_format(&table.description).context("Can’t read sound format")?;
+                let samples = table.audio_samples(|count| count / packing.samples_per_packet * packing.bytes_per_frame);
+                container.audio = Some(Track { format, time_scale, samples });
+            },
+            _ => {},
+        }
+    }
+
+    Ok(container)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    fn atom(kind: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        let data = children.concat();
+        let mut atom = (data.len() as u32 + 8).to_be_bytes().to_vec();
+        atom.extend_from_slice(kind);
+        atom.extend(data);
+        atom
+    }
+
+    fn table(values: &[u32]) -> Vec<u8> {
+        let mut data = vec![ 0; 4 ];
+        for value in values {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn read_video() {
+        let mut description = Vec::new();
+        description.extend_from_slice(&86_u32.to_be_bytes());
+        description.extend_from_slice(b"raw ");
+        description.extend_from_slice(&[ 0; 24 ]);
+        description.extend_from_slice(&[ 0, 2, 0, 1 ]);
+        description.extend_from_slice(&[ 0; 46 ]);
+        description.extend_from_slice(&[ 0, 16, 0xff, 0xff ]);
+        let mut stsd = table(&[ 1 ]);
+        stsd.extend(description);
+
+        let mdhd = table(&[ 0, 0, 600, 1200 ]);
+        let hdlr = [ &[ 0; 8 ][..], b"vide" ].concat();
+
+        let moov = atom(b"moov", &[
+            atom(b"trak", &[
+                atom(b"mdia", &[
+                    atom(b"mdhd", &[ mdhd ]),
+                    atom(b"hdlr", &[ hdlr ]),
+                    atom(b"minf", &[
+                        atom(b"stbl", &[
+                            atom(b"stsd", &[ stsd ]),
+                            atom(b"stts", &[ table(&[ 1, 2, 600 ]) ]),
+                            atom(b"stsc", &[ table(&[ 1, 1, 2, 1 ]) ]),
+                            atom(b"stsz", &[ table(&[ 4, 2 ]) ]),
+                            atom(b"stco", &[ table(&[ 1, 8 ]) ]),
+                        ]),
+                    ]),
+                ]),
+            ]),
+        ]);
+        let file = [ atom(b"mdat", &[ vec![ 0x7c, 0, 0, 0x1f, 0, 0, 0, 0 ] ]), moov ].concat();
+
+        let container = read(&mut Cursor::new(file)).unwrap();
+        assert!(container.audio.is_none());
+        let track = container.video.unwrap();
+        assert_eq!(track.format.codec, VideoCodec::Raw { bottom_up: false, little_endian: false });
+        assert_eq!((track.format.width, track.format.height, track.format.depth), (2, 1, 16));
+        assert_eq!(track.time_scale, 600);
+        assert_eq!(track.samples, vec![
+            Sample { offset: 8, size: 4, time: 0, duration: 600, key_frame: true },
+            Sample { offset: 12, size: 4, time: 600, duration: 600, key_frame: true },
+        ]);
+        assert_eq!(track.to_duration(track.duration()).as_secs(), 2);
+    }
+
+    #[test]
+    fn palette() {
+        let palette = default_palette();
+        assert_eq!(palette.len(), 256);
+        assert_eq!(palette.get(0), Some(RGBColor::WHITE));
+        assert_eq!(palette.get(215), Some(RGBColor { r: 0xeeee, g: 0, b: 0 }));
+        assert_eq!(palette.get(255), Some(RGBColor::BLACK));
+    }
+}
@@ -0,0 +1,108 @@
+//! A decoder for uncompressed video.
+
+use anyhow::{bail, ensure, Result as AResult};
+use libmactoolbox::quickdraw::{ColorTable, PixMap};
+use super::{palette_color, rgb555, rgb888, VideoDecoder, VideoFormat};
+
+#[derive(Debug)]
+pub(super) struct Decoder {
+    depth: u16,
+    bottom_up: bool,
+    little_endian: bool,
+    palette: ColorTable,
+}
+
+impl Decoder {
+    pub(super) fn new(format: &VideoFormat, bottom_up: bool, little_endian: bool) -> AResult<Self> {
+        match format.depth {
+            8 | 16 | 24 | 32 => {},
+            depth => bail!("Unsupported uncompressed video depth {}", depth),
+        }
+
+        Ok(Self {
+            depth: format.depth,
+            bottom_up,
+            little_endian,
+            palette: format.palette.clone().unwrap_or_default(),
+        })
+    }
+}
+
+impl VideoDecoder for Decoder {
+    fn decode(&mut self, data: &[u8], frame: &mut PixMap) -> AResult<()> {
+        let bounds = frame.bounds();
+        if bounds.is_empty() {
+            return Ok(());
+        }
+
+        let (width, height) = (bounds.width() as usize, bounds.height() as usize);
+        let bytes_per_pixel = usize::from(self.depth / 8);
+        // AVI rows are padded to 4 bytes and QuickTime rows to 2 bytes
+        let alignment = if self.little_endian { 4 } else { 2 };
+        let row_bytes = (width * bytes_per_pixel + alignment - 1) / alignment * alignment;
+        ensure!(data.len() >= row_bytes * height, "Uncompressed frame is {} bytes, expected {}", data.len(), row_bytes * height);
+
+        for (row_index, row) in data.chunks_exact(row_bytes).take(height).enumerate() {
+            let y = if self.bottom_up { height - 1 - row_index } else { row_index };
+            for (x, pixel) in row.chunks_exact(bytes_per_pixel).take(width).enumerate() {
+                let color = match (self.depth, self.little_endian) {
+                    (8, _) => palette_color(&self.palette, pixel[0]),
+                    (16, false) => rgb555(u16::from_be_bytes([ pixel[0], pixel[1] ])),
+                    (16, true) => rgb555(u16::from_le_bytes([ pixel[0], pixel[1] ])),
+                    (24, false) => rgb888(pixel[0], pixel[1], pixel[2]),
+                    (32, false) => rgb888(pixel[1], pixel[2], pixel[3]),
+                    // 24- and 32-bit little-endian pixels are BGR(A)
+                    _ => rgb888(pixel[2], pixel[1], pixel[0]),
+                };
+                frame.set_pixel(bounds.left + x as i16, bounds.top + y as i16, color);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use libmactoolbox::{quickdraw::RGBColor, Rect};
+    use super::super::VideoCodec;
+    use super::*;
+
+    const RED: RGBColor = RGBColor { r: 0xffff, g: 0, b: 0 };
+    const GREEN: RGBColor = RGBColor { r: 0, g: 0xffff, b: 0 };
+    const BLUE: RGBColor = RGBColor { r: 0, g: 0, b: 0xffff };
+
+    fn format(width: i16, height: i16, depth: u16, bottom_up: bool, little_endian: bool) -> VideoFormat {
+        VideoFormat { codec: VideoCodec::Raw { bottom_up, little_endian }, width, height, depth, palette: None }
+    }
+
+    #[test]
+    fn quicktime_16() {
+        let format = format(2, 2, 16, false, false);
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 2, right: 2 }, RGBColor::BLACK);
+        Decoder::new(&format, false, false).unwrap()
+            .decode(&[ 0x7c, 0x00, 0x00, 0x1f, 0x03, 0xe0, 0x7f, 0xff ], &mut frame).unwrap();
+        assert_eq!(frame.pixel(0, 0), Some(RED));
+        assert_eq!(frame.pixel(1, 0), Some(BLUE));
+        assert_eq!(frame.pixel(0, 1), Some(GREEN));
+        assert_eq!(frame.pixel(1, 1), Some(RGBColor::WHITE));
+    }
+
+    #[test]
+    fn avi_24() {
+        // Rows are stored bottom to top in BGR order and padded to 4 bytes
+        let format = format(1, 2, 24, true, true);
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 2, right: 1 }, RGBColor::BLACK);
+        let mut decoder = Decoder::new(&format, true, true).unwrap();
+        decoder.decode(&[ 0x00, 0x00, 0xff, 0, 0xff, 0x00, 0x00, 0 ], &mut frame).unwrap();
+        assert_eq!(frame.pixel(0, 1), Some(RED));
+        assert_eq!(frame.pixel(0, 0), Some(BLUE));
+        assert!(decoder.decode(&[ 0; 7 ], &mut frame).is_err());
+    }
+
+    #[test]
+    fn unsupported_depth() {
+        assert!(Decoder::new(&format(1, 1, 4, false, false), false, false).is_err());
+    }
+}
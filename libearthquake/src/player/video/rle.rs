@@ -0,0 +1,203 @@
+//! A decoder for Apple Animation (`rle `).
+//!
+//! Each frame updates a range of lines. Each line starts with a count of
+//! pixels to skip, followed by runs of repeated or literal pixels.
+
+use anyhow::{bail, Context, Result as AResult};
+use byteorder::{BigEndian, ReadBytesExt};
+use libmactoolbox::quickdraw::{ColorTable, PixMap, RGBColor};
+use std::io::{Cursor, Read};
+use super::{palette_color, rgb555, rgb888, VideoDecoder, VideoFormat};
+
+#[derive(Debug)]
+pub(super) struct Decoder {
+    depth: u16,
+    palette: ColorTable,
+}
+
+impl Decoder {
+    const HAS_LINE_RANGE: u16 = 8;
+
+    pub(super) fn new(format: &VideoFormat) -> AResult<Self> {
+        match format.depth {
+            8 | 16 | 24 | 32 => {},
+            depth => bail!("Unsupported Apple Animation depth {}", depth),
+        }
+
+        Ok(Self {
+            depth: format.depth,
+            palette: format.palette.clone().unwrap_or_default(),
+        })
+    }
+
+    /// The number of pixels in each unit of run-length encoded data. At 8
+    /// bits per pixel, runs are made from groups of four pixels.
+    fn pixels_per_unit(&self) -> usize {
+        if self.depth == 8 { 4 } else { 1 }
+    }
+
+    fn read_unit(&self, input: &mut Cursor<&[u8]>, unit: &mut Vec<RGBColor>) -> AResult<()> {
+        unit.clear();
+        match self.depth {
+            8 => {
+                let mut indices = [ 0; 4 ];
+                input.read_exact(&mut indices)?;
+                unit.extend(indices.iter().map(|&index| palette_color(&self.palette, index)));
+            },
+            16 => unit.push(rgb555(input.read_u16::<BigEndian>()?)),
+            24 => {
+                let mut pixel = [ 0; 3 ];
+                input.read_exact(&mut pixel)?;
+                unit.push(rgb888(pixel[0], pixel[1], pixel[2]));
+            },
+            _ => {
+                let mut pixel = [ 0; 4 ];
+                input.read_exact(&mut pixel)?;
+                unit.push(rgb888(pixel[1], pixel[2], pixel[3]));
+            },
+        }
+        Ok(())
+    }
+}
+
+impl VideoDecoder for Decoder {
+    fn decode(&mut self, data: &[u8], frame: &mut PixMap) -> AResult<()> {
+        // Frames smaller than the header do not change anything
+        if data.len() < 8 {
+            return Ok(());
+        }
+
+        let bounds = frame.bounds();
+        let mut input = Cursor::new(data);
+        input.set_position(4);
+        let header = input.read_u16::<BigEndian>()?;
+        let (start_line, line_count) = if header & Self::HAS_LINE_RANGE == 0 {
+            (0, bounds.height())
+        } else {
+            let start_line = input.read_i16::<BigEndian>()?;
+            input.read_u16::<BigEndian>()?;
+            let line_count = input.read_i16::<BigEndian>()?;
+            input.read_u16::<BigEndian>()?;
+            (start_line, line_count)
+        };
+
+        let pixels_per_unit = self.pixels_per_unit() as i16;
+        let mut unit = Vec::with_capacity(4);
+        for line in 0..line_count {
+            let y = start_line.checked_add(line)
+                .and_then(|line| bounds.top.checked_add(line))
+                .with_context(|| format!("Apple Animation line {} is out of range", line))?;
+            let mut x = skip(bounds.left, input.read_u8()?, pixels_per_unit)?;
+
+            loop {
+                let code = input.read_i8()?;
+                match code {
+                    -1 => break,
+                    0 => x = skip(x, input.read_u8()?, pixels_per_unit)?,
+                    code if code < 0 => {
+                        self.read_unit(&mut input, &mut unit)?;
+                        for _ in 0..-i16::from(code) {
+                            x = write_unit(frame, x, y, &unit)?;
+                        }
+                    },
+                    code => {
+                        for _ in 0..code {
+                            self.read_unit(&mut input, &mut unit)?;
+                            x = write_unit(frame, x, y, &unit)?;
+                        }
+                    },
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the position after skipping ahead by a skip code. The encoded
+/// value is one more than the number of units to skip.
+fn skip(x: i16, code: u8, pixels_per_unit: i16) -> AResult<i16> {
+    x.checked_add((i16::from(code) - 1) * pixels_per_unit)
+        .with_context(|| format!("Apple Animation skip {} from {} is out of range", code, x))
+}
+
+/// Writes a unit of pixels at the given position and returns the position
+/// after it.
+fn write_unit(frame: &mut PixMap, mut x: i16, y: i16, unit: &[RGBColor]) -> AResult<i16> {
+    for &color in unit {
+        frame.set_pixel(x, y, color);
+        x = x.checked_add(1).context("Apple Animation run is out of range")?;
+    }
+    Ok(x)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use libmactoolbox::Rect;
+    use super::super::VideoCodec;
+    use super::*;
+
+    #[test]
+    fn decode_16() {
+        let format = VideoFormat { codec: VideoCodec::AppleAnimation, width: 4, height: 2, depth: 16, palette: None };
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 2, right: 4 }, RGBColor::WHITE);
+        let data = [
+            0, 0, 0, 22,
+            0, 8, 0, 1, 0, 0, 0, 1, 0, 0,
+            // Skip one pixel, repeat red twice, copy one blue pixel
+            2, 0xfe, 0x7c, 0x00, 1, 0x00, 0x1f, 0xff,
+        ];
+        Decoder::new(&format).unwrap().decode(&data, &mut frame).unwrap();
+        let red = RGBColor { r: 0xffff, g: 0, b: 0 };
+        let blue = RGBColor { r: 0, g: 0, b: 0xffff };
+        assert_eq!(frame.pixel(0, 0), Some(RGBColor::WHITE));
+        assert_eq!(frame.pixel(0, 1), Some(RGBColor::WHITE));
+        assert_eq!(frame.pixel(1, 1), Some(red));
+        assert_eq!(frame.pixel(2, 1), Some(red));
+        assert_eq!(frame.pixel(3, 1), Some(blue));
+    }
+    #[test]
+    fn decode_zero_skip() {
+        let format = VideoFormat { codec: VideoCodec::AppleAnimation, width: 4, height: 2, depth: 16, palette: None };
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 2, right: 4 }, RGBColor::WHITE);
+        let data = [
+            0, 0, 0, 18,
+            0, 0,
+            // A skip code of zero starts one pixel before the line, so only
+            // the second of these pixels is visible
+            0, 2, 0x7c, 0x00, 0x00, 0x1f, 0xff,
+            // Decoding continues with the next line
+            1, 0xfe, 0x7c, 0x00, 0xff,
+        ];
+        Decoder::new(&format).unwrap().decode(&data, &mut frame).unwrap();
+        let red = RGBColor { r: 0xffff, g: 0, b: 0 };
+        let blue = RGBColor { r: 0, g: 0, b: 0xffff };
+        assert_eq!(frame.pixel(0, 0), Some(blue));
+        assert_eq!(frame.pixel(1, 0), Some(RGBColor::WHITE));
+        assert_eq!(frame.pixel(0, 1), Some(red));
+        assert_eq!(frame.pixel(1, 1), Some(red));
+        assert_eq!(frame.pixel(2, 1), Some(RGBColor::WHITE));
+    }
+
+    #[test]
+    fn decode_out_of_range() {
+        let format = VideoFormat { codec: VideoCodec::AppleAnimation, width: 4, height: 2, depth: 16, palette: None };
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 2, right: 4 }, RGBColor::WHITE);
+        // Starts on the largest line, so the second line is out of range
+        let data = [
+            0, 0, 0, 18,
+            0, 8, 0x7f, 0xff, 0, 0, 0, 2, 0, 0,
+            1, 0xff,
+            1, 0xff,
+        ];
+        assert!(Decoder::new(&format).unwrap().decode(&data, &mut frame).is_err());
+
+        // Skips past the largest coordinate
+        let mut data = vec![ 0, 0, 0, 0, 0, 0, 0xff ];
+        for _ in 0..200 {
+            data.extend_from_slice(&[ 0, 0xff ]);
+        }
+        assert!(Decoder::new(&format).unwrap().decode(&data, &mut frame).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! A decoder for Apple Video (`rpza`).
+//!
+//! Frames are divided into 4x4 blocks which are either skipped, filled with
+//! a single colour, drawn using four colours interpolated from two end
+//! points, or drawn with sixteen explicit colours.
+
+use anyhow::{ensure, Result as AResult};
+use byteorder::{BigEndian, ReadBytesExt};
+use libmactoolbox::quickdraw::{PixMap, RGBColor};
+use std::io::{Cursor, Read};
+use super::{rgb555, VideoDecoder};
+
+#[derive(Debug)]
+pub(super) struct Decoder;
+
+impl Decoder {
+    const FRAME_MARKER: u8 = 0xe1;
+}
+
+/// Interpolates one of the two intermediate colours between two 555 pixels.
+fn interpolate(a: u16, b: u16, weight_a: u16, weight_b: u16) -> u16 {
+    let mut result = 0;
+    for shift in &[ 10, 5, 0 ] {
+        let channel_a = (a >> shift) & 0x1f;
+        let channel_b = (b >> shift) & 0x1f;
+        result |= ((weight_a * channel_a + weight_b * channel_b) >> 5) << shift;
+    }
+    result
+}
+
+struct Blocks<'a> {
+    frame: &'a mut PixMap,
+    blocks_per_row: usize,
+    total: usize,
+    index: usize,
+}
+
+impl Blocks<'_> {
+    fn skip(&mut self, count: usize) {
+        self.index += count;
+    }
+
+    fn draw(&mut self, mut color_at: impl FnMut(usize, usize) -> RGBColor) {
+        if self.index >= self.total {
+            return;
+        }
+
+        let bounds = self.frame.bounds();
+        let left = bounds.left + ((self.index % self.blocks_per_row) * 4) as i16;
+        let top = bounds.top + ((self.index / self.blocks_per_row) * 4) as i16;
+        for y in 0..4 {
+            for x in 0..4 {
+                self.frame.set_pixel(left + x as i16, top + y as i16, color_at(x, y));
+            }
+        }
+        self.index += 1;
+    }
+}
+
+impl VideoDecoder for Decoder {
+    fn decode(&mut self, data: &[u8], frame: &mut PixMap) -> AResult<()> {
+        ensure!(data.first() == Some(&Self::FRAME_MARKER), "Invalid Apple Video frame marker");
+        let mut input = Cursor::new(data);
+        let size = input.read_u32::<BigEndian>()? & 0xff_ffff;
+        let end = data.len().min(size as usize) as u64;
+
+        let bounds = frame.bounds();
+        let blocks_per_row = (bounds.width() as usize + 3) / 4;
+        let total = blocks_per_row * ((bounds.height() as usize + 3) / 4);
+        let mut blocks = Blocks { frame, blocks_per_row, total, index: 0 };
+
+        while input.position() < end && blocks.index < blocks.total {
+            let mut opcode = input.read_u8()?;
+            let mut count = usize::from(opcode & 0x1f) + 1;
+            let mut color_a = 0;
+
+            // A colour with the high bit clear in place of an opcode starts
+            // either a four-colour block or a sixteen-colour block
+            if opcode & 0x80 == 0 {
+                color_a = (u16::from(opcode) << 8) | u16::from(input.read_u8()?);
+                opcode = 0;
+                if data.get(input.position() as usize).map_or(false, |byte| byte & 0x80 != 0) {
+                    opcode = 0x20;
+                    count = 1;
+                }
+            }
+
+            match opcode & 0xe0 {
+                0x80 => blocks.skip(count),
+                0xa0 => {
+                    let color = rgb555(input.read_u16::<BigEndian>()?);
+                    for _ in 0..count {
+                        blocks.draw(|_, _| color);
+                    }
+                },
+                0xc0 | 0x20 => {
+                    if opcode & 0xe0 == 0xc0 {
+                        color_a = input.read_u16::<BigEndian>()?;
+                    }
+                    let color_b = input.read_u16::<BigEndian>()?;
+                    let colors = [
+                        rgb555(color_b),
+                        rgb555(interpolate(color_a, color_b, 11, 21)),
+                        rgb555(interpolate(color_a, color_b, 21, 11)),
+                        rgb555(color_a),
+                    ];
+                    for _ in 0..count {
+                        let mut indices = [ 0; 4 ];
+                        input.read_exact(&mut indices)?;
+                        blocks.draw(|x, y| colors[usize::from((indices[y] >> (2 * (3 - x))) & 3)]);
+                    }
+                },
+                _ => {
+                    let mut colors = [ RGBColor::BLACK; 16 ];
+                    colors[0] = rgb555(color_a);
+                    for color in colors.iter_mut().skip(1) {
+                        *color = rgb555(input.read_u16::<BigEndian>()?);
+                    }
+                    blocks.draw(|x, y| colors[y * 4 + x]);
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use libmactoolbox::Rect;
+    use super::*;
+
+    const RED: RGBColor = RGBColor { r: 0xffff, g: 0, b: 0 };
+    const BLUE: RGBColor = RGBColor { r: 0, g: 0, b: 0xffff };
+
+    #[test]
+    fn fill() {
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 4, right: 4 }, RGBColor::WHITE);
+        Decoder.decode(&[ 0xe1, 0, 0, 7, 0xa0, 0x7c, 0x00 ], &mut frame).unwrap();
+        assert_eq!(frame.pixel(0, 0), Some(RED));
+        assert_eq!(frame.pixel(3, 3), Some(RED));
+    }
+
+    #[test]
+    fn skip_and_four_colors() {
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 4, right: 8 }, RGBColor::WHITE);
+        let data = [
+            0xe1, 0, 0, 14,
+            // Skip one block
+            0x80,
+            // One four-colour block with each row using all four colours
+            0xc0, 0x7c, 0x00, 0x00, 0x1f, 0x1b, 0x1b, 0x1b, 0x1b,
+        ];
+        Decoder.decode(&data, &mut frame).unwrap();
+        assert_eq!(frame.pixel(0, 0), Some(RGBColor::WHITE));
+        assert_eq!(frame.pixel(4, 0), Some(BLUE));
+        assert_eq!(frame.pixel(7, 3), Some(RED));
+        assert_ne!(frame.pixel(5, 1), Some(RED));
+        assert_ne!(frame.pixel(5, 1), Some(BLUE));
+    }
+
+    #[test]
+    fn bad_marker() {
+        let mut frame = PixMap::new(Rect { top: 0, left: 0, bottom: 4, right: 4 }, RGBColor::WHITE);
+        assert!(Decoder.decode(&[ 0, 0, 0, 4 ], &mut frame).is_err());
+    }
+}
@@ -204,7 +204,7 @@ pvec! {
         #[entry(0)]
         script_text: String,
         #[string_entry(1, StringKind::PascalStr)]
-        name: String,
+        pub name: String,
         #[string_entry(2, StringKind::PascalStr)]
         file_path: String,
         #[string_entry(3, StringKind::PascalStr)]
@@ -278,6 +278,11 @@ impl Resource for Member {
 }
 
 impl Member {
+    #[must_use]
+    pub fn info(&self) -> Option<&MemberInfo> {
+        self.info.as_ref()
+    }
+
     fn read_meta_d4(input: &mut Input<impl Reader>) -> AResult<(MemberKind, u32, u32)> {
         // TODO: This is incorrect guesswork.
         let _unknown = input.read_u16().context("Can’t read mystery word")?;
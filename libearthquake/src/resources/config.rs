@@ -213,6 +213,11 @@ impl Config {
         }
     }
 
+    #[must_use]
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
     #[must_use]
     pub fn min_cast_num(&self) -> MemberNum {
         self.min_cast_num
@@ -223,6 +228,11 @@ impl Config {
         self.original_version
     }
 
+    #[must_use]
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
     #[must_use]
     pub fn valid(&self) -> bool {
         if self.version < Version::V1113 {
@@ -59,6 +59,72 @@ pub struct Meta {
     button_kind: ButtonKind,
 }
 
+impl Meta {
+    #[must_use]
+    pub fn alignment(&self) -> Alignment {
+        self.alignment
+    }
+
+    #[must_use]
+    pub fn back_color(&self) -> RGBColor {
+        self.back_color
+    }
+
+    #[must_use]
+    pub fn border_size(&self) -> u8 {
+        self.border_size
+    }
+
+    /// The viewport of the field, excluding decorations.
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    #[must_use]
+    pub fn box_shadow_size(&self) -> u8 {
+        self.box_shadow_size
+    }
+
+    #[must_use]
+    pub fn button_kind(&self) -> ButtonKind {
+        self.button_kind
+    }
+
+    #[must_use]
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    #[must_use]
+    pub fn frame(&self) -> Frame {
+        self.frame
+    }
+
+    /// The space between the field viewport and the border.
+    #[must_use]
+    pub fn margin_size(&self) -> u8 {
+        self.margin_size
+    }
+
+    /// The total height of content, which may be larger than the viewport
+    /// if the field is scrollable.
+    #[must_use]
+    pub fn scroll_height(&self) -> i16 {
+        self.scroll_height
+    }
+
+    #[must_use]
+    pub fn scroll_top(&self) -> i16 {
+        self.scroll_top
+    }
+
+    #[must_use]
+    pub fn text_shadow_size(&self) -> u8 {
+        self.text_shadow_size
+    }
+}
+
 impl Resource for Meta {
     type Context = ();
 
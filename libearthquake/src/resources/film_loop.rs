@@ -29,6 +29,19 @@ pub struct Meta {
     field_14: u16,
 }
 
+impl Meta {
+    /// The bounds of the stage of the film loop.
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    #[must_use]
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+}
+
 impl Resource for Meta {
     type Context = ();
 
@@ -0,0 +1,373 @@
+use anyhow::{bail, Context, Result as AResult};
+use libcommon::{
+    encodings::{DecoderRef, EncoderRef, MAC_ROMAN, Unmappable, WIN_ROMAN},
+    Reader,
+    Resource,
+    resource::Input,
+    string::ReadExt,
+};
+use std::{collections::BTreeMap, convert::TryFrom, io::Read};
+
+/// The font substitutions used by Director when a movie does not include its
+/// own font map. This is the font part of the `FONTMAP.TXT` file which ships
+/// with Director; the character mappings are generated from the Mac OS Roman
+/// and Windows-1252 encodings instead.
+const DEFAULT_FONT_MAP: &str = r#"
+Mac:Chicago         => Win:System
+Mac:Courier         => Win:"Courier New"
+Mac:Geneva          => Win:"MS Sans Serif" 10=>9 12=>10
+Mac:Helvetica       => Win:Arial
+Mac:Monaco          => Win:Terminal MAP NONE
+Mac:"New York"      => Win:"MS Serif" 10=>9 12=>10
+Mac:Symbol          => Win:Symbol MAP NONE
+Mac:Times           => Win:"Times New Roman" 14=>12 18=>14 24=>18 30=>24
+
+Win:Arial           => Mac:Helvetica
+Win:Courier         => Mac:Courier
+Win:"Courier New"   => Mac:Courier
+Win:"MS Serif"      => Mac:"New York" 9=>10 10=>12
+Win:"MS Sans Serif" => Mac:Geneva 9=>10 10=>12
+Win:Symbol          => Mac:Symbol MAP NONE
+Win:System          => Mac:Chicago
+Win:Terminal        => Mac:Monaco MAP NONE
+Win:"Times New Roman" => Mac:Times 12=>14 14=>18 18=>24 24=>30
+"#;
+
+/// The table of font names used by the text in a movie, loaded from a `VWFM`
+/// resource.
+#[derive(Clone, Debug, Default)]
+pub struct FontMap(Vec<(i16, String)>);
+
+impl FontMap {
+    /// Returns the name of the font with the given font number.
+    #[must_use]
+    pub fn get(&self, font_num: i16) -> Option<&str> {
+        self.0.iter().find_map(|(num, name)| if *num == font_num { Some(name.as_str()) } else { None })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i16, &str)> {
+        self.0.iter().map(|(num, name)| (*num, name.as_str()))
+    }
+}
+
+impl Resource for FontMap {
+    type Context = (DecoderRef, );
+
+    fn load(input: &mut Input<impl Reader>, _: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
+        let count = input.read_u16().context("Can’t read font map size")?;
+        let mut font_nums = Vec::with_capacity(count.into());
+        for index in 0..count {
+            font_nums.push(input.read_i16().with_context(|| format!("Can’t read font map number {}", index))?);
+        }
+
+        let mut fonts = Vec::with_capacity(count.into());
+        for (index, font_num) in font_nums.into_iter().enumerate() {
+            let name = input.read_pascal_str(context.0).with_context(|| format!("Can’t read font map name {}", index))?;
+            fonts.push((font_num, name));
+        }
+
+        Ok(Self(fonts))
+    }
+}
+
+/// The platform which a font or character set belongs to.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Platform {
+    Mac,
+    Win,
+}
+
+impl Platform {
+    fn parse(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("mac") {
+            Some(Self::Mac)
+        } else if name.eq_ignore_ascii_case("win") {
+            Some(Self::Win)
+        } else {
+            None
+        }
+    }
+}
+
+/// A rule which replaces a font from one platform with a font from another
+/// platform.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FontSubstitution {
+    pub from_platform: Platform,
+    pub from_name: String,
+    pub to_platform: Platform,
+    pub to_name: String,
+    /// Whether the characters of the text should be converted to the
+    /// character set of the other platform. This is turned off for symbol
+    /// fonts.
+    pub map_chars: bool,
+    /// Replacement font sizes.
+    pub sizes: Vec<(u16, u16)>,
+}
+
+impl FontSubstitution {
+    /// Returns the replacement size for the given font size.
+    #[must_use]
+    pub fn map_size(&self, size: u16) -> u16 {
+        self.sizes.iter().find_map(|&(from, to)| if from == size { Some(to) } else { None }).unwrap_or(size)
+    }
+}
+
+/// The cross-platform font and character substitution table of a movie,
+/// loaded from an `FXmp` resource, which uses the same text format as the
+/// Director `FONTMAP.TXT` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CrossPlatformFontMap {
+    fonts: Vec<FontSubstitution>,
+    chars: BTreeMap<(Platform, Platform), BTreeMap<u8, u8>>,
+}
+
+impl CrossPlatformFontMap {
+    /// Parses a font map in the `FONTMAP.TXT` format.
+    pub fn parse(text: &str) -> AResult<Self> {
+        let mut fonts = Vec::new();
+        let mut chars: BTreeMap<(Platform, Platform), BTreeMap<u8, u8>> = BTreeMap::new();
+        for (index, line) in text.split(&[ '\r', '\n' ][..]).enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (from_platform, from_name, rest) = parse_font(line)
+                .with_context(|| format!("Can’t parse source font on line {}", index + 1))?;
+            let rest = if let Some(rest) = rest.trim_start().strip_prefix("=>") {
+                rest
+            } else {
+                bail!("Missing ‘=>’ on line {}", index + 1);
+            };
+            let (to_platform, to_name, rest) = parse_font(rest)
+                .with_context(|| format!("Can’t parse replacement font on line {}", index + 1))?;
+
+            let mut map_chars = true;
+            let mut pairs = Vec::new();
+            let tokens = rest.replace("=>", " => ");
+            let mut tokens = tokens.split_whitespace().peekable();
+            while let Some(token) = tokens.next() {
+                if token.eq_ignore_ascii_case("map") && tokens.peek().map_or(false, |next| next.eq_ignore_ascii_case("none")) {
+                    tokens.next();
+                    map_chars = false;
+                } else {
+                    let from = token.parse::<u16>()
+                        .with_context(|| format!("Invalid value ‘{}’ on line {}", token, index + 1))?;
+                    if tokens.next() != Some("=>") {
+                        bail!("Missing ‘=>’ after {} on line {}", from, index + 1);
+                    }
+                    let to = tokens.next().unwrap_or_default();
+                    let to = to.parse::<u16>()
+                        .with_context(|| format!("Invalid value ‘{}’ on line {}", to, index + 1))?;
+                    pairs.push((from, to));
+                }
+            }
+
+            if from_name.is_empty() && to_name.is_empty() {
+                let map = chars.entry((from_platform, to_platform)).or_default();
+                for (from, to) in pairs {
+                    match (u8::try_from(from), u8::try_from(to)) {
+                        (Ok(from), Ok(to)) => { map.insert(from, to); },
+                        _ => bail!("Invalid character mapping {}=>{} on line {}", from, to, index + 1),
+                    }
+                }
+            } else {
+                fonts.push(FontSubstitution {
+                    from_platform,
+                    from_name,
+                    to_platform,
+                    to_name,
+                    map_chars,
+                    sizes: pairs,
+                });
+            }
+        }
+
+        Ok(Self { fonts, chars })
+    }
+
+    /// Returns the substitution rule for the given font.
+    #[must_use]
+    pub fn find(&self, platform: Platform, name: &str) -> Option<&FontSubstitution> {
+        self.fonts.iter().find(|font| font.from_platform == platform && font.from_name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the Mac font name and size which should be used to draw text
+    /// in the given font from a movie authored on the given platform.
+    #[must_use]
+    pub fn mac_font<'a>(&'a self, platform: Platform, name: &'a str, size: u16) -> (&'a str, u16) {
+        if platform == Platform::Mac {
+            return (name, size);
+        }
+
+        self.fonts.iter()
+            .find(|font| font.from_platform == platform && font.to_platform == Platform::Mac && font.from_name.eq_ignore_ascii_case(name))
+            .map_or((name, size), |font| (font.to_name.as_str(), font.map_size(size)))
+    }
+
+    /// Converts a character from the character set of one platform to the
+    /// character set of another platform.
+    #[must_use]
+    pub fn map_char(&self, from: Platform, to: Platform, c: u8) -> u8 {
+        self.chars.get(&(from, to)).and_then(|map| map.get(&c)).copied().unwrap_or(c)
+    }
+
+    #[must_use]
+    pub fn substitutions(&self) -> &[FontSubstitution] {
+        &self.fonts
+    }
+
+    /// Returns a font map which uses the rules from this map and, where this
+    /// map has no rule for a font or character, the rules from the fallback
+    /// map.
+    #[must_use]
+    pub fn with_fallback(mut self, fallback: &Self) -> Self {
+        for font in &fallback.fonts {
+            if self.find(font.from_platform, &font.from_name).is_none() {
+                self.fonts.push(font.clone());
+            }
+        }
+
+        for (&platforms, fallback_map) in &fallback.chars {
+            let map = self.chars.entry(platforms).or_default();
+            for (&from, &to) in fallback_map {
+                map.entry(from).or_insert(to);
+            }
+        }
+
+        self
+    }
+}
+
+impl Default for CrossPlatformFontMap {
+    fn default() -> Self {
+        let mut map = Self::parse(DEFAULT_FONT_MAP).unwrap();
+        map.chars.insert((Platform::Mac, Platform::Win), char_map(MAC_ROMAN, WIN_ROMAN));
+        map.chars.insert((Platform::Win, Platform::Mac), char_map(WIN_ROMAN, MAC_ROMAN));
+        map
+    }
+}
+
+impl Resource for CrossPlatformFontMap {
+    type Context = (DecoderRef, );
+
+    fn load(input: &mut Input<impl Reader>, size: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
+        let mut data = Vec::with_capacity(size as usize);
+        input.take(size.into()).read_to_end(&mut data).context("Can’t read font map")?;
+        Self::parse(&context.0.decode(&data))
+    }
+}
+
+/// Builds a table which converts the upper half of one single-byte character
+/// set to another.
+fn char_map(decoder: DecoderRef, encoder: EncoderRef) -> BTreeMap<u8, u8> {
+    (0x80..=0xff).filter_map(|c| {
+        match encoder.encode(&decoder.decode(&[ c ]), Unmappable::Fail).as_deref() {
+            Ok(&[ mapped ]) if mapped != c => Some((c, mapped)),
+            _ => None,
+        }
+    }).collect()
+}
+
+/// Parses a `Platform:Name` font specifier, where the name may be quoted or
+/// empty. Returns the platform, the name, and the rest of the line.
+fn parse_font(text: &str) -> AResult<(Platform, String, &str)> {
+    let text = text.trim_start();
+    let colon = text.find(':').context("Missing ‘:’")?;
+    let platform = Platform::parse(&text[..colon])
+        .with_context(|| format!("Unknown platform ‘{}’", &text[..colon]))?;
+    let text = &text[colon + 1..];
+
+    if let Some(quoted) = text.strip_prefix('"') {
+        let end = quoted.find('"').context("Missing closing quote")?;
+        Ok((platform, quoted[..end].to_string(), &quoted[end + 1..]))
+    } else {
+        let end = text.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(text.len());
+        Ok((platform, text[..end].to_string(), &text[end..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn font_map() {
+        let data = b"\0\x02\0\x03\0\x16\x06Geneva\x08New York";
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        let map = FontMap::load(&mut input, data.len() as u32, &(MAC_ROMAN, )).unwrap();
+        assert_eq!(map.get(3), Some("Geneva"));
+        assert_eq!(map.get(22), Some("New York"));
+        assert_eq!(map.get(4), None);
+        assert_eq!(map.iter().count(), 2);
+    }
+
+    #[test]
+    fn parse() {
+        let map = CrossPlatformFontMap::parse(concat!(
+            "; Comment\r",
+            "Mac:Geneva => Win:\"MS Sans Serif\" 10=>9 12 => 10 ; trailing comment\r",
+            "Win:Wingdings=>Mac:Zapf MAP NONE\r",
+            "\r",
+            "Mac: => Win: 128=>196 129=>197\r",
+        )).unwrap();
+
+        assert_eq!(map.substitutions(), &[
+            FontSubstitution {
+                from_platform: Platform::Mac,
+                from_name: "Geneva".to_string(),
+                to_platform: Platform::Win,
+                to_name: "MS Sans Serif".to_string(),
+                map_chars: true,
+                sizes: vec![ (10, 9), (12, 10) ],
+            },
+            FontSubstitution {
+                from_platform: Platform::Win,
+                from_name: "Wingdings".to_string(),
+                to_platform: Platform::Mac,
+                to_name: "Zapf".to_string(),
+                map_chars: false,
+                sizes: Vec::new(),
+            },
+        ]);
+        assert_eq!(map.find(Platform::Mac, "geneva").unwrap().map_size(12), 10);
+        assert_eq!(map.find(Platform::Mac, "geneva").unwrap().map_size(14), 14);
+        assert_eq!(map.map_char(Platform::Mac, Platform::Win, 129), 197);
+        assert_eq!(map.map_char(Platform::Mac, Platform::Win, 130), 130);
+        assert_eq!(map.map_char(Platform::Win, Platform::Mac, 197), 197);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(CrossPlatformFontMap::parse("Mac:Geneva Win:Arial").is_err());
+        assert!(CrossPlatformFontMap::parse("Amiga:Topaz => Win:Arial").is_err());
+        assert!(CrossPlatformFontMap::parse("Mac:Geneva => Win:Arial 10=>").is_err());
+        assert!(CrossPlatformFontMap::parse("Mac: => Win: 300=>1").is_err());
+    }
+
+    #[test]
+    fn defaults() {
+        let map = CrossPlatformFontMap::default();
+        assert_eq!(map.mac_font(Platform::Win, "MS Sans Serif", 10), ("Geneva", 12));
+        assert_eq!(map.mac_font(Platform::Win, "Arial", 12), ("Helvetica", 12));
+        assert_eq!(map.mac_font(Platform::Win, "Unknown", 12), ("Unknown", 12));
+        assert_eq!(map.mac_font(Platform::Mac, "Geneva", 10), ("Geneva", 10));
+        // ä
+        assert_eq!(map.map_char(Platform::Mac, Platform::Win, 0x8a), 0xe4);
+        assert_eq!(map.map_char(Platform::Win, Platform::Mac, 0xe4), 0x8a);
+    }
+
+    #[test]
+    fn fallback() {
+        let movie_map = CrossPlatformFontMap::parse("Win:Arial => Mac:Geneva\nMac: => Win: 138=>1").unwrap();
+        let map = movie_map.with_fallback(&CrossPlatformFontMap::default());
+        assert_eq!(map.mac_font(Platform::Win, "Arial", 12), ("Geneva", 12));
+        assert_eq!(map.mac_font(Platform::Win, "System", 12), ("Chicago", 12));
+        assert_eq!(map.map_char(Platform::Mac, Platform::Win, 0x8a), 1);
+        assert_eq!(map.map_char(Platform::Mac, Platform::Win, 0x80), 0xc4);
+    }
+}
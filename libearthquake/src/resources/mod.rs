@@ -3,9 +3,11 @@ pub mod cast;
 pub mod config;
 pub mod field;
 pub mod film_loop;
+pub mod font_map;
 pub mod movie;
 pub mod script;
 pub mod shape;
+pub mod styled_text;
 pub mod text;
 pub mod transition;
 pub mod video;
@@ -34,6 +34,55 @@ pub struct Meta {
     line_direction: LineDirection,
 }
 
+impl Meta {
+    #[must_use]
+    pub fn back_color(&self) -> u8 {
+        self.back_color
+    }
+
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    #[must_use]
+    pub fn filled(&self) -> bool {
+        self.filled
+    }
+
+    #[must_use]
+    pub fn fore_color(&self) -> u8 {
+        self.fore_color
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    #[must_use]
+    pub fn line_direction(&self) -> LineDirection {
+        self.line_direction
+    }
+
+    /// The raw line size, as stored in the file.
+    #[must_use]
+    pub fn line_size(&self) -> u8 {
+        self.line_size
+    }
+
+    #[must_use]
+    pub fn pattern(&self) -> u16 {
+        self.pattern
+    }
+
+    /// The size of the pen which is actually used to draw the shape.
+    #[must_use]
+    pub fn pen_size(&self) -> i16 {
+        (i16::from(self.line_size & 0xf) - 1).max(0)
+    }
+}
+
 impl Resource for Meta {
     type Context = ();
 
@@ -0,0 +1,203 @@
+use anyhow::{Context, Result as AResult};
+use crate::ensure_sample;
+use libcommon::{Reader, Resource, resource::Input};
+use libmactoolbox::quickdraw::{RGBColor, Style};
+use std::io::Read;
+
+/// A run of text with the same style, like a `TextEdit` `ScrpSTElement`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TextStyle {
+    /// The offset of the first character of the run.
+    pub start: u32,
+    /// The line height of the run.
+    pub height: i16,
+    /// The font ascent of the run.
+    pub ascent: i16,
+    /// The movie font number of the run, which is mapped to a font name by
+    /// the movie font map.
+    pub font_id: i16,
+    pub style: Style,
+    pub size: i16,
+    pub color: RGBColor,
+}
+
+impl TextStyle {
+    const SIZE: u32 = 20;
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            height: 0,
+            ascent: 0,
+            font_id: 0,
+            style: Style::empty(),
+            size: 12,
+            color: RGBColor::BLACK,
+        }
+    }
+}
+
+impl Resource for TextStyle {
+    type Context = ();
+
+    fn load(input: &mut Input<impl Reader>, size: u32, _: &Self::Context) -> AResult<Self> where Self: Sized {
+        assert_eq!(size, Self::SIZE);
+        let start = input.read_u32().context("Can’t read style start")?;
+        let height = input.read_i16().context("Can’t read style line height")?;
+        let ascent = input.read_i16().context("Can’t read style ascent")?;
+        let font_id = input.read_i16().context("Can’t read style font")?;
+        let style = Style::from_bits_truncate(input.read_u8().context("Can’t read style face")?);
+        input.skip(1).context("Can’t skip style face padding")?;
+        let size = input.read_i16().context("Can’t read style font size")?;
+        let color = RGBColor::load(input, RGBColor::SIZE, &()).context("Can’t read style colour")?;
+        Ok(Self {
+            start,
+            height,
+            ascent,
+            font_id,
+            style,
+            size,
+            color,
+        })
+    }
+}
+
+/// The text and style runs of a field or button cast member, loaded from an
+/// `STXT` resource.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StyledText {
+    text: Vec<u8>,
+    styles: Vec<TextStyle>,
+}
+
+impl StyledText {
+    const HEADER_SIZE: u32 = 12;
+
+    /// Creates styled text from text and its style runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the first style run does not start at the beginning of the
+    /// text.
+    #[must_use]
+    pub fn new(text: Vec<u8>, styles: Vec<TextStyle>) -> Self {
+        assert!(styles.first().map_or(false, |style| style.start == 0));
+        Self { text, styles }
+    }
+
+    /// Returns the index of the style run which contains the character at the
+    /// given offset.
+    #[must_use]
+    pub fn style_index_at(&self, offset: usize) -> usize {
+        self.styles.iter()
+            .rposition(|style| style.start as usize <= offset)
+            .unwrap_or(0)
+    }
+
+    /// The style runs of the text. There is always at least one run, and the
+    /// first run always starts at the beginning of the text.
+    #[must_use]
+    pub fn styles(&self) -> &[TextStyle] {
+        &self.styles
+    }
+
+    #[must_use]
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+}
+
+impl Resource for StyledText {
+    type Context = ();
+
+    fn load(input: &mut Input<impl Reader>, _: u32, _: &Self::Context) -> AResult<Self> where Self: Sized {
+        let header_size = input.read_u32().context("Can’t read styled text header size")?;
+        ensure_sample!(header_size == Self::HEADER_SIZE, "Unexpected styled text header size {}", header_size);
+        let text_size = input.read_u32().context("Can’t read styled text size")?;
+        let styles_size = input.read_u32().context("Can’t read styled text styles size")?;
+
+        let mut text = Vec::with_capacity(text_size as usize);
+        input.take(text_size.into()).read_to_end(&mut text).context("Can’t read styled text")?;
+        ensure_sample!(text.len() == text_size as usize, "Styled text is {} bytes, expected {}", text.len(), text_size);
+
+        let mut styles = Vec::new();
+        if styles_size != 0 {
+            let count = input.read_u16().context("Can’t read styled text style count")?;
+            ensure_sample!(
+                styles_size == 2 + u32::from(count) * TextStyle::SIZE,
+                "Styled text has {} styles in {} bytes",
+                count,
+                styles_size
+            );
+            styles.reserve(count.into());
+            for index in 0..count {
+                let style = TextStyle::load(input, TextStyle::SIZE, &())
+                    .with_context(|| format!("Can’t read styled text style {}", index))?;
+                ensure_sample!(
+                    styles.last().map_or(style.start == 0, |last: &TextStyle| last.start <= style.start),
+                    "Styled text style {} starts at out-of-order offset {}",
+                    index,
+                    style.start
+                );
+                styles.push(style);
+            }
+        }
+
+        // Text without any style runs is drawn using the default style of
+        // the port
+        if styles.is_empty() {
+            styles.push(TextStyle::default());
+        }
+
+        Ok(Self { text, styles })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn styled_text() {
+        let data = [
+            0, 0, 0, 12, 0, 0, 0, 5, 0, 0, 0, 42,
+            b'H', b'e', b'l', b'l', b'o',
+            0, 2,
+            0, 0, 0, 0, 0, 16, 0, 12, 0, 3, 1, 0, 0, 12, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 3, 0, 11, 0, 9, 0, 22, 4, 0, 0, 9, 0xff, 0xff, 0, 0, 0, 0,
+        ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        let text = StyledText::load(&mut input, data.len() as u32, &()).unwrap();
+        assert_eq!(text.text(), b"Hello");
+        assert_eq!(text.styles().len(), 2);
+        assert_eq!(text.styles()[0], TextStyle {
+            start: 0,
+            height: 16,
+            ascent: 12,
+            font_id: 3,
+            style: Style::BOLD,
+            size: 12,
+            color: RGBColor::BLACK,
+        });
+        assert_eq!(text.styles()[1].font_id, 22);
+        assert_eq!(text.styles()[1].style, Style::UNDERLINE);
+        assert_eq!(text.styles()[1].color, RGBColor { r: 0xffff, g: 0, b: 0 });
+        assert_eq!(text.style_index_at(2), 0);
+        assert_eq!(text.style_index_at(3), 1);
+        assert_eq!(text.style_index_at(10), 1);
+    }
+
+    #[test]
+    fn unstyled_text() {
+        let data = [ 0, 0, 0, 12, 0, 0, 0, 2, 0, 0, 0, 0, b'H', b'i' ];
+        let mut input = ByteOrdered::new(Cursor::new(&data[..]), Endianness::Big);
+        let text = StyledText::load(&mut input, data.len() as u32, &()).unwrap();
+        assert_eq!(text.text(), b"Hi");
+        assert_eq!(text.styles(), &[ TextStyle::default() ]);
+    }
+}
@@ -1,14 +1,22 @@
 use anyhow::{Context, Result as AResult};
 use crate::ensure_sample;
 use libcommon::{Reader, Resource, resource::Input};
-use libmactoolbox::{Rect, quickdraw::RGBColor};
+use libmactoolbox::{Rect, quickdraw::{RGBColor, Style}};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use super::config::Version as ConfigVersion;
+use std::convert::TryFrom;
+use super::{config::Version as ConfigVersion, field::Alignment};
 
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub struct RGB24(u32);
 
+impl From<RGB24> for RGBColor {
+    fn from(color: RGB24) -> Self {
+        let channel = |shift: u32| ((color.0 >> shift) & 0xff) as u16 * 0x101;
+        Self { r: channel(16), g: channel(8), b: channel(0) }
+    }
+}
+
 impl std::fmt::Debug for RGB24 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "rgb8({}, {}, {})",
@@ -39,13 +47,64 @@ pub struct Meta {
     back_color: RGBColor,
 }
 
+impl Meta {
+    const SIZE: u32 = 0x22;
+    const MIN_SIZE: u32 = 0x12;
+
+    /// The smallest size of text which is anti-aliased when a text member
+    /// does not specify one.
+    const DEFAULT_ANTI_ALIAS_MIN_FONT_SIZE: i16 = 14;
+
+    #[must_use]
+    pub fn anti_alias(&self) -> bool {
+        self.anti_alias
+    }
+
+    #[must_use]
+    pub fn anti_alias_min_font_size(&self) -> i16 {
+        self.anti_alias_min_font_size
+    }
+
+    #[must_use]
+    pub fn back_color(&self) -> RGBColor {
+        self.back_color
+    }
+
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    #[must_use]
+    pub fn fore_color(&self) -> RGBColor {
+        self.fore_color.into()
+    }
+
+    #[must_use]
+    pub fn frame(&self) -> Frame {
+        self.frame
+    }
+
+    /// Returns whether text of the given size should be drawn anti-aliased.
+    #[must_use]
+    pub fn should_anti_alias(&self, size: i16) -> bool {
+        self.anti_alias && size >= self.anti_alias_min_font_size
+    }
+}
+
 impl Resource for Meta {
     type Context = (ConfigVersion, );
 
-    fn load(input: &mut Input<impl Reader>, _: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
-        if context.0 < ConfigVersion::V1217 {
-            todo!("text member kind for < V1217");
-        }
+    fn load(input: &mut Input<impl Reader>, size: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
+        // Before V1217, text metadata is a prefix of the V1217 layout which
+        // may end after any field from the frame onwards. Missing fields use
+        // the defaults of a new text member.
+        ensure_sample!(
+            size == Self::SIZE || (context.0 < ConfigVersion::V1217 && (Self::MIN_SIZE..Self::SIZE).contains(&size)),
+            "Unexpected text meta resource size {} for version {:?}",
+            size,
+            context.0
+        );
 
         let bounds = Rect::load(input, Rect::SIZE, &()).context("Can’t read text bounds")?;
         let rect_2 = Rect::load(input, Rect::SIZE, &()).context("Can’t read text rect 2")?;
@@ -55,11 +114,33 @@ impl Resource for Meta {
             let value = input.read_u8().context("Can’t read text frame")?;
             Frame::from_u8(value).with_context(|| format!("Invalid text frame {}", value))?
         };
-        let field_12 = input.read_u16().context("Can’t read text field_12")?;
-        let anti_alias_min_font_size = input.read_i16().context("Can’t read anti-aliasing minimum font size")?;
-        let height = input.read_u16().context("Can’t read text height")?;
-        let fore_color = RGB24(input.read_u32().context("Can’t read text foreground color")?);
-        let back_color = RGBColor::load(input, RGBColor::SIZE, &()).context("Can’t read text background color")?;
+        let field_12 = if size >= 0x14 {
+            input.read_u16().context("Can’t read text field_12")?
+        } else {
+            0
+        };
+        let anti_alias_min_font_size = if size >= 0x16 {
+            input.read_i16().context("Can’t read anti-aliasing minimum font size")?
+        } else {
+            Self::DEFAULT_ANTI_ALIAS_MIN_FONT_SIZE
+        };
+        let height = if size >= 0x18 {
+            input.read_u16().context("Can’t read text height")?
+        } else {
+            bounds.bottom.checked_sub(bounds.top)
+                .and_then(|height| u16::try_from(height).ok())
+                .with_context(|| format!("Invalid text bounds ({}, {}, {}, {})", bounds.top, bounds.left, bounds.bottom, bounds.right))?
+        };
+        let fore_color = if size >= 0x1c {
+            RGB24(input.read_u32().context("Can’t read text foreground color")?)
+        } else {
+            RGB24(0)
+        };
+        let back_color = if size >= Self::SIZE {
+            RGBColor::load(input, RGBColor::SIZE, &()).context("Can’t read text background color")?
+        } else {
+            RGBColor::WHITE
+        };
         Ok(Self {
             bounds,
             rect_2,
@@ -73,3 +154,176 @@ impl Resource for Meta {
         })
     }
 }
+
+/// How text is aligned to a tab stop.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TabKind {
+    Left,
+    Center,
+    Right,
+    /// Aligns the first decimal point after the tab to the tab stop.
+    Decimal,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TabStop {
+    /// The position of the tab stop, relative to the left edge of the text.
+    pub position: i16,
+    pub kind: TabKind,
+}
+
+/// The formatting of a paragraph of rich text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParagraphStyle {
+    /// The offset of the first character of the paragraph.
+    pub start: u32,
+    pub alignment: Alignment,
+    pub left_indent: i16,
+    pub right_indent: i16,
+    /// The indent of the first line of the paragraph, relative to the left
+    /// indent.
+    pub first_indent: i16,
+    pub space_before: i16,
+    pub space_after: i16,
+    /// Tab stops, in ascending order of position.
+    pub tab_stops: Vec<TabStop>,
+}
+
+impl ParagraphStyle {
+    /// The distance between tab stops after the last explicit tab stop.
+    pub const DEFAULT_TAB_WIDTH: i16 = 36;
+
+    /// Returns the first tab stop after the given position.
+    #[must_use]
+    pub fn next_tab_stop(&self, position: i16) -> TabStop {
+        self.tab_stops.iter()
+            .find(|stop| stop.position > position)
+            .copied()
+            .unwrap_or_else(|| TabStop {
+                position: (position.div_euclid(Self::DEFAULT_TAB_WIDTH) + 1) * Self::DEFAULT_TAB_WIDTH,
+                kind: TabKind::Left,
+            })
+    }
+}
+
+impl Default for ParagraphStyle {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            alignment: Alignment::Left,
+            left_indent: 0,
+            right_indent: 0,
+            first_indent: 0,
+            space_before: 0,
+            space_after: 0,
+            tab_stops: Vec::new(),
+        }
+    }
+}
+
+/// The character formatting of a run of rich text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunStyle {
+    /// The offset of the first character of the run.
+    pub start: u32,
+    pub font_name: String,
+    pub size: i16,
+    pub style: Style,
+    pub color: RGBColor,
+}
+
+/// The text and formatting of a rich text cast member.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RichText {
+    text: Vec<u8>,
+    paragraphs: Vec<ParagraphStyle>,
+    runs: Vec<RunStyle>,
+}
+
+impl RichText {
+    /// Creates rich text from text and its paragraph and character
+    /// formatting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the first paragraph or run does not start at the beginning
+    /// of the text.
+    #[must_use]
+    pub fn new(text: Vec<u8>, paragraphs: Vec<ParagraphStyle>, runs: Vec<RunStyle>) -> Self {
+        assert!(paragraphs.first().map_or(false, |paragraph| paragraph.start == 0));
+        assert!(runs.first().map_or(false, |run| run.start == 0));
+        Self { text, paragraphs, runs }
+    }
+
+    /// Returns the index of the paragraph which contains the character at the
+    /// given offset.
+    #[must_use]
+    pub fn paragraph_index_at(&self, offset: usize) -> usize {
+        self.paragraphs.iter()
+            .rposition(|paragraph| paragraph.start as usize <= offset)
+            .unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn paragraphs(&self) -> &[ParagraphStyle] {
+        &self.paragraphs
+    }
+
+    /// Returns the index of the run which contains the character at the
+    /// given offset.
+    #[must_use]
+    pub fn run_index_at(&self, offset: usize) -> usize {
+        self.runs.iter()
+            .rposition(|run| run.start as usize <= offset)
+            .unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn runs(&self) -> &[RunStyle] {
+        &self.runs
+    }
+
+    #[must_use]
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use std::io::Cursor;
+    use super::*;
+
+    const META: &[u8] = b"\0\x0a\0\x14\0\x3c\0\xc8\0\0\0\0\0\x32\0\xb4\x01\x01\0\0\0\x0c\0\x32\0\xff\0\0\xee\xee\xdd\xdd\xcc\xcc";
+
+    #[test]
+    fn meta() {
+        let mut input = ByteOrdered::new(Cursor::new(META), Endianness::Big);
+        let meta = Meta::load(&mut input, Meta::SIZE, &(ConfigVersion::V1217, )).unwrap();
+        assert_eq!(meta.bounds(), Rect { top: 10, left: 20, bottom: 60, right: 200 });
+        assert_eq!(meta.frame(), Frame::Scroll);
+        assert_eq!(meta.fore_color(), RGBColor { r: 0xffff, g: 0, b: 0 });
+        assert_eq!(meta.back_color(), RGBColor { r: 0xeeee, g: 0xdddd, b: 0xcccc });
+        assert!(meta.should_anti_alias(12));
+        assert!(!meta.should_anti_alias(11));
+    }
+
+    #[test]
+    fn meta_before_v1217() {
+        let size = Meta::MIN_SIZE;
+        let mut input = ByteOrdered::new(Cursor::new(&META[..size as usize]), Endianness::Big);
+        let meta = Meta::load(&mut input, size, &(ConfigVersion::V1215, )).unwrap();
+        assert_eq!(meta.bounds(), Rect { top: 10, left: 20, bottom: 60, right: 200 });
+        assert_eq!(meta.frame(), Frame::Scroll);
+        assert_eq!(meta.height, 50);
+        assert_eq!(meta.anti_alias_min_font_size(), Meta::DEFAULT_ANTI_ALIAS_MIN_FONT_SIZE);
+        assert_eq!(meta.fore_color(), RGBColor::BLACK);
+        assert_eq!(meta.back_color(), RGBColor::WHITE);
+
+        // Only the full layout is valid from V1217
+        let mut input = ByteOrdered::new(Cursor::new(&META[..size as usize]), Endianness::Big);
+        assert!(Meta::load(&mut input, size, &(ConfigVersion::V1217, )).is_err());
+    }
+}
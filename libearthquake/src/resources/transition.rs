@@ -4,6 +4,7 @@ use crate::ensure_sample;
 use libcommon::{encodings::DecoderRef, Reader, Resource, resource::Input};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use std::convert::TryFrom;
 use super::{
     config::Version as ConfigVersion,
     xtra::Meta as XtraMeta,
@@ -81,6 +82,12 @@ pub struct QuarterSeconds(pub u8);
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Milliseconds(pub i16);
 
+impl From<QuarterSeconds> for Milliseconds {
+    fn from(value: QuarterSeconds) -> Self {
+        Self(i16::try_from(i32::from(value.0) * 250).unwrap_or(i16::MAX))
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct StandardMeta {
     legacy_duration: QuarterSeconds,
@@ -90,12 +97,44 @@ pub struct StandardMeta {
     duration: Milliseconds,
 }
 
+impl StandardMeta {
+    #[must_use]
+    pub fn chunk_size(&self) -> u8 {
+        self.chunk_size
+    }
+
+    #[must_use]
+    pub fn duration(&self) -> Milliseconds {
+        self.duration
+    }
+
+    #[must_use]
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Meta {
     Standard(StandardMeta),
     Xtra(StandardMeta, XtraMeta),
 }
 
+impl Meta {
+    /// The parameters which are common to all transitions.
+    #[must_use]
+    pub fn standard(&self) -> &StandardMeta {
+        match self {
+            Self::Standard(meta) | Self::Xtra(meta, _) => meta,
+        }
+    }
+}
+
 impl Resource for Meta {
     type Context = (ConfigVersion, DecoderRef);
 
@@ -112,11 +151,11 @@ impl Resource for Meta {
             let value = input.read_u8().context("Can’t read transition flags")?;
             Flags::from_bits(value).with_context(|| format!("Invalid transition flags (0x{:x})", value))?
         };
-        let duration = Milliseconds(if context.0 < ConfigVersion::V1214 {
-            i16::from(legacy_duration.0) * 15
+        let duration = if context.0 < ConfigVersion::V1214 {
+            Milliseconds::from(legacy_duration)
         } else {
-            input.read_i16().context("Can’t read transition duration")?
-        });
+            Milliseconds(input.read_i16().context("Can’t read transition duration")?)
+        };
 
         let standard_meta = StandardMeta {
             legacy_duration,
@@ -137,3 +176,16 @@ impl Resource for Meta {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    #[test]
+    fn quarter_seconds() {
+        assert_eq!(Milliseconds::from(QuarterSeconds(4)), Milliseconds(1000));
+        assert_eq!(Milliseconds::from(QuarterSeconds(131)), Milliseconds(32750));
+        assert_eq!(Milliseconds::from(QuarterSeconds(255)), Milliseconds(i16::MAX));
+    }
+}
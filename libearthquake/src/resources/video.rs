@@ -49,6 +49,25 @@ pub struct Meta {
     frame_rate: u8,
 }
 
+impl Meta {
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    #[must_use]
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// The frame rate used when `FRAME_RATE_FIXED` is set, in frames per
+    /// second.
+    #[must_use]
+    pub fn frame_rate(&self) -> u8 {
+        self.frame_rate
+    }
+}
+
 impl Resource for Meta {
     type Context = ();
 
@@ -0,0 +1,523 @@
+use byteorder::{ByteOrder, BigEndian};
+use std::{convert::TryFrom, io::{Error, ErrorKind, Result as IoResult}};
+
+/// A decompressor for the standard Apple compressed resource format, which
+/// uses the `'dcmp'` 0, 1, and 2 decompressors built into the System file.
+#[derive(Debug)]
+pub struct Dcmp;
+
+impl Dcmp {
+    /// Decompresses the given data.
+    pub fn decompress(data: &[u8]) -> IoResult<Vec<u8>> {
+        const HEADER_SIZE: usize = 18;
+
+        if !Self::is_compressed(data) || data.len() < HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "Bad compressed resource header"));
+        }
+
+        let header_size = usize::from(BigEndian::read_u16(&data[4..]));
+        let version = BigEndian::read_u16(&data[6..]);
+        let decompressed_size = usize::try_from(BigEndian::read_u32(&data[8..]))
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Decompressed size is too large"))?;
+        let mut input = Stream(data.get(header_size.max(HEADER_SIZE)..).ok_or(ErrorKind::UnexpectedEof)?);
+        let mut output = Output::new(decompressed_size, input.0.len());
+
+        match version {
+            0x0801 => match BigEndian::read_i16(&data[14..]) {
+                0 => dcmp0(&mut input, &mut output),
+                1 => dcmp1(&mut input, &mut output),
+                id => Err(unsupported(id)),
+            },
+            0x0901 => match BigEndian::read_i16(&data[12..]) {
+                2 => dcmp2(&mut input, &mut output, &data[14..18]),
+                id => Err(unsupported(id)),
+            },
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("Unknown compressed resource version 0x{:04x}", version))),
+        }?;
+
+        // Decompressors which work on words may emit one extra byte when the
+        // decompressed size is odd
+        let mut output = output.data;
+        if output.len() > decompressed_size {
+            output.truncate(decompressed_size);
+        }
+
+        if output.len() == decompressed_size {
+            Ok(output)
+        } else {
+            Err(Error::new(ErrorKind::InvalidData, format!("Size mismatch (expected {}, got {})", decompressed_size, output.len())))
+        }
+    }
+
+    /// Determines whether the given data is in the Apple compressed resource
+    /// format.
+    #[must_use]
+    pub fn is_compressed(data: &[u8]) -> bool {
+        data.len() >= 4 && &data[0..4] == b"\xa8\x9f\x65\x72"
+    }
+}
+
+/// Decompresses `'dcmp'` (0), a word-based compressor optimised for 68k
+/// code.
+fn dcmp0(input: &mut Stream<'_>, output: &mut Output) -> IoResult<()> {
+    let mut literals = Vec::new();
+
+    loop {
+        let op = input.u8()?;
+        match op {
+            0x00..=0x1f => {
+                let count = if op & 0xf == 0 { input.u8()? } else { op & 0xf };
+                let literal = input.bytes(usize::from(count) * 2)?;
+                output.extend(literal)?;
+                if op >= 0x10 {
+                    literals.push(literal);
+                }
+            },
+            0x20 | 0x21 => {
+                let index = 0x28 + (usize::from(op - 0x20) << 8 | usize::from(input.u8()?));
+                copy_literal(&literals, index, output)?;
+            },
+            0x22 => {
+                let index = 0x28 + usize::from(input.u16()?);
+                copy_literal(&literals, index, output)?;
+            },
+            0x23..=0x4a => {
+                copy_literal(&literals, usize::from(op - 0x23), output)?;
+            },
+            0x4b..=0xfd => {
+                output.extend(&DCMP0_TABLE[usize::from(op - 0x4b)].to_be_bytes())?;
+            },
+            0xfe => match input.u8()? {
+                0x00 => {
+                    // Segment loader jump table entries. Each entry is
+                    // the common tail followed by the address of the next
+                    // entry, and the run ends with one more tail, so the
+                    // address of the first entry must come from an earlier
+                    // code. Addresses are stored as deltas from the
+                    // previous address with a bias of 6.
+                    let segment = input.var_int()? as u16;
+                    let tail = {
+                        let mut tail = [ 0x3f, 0x3c, 0, 0, 0xa9, 0xf0 ];
+                        BigEndian::write_u16(&mut tail[2..], segment);
+                        tail
+                    };
+                    let count = input.count()?;
+                    let mut address = 0_i32;
+                    for _ in 0..count {
+                        address = address.wrapping_add(input.var_int()?).wrapping_sub(6);
+                        output.extend(&tail)?;
+                        output.extend(&(address as u16).to_be_bytes())?;
+                    }
+                    output.extend(&tail)?;
+                },
+                0x02 => {
+                    let value = input.var_int()? as u8;
+                    let count = input.count()? + 1;
+                    output.fill(value, count)?;
+                },
+                0x03 => {
+                    let value = (input.var_int()? as u16).to_be_bytes();
+                    let count = input.count()? + 1;
+                    for _ in 0..count {
+                        output.extend(&value)?;
+                    }
+                },
+                0x04 => {
+                    let mut value = input.var_int()?;
+                    output.extend(&(value as u16).to_be_bytes())?;
+                    for _ in 0..input.count()? {
+                        value = value.wrapping_add(i32::from(input.u8()? as i8));
+                        output.extend(&(value as u16).to_be_bytes())?;
+                    }
+                },
+                0x06 => {
+                    let mut value = input.var_int()?;
+                    output.extend(&value.to_be_bytes())?;
+                    for _ in 0..input.count()? {
+                        value = value.wrapping_add(input.var_int()?);
+                        output.extend(&value.to_be_bytes())?;
+                    }
+                },
+                kind => return Err(unsupported_extension(kind)),
+            },
+            0xff => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Decompresses `'dcmp'` (1), a byte-based compressor for general data.
+fn dcmp1(input: &mut Stream<'_>, output: &mut Output) -> IoResult<()> {
+    let mut literals = Vec::new();
+
+    loop {
+        let op = input.u8()?;
+        match op {
+            0x00..=0x1f | 0xd0 | 0xd1 => {
+                let (count, store) = if op < 0x20 {
+                    ((op & 0xf) + 1, op >= 0x10)
+                } else {
+                    (input.u8()?, op == 0xd1)
+                };
+                let literal = input.bytes(count.into())?;
+                output.extend(literal)?;
+                if store {
+                    literals.push(literal);
+                }
+            },
+            0x20..=0xcf => {
+                copy_literal(&literals, usize::from(op - 0x20), output)?;
+            },
+            0xd2 | 0xd3 => {
+                let index = 0xb0 + (usize::from(op - 0xd2) << 8 | usize::from(input.u8()?));
+                copy_literal(&literals, index, output)?;
+            },
+            0xd4 => {
+                let index = 0xb0 + usize::from(input.u16()?);
+                copy_literal(&literals, index, output)?;
+            },
+            0xd5..=0xfd => {
+                output.extend(&DCMP1_TABLE[usize::from(op - 0xd5)].to_be_bytes())?;
+            },
+            0xfe => match input.u8()? {
+                0x02 => {
+                    // Unlike `'dcmp'` (0), the value is a raw byte, but the
+                    // count is still a variable-length integer
+                    let value = input.u8()?;
+                    let count = input.count()? + 1;
+                    output.fill(value, count)?;
+                },
+                kind => return Err(unsupported_extension(kind)),
+            },
+            0xff => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Decompresses `'dcmp'` (2), a word-based dictionary compressor.
+fn dcmp2(input: &mut Stream<'_>, output: &mut Output, params: &[u8]) -> IoResult<()> {
+    const CUSTOM_TABLE: u8 = 1;
+    const TAGGED: u8 = 2;
+
+    let table_size = usize::from(params[2]) + 1;
+    let flags = params[3];
+
+    let custom_table;
+    let table = if flags & CUSTOM_TABLE == 0 {
+        &DCMP2_TABLE[..]
+    } else {
+        custom_table = input.bytes(table_size * 2)?
+            .chunks_exact(2)
+            .map(BigEndian::read_u16)
+            .collect::<Vec<_>>();
+        &custom_table[..]
+    };
+
+    let lookup = |index: u8| table.get(usize::from(index))
+        .map(|value| value.to_be_bytes())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Bad table index {}", index)));
+
+    if flags & TAGGED == 0 {
+        while !input.is_empty() {
+            // An odd final byte is stored as-is after all the table
+            // references
+            if output.len() + 1 == output.size {
+                output.extend(&[ input.u8()? ])?;
+            } else {
+                output.extend(&lookup(input.u8()?)?)?;
+            }
+        }
+    } else {
+        'blocks: while !input.is_empty() {
+            let tag = input.u8()?;
+            for bit in (0..8).rev() {
+                if input.is_empty() {
+                    break 'blocks;
+                }
+
+                if tag & (1 << bit) == 0 {
+                    // An odd final byte is stored as a truncated literal
+                    let literal = input.bytes(2).or_else(|_| input.bytes(1))?;
+                    output.extend(literal)?;
+                } else {
+                    output.extend(&lookup(input.u8()?)?)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_literal(literals: &[&[u8]], index: usize, output: &mut Output) -> IoResult<()> {
+    let literal = literals.get(index)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Bad literal index {}", index)))?;
+    output.extend(literal)
+}
+
+fn unsupported(id: i16) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Unsupported decompressor 'dcmp' ({})", id))
+}
+
+fn unsupported_extension(kind: u8) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Unsupported extended code 0x{:02x}", kind))
+}
+
+/// The decompressed data. The size in the header is only trusted as a limit,
+/// so a corrupt header cannot force a large allocation before any data has
+/// been decompressed.
+struct Output {
+    data: Vec<u8>,
+    size: usize,
+}
+
+impl Output {
+    /// The largest ratio of decompressed data to compressed data which is
+    /// allocated up front.
+    const MAX_RATIO: usize = 8;
+
+    fn new(size: usize, input_size: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(size.min(input_size.saturating_mul(Self::MAX_RATIO))),
+            size,
+        }
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> IoResult<()> {
+        self.reserve(bytes.len())?;
+        self.data.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn fill(&mut self, value: u8, count: usize) -> IoResult<()> {
+        self.reserve(count)?;
+        self.data.resize(self.data.len() + count, value);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Ensures `count` more bytes fit. Decompressors which work on words may
+    /// emit one byte more than the decompressed size.
+    fn reserve(&self, count: usize) -> IoResult<()> {
+        if self.data.len().saturating_add(count) > self.size.saturating_add(1) {
+            Err(Error::new(ErrorKind::InvalidData, format!("Decompressed data is larger than {} bytes", self.size)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Stream<'a>(&'a [u8]);
+
+impl <'a> Stream<'a> {
+    fn bytes(&mut self, count: usize) -> IoResult<&'a [u8]> {
+        if count > self.0.len() {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// Reads a variable-length integer and ensures it is a valid count.
+    fn count(&mut self) -> IoResult<usize> {
+        let value = self.var_int()?;
+        usize::try_from(value)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Bad count {}", value)))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn u8(&mut self) -> IoResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> IoResult<u16> {
+        Ok(BigEndian::read_u16(self.bytes(2)?))
+    }
+
+    /// Reads a variable-length signed integer. Values in the range 0x00 to
+    /// 0x7f are stored in one byte; 0x80 to 0xfe start a two-byte value with
+    /// a bias of 0xc000; and 0xff starts a four-byte value.
+    fn var_int(&mut self) -> IoResult<i32> {
+        let first = self.u8()?;
+        Ok(if first == 0xff {
+            BigEndian::read_i32(self.bytes(4)?)
+        } else if first >= 0x80 {
+            i32::from(i16::from_be_bytes([ first.wrapping_sub(0xc0), self.u8()? ]))
+        } else {
+            i32::from(first)
+        })
+    }
+}
+
+const DCMP0_TABLE: [ u16; 179 ] = [
+    0x0000, 0x4eba, 0x0008, 0x4e75, 0x000c, 0x4ead, 0x2053, 0x2f0b,
+    0x6100, 0x0010, 0x7000, 0x2f00, 0x486e, 0x2050, 0x206e, 0x2f2e,
+    0xfffc, 0x48e7, 0x3f3c, 0x0004, 0xfff8, 0x2f0c, 0x2006, 0x4eed,
+    0x4e56, 0x2068, 0x4e5e, 0x0001, 0x588f, 0x4fef, 0x0002, 0x0018,
+    0x6000, 0xffff, 0x508f, 0x4e90, 0x0006, 0x266e, 0x0014, 0xfff4,
+    0x4cee, 0x000a, 0x000e, 0x41ee, 0x4cdf, 0x48c0, 0xfff0, 0x2d40,
+    0x0012, 0x302e, 0x7001, 0x2f28, 0x2054, 0x6700, 0x0020, 0x001c,
+    0x205f, 0x1800, 0x266f, 0x4878, 0x0016, 0x41fa, 0x303c, 0x2840,
+    0x7200, 0x286e, 0x200c, 0x6600, 0x206b, 0x2f07, 0x558f, 0x0028,
+    0xfffe, 0xffec, 0x22d8, 0x200b, 0x000f, 0x598f, 0x2f3c, 0xff00,
+    0x0118, 0x81e1, 0x4a00, 0x4eb0, 0xffe8, 0x48c7, 0x0003, 0x0022,
+    0x0007, 0x001a, 0x6706, 0x6708, 0x4ef9, 0x0024, 0x2078, 0x0800,
+    0x6604, 0x002a, 0x4ed0, 0x3028, 0x265f, 0x6704, 0x0030, 0x43ee,
+    0x3f00, 0x201f, 0x001e, 0xfff6, 0x202e, 0x42a7, 0x2007, 0xfffa,
+    0x6002, 0x3d40, 0x0c40, 0x6606, 0x0026, 0x2d48, 0x2f01, 0x70ff,
+    0x6004, 0x1880, 0x4a40, 0x0040, 0x002c, 0x2f08, 0x0011, 0xffe4,
+    0x2140, 0x2640, 0xfff2, 0x426e, 0x4eb9, 0x3d7c, 0x0038, 0x000d,
+    0x6006, 0x422e, 0x203c, 0x670c, 0x2d68, 0x6608, 0x4a2e, 0x4aae,
+    0x002e, 0x4840, 0x225f, 0x2200, 0x670a, 0x3007, 0x4267, 0x0032,
+    0x2028, 0x0009, 0x487a, 0x0200, 0x2f2b, 0x0005, 0x226e, 0x6602,
+    0xe580, 0x670e, 0x660a, 0x0050, 0x3e00, 0x660c, 0x2e00, 0xffee,
+    0x206d, 0x2040, 0xffe0, 0x5340, 0x6008, 0x0480, 0x0068, 0x0b7c,
+    0x4400, 0x41e8, 0x4941,
+];
+
+const DCMP1_TABLE: [ u16; 41 ] = [
+    0x0000, 0x0001, 0x0002, 0x0003, 0x2e01, 0x3e01, 0x0101, 0x1e01,
+    0xffff, 0x0e01, 0x3100, 0x1112, 0x0107, 0x3332, 0x1239, 0xed10,
+    0x0127, 0x2322, 0x0137, 0x0706, 0x0117, 0x0123, 0x00ff, 0x002f,
+    0x070e, 0xfd3c, 0x0135, 0x0115, 0x0102, 0x0007, 0x003e, 0x05d5,
+    0x0201, 0x0607, 0x0708, 0x3001, 0x0133, 0x0010, 0x1716, 0x373e,
+    0x3637,
+];
+
+const DCMP2_TABLE: [ u16; 256 ] = [
+    0x0000, 0x0008, 0x4eba, 0x206e, 0x4e75, 0x000c, 0x0004, 0x7000,
+    0x0010, 0x0002, 0x486e, 0xfffc, 0x6000, 0x0001, 0x48e7, 0x2f2e,
+    0x4e56, 0x0006, 0x4e5e, 0x2f00, 0x6100, 0xfff8, 0x2f0b, 0xffff,
+    0x0014, 0x000a, 0x0018, 0x205f, 0x000e, 0x2050, 0x3f3c, 0xfff4,
+    0x4cee, 0x302e, 0x6700, 0x4cdf, 0x266e, 0x0012, 0x001c, 0x4267,
+    0xfff0, 0x303c, 0x2f0c, 0x0003, 0x4ed0, 0x0020, 0x7001, 0x0016,
+    0x2d40, 0x48c0, 0x2078, 0x7200, 0x588f, 0x6600, 0x4fef, 0x42a7,
+    0x6706, 0xfffa, 0x558f, 0x286e, 0x3f00, 0xfffe, 0x2f3c, 0x6704,
+    0x598f, 0x206b, 0x0024, 0x201f, 0x41fa, 0x81e1, 0x6604, 0x6708,
+    0x001a, 0x4eb9, 0x508f, 0x202e, 0x0007, 0x4eb0, 0xfff2, 0x3d40,
+    0x001e, 0x2068, 0x6606, 0xfff6, 0x4ef9, 0x0800, 0x0c40, 0x3d7c,
+    0xffec, 0x0005, 0x203c, 0xffe8, 0xdefc, 0x4a2e, 0x0030, 0x0028,
+    0x2f08, 0x200b, 0x6002, 0x426e, 0x2d48, 0x2053, 0x2040, 0x1800,
+    0x6004, 0x41ee, 0x2f28, 0x2f01, 0x670a, 0x4840, 0x2007, 0x6608,
+    0x0118, 0x2f07, 0x3028, 0x3f2e, 0x302b, 0x226e, 0x2f2b, 0x002c,
+    0x670c, 0x225f, 0x6006, 0x00ff, 0x3007, 0xffee, 0x5340, 0x0040,
+    0xffe4, 0x4a40, 0x660a, 0x000f, 0x4ead, 0x70ff, 0x22d8, 0x486b,
+    0x0022, 0x204b, 0x670e, 0x4aae, 0x4e90, 0xffe0, 0xffc0, 0x002a,
+    0x2740, 0x6702, 0x51c8, 0x02b6, 0x487a, 0x2278, 0xb06e, 0xffe6,
+    0x0009, 0x322e, 0x3e00, 0x4841, 0xffea, 0x43ee, 0x4e71, 0x7400,
+    0x2f2c, 0x206c, 0x003c, 0x0026, 0x0050, 0x1880, 0x301f, 0x2200,
+    0x660c, 0xffda, 0x0038, 0x6602, 0x302c, 0x200c, 0x2d6e, 0x4240,
+    0xffe2, 0xa9f0, 0xff00, 0x377c, 0xe580, 0xffdc, 0x4868, 0x594f,
+    0x0034, 0x3e1f, 0x6008, 0x2f06, 0xffde, 0x600a, 0x7002, 0x0032,
+    0xffcc, 0x0080, 0x2251, 0x101f, 0x317c, 0xa029, 0xffd8, 0x5240,
+    0x0100, 0x6710, 0xa023, 0xffce, 0xffd4, 0x2006, 0x4878, 0x002e,
+    0x504f, 0x43fa, 0x6712, 0x7600, 0x41e8, 0x4a6e, 0x20d9, 0x005a,
+    0x7fff, 0x51ca, 0x005c, 0x2e00, 0x0240, 0x48c7, 0x6714, 0x0c80,
+    0x2e9f, 0xffd6, 0x8000, 0x1000, 0x4842, 0x4a6b, 0xffd2, 0x0048,
+    0x4a47, 0x4ed1, 0x206f, 0x0041, 0x600c, 0x2a78, 0x422e, 0x3200,
+    0x6574, 0x6716, 0x0044, 0x486d, 0x2008, 0x486c, 0x0b7c, 0x2640,
+    0x0400, 0x0068, 0x206d, 0x000d, 0x2a40, 0x000b, 0x003e, 0x0220,
+];
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    fn compressed(version: u16, id: i16, params: [ u8; 4 ], size: u32, body: &[u8]) -> Vec<u8> {
+        let mut data = b"\xa8\x9f\x65\x72\x00\x12".to_vec();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&size.to_be_bytes());
+        if version == 0x0801 {
+            data.extend_from_slice(&params[0..2]);
+            data.extend_from_slice(&id.to_be_bytes());
+            data.extend_from_slice(&params[2..4]);
+        } else {
+            data.extend_from_slice(&id.to_be_bytes());
+            data.extend_from_slice(&params);
+        }
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn var_int() {
+        let mut input = Stream(b"\x7f\x80\x00\xbf\xff\xc0\x80\xff\x12\x34\x56\x78");
+        assert_eq!(input.var_int().unwrap(), 0x7f);
+        assert_eq!(input.var_int().unwrap(), -0x4000);
+        assert_eq!(input.var_int().unwrap(), -1);
+        assert_eq!(input.var_int().unwrap(), 0x80);
+        assert_eq!(input.var_int().unwrap(), 0x1234_5678);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn dcmp0_literals_and_tables() {
+        let data = compressed(0x0801, 0, [ 0; 4 ], 15, b"\x12ABCD\x23\x4b\x4c\xfe\x02\x41\x02\xff");
+        assert_eq!(Dcmp::decompress(&data).unwrap(), b"ABCDABCD\x00\x00\x4e\xbaAAA");
+    }
+
+    #[test]
+    fn dcmp0_extended() {
+        let data = compressed(0x0801, 0, [ 0; 4 ], 36, b"\xfe\x00\x01\x02\x0e\x0e\xfe\x04\x10\x02\x01\xff\xfe\x06\x01\x01\x7f\xff");
+        assert_eq!(Dcmp::decompress(&data).unwrap(), &[
+            0x3f, 0x3c, 0x00, 0x01, 0xa9, 0xf0, 0x00, 0x08,
+            0x3f, 0x3c, 0x00, 0x01, 0xa9, 0xf0, 0x00, 0x10,
+            0x3f, 0x3c, 0x00, 0x01, 0xa9, 0xf0,
+            0x00, 0x10, 0x00, 0x11, 0x00, 0x10,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x80,
+        ][..]);
+    }
+
+    #[test]
+    fn dcmp1() {
+        let data = compressed(0x0801, 1, [ 0; 4 ], 13, b"\x02xyz\x11hi\x20\xd5\xd6\xfe\x02z\x01\xff");
+        assert_eq!(Dcmp::decompress(&data).unwrap(), b"xyzhihi\x00\x00\x00\x01zz");
+
+        // The repeat count is a variable-length integer
+        let data = compressed(0x0801, 1, [ 0; 4 ], 0x101, b"\xfe\x02z\xc1\x00\xff");
+        assert_eq!(Dcmp::decompress(&data).unwrap(), vec![ b'z'; 0x101 ]);
+    }
+
+    #[test]
+    fn dcmp2_default_table() {
+        let data = compressed(0x0901, 2, [ 0; 4 ], 5, b"\x01\x02z");
+        assert_eq!(Dcmp::decompress(&data).unwrap(), b"\x00\x08\x4e\xbaz");
+    }
+
+    #[test]
+    fn dcmp2_tagged_custom_table() {
+        let data = compressed(0x0901, 2, [ 0, 0, 1, 3 ], 9, b"\x12\x34\x56\x78\xa0\x00ab\x01cdz");
+        assert_eq!(Dcmp::decompress(&data).unwrap(), b"\x12\x34ab\x56\x78cdz");
+    }
+
+    #[test]
+    fn bad_data() {
+        assert!(!Dcmp::is_compressed(b"\xa8\x9f\x00\x0c"));
+        assert!(Dcmp::decompress(b"\xa8\x9f\x65\x72").is_err());
+        assert!(Dcmp::decompress(&compressed(0x0801, 3, [ 0; 4 ], 0, b"\xff")).is_err());
+        assert!(Dcmp::decompress(&compressed(0x0801, 0, [ 0; 4 ], 4, b"\x24\xff")).is_err());
+        assert!(Dcmp::decompress(&compressed(0x0801, 0, [ 0; 4 ], 4, b"\x4b\xff")).is_err());
+    }
+
+    #[test]
+    fn oversized_output() {
+        // A run longer than the decompressed size
+        let data = compressed(0x0801, 1, [ 0; 4 ], 4, b"\xfe\x02z\xff\x7f\xff\xff\xff\xff");
+        assert!(Dcmp::decompress(&data).is_err());
+
+        // A huge decompressed size with no data to back it
+        let data = compressed(0x0801, 1, [ 0; 4 ], u32::MAX, b"\x00a\xff");
+        assert!(Dcmp::decompress(&data).is_err());
+    }
+}
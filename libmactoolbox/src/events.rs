@@ -2,14 +2,16 @@
 #![allow(dead_code)]
 #![allow(clippy::unused_self)]
 
-use anyhow::{bail, Result as AResult};
+use anyhow::{bail, Context, Result as AResult};
 use bitflags::bitflags;
+use cpp_core::{CastInto, CppBox, Ptr};
 use crate::{OSType, Point};
 use libcommon::UnkPtr;
 use smart_default::SmartDefault;
 use std::{collections::VecDeque, convert::TryInto, rc::Weak, time::{Duration, Instant}};
-use qt_core::{MouseButton, KeyboardModifier};
+use qt_core::{MouseButton, KeyboardModifier, QPoint, QPtr};
 use qt_gui::{QCursor, QGuiApplication};
+use qt_widgets::QWidget;
 
 type Tick = Instant;
 
@@ -147,13 +149,24 @@ pub enum EventData {
     HighLevel(OSType),
 }
 
-#[derive(Debug, SmartDefault)]
+#[derive(SmartDefault)]
 pub struct EventManager {
     #[default(Instant::now())]
     start: Instant,
     #[default(Instant::now())]
     instance_start: Instant,
     queue: VecDeque<EventRecord>,
+    port: Option<QPtr<QWidget>>,
+}
+
+impl std::fmt::Debug for EventManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("start", &self.start)
+            .field("instance_start", &self.instance_start)
+            .field("queue", &self.queue)
+            .finish()
+    }
 }
 
 impl EventManager {
@@ -179,10 +192,30 @@ impl EventManager {
     }
 
     /// `GetMouse`
-    #[must_use]
-    pub fn mouse(&self) -> Point {
-        // TODO: Supposed to be mouse position within grafport.
-        todo!("mouse position")
+    ///
+    /// Returns the position of the mouse in the local coordinates of the
+    /// window set by [`set_port`](Self::set_port).
+    pub fn mouse(&self) -> AResult<Point> {
+        let port = match &self.port {
+            Some(port) if unsafe { !port.is_null() } => port,
+            _ => bail!("Can’t get mouse position without a port"),
+        };
+
+        unsafe {
+            let global = QCursor::pos_0a();
+            to_point(&port.map_from_global(&global))
+        }
+    }
+
+    /// `SetPort`
+    ///
+    /// Sets the window whose local coordinates are used by `GetMouse`.
+    ///
+    /// # Safety
+    ///
+    /// The window must be a valid `QWidget`.
+    pub unsafe fn set_port(&mut self, window: impl CastInto<Ptr<QWidget>>) {
+        self.port = Some(QPtr::new(window));
     }
 
     /// `PostEvent`
@@ -195,7 +228,7 @@ impl EventManager {
                     kind,
                     when: Tick::now(),
                     modifiers: self.modifiers(),
-                    data: EventData::Mouse(self.mouse_pos()),
+                    data: EventData::Mouse(self.mouse_pos()?),
                 }),
                 _ => None
             },
@@ -206,7 +239,7 @@ impl EventManager {
                     kind,
                     when: Tick::now(),
                     modifiers: self.modifiers(),
-                    data: EventData::Key(self.mouse_pos(), c, s),
+                    data: EventData::Key(self.mouse_pos()?, c, s),
                 })
             } else {
                 None
@@ -216,7 +249,7 @@ impl EventManager {
                     kind,
                     when: Tick::now(),
                     modifiers: self.modifiers(),
-                    data: EventData::Window(self.mouse_pos(), w),
+                    data: EventData::Window(self.mouse_pos()?, w),
                 })
             } else {
                 None
@@ -227,7 +260,7 @@ impl EventManager {
                     kind,
                     when: Tick::now(),
                     modifiers: self.modifiers(),
-                    data: EventData::ActiveWindow(self.mouse_pos(), w, a),
+                    data: EventData::ActiveWindow(self.mouse_pos()?, w, a),
                 })
             } else {
                 None
@@ -300,11 +333,8 @@ impl EventManager {
         modifiers
     }
 
-    fn mouse_pos(&self) -> Point {
-        unsafe {
-            let p = QCursor::pos_0a();
-            Point { x: p.x().try_into().unwrap(), y: p.y().try_into().unwrap() }
-        }
+    fn mouse_pos(&self) -> AResult<Point> {
+        unsafe { to_point(&QCursor::pos_0a()) }
     }
 
     #[must_use]
@@ -314,3 +344,10 @@ impl EventManager {
         }.try_into().unwrap())
     }
 }
+
+unsafe fn to_point(point: &CppBox<QPoint>) -> AResult<Point> {
+    Ok(Point {
+        x: point.x().try_into().with_context(|| format!("Mouse x-coordinate {} is out of range", point.x()))?,
+        y: point.y().try_into().with_context(|| format!("Mouse y-coordinate {} is out of range", point.y()))?,
+    })
+}
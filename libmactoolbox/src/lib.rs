@@ -13,6 +13,7 @@
 #![warn(rust_2018_idioms)]
 
 mod application_vise;
+mod dcmp;
 #[cfg(feature = "dialogs")]
 mod dialogs;
 #[cfg(feature = "events")]
@@ -21,31 +22,38 @@ mod files;
 mod os_type;
 pub mod resources;
 mod resource_file;
+mod resource_file_builder;
 mod resource_id;
 mod resource_manager;
 #[cfg(feature = "quickdraw")]
 pub mod quickdraw;
 pub mod script_manager;
 mod system;
+#[cfg(feature = "quickdraw")]
+mod text_edit;
 pub mod vfs;
 
 #[deprecated]
 pub use files::AppleDouble;
 pub use application_vise::*;
+pub use dcmp::*;
 pub use events::*;
 #[deprecated]
 pub use files::MacBinary;
 pub use os_type::*;
 pub use resource_file::*;
+pub use resource_file_builder::*;
 pub use resource_id::*;
 pub use resource_manager::*;
 pub use system::System;
+#[cfg(feature = "quickdraw")]
+pub use text_edit::*;
 use anyhow::Result as AResult;
 use binread::BinRead;
 use byteordered::{ByteOrdered, Endianness};
 use libcommon::{Reader, Resource, resource::Input};
 
-#[derive(BinRead, Clone, Copy, Debug, Default)]
+#[derive(BinRead, Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct Point {
     pub x: i16,
     pub y: i16,
@@ -66,7 +74,7 @@ impl Resource for Point {
     }
 }
 
-#[derive(BinRead, Clone, Copy, Default)]
+#[derive(BinRead, Clone, Copy, Default, Eq, PartialEq)]
 pub struct Rect {
     pub top: i16,
     pub left: i16,
@@ -77,6 +85,73 @@ pub struct Rect {
 impl Rect {
     pub const SIZE: u32 = 8;
 
+    /// Returns whether the given point is inside the rectangle. Like
+    /// `PtInRect`, the bottom and right edges are not part of the rectangle.
+    #[must_use]
+    pub fn contains(self, point: Point) -> bool {
+        point.x >= self.left && point.x < self.right && point.y >= self.top && point.y < self.bottom
+    }
+
+    /// Returns the rectangle shrunk by `dh` on the left and right and `dv` on
+    /// the top and bottom, like `InsetRect`.
+    #[must_use]
+    pub fn inset(self, dh: i16, dv: i16) -> Self {
+        Self {
+            top: self.top + dv,
+            left: self.left + dh,
+            bottom: self.bottom - dv,
+            right: self.right - dh,
+        }
+    }
+
+    /// Returns the intersection of two rectangles, like `SectRect`. The
+    /// result is empty if the rectangles do not intersect.
+    #[must_use]
+    pub fn intersect(self, other: Self) -> Self {
+        Self {
+            top: self.top.max(other.top),
+            left: self.left.max(other.left),
+            bottom: self.bottom.min(other.bottom),
+            right: self.right.min(other.right),
+        }
+    }
+
+    /// Returns whether the rectangle encloses no pixels, like `EmptyRect`.
+    #[must_use]
+    pub fn is_empty(self) -> bool {
+        self.bottom <= self.top || self.right <= self.left
+    }
+
+    /// Returns the rectangle moved by `dh` horizontally and `dv` vertically,
+    /// like `OffsetRect`.
+    #[must_use]
+    pub fn offset(self, dh: i16, dv: i16) -> Self {
+        Self {
+            top: self.top + dv,
+            left: self.left + dh,
+            bottom: self.bottom + dv,
+            right: self.right + dh,
+        }
+    }
+
+    /// Returns the smallest rectangle which encloses both rectangles, like
+    /// `UnionRect`.
+    #[must_use]
+    pub fn union(self, other: Self) -> Self {
+        if self.is_empty() {
+            other
+        } else if other.is_empty() {
+            self
+        } else {
+            Self {
+                top: self.top.min(other.top),
+                left: self.left.min(other.left),
+                bottom: self.bottom.max(other.bottom),
+                right: self.right.max(other.right),
+            }
+        }
+    }
+
     #[inline]
     #[must_use]
     pub fn height(self) -> i16 {
@@ -115,7 +190,3 @@ impl Resource for Rect {
         })
     }
 }
-
-// TODO
-#[derive(Clone, Copy, Debug, Default)]
-pub struct TEHandle(u32);
@@ -0,0 +1,637 @@
+use anyhow::{anyhow, Result as AResult};
+use crate::{Point, Rect, ResourceManager};
+use num_traits::FromPrimitive;
+use super::{CGrafPort, Font, GrafVerb, Pattern, Pen, PixMap, PixPat, Polygon, Region, RGBColor, Style};
+
+/// A value which is combined with a destination pixel by a transfer mode.
+#[derive(Clone, Copy, Debug)]
+enum Source {
+    /// A monochrome pattern or image bit, which is colourised using the
+    /// foreground and background colours of the port.
+    Bit(bool),
+    Color(RGBColor),
+}
+
+impl Source {
+    fn invert(self) -> Self {
+        match self {
+            Self::Bit(bit) => Self::Bit(!bit),
+            Self::Color(color) => Self::Color(color.invert()),
+        }
+    }
+}
+
+/// The set of pixels affected by a drawing operation.
+pub(super) struct Mask {
+    left: i32,
+    top: i32,
+    width: i32,
+    height: i32,
+    bits: Vec<bool>,
+}
+
+impl Mask {
+    pub(super) fn new(bounds: Rect) -> Self {
+        let (width, height) = if bounds.is_empty() {
+            (0, 0)
+        } else {
+            (i32::from(bounds.right) - i32::from(bounds.left), i32::from(bounds.bottom) - i32::from(bounds.top))
+        };
+
+        Self {
+            left: bounds.left.into(),
+            top: bounds.top.into(),
+            width,
+            height,
+            bits: vec![ false; (width * height) as usize ],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let (x, y) = (x - self.left, y - self.top);
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn get(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).map_or(false, |index| self.bits[index])
+    }
+
+    pub(super) fn set(&mut self, x: i32, y: i32) {
+        if let Some(index) = self.index(x, y) {
+            self.bits[index] = true;
+        }
+    }
+
+    /// Sets pixels `x0..x1` in row `y`.
+    fn fill_span(&mut self, y: i32, x0: i32, x1: i32) {
+        for x in x0.max(self.left)..x1.min(self.left + self.width) {
+            self.set(x, y);
+        }
+    }
+
+    /// Clears every pixel which is not accepted by the predicate.
+    fn retain(&mut self, f: impl Fn(i32, i32) -> bool) {
+        for y in self.top..self.top + self.height {
+            for x in self.left..self.left + self.width {
+                if !f(x, y) {
+                    let index = self.index(x, y).unwrap();
+                    self.bits[index] = false;
+                }
+            }
+        }
+    }
+
+    /// Clears every pixel which is set in the other mask.
+    fn subtract(&mut self, other: &Self) {
+        let bits = &mut self.bits;
+        let (left, top, width) = (self.left, self.top, self.width);
+        for (index, bit) in bits.iter_mut().enumerate() {
+            let (x, y) = (left + index as i32 % width, top + index as i32 / width);
+            if other.get(x, y) {
+                *bit = false;
+            }
+        }
+    }
+
+    pub(super) fn bounds(&self) -> Rect {
+        Rect {
+            top: self.top as i16,
+            left: self.left as i16,
+            bottom: (self.top + self.height) as i16,
+            right: (self.left + self.width) as i16,
+        }
+    }
+
+    pub(super) fn points(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.bits.iter().enumerate().filter(|(_, &bit)| bit).map(move |(index, _)| {
+            (self.left + index as i32 % self.width, self.top + index as i32 / self.width)
+        })
+    }
+
+    /// Creates a mask for a rectangle with rounded corners, which is also
+    /// used for plain rectangles (with no rounding) and ovals (with rounding
+    /// equal to the size of the rectangle).
+    fn round_rect(rect: Rect, oval_width: i16, oval_height: i16, clip: Rect) -> Self {
+        let mut mask = Self::new(rect.intersect(clip));
+        if rect.is_empty() {
+            return mask;
+        }
+
+        let (left, top) = (f64::from(rect.left), f64::from(rect.top));
+        let (right, bottom) = (f64::from(rect.right), f64::from(rect.bottom));
+        let a = f64::from(oval_width.max(0).min(rect.width())) / 2.0;
+        let b = f64::from(oval_height.max(0).min(rect.height())) / 2.0;
+
+        for y in mask.top..mask.top + mask.height {
+            // Pixels are inside the shape when their centres are inside
+            let center_y = f64::from(y) + 0.5;
+            let corner_distance = if center_y < top + b {
+                top + b - center_y
+            } else if center_y > bottom - b {
+                center_y - (bottom - b)
+            } else {
+                0.0
+            };
+
+            let inset = if corner_distance > 0.0 && b > 0.0 {
+                a - a * (1.0 - (corner_distance / b).powi(2)).max(0.0).sqrt()
+            } else {
+                0.0
+            };
+
+            let x0 = (left + inset - 0.5).floor() as i32 + 1;
+            let x1 = (right - inset - 0.5).ceil() as i32;
+            mask.fill_span(y, x0, x1);
+        }
+
+        mask
+    }
+
+    /// Creates a mask for the outline of a rectangle with rounded corners,
+    /// drawn inside the rectangle with the given pen size.
+    fn round_rect_frame(rect: Rect, oval_width: i16, oval_height: i16, pen_size: Point, clip: Rect) -> Self {
+        let mut mask = Self::round_rect(rect, oval_width, oval_height, clip);
+        let inner_rect = rect.inset(pen_size.x, pen_size.y);
+        if !inner_rect.is_empty() {
+            let inner = Self::round_rect(
+                inner_rect,
+                oval_width - 2 * pen_size.x,
+                oval_height - 2 * pen_size.y,
+                clip
+            );
+            mask.subtract(&inner);
+        }
+        mask
+    }
+
+    /// Creates a mask for a closed polygon using the even-odd fill rule.
+    fn polygon(points: &[Point], clip: Rect) -> Self {
+        let bounds = Polygon::new(points.to_vec()).bounds();
+        let mut mask = Self::new(bounds.intersect(clip));
+        if points.len() < 3 {
+            return mask;
+        }
+
+        let edges = points.iter().zip(points.iter().cycle().skip(1)).take(points.len());
+        let edges = edges.filter(|(a, b)| a.y != b.y).collect::<Vec<_>>();
+        let mut crossings = Vec::with_capacity(edges.len());
+        for y in mask.top..mask.top + mask.height {
+            let center_y = f64::from(y) + 0.5;
+            crossings.clear();
+            for &(a, b) in &edges {
+                let (y0, y1) = (f64::from(a.y.min(b.y)), f64::from(a.y.max(b.y)));
+                if center_y >= y0 && center_y < y1 {
+                    let t = (center_y - f64::from(a.y)) / f64::from(b.y - a.y);
+                    crossings.push(f64::from(a.x) + t * f64::from(b.x - a.x));
+                }
+            }
+            crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for span in crossings.chunks_exact(2) {
+                let x0 = (span[0] - 0.5).ceil() as i32;
+                let x1 = (span[1] - 0.5).ceil() as i32;
+                mask.fill_span(y, x0, x1);
+            }
+        }
+
+        mask
+    }
+
+    /// Creates a mask for a region.
+    fn region(region: &Region, clip: Rect) -> Self {
+        let mut mask = Self::new(region.bounds().intersect(clip));
+        for (y, spans) in region.scan_lines() {
+            for &(start, end) in spans {
+                mask.fill_span(y.into(), start.into(), end.into());
+            }
+        }
+        mask
+    }
+
+    /// Creates a mask for a set of connected lines drawn with a rectangular
+    /// pen, which hangs below and to the right of each point on the line.
+    fn lines(points: &[Point], pen_size: Point, clip: Rect) -> Self {
+        let (pen_width, pen_height) = (i32::from(pen_size.x), i32::from(pen_size.y));
+        let bounds = points.iter().fold(Rect::default(), |bounds, point| {
+            bounds.union(Rect {
+                top: point.y,
+                left: point.x,
+                bottom: point.y.saturating_add(pen_size.y),
+                right: point.x.saturating_add(pen_size.x),
+            })
+        });
+        let mut mask = Self::new(bounds.intersect(clip));
+        if pen_width <= 0 || pen_height <= 0 {
+            return mask;
+        }
+
+        let mut stamp = |x: i32, y: i32| {
+            for stamp_y in y..y + pen_height {
+                mask.fill_span(stamp_y, x, x + pen_width);
+            }
+        };
+
+        if let [ point ] = points {
+            stamp(point.x.into(), point.y.into());
+        }
+
+        for line in points.windows(2) {
+            let (mut x, mut y) = (i32::from(line[0].x), i32::from(line[0].y));
+            let (x1, y1) = (i32::from(line[1].x), i32::from(line[1].y));
+            let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+            let (step_x, step_y) = ((x1 - x).signum(), (y1 - y).signum());
+            let mut error = dx + dy;
+            loop {
+                stamp(x, y);
+                if x == x1 && y == y1 {
+                    break;
+                }
+                let error2 = 2 * error;
+                if error2 >= dy {
+                    error += dy;
+                    x += step_x;
+                }
+                if error2 <= dx {
+                    error += dx;
+                    y += step_y;
+                }
+            }
+        }
+
+        mask
+    }
+}
+
+/// Returns whether the centre of the given pixel is inside the wedge of an
+/// arc. Like `QuickDraw`, angles are in degrees clockwise from 12 o’clock and
+/// are relative to the enclosing rectangle, so 45° always points at its
+/// top-right corner.
+fn in_arc(rect: Rect, start_angle: i16, arc_angle: i16, x: i32, y: i32) -> bool {
+    let (start_angle, arc_angle) = if arc_angle < 0 {
+        (i32::from(start_angle) + i32::from(arc_angle), -i32::from(arc_angle))
+    } else {
+        (i32::from(start_angle), i32::from(arc_angle))
+    };
+
+    if arc_angle >= 360 {
+        return true;
+    }
+
+    let center_x = (f64::from(rect.left) + f64::from(rect.right)) / 2.0;
+    let center_y = (f64::from(rect.top) + f64::from(rect.bottom)) / 2.0;
+    let dx = (f64::from(x) + 0.5 - center_x) / f64::from(rect.width());
+    let dy = (f64::from(y) + 0.5 - center_y) / f64::from(rect.height());
+    let angle = dx.atan2(-dy).to_degrees().rem_euclid(360.0);
+    (angle - f64::from(start_angle)).rem_euclid(360.0) <= f64::from(arc_angle)
+}
+
+impl CGrafPort {
+    /// Draws an arc of the oval which fits inside the given rectangle, like
+    /// `StdArc`. Framing an arc draws only its curved edge.
+    pub fn std_arc(&mut self, verb: GrafVerb, rect: Rect, start_angle: i16, arc_angle: i16) {
+        let clip = self.clip_bounds();
+        let mut mask = if verb == GrafVerb::Frame {
+            Mask::round_rect_frame(rect, rect.width(), rect.height(), self.pn_size, clip)
+        } else {
+            Mask::round_rect(rect, rect.width(), rect.height(), clip)
+        };
+        mask.retain(|x, y| in_arc(rect, start_angle, arc_angle, x, y));
+        self.draw_mask(verb, &mask);
+    }
+
+    /// Copies pixels from the source image into the port, scaling
+    /// `src_rect` to fit `dst_rect`, like `StdBits`. If a mask region is
+    /// given, only pixels inside it are drawn.
+    ///
+    /// The source image must not be the pixel image of this port.
+    pub fn std_bits(&mut self, src: &PixMap, src_rect: Rect, dst_rect: Rect, mode: u16, mask_rgn: Option<&Region>) {
+        if src_rect.is_empty() || dst_rect.is_empty() {
+            return;
+        }
+
+        let mut clip = self.clip_region().intersect(&Region::from(dst_rect));
+        if let Some(mask_rgn) = mask_rgn {
+            clip = clip.intersect(mask_rgn);
+        }
+
+        let (src_width, src_height) = (i32::from(src_rect.width()), i32::from(src_rect.height()));
+        let (dst_width, dst_height) = (i32::from(dst_rect.width()), i32::from(dst_rect.height()));
+        let mut pix_map = self.port_pix_map.borrow_mut();
+        for (y, spans) in clip.scan_lines() {
+            for x in spans.iter().flat_map(|&(start, end)| start..end) {
+                let src_x = i32::from(src_rect.left) + (i32::from(x) - i32::from(dst_rect.left)) * src_width / dst_width;
+                let src_y = i32::from(src_rect.top) + (i32::from(y) - i32::from(dst_rect.top)) * src_height / dst_height;
+                if let (Some(color), Some(dst)) = (src.pixel(src_x as i16, src_y as i16), pix_map.pixel(x, y)) {
+                    pix_map.set_pixel(x, y, self.transfer(mode, Source::Color(color), dst));
+                }
+            }
+        }
+    }
+
+    /// Draws a line from the current pen location to the given point and
+    /// moves the pen there, like `StdLine`.
+    pub fn std_line(&mut self, new_pt: Point) {
+        let mask = Mask::lines(&[ self.pn_loc, new_pt ], self.pn_size, self.clip_bounds());
+        self.pn_loc = new_pt;
+        self.draw_mask(GrafVerb::Frame, &mask);
+    }
+
+    /// Draws an oval which fits inside the given rectangle, like `StdOval`.
+    pub fn std_oval(&mut self, verb: GrafVerb, rect: Rect) {
+        self.std_rrect(verb, rect, rect.width(), rect.height());
+    }
+
+    /// Draws a polygon, like `StdPoly`. Framing a polygon draws lines between
+    /// its points without closing it.
+    pub fn std_poly(&mut self, verb: GrafVerb, poly: &Polygon) {
+        let clip = self.clip_bounds();
+        let mask = if verb == GrafVerb::Frame {
+            Mask::lines(poly.points(), self.pn_size, clip)
+        } else {
+            Mask::polygon(poly.points(), clip)
+        };
+        self.draw_mask(verb, &mask);
+    }
+
+    /// Draws a rectangle, like `StdRect`.
+    pub fn std_rect(&mut self, verb: GrafVerb, rect: Rect) {
+        self.std_rrect(verb, rect, 0, 0);
+    }
+
+    /// Draws a region, like `StdRgn`.
+    pub fn std_rgn(&mut self, verb: GrafVerb, region: &Region) {
+        let clip = self.clip_bounds();
+        let mask = if verb == GrafVerb::Frame {
+            let outline = region.difference(&region.inset(self.pn_size.x, self.pn_size.y));
+            Mask::region(&outline, clip)
+        } else {
+            Mask::region(region, clip)
+        };
+        self.draw_mask(verb, &mask);
+    }
+
+    /// Draws text at the pen location using the font selected by the text
+    /// font, size, and style of the port, and moves the pen past the text,
+    /// like `StdText`. If the font does not exist, the system font is used.
+    pub fn std_text(&mut self, resources: &ResourceManager<'_>, text: &[u8]) -> AResult<()> {
+        let style = Style::from_bits_truncate(self.tx_face as u8);
+        let size = self.tx_size as i16;
+        let font = match Font::find(resources, self.tx_font as i16, size, style)? {
+            Some(font) => font,
+            None => Font::find(resources, Font::SYSTEM_FONT, size, style)?
+                .ok_or_else(|| anyhow!("Can’t find font {} or the system font", self.tx_font))?,
+        };
+        self.draw_text(&font, text);
+        Ok(())
+    }
+
+    /// Draws text at the pen location using the given font and moves the pen
+    /// past the text. This is the part of `StdText` which runs after the
+    /// Font Manager has found the font.
+    pub fn draw_text(&mut self, font: &Font, text: &[u8]) {
+        let space_extra = ((self.sp_extra as i32 + 0x8000) >> 16) as i16;
+        let (mask, width) = font.render(text, self.pn_loc, space_extra);
+
+        // Copy modes also draw the background of the text
+        let mode = self.tx_mode;
+        let background = if mode & !(Pen::NotSrcCopy as u16) == 0 {
+            let info = font.info();
+            Rect {
+                top: self.pn_loc.y - info.ascent,
+                left: self.pn_loc.x,
+                bottom: self.pn_loc.y + info.descent,
+                right: self.pn_loc.x + width,
+            }
+        } else {
+            Rect::default()
+        };
+
+        let clip = self.clip_region().intersect(&Region::from(mask.bounds()));
+        let mut pix_map = self.port_pix_map.borrow_mut();
+        for (y, spans) in clip.scan_lines() {
+            for x in spans.iter().flat_map(|&(start, end)| start..end) {
+                let bit = mask.get(x.into(), y.into());
+                if !bit && !background.contains(Point { x, y }) {
+                    continue;
+                }
+
+                if let Some(dst) = pix_map.pixel(x, y) {
+                    pix_map.set_pixel(x, y, self.transfer(mode, Source::Bit(bit), dst));
+                }
+            }
+        }
+
+        self.pn_loc.x += width;
+    }
+
+    /// Draws anti-aliased text at the pen location using a font which is
+    /// `scale` times larger than the text, and moves the pen past the text.
+    /// Each pixel is drawn in the foreground colour with an opacity which is
+    /// the fraction of the matching `scale`×`scale` block of the larger text
+    /// which is set.
+    pub fn draw_text_smooth(&mut self, font: &Font, text: &[u8], scale: i16) {
+        let scale = scale.max(1);
+        let space_extra = ((self.sp_extra as i32 + 0x8000) >> 16) as i16;
+        let origin = Point {
+            x: self.pn_loc.x.saturating_mul(scale),
+            y: self.pn_loc.y.saturating_mul(scale),
+        };
+        let (mask, width) = font.render(text, origin, space_extra.saturating_mul(scale));
+
+        let bounds = mask.bounds();
+        let dst_bounds = Rect {
+            top: bounds.top.div_euclid(scale),
+            left: bounds.left.div_euclid(scale),
+            bottom: (i32::from(bounds.bottom) + i32::from(scale) - 1).div_euclid(i32::from(scale)) as i16,
+            right: (i32::from(bounds.right) + i32::from(scale) - 1).div_euclid(i32::from(scale)) as i16,
+        };
+
+        let fg = self.rgb_fg_color;
+        let total = i64::from(scale) * i64::from(scale);
+        let clip = self.clip_region().intersect(&Region::from(dst_bounds));
+        let mut pix_map = self.port_pix_map.borrow_mut();
+        for (y, spans) in clip.scan_lines() {
+            for x in spans.iter().flat_map(|&(start, end)| start..end) {
+                let (sx, sy) = (i32::from(x) * i32::from(scale), i32::from(y) * i32::from(scale));
+                let coverage = (0..i32::from(scale))
+                    .flat_map(|dy| (0..i32::from(scale)).map(move |dx| (dx, dy)))
+                    .filter(|&(dx, dy)| mask.get(sx + dx, sy + dy))
+                    .count() as i64;
+                if coverage == 0 {
+                    continue;
+                }
+
+                if let Some(dst) = pix_map.pixel(x, y) {
+                    let blend = |src: u16, dst: u16| {
+                        let (src, dst) = (i64::from(src), i64::from(dst));
+                        (dst + (src - dst) * coverage / total) as u16
+                    };
+                    pix_map.set_pixel(x, y, RGBColor {
+                        r: blend(fg.r, dst.r),
+                        g: blend(fg.g, dst.g),
+                        b: blend(fg.b, dst.b),
+                    });
+                }
+            }
+        }
+
+        self.pn_loc.x += (width + scale / 2) / scale;
+    }
+
+    /// Draws a rectangle with rounded corners, like `StdRRect`.
+    pub fn std_rrect(&mut self, verb: GrafVerb, rect: Rect, oval_width: i16, oval_height: i16) {
+        let clip = self.clip_bounds();
+        let mask = if verb == GrafVerb::Frame {
+            Mask::round_rect_frame(rect, oval_width, oval_height, self.pn_size, clip)
+        } else {
+            Mask::round_rect(rect, oval_width, oval_height, clip)
+        };
+        self.draw_mask(verb, &mask);
+    }
+
+    /// The bounding box of the clipping region.
+    fn clip_bounds(&self) -> Rect {
+        self.port_rect
+            .intersect(self.port_pix_map.borrow().bounds())
+            .intersect(self.vis_rgn.bounds())
+            .intersect(self.clip_rgn.bounds())
+    }
+
+    /// The region outside of which nothing may be drawn.
+    fn clip_region(&self) -> Region {
+        let bounds = self.port_rect.intersect(self.port_pix_map.borrow().bounds());
+        Region::from(bounds)
+            .intersect(&self.vis_rgn)
+            .intersect(&self.clip_rgn)
+    }
+
+    fn draw_mask(&mut self, verb: GrafVerb, mask: &Mask) {
+        let (pattern, mode) = match verb {
+            GrafVerb::Frame | GrafVerb::Paint => {
+                if (self.pn_vis as i16) < 0 {
+                    return;
+                }
+                (self.pn_pix_pat.clone(), self.pn_mode)
+            },
+            GrafVerb::Erase => (self.bk_pix_pat.clone(), Pen::PatCopy as u16),
+            GrafVerb::Invert => (PixPat::from(Pattern::BLACK), Pen::PatXor as u16),
+            GrafVerb::Fill => (self.fill_pix_pat.clone(), Pen::PatCopy as u16),
+        };
+
+        let clip = self.clip_region();
+        let mut pix_map = self.port_pix_map.borrow_mut();
+        let origin = pix_map.bounds();
+        for (x, y) in mask.points() {
+            if !clip.contains(Point { x: x as i16, y: y as i16 }) {
+                continue;
+            }
+
+            let (pattern_x, pattern_y) = (x - i32::from(origin.left), y - i32::from(origin.top));
+            let source = if let Some(color) = pattern.color() {
+                let bounds = color.bounds();
+                let color_x = i32::from(bounds.left) + pattern_x.rem_euclid(bounds.width().into());
+                let color_y = i32::from(bounds.top) + pattern_y.rem_euclid(bounds.height().into());
+                Source::Color(color.pixel(color_x as i16, color_y as i16).unwrap())
+            } else {
+                Source::Bit(pattern.pattern().bit(pattern_x, pattern_y))
+            };
+
+            if let Some(dst) = pix_map.pixel(x as i16, y as i16) {
+                pix_map.set_pixel(x as i16, y as i16, self.transfer(mode, source, dst));
+            }
+        }
+    }
+
+    /// Combines a source value with a destination pixel using a transfer
+    /// mode.
+    fn transfer(&self, mode: u16, source: Source, dst: RGBColor) -> RGBColor {
+        let (fg, bk) = (self.rgb_fg_color, self.rgb_bk_color);
+
+        // Like Color QuickDraw, black and white source pixels are colourised
+        // using the foreground and background colours
+        let source = match source {
+            Source::Color(RGBColor::BLACK) => Source::Bit(true),
+            Source::Color(RGBColor::WHITE) => Source::Bit(false),
+            source => source,
+        };
+
+        // Dithering is not emulated
+        let mode = mode & !(Pen::DitherCopy as u16);
+
+        if mode < Pen::Blend as u16 {
+            let source = if mode & 4 == 0 { source } else { source.invert() };
+            return match (mode & 3, source) {
+                (0, Source::Bit(bit)) => if bit { fg } else { bk },
+                (0, Source::Color(color)) | (1, Source::Color(color)) => color,
+                (1, Source::Bit(bit)) => if bit { fg } else { dst },
+                (2, Source::Bit(bit)) => if bit { dst.invert() } else { dst },
+                (2, Source::Color(color)) => RGBColor {
+                    r: dst.r ^ !color.r,
+                    g: dst.g ^ !color.g,
+                    b: dst.b ^ !color.b,
+                },
+                (_, Source::Bit(bit)) => if bit { bk } else { dst },
+                (_, Source::Color(_)) => bk,
+            };
+        }
+
+        let src = match source {
+            Source::Bit(bit) => if bit { fg } else { bk },
+            Source::Color(color) => color,
+        };
+        let op = self.graf_vars.rgb_op_color;
+        let channels = |f: &dyn Fn(u16, u16, u16) -> u16| RGBColor {
+            r: f(src.r, dst.r, op.r),
+            g: f(src.g, dst.g, op.g),
+            b: f(src.b, dst.b, op.b),
+        };
+
+        match Pen::from_u16(mode) {
+            Some(Pen::Blend) => channels(&|src, dst, weight| {
+                let (src, dst, weight) = (i64::from(src), i64::from(dst), i64::from(weight));
+                (dst + (src - dst) * weight / 0xffff) as u16
+            }),
+            Some(Pen::AddPin) => channels(&|src, dst, pin| src.saturating_add(dst).min(pin)),
+            Some(Pen::AddOver) => channels(&|src, dst, _| src.wrapping_add(dst)),
+            Some(Pen::SubPin) => channels(&|src, dst, pin| dst.saturating_sub(src).max(pin)),
+            Some(Pen::SubOver) => channels(&|src, dst, _| dst.wrapping_sub(src)),
+            Some(Pen::AdMax) => channels(&|src, dst, _| src.max(dst)),
+            Some(Pen::AdMin) => channels(&|src, dst, _| src.min(dst)),
+            Some(Pen::Hilite) => {
+                let hilite = self.graf_vars.rgb_hilite_color;
+                if dst == bk {
+                    hilite
+                } else if dst == hilite {
+                    bk
+                } else {
+                    dst
+                }
+            },
+            _ => if src == bk { dst } else { src },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+    use super::super::font::tests::load_font;
+
+    #[test]
+    fn draw_smooth() {
+        let mut port = CGrafPort::new(Rect { top: 0, left: 0, bottom: 2, right: 2 });
+        port.move_to(0, 2);
+        port.draw_text_smooth(&load_font(Style::empty()), b"A", 2);
+        assert_eq!(port.pen_loc(), Point { x: 2, y: 2 });
+        let pix_map = port.pix_map().borrow();
+        let gray = |value| Some(RGBColor { r: value, g: value, b: value });
+        assert_eq!(pix_map.pixel(0, 0), gray(0x8000));
+        assert_eq!(pix_map.pixel(1, 0), gray(0xc000));
+        assert_eq!(pix_map.pixel(0, 1), gray(0x4000));
+        assert_eq!(pix_map.pixel(1, 1), gray(0x8000));
+    }
+}
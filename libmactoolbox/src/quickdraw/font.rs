@@ -0,0 +1,602 @@
+use anyhow::{bail, Context, Result as AResult};
+use bitflags::bitflags;
+use crate::{Point, Rect, ResourceId, ResourceManager};
+use libcommon::{Reader, Resource, resource::Input};
+use std::{convert::TryFrom, io::Read, rc::Rc};
+use super::draw::Mask;
+
+bitflags! {
+    /// A text style, like the `QuickDraw` `Style` set.
+    #[derive(Default)]
+    pub struct Style: u8 {
+        const BOLD      = 1;
+        const ITALIC    = 2;
+        const UNDERLINE = 4;
+        const OUTLINE   = 8;
+        const SHADOW    = 0x10;
+        const CONDENSE  = 0x20;
+        const EXTEND    = 0x40;
+    }
+}
+
+impl Style {
+    /// The number of extra pixels added to the width of each character when
+    /// `QuickDraw` synthesises this style.
+    #[must_use]
+    pub fn extra_width(self) -> i16 {
+        let mut extra = 0;
+        if self.contains(Self::BOLD) {
+            extra += 1;
+        }
+        if self.contains(Self::SHADOW) {
+            extra += 2;
+        } else if self.contains(Self::OUTLINE) {
+            extra += 1;
+        }
+        if self.contains(Self::CONDENSE) {
+            extra -= 1;
+        }
+        if self.contains(Self::EXTEND) {
+            extra += 1;
+        }
+        extra
+    }
+}
+
+/// The metrics of a font, like the `QuickDraw` `FontInfo` record.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FontInfo {
+    pub ascent: i16,
+    pub descent: i16,
+    pub wid_max: i16,
+    pub leading: i16,
+}
+
+/// A single size of a bitmap font, loaded from a `FONT` or `NFNT` resource.
+#[derive(Clone, Eq, PartialEq)]
+pub struct BitmapFont {
+    font_type: u16,
+    first_char: u8,
+    last_char: u8,
+    wid_max: i16,
+    kern_max: i16,
+    f_rect_height: i16,
+    ascent: i16,
+    descent: i16,
+    leading: i16,
+    row_bytes: usize,
+    bit_image: Vec<u8>,
+    loc_table: Vec<u16>,
+    ow_table: Vec<u16>,
+}
+
+/// The location of a character within the strike of a bitmap font.
+#[derive(Clone, Copy, Debug)]
+struct Glyph {
+    /// The horizontal offset from the pen location to the image, not
+    /// including the kerning of the font.
+    offset: i16,
+    width: i16,
+    image_start: usize,
+    image_end: usize,
+}
+
+impl BitmapFont {
+    /// The value in the offset/width table for a character which does not
+    /// exist in the font.
+    const MISSING: u16 = 0xffff;
+
+    #[must_use]
+    pub fn ascent(&self) -> i16 {
+        self.ascent
+    }
+
+    /// Returns the advance width of the given character.
+    #[must_use]
+    pub fn char_width(&self, c: u8) -> i16 {
+        self.glyph(c).map_or(0, |glyph| glyph.width)
+    }
+
+    #[must_use]
+    pub fn descent(&self) -> i16 {
+        self.descent
+    }
+
+    #[must_use]
+    pub fn leading(&self) -> i16 {
+        self.leading
+    }
+
+    #[must_use]
+    pub fn wid_max(&self) -> i16 {
+        self.wid_max
+    }
+
+    /// Returns the glyph for the given character, using the missing
+    /// character glyph if the character is not in the font.
+    fn glyph(&self, c: u8) -> Option<Glyph> {
+        let missing_index = usize::from(self.last_char - self.first_char) + 1;
+        let index = if c < self.first_char || c > self.last_char {
+            missing_index
+        } else {
+            usize::from(c - self.first_char)
+        };
+
+        let index = if self.ow_table[index] == Self::MISSING { missing_index } else { index };
+        let offset_width = self.ow_table[index];
+        if offset_width == Self::MISSING {
+            return None;
+        }
+
+        Some(Glyph {
+            offset: (offset_width >> 8) as i16,
+            width: (offset_width & 0xff) as i16,
+            image_start: self.loc_table[index].into(),
+            image_end: self.loc_table[index + 1].into(),
+        })
+    }
+
+    /// Returns whether the pixel at the given column and row of the strike
+    /// is set.
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        self.bit_image.get(y * self.row_bytes + x / 8).map_or(false, |byte| byte & (0x80 >> (x & 7)) != 0)
+    }
+}
+
+impl std::fmt::Debug for BitmapFont {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("font_type", &self.font_type)
+            .field("first_char", &self.first_char)
+            .field("last_char", &self.last_char)
+            .field("wid_max", &self.wid_max)
+            .field("kern_max", &self.kern_max)
+            .field("ascent", &self.ascent)
+            .field("descent", &self.descent)
+            .field("leading", &self.leading)
+            .finish()
+    }
+}
+
+impl Resource for BitmapFont {
+    type Context = ();
+    fn load(input: &mut Input<impl Reader>, _: u32, _: &Self::Context) -> AResult<Self> where Self: Sized {
+        const DEPTH_MASK: u16 = 0xc;
+
+        let font_type = input.read_u16().context("Can’t read font type")?;
+        if font_type & DEPTH_MASK != 0 {
+            bail!("Colour bitmap fonts are not supported");
+        }
+
+        let first_char = input.read_i16().context("Can’t read first character")?;
+        let last_char = input.read_i16().context("Can’t read last character")?;
+        let (first_char, last_char) = match (u8::try_from(first_char), u8::try_from(last_char)) {
+            (Ok(first_char), Ok(last_char)) if first_char <= last_char => (first_char, last_char),
+            _ => bail!("Invalid character range {}..={}", first_char, last_char),
+        };
+
+        let wid_max = input.read_i16().context("Can’t read maximum width")?;
+        let kern_max = input.read_i16().context("Can’t read maximum kern")?;
+        let _n_descent = input.read_i16().context("Can’t read negated descent")?;
+        let _f_rect_width = input.read_i16().context("Can’t read font rectangle width")?;
+        let f_rect_height = input.read_i16().context("Can’t read font rectangle height")?;
+        let _ow_t_loc = input.read_u16().context("Can’t read offset/width table offset")?;
+        let ascent = input.read_i16().context("Can’t read ascent")?;
+        let descent = input.read_i16().context("Can’t read descent")?;
+        let leading = input.read_i16().context("Can’t read leading")?;
+        let row_words = input.read_u16().context("Can’t read row width")?;
+
+        let row_bytes = usize::from(row_words) * 2;
+        let height = usize::try_from(f_rect_height).context("Invalid font rectangle height")?;
+        let mut bit_image = vec![ 0; row_bytes * height ];
+        input.read_exact(&mut bit_image).context("Can’t read font strike")?;
+
+        // Each table has an entry for every character, the missing character
+        // glyph, and a final entry which ends the last glyph
+        let table_size = usize::from(last_char - first_char) + 3;
+        let mut loc_table = Vec::with_capacity(table_size);
+        for _ in 0..table_size {
+            loc_table.push(input.read_u16().context("Can’t read location table")?);
+        }
+        let mut ow_table = Vec::with_capacity(table_size);
+        for _ in 0..table_size {
+            ow_table.push(input.read_u16().context("Can’t read offset/width table")?);
+        }
+
+        Ok(Self {
+            font_type,
+            first_char,
+            last_char,
+            wid_max,
+            kern_max,
+            f_rect_height,
+            ascent,
+            descent,
+            leading,
+            row_bytes,
+            bit_image,
+            loc_table,
+            ow_table,
+        })
+    }
+}
+
+/// An entry in the font association table of a font family, which maps a
+/// size and style to a bitmap font resource.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FontAssociation {
+    pub size: i16,
+    pub style: Style,
+    pub id: i16,
+}
+
+/// A font family, loaded from a `FOND` resource.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FontFamily {
+    flags: u16,
+    family_id: i16,
+    first_char: i16,
+    last_char: i16,
+    associations: Vec<FontAssociation>,
+}
+
+impl FontFamily {
+    #[must_use]
+    pub fn associations(&self) -> &[FontAssociation] {
+        &self.associations
+    }
+
+    /// Returns the bitmap font which best matches the given size and style.
+    ///
+    /// A font with the exact size is preferred, then the font of that size
+    /// which has the most of the requested styles without having any
+    /// unrequested ones.
+    // TODO: QuickDraw scales the nearest size of a font when the requested
+    // size does not exist, but the nearest size is used as-is here.
+    #[must_use]
+    pub fn best_association(&self, size: i16, style: Style) -> Option<FontAssociation> {
+        let nearest_size = self.associations.iter()
+            .min_by_key(|entry| (i32::from(entry.size) - i32::from(size)).abs())?
+            .size;
+
+        self.associations.iter()
+            .filter(|entry| entry.size == nearest_size && style.contains(entry.style))
+            .max_by_key(|entry| entry.style.bits().count_ones())
+            .copied()
+    }
+
+    #[must_use]
+    pub fn family_id(&self) -> i16 {
+        self.family_id
+    }
+}
+
+impl Resource for FontFamily {
+    type Context = ();
+    fn load(input: &mut Input<impl Reader>, _: u32, _: &Self::Context) -> AResult<Self> where Self: Sized {
+        // Ascent, descent, leading, maximum width, width table offset,
+        // kerning table offset, style mapping table offset, style properties,
+        // international info, and version
+        const UNUSED_HEADER_SIZE: usize = 2 * 4 + 4 * 3 + 2 * 9 + 2 * 2 + 2;
+
+        let flags = input.read_u16().context("Can’t read family flags")?;
+        let family_id = input.read_i16().context("Can’t read family ID")?;
+        let first_char = input.read_i16().context("Can’t read first character")?;
+        let last_char = input.read_i16().context("Can’t read last character")?;
+        let mut header = [ 0; UNUSED_HEADER_SIZE ];
+        input.read_exact(&mut header).context("Can’t read family header")?;
+
+        let count = i32::from(input.read_i16().context("Can’t read font association count")?) + 1;
+        let mut associations = Vec::with_capacity(count.max(0) as usize);
+        for index in 0..count {
+            let size = input.read_i16().with_context(|| format!("Can’t read font association {} size", index))?;
+            let style = input.read_u16().with_context(|| format!("Can’t read font association {} style", index))?;
+            let id = input.read_i16().with_context(|| format!("Can’t read font association {} ID", index))?;
+            associations.push(FontAssociation {
+                size,
+                style: Style::from_bits_truncate(style as u8),
+                id,
+            });
+        }
+
+        Ok(Self {
+            flags,
+            family_id,
+            first_char,
+            last_char,
+            associations,
+        })
+    }
+}
+
+/// A bitmap font together with the styles which must be synthesised when
+/// drawing with it.
+#[derive(Clone, Debug)]
+pub struct Font {
+    strike: Rc<BitmapFont>,
+    style: Style,
+}
+
+impl Font {
+    /// The font number of the system font.
+    pub const SYSTEM_FONT: i16 = 0;
+
+    /// The font number of the application font.
+    pub const APPLICATION_FONT: i16 = 1;
+
+    /// The font which is used as the application font.
+    const GENEVA: i16 = 3;
+
+    /// The size used when a font size of zero is requested.
+    const DEFAULT_SIZE: i16 = 12;
+
+    /// Creates a font which draws the given bitmap font, synthesising the
+    /// given styles.
+    #[must_use]
+    pub fn new(strike: Rc<BitmapFont>, style: Style) -> Self {
+        Self { strike, style }
+    }
+
+    /// Finds the bitmap font for the given font number, size, and style in
+    /// the open resource files, like `FMSwapFont`. Styles which are not
+    /// available as a separate bitmap font are synthesised.
+    pub fn find(resources: &ResourceManager<'_>, family: i16, size: i16, style: Style) -> AResult<Option<Self>> {
+        let family = if family == Self::APPLICATION_FONT { Self::GENEVA } else { family };
+        let size = if size == 0 { Self::DEFAULT_SIZE } else { size };
+
+        if let Some(fond) = resources.get_resource::<FontFamily>(ResourceId::new(b"FOND", family), &())? {
+            if let Some(entry) = fond.best_association(size, style) {
+                for &kind in &[ b"NFNT", b"FONT" ] {
+                    if let Some(strike) = resources.get_resource::<BitmapFont>(ResourceId::new(kind, entry.id), &())? {
+                        return Ok(Some(Self::new(strike, style - entry.style)));
+                    }
+                }
+            }
+        }
+
+        // Fonts without a family resource use the font number and size to
+        // make their resource ID
+        if family < 256 && size > 0 && size < 128 {
+            let id = ResourceId::new(b"FONT", family * 128 + size);
+            if let Some(strike) = resources.get_resource::<BitmapFont>(id, &())? {
+                return Ok(Some(Self::new(strike, style)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the font number of the font family with the given name, like
+    /// `GetFNum`.
+    pub fn get_font_number(resources: &ResourceManager<'_>, name: impl AsRef<[u8]>) -> AResult<Option<i16>> {
+        Ok(resources.get_named_resource::<FontFamily>(b"FOND".into(), name, &())?
+            .map(|fond| fond.family_id()))
+    }
+
+    /// Returns the advance width of the given character, like `CharWidth`.
+    #[must_use]
+    pub fn char_width(&self, c: u8) -> i16 {
+        if self.strike.glyph(c).is_some() {
+            self.strike.char_width(c) + self.style.extra_width()
+        } else {
+            0
+        }
+    }
+
+    /// Returns the metrics of the font, like `GetFontInfo`.
+    #[must_use]
+    pub fn info(&self) -> FontInfo {
+        FontInfo {
+            ascent: self.strike.ascent,
+            descent: self.strike.descent,
+            wid_max: self.strike.wid_max + self.style.extra_width().max(0),
+            leading: self.strike.leading,
+        }
+    }
+
+    /// The styles which are synthesised when drawing.
+    #[must_use]
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// Returns the total advance width of the given text, like `TextWidth`.
+    #[must_use]
+    pub fn text_width(&self, text: &[u8]) -> i16 {
+        text.iter().fold(0, |width, &c| width + self.char_width(c))
+    }
+
+    /// Renders text with its baseline starting at the given point, adding
+    /// `space_extra` pixels to the width of each space. Returns the rendered
+    /// pixels and the total advance width.
+    pub(super) fn render(&self, text: &[u8], origin: Point, space_extra: i16) -> (Mask, i16) {
+        let strike = &self.strike;
+        let extra = self.style.extra_width();
+        let width = text.iter().fold(0, |width, &c| {
+            width + self.char_width(c) + if c == b' ' { space_extra } else { 0 }
+        });
+
+        let (x, y) = (i32::from(origin.x), i32::from(origin.y));
+        let top = y - i32::from(strike.ascent);
+        let bounds = Rect {
+            top: (top - 2) as i16,
+            left: (x + i32::from(strike.kern_max.min(0)) - i32::from(strike.f_rect_height) - 2) as i16,
+            bottom: (top + i32::from(strike.f_rect_height) + 3) as i16,
+            right: (x + i32::from(width) + i32::from(strike.wid_max) + i32::from(strike.f_rect_height) + 4) as i16,
+        };
+        let mut glyphs = Mask::new(bounds);
+
+        let mut pen_x = x;
+        for &c in text {
+            if let Some(glyph) = strike.glyph(c) {
+                let image_x = pen_x + i32::from(strike.kern_max) + i32::from(glyph.offset);
+                for row in 0..strike.f_rect_height.max(0) as usize {
+                    let pixel_y = top + row as i32;
+                    let slant = if self.style.contains(Style::ITALIC) { (y - pixel_y).div_euclid(2) } else { 0 };
+                    for column in glyph.image_start..glyph.image_end {
+                        if strike.pixel(column, row) {
+                            let pixel_x = image_x + (column - glyph.image_start) as i32 + slant;
+                            glyphs.set(pixel_x, pixel_y);
+                            if self.style.contains(Style::BOLD) {
+                                glyphs.set(pixel_x + 1, pixel_y);
+                            }
+                        }
+                    }
+                }
+                pen_x += i32::from(glyph.width + extra);
+            }
+
+            if c == b' ' {
+                pen_x += i32::from(space_extra);
+            }
+        }
+
+        let mut mask = if self.style.intersects(Style::OUTLINE | Style::SHADOW) {
+            let mut outline = Mask::new(bounds);
+            let shadow = i32::from(self.style.contains(Style::SHADOW));
+            for (pixel_x, pixel_y) in glyphs.points() {
+                for dy in -1..=1 + shadow {
+                    for dx in -1..=1 + shadow {
+                        if !glyphs.get(pixel_x + dx, pixel_y + dy) {
+                            outline.set(pixel_x + dx, pixel_y + dy);
+                        }
+                    }
+                }
+            }
+            outline
+        } else {
+            glyphs
+        };
+
+        if self.style.contains(Style::UNDERLINE) {
+            // The underline skips over descenders, leaving a one pixel gap on
+            // each side
+            let underline_y = y + 1;
+            let skip = (x..x + i32::from(width))
+                .map(|pixel_x| (pixel_x - 1..=pixel_x + 1).any(|x| mask.get(x, underline_y)))
+                .collect::<Vec<_>>();
+            for (pixel_x, skip) in (x..x + i32::from(width)).zip(skip) {
+                if !skip {
+                    mask.set(pixel_x, underline_y);
+                }
+            }
+        }
+
+        (mask, width)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use std::io::Cursor;
+    use super::*;
+
+    /// Builds a font with ascent 3 and descent 2 containing ‘A’, which is a
+    /// 3×3 box with a hole, ‘B’, which is a one pixel wide descender, and a
+    /// missing character glyph which is a 2×3 block.
+    fn test_font() -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in &[
+            0x9000_u16, // font type
+            0x41, 0x42, // first and last characters
+            4, 0, 0xfffd, // maximum width, maximum kern, negated descent
+            6, 5, 0, // font rectangle width and height, ow table offset
+            3, 2, 1, // ascent, descent, leading
+            1, // row words
+        ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        // A occupies columns 0..3, B column 3, the missing glyph columns 4..6
+        data.extend_from_slice(&[ 0b1110_1100, 0 ]);
+        data.extend_from_slice(&[ 0b1010_1100, 0 ]);
+        data.extend_from_slice(&[ 0b1110_1100, 0 ]);
+        data.extend_from_slice(&[ 0b0001_0000, 0 ]);
+        data.extend_from_slice(&[ 0b0001_0000, 0 ]);
+        for value in &[ 0_u16, 3, 4, 6 ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        for value in &[ 0x0004_u16, 0x0102, 0x0003, 0xffff ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data
+    }
+
+    pub(crate) fn load_font(style: Style) -> Font {
+        let data = test_font();
+        let mut input = ByteOrdered::new(Cursor::new(&data), Endianness::Big);
+        let strike = BitmapFont::load(&mut input, data.len() as u32, &()).unwrap();
+        Font::new(Rc::new(strike), style)
+    }
+
+    fn rows(mask: &Mask, top: i32, bottom: i32, left: i32, right: i32) -> Vec<String> {
+        (top..bottom).map(|y| {
+            (left..right).map(|x| if mask.get(x, y) { '#' } else { '.' }).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn metrics() {
+        let font = load_font(Style::empty());
+        assert_eq!(font.info(), FontInfo { ascent: 3, descent: 2, wid_max: 4, leading: 1 });
+        assert_eq!(font.char_width(b'A'), 4);
+        assert_eq!(font.char_width(b'B'), 2);
+        assert_eq!(font.char_width(b'Z'), 3);
+        assert_eq!(font.text_width(b"AB"), 6);
+        assert_eq!(load_font(Style::BOLD | Style::EXTEND).text_width(b"AB"), 10);
+    }
+
+    #[test]
+    fn render_plain() {
+        let (mask, width) = load_font(Style::empty()).render(b"ABZ", Point { x: 0, y: 3 }, 0);
+        assert_eq!(width, 9);
+        assert_eq!(rows(&mask, 0, 5, 0, 9), vec![
+            "###...##.",
+            "#.#...##.",
+            "###...##.",
+            ".....#...",
+            ".....#...",
+        ]);
+    }
+
+    #[test]
+    fn render_styles() {
+        let (mask, _) = load_font(Style::BOLD).render(b"A", Point { x: 0, y: 3 }, 0);
+        assert_eq!(rows(&mask, 0, 3, 0, 5), vec![ "####.", "####.", "####." ]);
+
+        let (mask, _) = load_font(Style::UNDERLINE).render(b"AB", Point { x: 0, y: 3 }, 0);
+        assert_eq!(rows(&mask, 4, 5, 0, 6), vec![ "####.#" ]);
+
+        let (mask, _) = load_font(Style::OUTLINE).render(b"A", Point { x: 0, y: 3 }, 0);
+        assert_eq!(rows(&mask, -1, 4, -1, 4), vec![
+            "#####",
+            "#...#",
+            "#.#.#",
+            "#...#",
+            "#####",
+        ]);
+    }
+
+    #[test]
+    fn best_association() {
+        let entry = |size, style, id| FontAssociation { size, style, id };
+        let family = FontFamily {
+            flags: 0,
+            family_id: 3,
+            first_char: 0,
+            last_char: 255,
+            associations: vec![
+                entry(9, Style::empty(), 393),
+                entry(12, Style::empty(), 396),
+                entry(12, Style::BOLD, 397),
+                entry(12, Style::BOLD | Style::ITALIC, 398),
+            ],
+        };
+        assert_eq!(family.best_association(12, Style::empty()).unwrap().id, 396);
+        assert_eq!(family.best_association(12, Style::BOLD | Style::UNDERLINE).unwrap().id, 397);
+        assert_eq!(family.best_association(12, Style::BOLD | Style::ITALIC).unwrap().id, 398);
+        assert_eq!(family.best_association(12, Style::ITALIC).unwrap().id, 396);
+        assert_eq!(family.best_association(10, Style::empty()).unwrap().id, 393);
+    }
+}
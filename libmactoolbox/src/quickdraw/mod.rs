@@ -1,6 +1,19 @@
 // TODO: You know, finish this file and then remove these overrides
 #![allow(dead_code)]
 
+mod draw;
+mod font;
+mod pack_bits;
+mod pict;
+mod pix_map;
+mod region;
+
+pub use font::*;
+pub use pack_bits::*;
+pub use pict::*;
+pub use pix_map::*;
+pub use region::*;
+
 use crate::{Point, Rect};
 use anyhow::{Context, Result as AResult};
 use num_derive::FromPrimitive;
@@ -13,12 +26,14 @@ use libcommon::{
 };
 use std::{
     cell::{Ref, RefCell},
+    convert::TryFrom,
+    io::Read,
     rc::Rc,
 };
 
-type PixPatHandle = UnkHnd;
-type PixMapHandle = UnkHnd;
-type RgnHandle = UnkHnd;
+type PixPatHandle = PixPat;
+type PixMapHandle = Rc<RefCell<PixMap>>;
+type RgnHandle = Region;
 type StyleField = u16;
 type Fixed = u32;
 
@@ -62,6 +77,15 @@ pub struct RGBColor {
 
 impl RGBColor {
     pub const SIZE: u32 = 6;
+    pub const BLACK: Self = Self { r: 0, g: 0, b: 0 };
+    pub const WHITE: Self = Self { r: 0xffff, g: 0xffff, b: 0xffff };
+
+    /// Returns the complement of the colour, like `InvertColor` with the
+    /// default complement procedure.
+    #[must_use]
+    pub fn invert(self) -> Self {
+        Self { r: !self.r, g: !self.g, b: !self.b }
+    }
 }
 
 impl Resource for RGBColor {
@@ -83,12 +107,173 @@ impl std::fmt::Debug for RGBColor {
     }
 }
 
-#[derive(Clone, Copy)]
+/// An 8×8 monochrome pattern. Set bits are drawn in the foreground colour and
+/// clear bits in the background colour.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Pattern(pub [ u8; 8 ]);
+
+impl Pattern {
+    pub const BLACK: Self = Self([ 0xff; 8 ]);
+    pub const WHITE: Self = Self([ 0; 8 ]);
+
+    /// Returns whether the pattern bit for the given local coordinate is set.
+    #[must_use]
+    pub fn bit(self, x: i32, y: i32) -> bool {
+        self.0[(y & 7) as usize] & (0x80 >> (x & 7)) != 0
+    }
+}
+
+/// A list of patterns, loaded from a `PAT#` resource.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PatternList(Vec<Pattern>);
+
+impl PatternList {
+    /// The resource number of the system pattern list.
+    pub const SYSTEM_ID: i16 = 0;
+
+    /// Returns the pattern with the given one-based index, like
+    /// `GetIndPattern`.
+    #[must_use]
+    pub fn get(&self, index: i16) -> Option<Pattern> {
+        usize::try_from(index).ok()
+            .and_then(|index| index.checked_sub(1))
+            .and_then(|index| self.0.get(index))
+            .copied()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Resource for PatternList {
+    type Context = ();
+
+    fn load(input: &mut Input<impl Reader>, _: u32, _: &Self::Context) -> AResult<Self> where Self: Sized {
+        let count = input.read_u16().context("Can’t read pattern list size")?;
+        let mut patterns = Vec::with_capacity(count.into());
+        for index in 0..count {
+            let mut pattern = Pattern::default();
+            input.read_exact(&mut pattern.0).with_context(|| format!("Can’t read pattern {}", index))?;
+            patterns.push(pattern);
+        }
+        Ok(Self(patterns))
+    }
+}
+
+/// A pixel pattern. Colour patterns carry their own image; every pattern also
+/// has a monochrome version which is used in pattern transfer modes that only
+/// make sense for one bit per pixel.
+#[derive(Clone, Debug)]
+pub struct PixPat {
+    pattern: Pattern,
+    color: Option<Rc<PixMap>>,
+}
+
+impl PixPat {
+    /// Creates a pixel pattern from a colour image, like `NewPixPat`.
+    #[must_use]
+    pub fn new(pattern: Pattern, color: PixMap) -> Self {
+        Self { pattern, color: Some(Rc::new(color)) }
+    }
+
+    /// Creates a pixel pattern which is a solid colour, like `MakeRGBPat`.
+    #[must_use]
+    pub fn from_rgb(pattern: Pattern, color: RGBColor) -> Self {
+        let bounds = Rect { top: 0, left: 0, bottom: 1, right: 1 };
+        Self::new(pattern, PixMap::new(bounds, color))
+    }
+
+    #[must_use]
+    pub fn color(&self) -> Option<&PixMap> {
+        self.color.as_deref()
+    }
+
+    #[must_use]
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+}
+
+impl From<Pattern> for PixPat {
+    fn from(pattern: Pattern) -> Self {
+        Self { pattern, color: None }
+    }
+}
+
+/// The drawing operations which are applied to shapes by the `Std*`
+/// bottleneck procedures.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GrafVerb {
+    Frame,
+    Paint,
+    Erase,
+    Invert,
+    Fill,
+}
+
+/// Additional colour state of a colour graphics port.
+#[derive(Clone, Copy, Debug)]
+pub struct GrafVars {
+    rgb_op_color: RGBColor,
+    rgb_hilite_color: RGBColor,
+}
+
+impl Default for GrafVars {
+    fn default() -> Self {
+        Self {
+            rgb_op_color: RGBColor::BLACK,
+            rgb_hilite_color: RGBColor { r: 0, g: 0, b: 0xffff },
+        }
+    }
+}
+
+/// A closed polygon.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Polygon {
+    bounds: Rect,
+    points: Vec<Point>,
+}
+
+impl Polygon {
+    /// Creates a polygon from its vertices.
+    #[must_use]
+    pub fn new(points: Vec<Point>) -> Self {
+        let bounds = points.iter().fold(None, |bounds: Option<Rect>, point| {
+            let point_rect = Rect { top: point.y, left: point.x, bottom: point.y, right: point.x };
+            Some(bounds.map_or(point_rect, |bounds| Rect {
+                top: bounds.top.min(point.y),
+                left: bounds.left.min(point.x),
+                bottom: bounds.bottom.max(point.y),
+                right: bounds.right.max(point.x),
+            }))
+        }).unwrap_or_default();
+        Self { bounds, points }
+    }
+
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    #[must_use]
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+}
+
+#[derive(Clone)]
 pub struct CGrafPort {
     device: u16,
     port_pix_map: PixMapHandle,
     port_version: u16,
-    graf_vars: UnkHnd,
+    graf_vars: GrafVars,
     ch_extra: u16,
     pn_loc_h_frac: u16,
     port_rect: Rect,
@@ -118,13 +303,156 @@ pub struct CGrafPort {
     graf_procs: UnkPtr,
 }
 
+impl CGrafPort {
+    /// Creates a new colour graphics port which draws into a new off-screen
+    /// pixel image with the given bounds, like `OpenCPort`.
+    #[must_use]
+    pub fn new(bounds: Rect) -> Self {
+        let wide_open = Rect { top: -32767, left: -32767, bottom: 32767, right: 32767 };
+        Self {
+            device: 0,
+            port_pix_map: Rc::new(RefCell::new(PixMap::new(bounds, RGBColor::WHITE))),
+            port_version: 0xc000,
+            graf_vars: GrafVars::default(),
+            ch_extra: 0,
+            pn_loc_h_frac: 0x8000,
+            port_rect: bounds,
+            vis_rgn: Region::from(bounds),
+            clip_rgn: Region::from(wide_open),
+            bk_pix_pat: Pattern::WHITE.into(),
+            rgb_fg_color: RGBColor::BLACK,
+            rgb_bk_color: RGBColor::WHITE,
+            pn_loc: Point::default(),
+            pn_size: Point { x: 1, y: 1 },
+            pn_mode: Pen::PatCopy as u16,
+            pn_pix_pat: Pattern::BLACK.into(),
+            fill_pix_pat: Pattern::BLACK.into(),
+            pn_vis: 0,
+            tx_font: 0,
+            tx_face: 0,
+            tx_mode: Pen::SrcOr as u16,
+            tx_size: 0,
+            sp_extra: 0,
+            fg_color: 33,
+            bk_color: 30,
+            colr_bit: 0,
+            pat_stretch: 0,
+            pic_save: UnkHnd::default(),
+            rgn_save: UnkHnd::default(),
+            poly_save: UnkHnd::default(),
+            graf_procs: UnkPtr::default(),
+        }
+    }
+
+    /// Sets the background pattern, like `BackPixPat`.
+    pub fn back_pix_pat(&mut self, pattern: PixPat) {
+        self.bk_pix_pat = pattern;
+    }
+
+    /// Sets the clipping region, like `SetClip`.
+    pub fn set_clip(&mut self, region: Region) {
+        self.clip_rgn = region;
+    }
+
+    #[must_use]
+    pub fn clip_rgn(&self) -> &Region {
+        &self.clip_rgn
+    }
+
+    /// Sets the fill pattern used by the `Fill*` calls.
+    pub fn fill_pix_pat(&mut self, pattern: PixPat) {
+        self.fill_pix_pat = pattern;
+    }
+
+    /// Sets the colour used by arithmetic transfer modes, like `OpColor`.
+    pub fn op_color(&mut self, color: RGBColor) {
+        self.graf_vars.rgb_op_color = color;
+    }
+
+    /// Sets the highlight colour, like `HiliteColor`.
+    pub fn hilite_color(&mut self, color: RGBColor) {
+        self.graf_vars.rgb_hilite_color = color;
+    }
+
+    /// Moves the pen to the given local coordinate, like `MoveTo`.
+    pub fn move_to(&mut self, h: i16, v: i16) {
+        self.pn_loc = Point { x: h, y: v };
+    }
+
+    #[must_use]
+    pub fn pen_loc(&self) -> Point {
+        self.pn_loc
+    }
+
+    /// Sets the pen transfer mode, like `PenMode`.
+    pub fn pen_mode(&mut self, mode: u16) {
+        self.pn_mode = mode;
+    }
+
+    /// Sets the pen pattern, like `PenPixPat`.
+    pub fn pen_pix_pat(&mut self, pattern: PixPat) {
+        self.pn_pix_pat = pattern;
+    }
+
+    /// Sets the pen size, like `PenSize`.
+    pub fn pen_size(&mut self, width: i16, height: i16) {
+        self.pn_size = Point { x: width, y: height };
+    }
+
+    /// Returns the pixel image which the port draws into.
+    #[must_use]
+    pub fn pix_map(&self) -> &PixMapHandle {
+        &self.port_pix_map
+    }
+
+    #[must_use]
+    pub fn port_rect(&self) -> Rect {
+        self.port_rect
+    }
+
+    /// Sets the background colour, like `RGBBackColor`.
+    pub fn rgb_back_color(&mut self, color: RGBColor) {
+        self.rgb_bk_color = color;
+    }
+
+    /// Sets the foreground colour, like `RGBForeColor`.
+    pub fn rgb_fore_color(&mut self, color: RGBColor) {
+        self.rgb_fg_color = color;
+    }
+
+    /// Sets the extra width added to space characters, like `SpaceExtra`.
+    pub fn space_extra(&mut self, extra: Fixed) {
+        self.sp_extra = extra;
+    }
+
+    /// Sets the text style, like `TextFace`.
+    pub fn text_face(&mut self, face: StyleField) {
+        self.tx_face = face;
+    }
+
+    /// Sets the text font, like `TextFont`.
+    pub fn text_font(&mut self, font: u16) {
+        self.tx_font = font;
+    }
+
+    /// Sets the text transfer mode, like `TextMode`.
+    pub fn text_mode(&mut self, mode: u16) {
+        self.tx_mode = mode;
+    }
+
+    /// Sets the text size, like `TextSize`.
+    pub fn text_size(&mut self, size: u16) {
+        self.tx_size = size;
+    }
+}
+
 pub struct QuickDraw {
     graf_port: Rc<RefCell<CGrafPort>>,
 }
 
 impl QuickDraw {
     pub fn use_port(&self, f: impl Fn(Ref<'_, CGrafPort>)) {
-        let old_port = *self.graf_port.borrow();
+        let old_port = self.graf_port.borrow().clone();
         f(self.graf_port.borrow());
         self.graf_port.replace(old_port);
     }
@@ -0,0 +1,69 @@
+use anyhow::{bail, Result as AResult};
+
+/// Decompresses data compressed with the `PackBits` run-length encoding, like
+/// `UnpackBits`. `item_size` is the size of each repeated item in bytes; it is
+/// 1 for ordinary data and 2 for the word-based packing of 16-bit pixel
+/// images.
+pub fn unpack_bits(data: &[u8], item_size: usize, expected_size: usize) -> AResult<Vec<u8>> {
+    let mut output = Vec::with_capacity(expected_size);
+    let mut input = data;
+    while output.len() < expected_size {
+        let (&flag, rest) = if let Some(split) = input.split_first() {
+            split
+        } else {
+            bail!("PackBits data ended after {} of {} bytes", output.len(), expected_size);
+        };
+        input = rest;
+
+        match flag {
+            0..=0x7f => {
+                let size = (usize::from(flag) + 1) * item_size;
+                if input.len() < size {
+                    bail!("PackBits literal run overflows input");
+                }
+                output.extend_from_slice(&input[..size]);
+                input = &input[size..];
+            },
+            // No-op for compatibility with older packers
+            0x80 => {},
+            _ => {
+                if input.len() < item_size {
+                    bail!("PackBits repeat run overflows input");
+                }
+                let count = 257 - usize::from(flag);
+                for _ in 0..count {
+                    output.extend_from_slice(&input[..item_size]);
+                }
+                input = &input[item_size..];
+            },
+        }
+    }
+
+    output.truncate(expected_size);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    #[test]
+    fn unpack_bytes() {
+        // Example from Apple Technical Note TN1023
+        let packed = b"\xfe\xaa\x02\x80\x00\x2a\xfd\xaa\x03\x80\x00\x2a\x22\xf7\xaa";
+        let expected = b"\xaa\xaa\xaa\x80\x00\x2a\xaa\xaa\xaa\xaa\x80\x00\x2a\x22\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa";
+        assert_eq!(unpack_bits(packed, 1, expected.len()).unwrap(), expected.to_vec());
+    }
+
+    #[test]
+    fn unpack_words() {
+        assert_eq!(unpack_bits(b"\xfe\x12\x34\x00\x56\x78", 2, 8).unwrap(), b"\x12\x34\x12\x34\x12\x34\x56\x78".to_vec());
+    }
+
+    #[test]
+    fn unpack_truncated() {
+        assert!(unpack_bits(b"\x03\x01", 1, 4).is_err());
+        assert!(unpack_bits(b"", 1, 1).is_err());
+    }
+}
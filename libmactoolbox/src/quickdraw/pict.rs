@@ -0,0 +1,1152 @@
+use anyhow::{anyhow, bail, Context, Result as AResult};
+use byteorder::{BigEndian, ByteOrder};
+use crate::{Point, Rect, ResourceManager};
+use libcommon::{Reader, Resource, resource::Input};
+use super::{
+    CGrafPort,
+    ColorTable,
+    GrafVerb,
+    map_point,
+    Pattern,
+    Pen,
+    PixMap,
+    PixPat,
+    Polygon,
+    Region,
+    RGBColor,
+    unpack_bits,
+};
+
+/// A `QuickDraw` picture, which is a recording of drawing commands that can be
+/// played back into a graphics port.
+///
+/// Both version 1 pictures, which use byte opcodes and only support
+/// monochrome images, and version 2 pictures, which use word opcodes and add
+/// colour, are supported.
+#[derive(Clone)]
+pub struct Picture {
+    frame: Rect,
+    version: u8,
+    data: Vec<u8>,
+}
+
+impl Picture {
+    /// Creates a picture from the contents of a `PICT` resource. Data from
+    /// `PICT` files must have its 512-byte file header removed first.
+    pub fn new(data: Vec<u8>) -> AResult<Self> {
+        const HEADER_SIZE: usize = 10;
+
+        if data.len() < HEADER_SIZE + 2 {
+            bail!("Picture is too small ({} bytes)", data.len());
+        }
+
+        let frame = read_rect(&data[2..]);
+        let version = if data[HEADER_SIZE..HEADER_SIZE + 2] == [ 0x11, 0x01 ] {
+            1
+        } else if data.get(HEADER_SIZE..HEADER_SIZE + 4) == Some(&[ 0x00, 0x11, 0x02, 0xff ][..]) {
+            2
+        } else {
+            bail!("Unknown picture version {:02x?}", &data[HEADER_SIZE..HEADER_SIZE + 2]);
+        };
+
+        Ok(Self { frame, version, data })
+    }
+
+    /// Draws the picture into the given port, scaled to fit the destination
+    /// rectangle, like `DrawPicture`. Text in the picture is not drawn; use
+    /// [`Picture::draw_with_fonts`] to draw it.
+    pub fn draw(&self, port: &mut CGrafPort, dst_rect: Rect) -> AResult<()> {
+        self.draw_impl(port, dst_rect, None)
+    }
+
+    /// Draws the picture into the given port, scaled to fit the destination
+    /// rectangle, like `DrawPicture`. Fonts for text in the picture are loaded
+    /// from the given resource manager.
+    pub fn draw_with_fonts(&self, port: &mut CGrafPort, dst_rect: Rect, resources: &ResourceManager<'_>) -> AResult<()> {
+        self.draw_impl(port, dst_rect, Some(resources))
+    }
+
+    fn draw_impl(&self, port: &mut CGrafPort, dst_rect: Rect, resources: Option<&ResourceManager<'_>>) -> AResult<()> {
+        let saved_port = port.clone();
+        let result = Interpreter::new(self, port, dst_rect, resources).run();
+        *port = saved_port;
+        result
+    }
+
+    /// The bounding rectangle of the picture at 72 dpi.
+    #[must_use]
+    pub fn frame(&self) -> Rect {
+        self.frame
+    }
+
+    /// Draws the picture at its original size into a new image with a white
+    /// background.
+    pub fn to_pix_map(&self) -> AResult<PixMap> {
+        let mut port = CGrafPort::new(self.frame);
+        self.draw(&mut port, self.frame)?;
+        let pix_map = port.pix_map().borrow().clone();
+        Ok(pix_map)
+    }
+
+    #[must_use]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+impl std::fmt::Debug for Picture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("frame", &self.frame)
+            .field("version", &self.version)
+            .field("(size)", &self.data.len())
+            .finish()
+    }
+}
+
+impl Resource for Picture {
+    type Context = ();
+    fn load(input: &mut Input<impl Reader>, size: u32, context: &Self::Context) -> AResult<Self> where Self: Sized {
+        Self::new(Vec::<u8>::load(input, size, context)?)
+    }
+}
+
+/// Picture data with a read cursor.
+struct Stream<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl <'a> Stream<'a> {
+    fn align(&mut self) {
+        self.pos += self.pos & 1;
+    }
+
+    fn bytes(&mut self, size: usize) -> AResult<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + size)
+            .ok_or_else(|| anyhow!("Unexpected end of picture data at offset {}", self.pos))?;
+        self.pos += size;
+        Ok(bytes)
+    }
+
+    fn i8(&mut self) -> AResult<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn i16(&mut self) -> AResult<i16> {
+        Ok(BigEndian::read_i16(self.bytes(2)?))
+    }
+
+    fn pattern(&mut self) -> AResult<Pattern> {
+        let mut pattern = Pattern::default();
+        pattern.0.copy_from_slice(self.bytes(8)?);
+        Ok(pattern)
+    }
+
+    /// Reads a point, which is stored vertical coordinate first.
+    fn point(&mut self) -> AResult<Point> {
+        let y = self.i16()?;
+        let x = self.i16()?;
+        Ok(Point { x, y })
+    }
+
+    fn rect(&mut self) -> AResult<Rect> {
+        Ok(read_rect(self.bytes(8)?))
+    }
+
+    fn rgb(&mut self) -> AResult<RGBColor> {
+        Ok(RGBColor {
+            r: self.u16()?,
+            g: self.u16()?,
+            b: self.u16()?,
+        })
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    fn skip(&mut self, size: usize) -> AResult<()> {
+        self.bytes(size).map(|_| ())
+    }
+
+    fn u8(&mut self) -> AResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> AResult<u16> {
+        Ok(BigEndian::read_u16(self.bytes(2)?))
+    }
+
+    fn u32(&mut self) -> AResult<u32> {
+        Ok(BigEndian::read_u32(self.bytes(4)?))
+    }
+}
+
+/// The layout of a pixel image stored in a picture.
+#[derive(Debug)]
+struct ImageHeader {
+    row_bytes: usize,
+    bounds: Rect,
+    is_pix_map: bool,
+    pack_type: u16,
+    pixel_size: u16,
+    cmp_count: u16,
+    color_table: ColorTable,
+}
+
+/// Plays back the opcodes of a picture into a graphics port.
+struct Interpreter<'a, 'vfs> {
+    input: Stream<'a>,
+    version: u8,
+    port: &'a mut CGrafPort,
+    resources: Option<&'a ResourceManager<'vfs>>,
+    src_frame: Rect,
+    dst_rect: Rect,
+    clip: Region,
+    origin: Point,
+    pen_loc: Point,
+    text_loc: Point,
+    oval_size: Point,
+    last_rect: Rect,
+    last_rrect: Rect,
+    last_oval: Rect,
+    last_poly: Polygon,
+    last_rgn: Region,
+}
+
+impl <'a, 'vfs> Interpreter<'a, 'vfs> {
+    fn new(picture: &'a Picture, port: &'a mut CGrafPort, dst_rect: Rect, resources: Option<&'a ResourceManager<'vfs>>) -> Self {
+        let clip = port.clip_rgn().clone();
+        port.pen_size(1, 1);
+        port.pen_mode(Pen::PatCopy as u16);
+        port.pen_pix_pat(Pattern::BLACK.into());
+        port.fill_pix_pat(Pattern::BLACK.into());
+        port.back_pix_pat(Pattern::WHITE.into());
+        port.rgb_fore_color(RGBColor::BLACK);
+        port.rgb_back_color(RGBColor::WHITE);
+
+        Self {
+            input: Stream {
+                data: &picture.data,
+                pos: if picture.version == 1 { 12 } else { 14 },
+            },
+            version: picture.version,
+            port,
+            resources,
+            src_frame: picture.frame,
+            dst_rect,
+            clip,
+            origin: Point::default(),
+            pen_loc: Point::default(),
+            text_loc: Point::default(),
+            oval_size: Point::default(),
+            last_rect: Rect::default(),
+            last_rrect: Rect::default(),
+            last_oval: Rect::default(),
+            last_poly: Polygon::default(),
+            last_rgn: Region::default(),
+        }
+    }
+
+    fn run(&mut self) -> AResult<()> {
+        const OP_END_PIC: u16 = 0xff;
+
+        loop {
+            let offset = self.input.pos;
+            let opcode = if self.version == 1 {
+                u16::from(self.input.u8()?)
+            } else {
+                self.input.align();
+                self.input.u16()?
+            };
+
+            if opcode == OP_END_PIC {
+                return Ok(());
+            }
+
+            self.execute(opcode)
+                .with_context(|| format!("Can’t execute picture opcode 0x{:x} at offset {}", opcode, offset))?;
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn execute(&mut self, opcode: u16) -> AResult<()> {
+        match opcode {
+            // NOP, reserved, HiliteMode, DefHilite, OpEndPic (which is
+            // handled by the caller)
+            0x00 | 0x17..=0x19 | 0x1c | 0x1e | 0xb0..=0xcf | 0xff | 0x8000..=0x80ff => {},
+            // Clip
+            0x01 => {
+                let region = self.read_region()?;
+                let region = self.map_region(&region)?.intersect(&self.clip);
+                self.port.set_clip(region);
+            },
+            // BkPat
+            0x02 => self.port.back_pix_pat(self.input.pattern()?.into()),
+            // TxFont
+            0x03 => self.port.text_font(self.input.u16()?),
+            // TxFace
+            0x04 => self.port.text_face(self.input.u8()?.into()),
+            // TxMode
+            0x05 => self.port.text_mode(self.input.u16()?),
+            // SpExtra
+            0x06 => self.port.space_extra(self.input.u32()?),
+            // PnSize
+            0x07 => {
+                let size = self.input.point()?;
+                let (width, height) = self.map_size(size);
+                self.port.pen_size(width, height);
+            },
+            // PnMode
+            0x08 => self.port.pen_mode(self.input.u16()?),
+            // PnPat
+            0x09 => self.port.pen_pix_pat(self.input.pattern()?.into()),
+            // FillPat
+            0x0a => self.port.fill_pix_pat(self.input.pattern()?.into()),
+            // OvSize
+            0x0b => self.oval_size = self.input.point()?,
+            // Origin
+            0x0c => {
+                let dh = self.input.i16()?;
+                let dv = self.input.i16()?;
+                self.origin = offset_point(self.origin, dh, dv).context("Origin is out of range")?;
+            },
+            // TxSize
+            0x0d => self.port.text_size(self.input.u16()?),
+            // FgColor
+            0x0e => self.port.rgb_fore_color(old_color(self.input.u32()?)),
+            // BkColor
+            0x0f => self.port.rgb_back_color(old_color(self.input.u32()?)),
+            // TxRatio
+            // TODO: Scale text when drawing the picture at a different size
+            0x10 => self.input.skip(8)?,
+            // VersionOp
+            0x11 => self.input.skip(self.version.into())?,
+            // BkPixPat
+            0x12 => {
+                let pix_pat = self.read_pix_pat()?;
+                self.port.back_pix_pat(pix_pat);
+            },
+            // PnPixPat
+            0x13 => {
+                let pix_pat = self.read_pix_pat()?;
+                self.port.pen_pix_pat(pix_pat);
+            },
+            // FillPixPat
+            0x14 => {
+                let pix_pat = self.read_pix_pat()?;
+                self.port.fill_pix_pat(pix_pat);
+            },
+            // PnLocHFrac, ChExtra
+            0x15 | 0x16 => self.input.skip(2)?,
+            // RGBFgCol
+            0x1a => self.port.rgb_fore_color(self.input.rgb()?),
+            // RGBBkCol
+            0x1b => self.port.rgb_back_color(self.input.rgb()?),
+            // HiliteColor
+            0x1d => self.port.hilite_color(self.input.rgb()?),
+            // OpColor
+            0x1f => self.port.op_color(self.input.rgb()?),
+            // Line
+            0x20 => {
+                self.pen_loc = self.input.point()?;
+                let to = self.input.point()?;
+                self.line_to(to)?;
+            },
+            // LineFrom
+            0x21 => {
+                let to = self.input.point()?;
+                self.line_to(to)?;
+            },
+            // ShortLine
+            0x22 => {
+                self.pen_loc = self.input.point()?;
+                let to = self.short_offset()?;
+                self.line_to(to)?;
+            },
+            // ShortLineFrom
+            0x23 => {
+                let to = self.short_offset()?;
+                self.line_to(to)?;
+            },
+            // LongText
+            0x28 => {
+                self.text_loc = self.input.point()?;
+                self.draw_text()?;
+            },
+            // DHText
+            0x29 => {
+                let dh = self.input.u8()?;
+                self.move_text(dh, 0)?;
+            },
+            // DVText
+            0x2a => {
+                let dv = self.input.u8()?;
+                self.move_text(0, dv)?;
+            },
+            // DHDVText
+            0x2b => {
+                let dh = self.input.u8()?;
+                let dv = self.input.u8()?;
+                self.move_text(dh, dv)?;
+            },
+            // Reserved, FontName, LineJustify, GlyphState
+            0x24..=0x27 | 0x2c..=0x2f | 0x92..=0x97 | 0x9c..=0x9f | 0xa2..=0xaf => {
+                let size = self.input.u16()?;
+                self.input.skip(size.into())?;
+            },
+            // frameRect, paintRect, eraseRect, invertRect, fillRect
+            0x30..=0x37 => {
+                self.last_rect = self.input.rect()?;
+                self.draw_rect(opcode)?;
+            },
+            0x38..=0x3f => self.draw_rect(opcode)?,
+            // frameRRect, paintRRect, eraseRRect, invertRRect, fillRRect
+            0x40..=0x47 => {
+                self.last_rrect = self.input.rect()?;
+                self.draw_rrect(opcode)?;
+            },
+            0x48..=0x4f => self.draw_rrect(opcode)?,
+            // frameOval, paintOval, eraseOval, invertOval, fillOval
+            0x50..=0x57 => {
+                self.last_oval = self.input.rect()?;
+                self.draw_oval(opcode)?;
+            },
+            0x58..=0x5f => self.draw_oval(opcode)?,
+            // frameArc, paintArc, eraseArc, invertArc, fillArc
+            0x60..=0x67 => {
+                self.last_oval = self.input.rect()?;
+                self.draw_arc(opcode)?;
+            },
+            0x68..=0x6f => self.draw_arc(opcode)?,
+            // framePoly, paintPoly, erasePoly, invertPoly, fillPoly
+            0x70..=0x77 => {
+                self.last_poly = self.read_polygon()?;
+                self.draw_poly(opcode)?;
+            },
+            0x78..=0x7f => self.draw_poly(opcode)?,
+            // frameRgn, paintRgn, eraseRgn, invertRgn, fillRgn
+            0x80..=0x87 => {
+                self.last_rgn = self.read_region()?;
+                self.draw_rgn(opcode)?;
+            },
+            0x88..=0x8f => self.draw_rgn(opcode)?,
+            // BitsRect, BitsRgn, PackBitsRect, PackBitsRgn
+            0x90 | 0x91 | 0x98 | 0x99 => self.draw_bits(opcode & 8 != 0, opcode & 1 != 0, false)?,
+            // DirectBitsRect, DirectBitsRgn
+            0x9a | 0x9b => self.draw_bits(true, opcode & 1 != 0, true)?,
+            // ShortComment
+            0xa0 => {
+                // Picture comments are only meaningful to printer drivers
+                self.input.u16()?;
+            },
+            // LongComment
+            0xa1 => {
+                self.input.skip(2)?;
+                let size = self.input.u16()?;
+                self.input.skip(size.into())?;
+            },
+            // Reserved
+            0xd0..=0xfe | 0x8100..=0x81ff | 0x8202..=0xffff => {
+                let size = self.input.u32()?;
+                self.input.skip(size as usize)?;
+            },
+            // HeaderOp
+            0x0c00 => {
+                const EXTENDED_VERSION: i16 = -2;
+                let header = self.input.bytes(24)?;
+                // Extended pictures store coordinates at their native
+                // resolution instead of 72 dpi
+                if BigEndian::read_i16(header) == EXTENDED_VERSION {
+                    self.src_frame = read_rect(&header[12..]);
+                }
+            },
+            // Reserved
+            0x0100..=0x7fff => self.input.skip(usize::from(opcode >> 8) * 2)?,
+            // CompressedQuickTime, UncompressedQuickTime
+            0x8200 | 0x8201 => bail!("QuickTime pictures are not supported"),
+        }
+
+        Ok(())
+    }
+
+    fn draw_arc(&mut self, opcode: u16) -> AResult<()> {
+        let start_angle = self.input.i16()?;
+        let arc_angle = self.input.i16()?;
+        if let Some(verb) = verb(opcode) {
+            let rect = self.map_rect(self.last_oval)?;
+            self.port.std_arc(verb, rect, start_angle, arc_angle);
+        }
+        Ok(())
+    }
+
+    fn draw_bits(&mut self, is_packed: bool, has_region: bool, is_direct: bool) -> AResult<()> {
+        let header = self.read_image_header(is_direct)?;
+        let src_rect = self.input.rect()?;
+        let dst_rect = self.input.rect()?;
+        let mode = self.input.u16()?;
+        let mask_rgn = if has_region {
+            let region = self.read_region()?;
+            Some(self.map_region(&region)?)
+        } else {
+            None
+        };
+        let image = self.read_pixels(&header, is_packed)?;
+        let dst_rect = self.map_rect(dst_rect)?;
+        self.port.std_bits(&image, src_rect, dst_rect, mode, mask_rgn.as_ref());
+        Ok(())
+    }
+
+    fn draw_oval(&mut self, opcode: u16) -> AResult<()> {
+        if let Some(verb) = verb(opcode) {
+            let rect = self.map_rect(self.last_oval)?;
+            self.port.std_oval(verb, rect);
+        }
+        Ok(())
+    }
+
+    fn draw_poly(&mut self, opcode: u16) -> AResult<()> {
+        if let Some(verb) = verb(opcode) {
+            let points = self.last_poly.points().iter()
+                .map(|&point| self.map_point(point))
+                .collect::<AResult<_>>()?;
+            self.port.std_poly(verb, &Polygon::new(points));
+        }
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, opcode: u16) -> AResult<()> {
+        if let Some(verb) = verb(opcode) {
+            let rect = self.map_rect(self.last_rect)?;
+            self.port.std_rect(verb, rect);
+        }
+        Ok(())
+    }
+
+    fn draw_rgn(&mut self, opcode: u16) -> AResult<()> {
+        if let Some(verb) = verb(opcode) {
+            let region = self.map_region(&self.last_rgn)?;
+            self.port.std_rgn(verb, &region);
+        }
+        Ok(())
+    }
+
+    fn draw_rrect(&mut self, opcode: u16) -> AResult<()> {
+        if let Some(verb) = verb(opcode) {
+            let rect = self.map_rect(self.last_rrect)?;
+            let (oval_width, oval_height) = self.map_size(self.oval_size);
+            self.port.std_rrect(verb, rect, oval_width, oval_height);
+        }
+        Ok(())
+    }
+
+    fn line_to(&mut self, to: Point) -> AResult<()> {
+        let from = self.map_point(self.pen_loc)?;
+        self.port.move_to(from.x, from.y);
+        self.port.std_line(self.map_point(to)?);
+        self.pen_loc = to;
+        Ok(())
+    }
+
+    /// Converts a point from picture coordinates to port coordinates.
+    fn map_point(&self, point: Point) -> AResult<Point> {
+        let point = Point {
+            x: point.x.checked_sub(self.origin.x).context("Horizontal coordinate is out of range")?,
+            y: point.y.checked_sub(self.origin.y).context("Vertical coordinate is out of range")?,
+        };
+        Ok(map_point(point, self.src_frame, self.dst_rect))
+    }
+
+    /// Converts a rectangle from picture coordinates to port coordinates.
+    fn map_rect(&self, rect: Rect) -> AResult<Rect> {
+        let top_left = self.map_point(Point { x: rect.left, y: rect.top })?;
+        let bottom_right = self.map_point(Point { x: rect.right, y: rect.bottom })?;
+        Ok(Rect {
+            top: top_left.y,
+            left: top_left.x,
+            bottom: bottom_right.y,
+            right: bottom_right.x,
+        })
+    }
+
+    /// Converts a region from picture coordinates to port coordinates.
+    fn map_region(&self, region: &Region) -> AResult<Region> {
+        let dh = self.origin.x.checked_neg().context("Horizontal origin is out of range")?;
+        let dv = self.origin.y.checked_neg().context("Vertical origin is out of range")?;
+        Ok(region.offset(dh, dv).map(self.src_frame, self.dst_rect))
+    }
+
+    /// Moves the text location by unsigned offsets and draws the text which
+    /// follows.
+    fn move_text(&mut self, dh: u8, dv: u8) -> AResult<()> {
+        self.text_loc = offset_point(self.text_loc, dh.into(), dv.into())
+            .context("Text location is out of range")?;
+        self.draw_text()
+    }
+
+    /// Converts a size from picture coordinates to port coordinates, keeping
+    /// non-zero sizes at least one pixel.
+    fn map_size(&self, size: Point) -> (i16, i16) {
+        let scale = |value: i16, from: i16, to: i16| {
+            if value <= 0 || from == 0 {
+                value
+            } else {
+                (i32::from(value) * i32::from(to) / i32::from(from)).max(1) as i16
+            }
+        };
+
+        (
+            scale(size.x, self.src_frame.width(), self.dst_rect.width()),
+            scale(size.y, self.src_frame.height(), self.dst_rect.height()),
+        )
+    }
+
+    fn read_color_table(&mut self) -> AResult<ColorTable> {
+        const DEVICE_FLAG: u16 = 0x8000;
+        let _seed = self.input.u32()?;
+        let flags = self.input.u16()?;
+        let count = usize::from(self.input.u16()?) + 1;
+        let mut entries = Vec::with_capacity(count);
+        for index in 0..count {
+            let value = self.input.u16()?;
+            let color = self.input.rgb()?;
+            let value = if flags & DEVICE_FLAG == 0 { value } else { index as u16 };
+            entries.push((value, color));
+        }
+        Ok(ColorTable::new(entries))
+    }
+
+    fn read_image_header(&mut self, is_direct: bool) -> AResult<ImageHeader> {
+        const PIX_MAP_FLAG: u16 = 0x8000;
+        const ROW_BYTES_MASK: u16 = 0x3fff;
+
+        if is_direct {
+            let _base_addr = self.input.u32()?;
+        }
+
+        let row_bytes = self.input.u16()?;
+        let bounds = self.input.rect()?;
+        if row_bytes & PIX_MAP_FLAG == 0 {
+            return Ok(ImageHeader {
+                row_bytes: usize::from(row_bytes & ROW_BYTES_MASK),
+                bounds,
+                is_pix_map: false,
+                pack_type: 0,
+                pixel_size: 1,
+                cmp_count: 1,
+                color_table: ColorTable::default(),
+            });
+        }
+
+        let _version = self.input.u16()?;
+        let pack_type = self.input.u16()?;
+        let _pack_size = self.input.u32()?;
+        let _h_res = self.input.u32()?;
+        let _v_res = self.input.u32()?;
+        let _pixel_type = self.input.u16()?;
+        let pixel_size = self.input.u16()?;
+        let cmp_count = self.input.u16()?;
+        let _cmp_size = self.input.u16()?;
+        let _plane_bytes = self.input.u32()?;
+        let _pm_table = self.input.u32()?;
+        let _pm_reserved = self.input.u32()?;
+
+        let color_table = if is_direct {
+            ColorTable::default()
+        } else {
+            self.read_color_table()?
+        };
+
+        if !matches!(pixel_size, 1 | 2 | 4 | 8 | 16 | 32) {
+            bail!("Invalid pixel size {}", pixel_size);
+        }
+
+        Ok(ImageHeader {
+            row_bytes: usize::from(row_bytes & ROW_BYTES_MASK),
+            bounds,
+            is_pix_map: true,
+            pack_type,
+            pixel_size,
+            cmp_count,
+            color_table,
+        })
+    }
+
+    fn read_pix_pat(&mut self) -> AResult<PixPat> {
+        const COLOR_PATTERN: u16 = 1;
+        const DITHER_PATTERN: u16 = 2;
+
+        let pat_type = self.input.u16()?;
+        let pattern = self.input.pattern()?;
+        Ok(match pat_type {
+            COLOR_PATTERN => {
+                let header = self.read_image_header(false)?;
+                PixPat::new(pattern, self.read_pixels(&header, true)?)
+            },
+            DITHER_PATTERN => PixPat::from_rgb(pattern, self.input.rgb()?),
+            _ => pattern.into(),
+        })
+    }
+
+    fn read_pixels(&mut self, header: &ImageHeader, is_packed: bool) -> AResult<PixMap> {
+        // 32-bit pixels with pack type 2 have the unused byte removed but are
+        // otherwise stored unpacked
+        const DROP_PAD: u16 = 2;
+        // 16-bit pixels are packed a word at a time
+        const PACK_WORDS: u16 = 3;
+        // 32-bit pixels are packed by component, one plane after another
+        const PACK_COMPONENTS: u16 = 4;
+
+        if header.bounds.is_empty() {
+            return Ok(PixMap::new(header.bounds, RGBColor::WHITE));
+        }
+
+        let bounds = header.bounds;
+        let width = bounds.right.checked_sub(bounds.left)
+            .with_context(|| format!("Image width {}..{} is out of range", bounds.left, bounds.right))? as usize;
+        let height = bounds.bottom.checked_sub(bounds.top)
+            .with_context(|| format!("Image height {}..{} is out of range", bounds.top, bounds.bottom))?;
+        let row_bytes = header.row_bytes;
+        let pixel_size = usize::from(header.pixel_size);
+        let cmp_count = usize::from(header.cmp_count);
+        let pack_type = if header.pixel_size == 32 && header.pack_type == 0 {
+            PACK_COMPONENTS
+        } else if header.pixel_size == 16 && header.pack_type == 0 {
+            PACK_WORDS
+        } else {
+            header.pack_type
+        };
+        let is_unpacked = !is_packed || row_bytes < 8 || pack_type == 1;
+        let is_planar = !is_unpacked && pack_type == PACK_COMPONENTS && pixel_size == 32;
+        if is_planar && cmp_count < 3 {
+            bail!("Invalid component count {} for planar pixels", cmp_count);
+        }
+        let is_drop_pad = !is_unpacked && pack_type == DROP_PAD && pixel_size == 32;
+        let item_size = if pack_type == PACK_WORDS && pixel_size == 16 { 2 } else { 1 };
+        let expected_size = if is_planar { width * cmp_count } else { row_bytes };
+
+        // The bounds are untrusted, so they are checked against the row size
+        // and the remaining data before the image is allocated
+        if width * pixel_size > row_bytes * 8 {
+            bail!("Image width {} is too large for row bytes {}", width, row_bytes);
+        }
+        let min_row_size = if is_unpacked {
+            row_bytes
+        } else if is_drop_pad {
+            width * 3
+        } else {
+            // Each PackBits run takes at least two bytes and expands to at
+            // most 128 items
+            let count_size = if row_bytes > 250 { 2 } else { 1 };
+            count_size + 2 * ((expected_size + 128 * item_size - 1) / (128 * item_size))
+        };
+        if min_row_size * height as usize > self.input.remaining() {
+            bail!("Not enough picture data for {}x{} image", width, height);
+        }
+
+        let mut image = PixMap::new(bounds, RGBColor::WHITE);
+        for y in 0..height {
+            let row = if is_unpacked {
+                self.input.bytes(row_bytes)?.to_vec()
+            } else if is_drop_pad {
+                self.input.bytes(width * 3)?.to_vec()
+            } else {
+                let packed_size = if row_bytes > 250 {
+                    usize::from(self.input.u16()?)
+                } else {
+                    usize::from(self.input.u8()?)
+                };
+                let packed = self.input.bytes(packed_size)?;
+                unpack_bits(packed, item_size, expected_size)
+                    .with_context(|| format!("Can’t unpack row {}", y))?
+            };
+
+            for x in 0..width {
+                let color = match pixel_size {
+                    1 | 2 | 4 | 8 => {
+                        let bit_offset = x * pixel_size;
+                        let byte = *row.get(bit_offset / 8).context("Pixel row too short")?;
+                        let shift = 8 - pixel_size - bit_offset % 8;
+                        let value = u16::from(byte >> shift) & ((1 << pixel_size) - 1);
+                        if header.is_pix_map {
+                            header.color_table.get(value).unwrap_or(RGBColor::BLACK)
+                        } else if value == 0 {
+                            RGBColor::WHITE
+                        } else {
+                            RGBColor::BLACK
+                        }
+                    },
+                    16 => {
+                        let value = BigEndian::read_u16(row.get(x * 2..x * 2 + 2).context("Pixel row too short")?);
+                        let expand = |value: u16| (u32::from(value & 0x1f) * 0xffff / 0x1f) as u16;
+                        RGBColor { r: expand(value >> 10), g: expand(value >> 5), b: expand(value) }
+                    },
+                    _ => {
+                        let (r, g, b) = if is_planar {
+                            let first = (cmp_count - 3) * width + x;
+                            (first, first + width, first + width * 2)
+                        } else if !is_unpacked && pack_type == DROP_PAD {
+                            (x * 3, x * 3 + 1, x * 3 + 2)
+                        } else {
+                            (x * 4 + 1, x * 4 + 2, x * 4 + 3)
+                        };
+                        let channel = |index: usize| row.get(index).map(|&value| u16::from(value) * 0x101)
+                            .context("Pixel row too short");
+                        RGBColor { r: channel(r)?, g: channel(g)?, b: channel(b)? }
+                    },
+                };
+
+                image.set_pixel(bounds.left + x as i16, bounds.top + y, color);
+            }
+        }
+
+        Ok(image)
+    }
+
+    fn read_polygon(&mut self) -> AResult<Polygon> {
+        const HEADER_SIZE: u16 = 10;
+        let size = self.input.u16()?;
+        if size < HEADER_SIZE {
+            bail!("Invalid polygon size {}", size);
+        }
+        let _bounds = self.input.rect()?;
+        let mut points = Vec::with_capacity(usize::from((size - HEADER_SIZE) / 4));
+        for _ in 0..(size - HEADER_SIZE) / 4 {
+            points.push(self.input.point()?);
+        }
+        Ok(Polygon::new(points))
+    }
+
+    fn read_region(&mut self) -> AResult<Region> {
+        let size = BigEndian::read_u16(self.input.bytes(2)?);
+        self.input.pos -= 2;
+        if size < Region::RECT_SIZE {
+            bail!("Invalid region size {}", size);
+        }
+        Region::parse(self.input.bytes(size.into())?)
+    }
+
+    fn draw_text(&mut self) -> AResult<()> {
+        let size = self.input.u8()?;
+        let text = self.input.bytes(size.into())?;
+        if let Some(resources) = self.resources {
+            let loc = self.map_point(self.text_loc)?;
+            self.port.move_to(loc.x, loc.y);
+            self.port.std_text(resources, text)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a pen offset stored as two signed bytes.
+    fn short_offset(&mut self) -> AResult<Point> {
+        let dh = self.input.i8()?;
+        let dv = self.input.i8()?;
+        offset_point(self.pen_loc, dh.into(), dv.into()).context("Pen location is out of range")
+    }
+}
+
+/// Moves a point, or returns `None` if it would move out of range.
+fn offset_point(point: Point, dh: i16, dv: i16) -> Option<Point> {
+    Some(Point {
+        x: point.x.checked_add(dh)?,
+        y: point.y.checked_add(dv)?,
+    })
+}
+
+/// Converts one of the eight colours from the original `QuickDraw` colour model
+/// to an RGB colour.
+fn old_color(color: u32) -> RGBColor {
+    const WHITE_COLOR: u32 = 30;
+    const YELLOW_COLOR: u32 = 69;
+    const MAGENTA_COLOR: u32 = 137;
+    const RED_COLOR: u32 = 205;
+    const CYAN_COLOR: u32 = 273;
+    const GREEN_COLOR: u32 = 341;
+    const BLUE_COLOR: u32 = 409;
+
+    let (r, g, b) = match color {
+        WHITE_COLOR => (0xffff, 0xffff, 0xffff),
+        YELLOW_COLOR => (0xffff, 0xffff, 0),
+        MAGENTA_COLOR => (0xffff, 0, 0xffff),
+        RED_COLOR => (0xffff, 0, 0),
+        CYAN_COLOR => (0, 0xffff, 0xffff),
+        GREEN_COLOR => (0, 0xffff, 0),
+        BLUE_COLOR => (0, 0, 0xffff),
+        // Black, and the fallback for invalid colours
+        _ => (0, 0, 0),
+    };
+    RGBColor { r, g, b }
+}
+
+fn read_rect(data: &[u8]) -> Rect {
+    Rect {
+        top: BigEndian::read_i16(data),
+        left: BigEndian::read_i16(&data[2..]),
+        bottom: BigEndian::read_i16(&data[4..]),
+        right: BigEndian::read_i16(&data[6..]),
+    }
+}
+
+/// Returns the drawing operation for a shape opcode, or `None` if the opcode
+/// is one of the reserved variants.
+fn verb(opcode: u16) -> Option<GrafVerb> {
+    match opcode & 7 {
+        0 => Some(GrafVerb::Frame),
+        1 => Some(GrafVerb::Paint),
+        2 => Some(GrafVerb::Erase),
+        3 => Some(GrafVerb::Invert),
+        4 => Some(GrafVerb::Fill),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    fn rect(top: i16, left: i16, bottom: i16, right: i16) -> Vec<u8> {
+        [ top, left, bottom, right ].iter().flat_map(|value| value.to_be_bytes().to_vec()).collect()
+    }
+
+    fn picture_v1(frame: &[u8], ops: &[u8]) -> Picture {
+        let mut data = vec![ 0; 2 ];
+        data.extend_from_slice(frame);
+        data.extend_from_slice(b"\x11\x01");
+        data.extend_from_slice(ops);
+        data.push(0xff);
+        Picture::new(data).unwrap()
+    }
+
+    fn picture_v2(frame: &[u8], ops: &[u8]) -> Picture {
+        let mut data = vec![ 0; 2 ];
+        data.extend_from_slice(frame);
+        data.extend_from_slice(b"\x00\x11\x02\xff\x0c\x00\xff\xff\xff\xff");
+        data.extend_from_slice(&[ 0; 20 ]);
+        data.extend_from_slice(ops);
+        if data.len() & 1 == 1 {
+            data.push(0);
+        }
+        data.extend_from_slice(b"\x00\xff");
+        Picture::new(data).unwrap()
+    }
+
+    fn pixel(image: &PixMap, x: i16, y: i16) -> RGBColor {
+        image.pixel(x, y).unwrap()
+    }
+
+    const RED: RGBColor = RGBColor { r: 0xffff, g: 0, b: 0 };
+    const GREEN: RGBColor = RGBColor { r: 0, g: 0xffff, b: 0 };
+
+    #[test]
+    fn bad_version() {
+        let mut data = vec![ 0; 2 ];
+        data.extend_from_slice(&rect(0, 0, 1, 1));
+        data.extend_from_slice(b"\x00\x11\x03\xff");
+        assert!(Picture::new(data).is_err());
+        assert!(Picture::new(vec![ 0; 4 ]).is_err());
+    }
+
+    #[test]
+    fn v1_paint_rect() {
+        let mut ops = vec![ 0x31 ];
+        ops.extend(rect(2, 2, 5, 5));
+        let picture = picture_v1(&rect(0, 0, 10, 10), &ops);
+        assert_eq!(picture.version(), 1);
+        let image = picture.to_pix_map().unwrap();
+        assert_eq!(pixel(&image, 2, 2), RGBColor::BLACK);
+        assert_eq!(pixel(&image, 4, 4), RGBColor::BLACK);
+        assert_eq!(pixel(&image, 5, 5), RGBColor::WHITE);
+        assert_eq!(pixel(&image, 1, 1), RGBColor::WHITE);
+    }
+
+    #[test]
+    fn v1_clip_and_paint_region() {
+        // A 10×10 square with a 4×4 hole in the middle
+        let donut = [
+            0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x0a,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x7f, 0xff,
+            0x00, 0x03, 0x00, 0x03, 0x00, 0x07, 0x7f, 0xff,
+            0x00, 0x07, 0x00, 0x03, 0x00, 0x07, 0x7f, 0xff,
+            0x00, 0x0a, 0x00, 0x00, 0x00, 0x0a, 0x7f, 0xff,
+            0x7f, 0xff,
+        ];
+
+        let mut paint_ops = vec![ 0x81 ];
+        paint_ops.extend_from_slice(&donut);
+
+        let mut clip_ops = vec![ 0x01 ];
+        clip_ops.extend_from_slice(&donut);
+        clip_ops.push(0x31);
+        clip_ops.extend(rect(0, 0, 10, 10));
+
+        for ops in &[ paint_ops, clip_ops ] {
+            let picture = picture_v1(&rect(0, 0, 10, 10), ops);
+            let image = picture.to_pix_map().unwrap();
+            assert_eq!(pixel(&image, 0, 0), RGBColor::BLACK);
+            assert_eq!(pixel(&image, 9, 5), RGBColor::BLACK);
+            assert_eq!(pixel(&image, 5, 5), RGBColor::WHITE);
+        }
+    }
+
+    #[test]
+    fn v1_scaled() {
+        let mut ops = vec![ 0x31 ];
+        ops.extend(rect(0, 0, 5, 5));
+        let picture = picture_v1(&rect(0, 0, 10, 10), &ops);
+        let bounds = Rect { top: 0, left: 0, bottom: 20, right: 20 };
+        let mut port = CGrafPort::new(bounds);
+        picture.draw(&mut port, bounds).unwrap();
+        let image = port.pix_map().borrow();
+        assert_eq!(pixel(&image, 9, 9), RGBColor::BLACK);
+        assert_eq!(pixel(&image, 10, 10), RGBColor::WHITE);
+    }
+
+    #[test]
+    fn v2_frame_rect() {
+        let mut ops = b"\x00\x07\x00\x02\x00\x02\x00\x30".to_vec();
+        ops.extend(rect(0, 0, 10, 10));
+        let image = picture_v2(&rect(0, 0, 10, 10), &ops).to_pix_map().unwrap();
+        assert_eq!(pixel(&image, 1, 1), RGBColor::BLACK);
+        assert_eq!(pixel(&image, 9, 9), RGBColor::BLACK);
+        assert_eq!(pixel(&image, 2, 2), RGBColor::WHITE);
+        assert_eq!(pixel(&image, 5, 8), RGBColor::BLACK);
+    }
+
+    #[test]
+    fn v2_rgb_oval() {
+        let mut ops = b"\x00\x1a\xff\xff\x00\x00\x00\x00\x00\x51".to_vec();
+        ops.extend(rect(0, 0, 10, 10));
+        let picture = picture_v2(&rect(0, 0, 10, 10), &ops);
+        assert_eq!(picture.version(), 2);
+        let image = picture.to_pix_map().unwrap();
+        assert_eq!(pixel(&image, 5, 5), RED);
+        assert_eq!(pixel(&image, 0, 5), RED);
+        assert_eq!(pixel(&image, 0, 0), RGBColor::WHITE);
+        assert_eq!(pixel(&image, 9, 9), RGBColor::WHITE);
+    }
+
+    #[test]
+    fn v2_polygon() {
+        let mut ops = b"\x00\x71\x00\x16".to_vec();
+        ops.extend(rect(0, 0, 10, 10));
+        ops.extend_from_slice(b"\x00\x00\x00\x00\x00\x00\x00\x0a\x00\x0a\x00\x00");
+        let image = picture_v2(&rect(0, 0, 10, 10), &ops).to_pix_map().unwrap();
+        assert_eq!(pixel(&image, 0, 1), RGBColor::BLACK);
+        assert_eq!(pixel(&image, 1, 8), RGBColor::WHITE);
+    }
+
+    #[test]
+    fn bits_rect() {
+        let mut ops = b"\x00\x90\x00\x02".to_vec();
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend_from_slice(b"\x00\x00\xaa\x00\x55\x00");
+        let image = picture_v2(&rect(0, 0, 2, 8), &ops).to_pix_map().unwrap();
+        assert_eq!(pixel(&image, 0, 0), RGBColor::BLACK);
+        assert_eq!(pixel(&image, 1, 0), RGBColor::WHITE);
+        assert_eq!(pixel(&image, 0, 1), RGBColor::WHITE);
+        assert_eq!(pixel(&image, 1, 1), RGBColor::BLACK);
+    }
+
+    #[test]
+    fn bits_rect_too_large() {
+        // More rows than the data
+        let mut ops = b"\x00\x90\x00\x02".to_vec();
+        ops.extend(rect(0, 0, 0x7fff, 8));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend_from_slice(b"\x00\x00\xaa\x00\x55\x00");
+        assert!(picture_v2(&rect(0, 0, 2, 8), &ops).to_pix_map().is_err());
+
+        // Wider than the row bytes
+        let mut ops = b"\x00\x90\x00\x02".to_vec();
+        ops.extend(rect(0, 0, 2, 0x7fff));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend_from_slice(b"\x00\x00\xaa\x00\x55\x00");
+        assert!(picture_v2(&rect(0, 0, 2, 8), &ops).to_pix_map().is_err());
+
+        // Bounds whose size does not fit in a coordinate
+        let mut ops = b"\x00\x90\x00\x02".to_vec();
+        ops.extend(rect(-0x8000, 0, 0x7fff, 8));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend(rect(0, 0, 2, 8));
+        ops.extend_from_slice(b"\x00\x00\xaa\x00\x55\x00");
+        assert!(picture_v2(&rect(0, 0, 2, 8), &ops).to_pix_map().is_err());
+    }
+
+    #[test]
+    fn coordinate_overflow() {
+        // Origin
+        let ops = [ 0x0c, 0x7f, 0xff, 0, 0, 0x0c, 0x7f, 0xff, 0, 0 ];
+        assert!(picture_v1(&rect(0, 0, 10, 10), &ops).to_pix_map().is_err());
+
+        // Origin at the most negative coordinate, which can’t be negated
+        let mut ops = vec![ 0x0c, 0x80, 0x00, 0, 0, 0x81 ];
+        ops.extend_from_slice(&[ 0x00, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x0a ]);
+        assert!(picture_v1(&rect(0, 0, 10, 10), &ops).to_pix_map().is_err());
+
+        // LongText then DHText
+        let ops = [ 0x28, 0, 0, 0x7f, 0xff, 0, 0x29, 1, 0 ];
+        assert!(picture_v1(&rect(0, 0, 10, 10), &ops).to_pix_map().is_err());
+
+        // ShortLine
+        let ops = [ 0x22, 0, 0, 0x7f, 0xff, 1, 0 ];
+        assert!(picture_v1(&rect(0, 0, 10, 10), &ops).to_pix_map().is_err());
+    }
+
+    #[test]
+    fn pack_bits_rect() {
+        let mut ops = b"\x00\x98\x80\x08".to_vec();
+        ops.extend(rect(0, 0, 1, 8));
+        // version, packType, packSize, hRes, vRes, pixelType, pixelSize,
+        // cmpCount, cmpSize, planeBytes, pmTable, pmReserved
+        ops.extend_from_slice(b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x48\x00\x00\x00\x48\x00\x00");
+        ops.extend_from_slice(b"\x00\x00\x00\x08\x00\x01\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+        // ctSeed, ctFlags, ctSize, entries
+        ops.extend_from_slice(b"\x00\x00\x00\x00\x00\x00\x00\x01");
+        ops.extend_from_slice(b"\x00\x00\xff\xff\xff\xff\xff\xff\x00\x01\xff\xff\x00\x00\x00\x00");
+        ops.extend(rect(0, 0, 1, 8));
+        ops.extend(rect(0, 0, 1, 8));
+        ops.extend_from_slice(b"\x00\x00\x07\x03\x00\x00\x01\x01\xfd\x01");
+        let image = picture_v2(&rect(0, 0, 1, 8), &ops).to_pix_map().unwrap();
+        assert_eq!(pixel(&image, 0, 0), RGBColor::WHITE);
+        assert_eq!(pixel(&image, 1, 0), RGBColor::WHITE);
+        assert_eq!(pixel(&image, 2, 0), RED);
+        assert_eq!(pixel(&image, 7, 0), RED);
+    }
+
+    #[test]
+    fn direct_bits_rect() {
+        let mut ops = b"\x00\x9a\x00\x00\x00\xff\x80\x04".to_vec();
+        ops.extend(rect(0, 0, 1, 1));
+        ops.extend_from_slice(b"\x00\x00\x00\x04\x00\x00\x00\x00\x00\x48\x00\x00\x00\x48\x00\x00");
+        ops.extend_from_slice(b"\x00\x10\x00\x20\x00\x03\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+        ops.extend(rect(0, 0, 1, 1));
+        ops.extend(rect(0, 0, 1, 1));
+        ops.extend_from_slice(b"\x00\x00\x00\x00\xff\x00");
+        let image = picture_v2(&rect(0, 0, 1, 1), &ops).to_pix_map().unwrap();
+        assert_eq!(pixel(&image, 0, 0), GREEN);
+    }
+
+    #[test]
+    fn direct_bits_rect_bad_planar() {
+        let mut ops = b"\x00\x9a\x00\x00\x00\xff\x80\x08".to_vec();
+        ops.extend(rect(0, 0, 1, 2));
+        ops.extend_from_slice(b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x48\x00\x00\x00\x48\x00\x00");
+        ops.extend_from_slice(b"\x00\x10\x00\x20\x00\x01\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+        ops.extend(rect(0, 0, 1, 2));
+        ops.extend(rect(0, 0, 1, 2));
+        ops.extend_from_slice(b"\x00\x00\x02\x01\xff\x00");
+        assert!(picture_v2(&rect(0, 0, 1, 2), &ops).to_pix_map().is_err());
+    }
+
+    #[test]
+    fn quicktime_unsupported() {
+        let picture = picture_v2(&rect(0, 0, 1, 1), b"\x82\x00\x00\x00\x00\x00");
+        assert!(picture.to_pix_map().is_err());
+    }
+}
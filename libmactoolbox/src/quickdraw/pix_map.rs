@@ -0,0 +1,131 @@
+use crate::Rect;
+use super::RGBColor;
+
+/// A colour lookup table for indexed pixel images.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ColorTable {
+    entries: Vec<(u16, RGBColor)>,
+}
+
+impl ColorTable {
+    /// Creates a new colour table from a list of pixel values and their
+    /// colours.
+    #[must_use]
+    pub fn new(entries: Vec<(u16, RGBColor)>) -> Self {
+        Self { entries }
+    }
+
+    /// Returns the pixel value of the entry which is closest to the given
+    /// colour, like `Color2Index`, or `None` if the table is empty.
+    #[must_use]
+    pub fn color_to_index(&self, color: RGBColor) -> Option<u16> {
+        let distance = |other: RGBColor| {
+            let channel = |a: u16, b: u16| {
+                let delta = i64::from(a) - i64::from(b);
+                delta * delta
+            };
+            channel(color.r, other.r) + channel(color.g, other.g) + channel(color.b, other.b)
+        };
+
+        self.entries.iter()
+            .min_by_key(|&&(_, entry_color)| distance(entry_color))
+            .map(|&(value, _)| value)
+    }
+
+    /// The pixel values and colours in the table.
+    #[must_use]
+    pub fn entries(&self) -> &[(u16, RGBColor)] {
+        &self.entries
+    }
+
+    /// Returns the colour for the given pixel value, or `None` if the table
+    /// does not contain the value.
+    #[must_use]
+    pub fn get(&self, value: u16) -> Option<RGBColor> {
+        self.entries.iter()
+            .find_map(|&(entry_value, color)| if entry_value == value { Some(color) } else { None })
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// An off-screen image with direct 48-bit colour pixels.
+///
+/// Indexed images are expanded through their colour tables when they are
+/// loaded, so drawing never needs to consult a colour table.
+#[derive(Clone, Eq, PartialEq)]
+pub struct PixMap {
+    bounds: Rect,
+    pixels: Vec<RGBColor>,
+}
+
+impl PixMap {
+    /// Creates a new image with the given bounds, filled with the given
+    /// colour.
+    #[must_use]
+    pub fn new(bounds: Rect, color: RGBColor) -> Self {
+        let size = if bounds.is_empty() {
+            0
+        } else {
+            bounds.width() as usize * bounds.height() as usize
+        };
+
+        Self {
+            bounds,
+            pixels: vec![ color; size ],
+        }
+    }
+
+    /// The bounds of the image, which define its coordinate system.
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// Returns the colour of the pixel at the given coordinate, or `None` if
+    /// the coordinate is outside the image.
+    #[must_use]
+    pub fn pixel(&self, x: i16, y: i16) -> Option<RGBColor> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
+
+    /// Returns all pixels in the image, in rows from top to bottom.
+    #[must_use]
+    pub fn pixels(&self) -> &[RGBColor] {
+        &self.pixels
+    }
+
+    /// Sets the colour of the pixel at the given coordinate. Coordinates
+    /// outside the image are ignored.
+    pub fn set_pixel(&mut self, x: i16, y: i16, color: RGBColor) {
+        if let Some(index) = self.index(x, y) {
+            self.pixels[index] = color;
+        }
+    }
+
+    fn index(&self, x: i16, y: i16) -> Option<usize> {
+        if x >= self.bounds.left && x < self.bounds.right && y >= self.bounds.top && y < self.bounds.bottom {
+            let x = (i32::from(x) - i32::from(self.bounds.left)) as usize;
+            let y = (i32::from(y) - i32::from(self.bounds.top)) as usize;
+            Some(y * self.bounds.width() as usize + x)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Debug for PixMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("bounds", &self.bounds)
+            .finish()
+    }
+}
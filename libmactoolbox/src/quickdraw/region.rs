@@ -0,0 +1,456 @@
+use anyhow::{bail, Context, Result as AResult};
+use byteorder::{BigEndian, ByteOrder};
+use byteordered::{ByteOrdered, Endianness};
+use crate::{Point, Rect};
+use libcommon::{Reader, Resource, resource::Input};
+use std::{cmp::Ordering, convert::TryInto, io::{Cursor, Read}, iter::once};
+
+/// A half-open range of columns `start..end` inside a region.
+type Span = (i16, i16);
+
+/// A horizontal strip of a region in which every row contains the same
+/// spans.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Band {
+    top: i16,
+    bottom: i16,
+    /// Sorted spans which never overlap or touch.
+    spans: Vec<Span>,
+}
+
+/// An arbitrary area of the coordinate plane.
+///
+/// Regions are stored as a list of bands from top to bottom, so two regions
+/// covering the same area always compare equal.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Region {
+    bounds: Rect,
+    bands: Vec<Band>,
+}
+
+impl Region {
+    /// The size of a rectangular region in the packed region format.
+    pub const RECT_SIZE: u16 = 10;
+
+    /// The value which terminates a scan line, and the list of scan lines, in
+    /// the packed region format.
+    const END_MARKER: i16 = 0x7fff;
+
+    /// Parses a region in the packed region format.
+    pub fn parse(data: &[u8]) -> AResult<Self> {
+        let mut input = ByteOrdered::new(Cursor::new(data), Endianness::Big);
+        Self::load(&mut input, data.len().try_into()?, &())
+    }
+
+    #[must_use]
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// Returns whether the given point is inside the region, like `PtInRgn`.
+    #[must_use]
+    pub fn contains(&self, point: Point) -> bool {
+        self.spans_at(point.y).iter().any(|&(start, end)| point.x >= start && point.x < end)
+    }
+
+    /// Returns the area which is inside this region but not the other
+    /// region, like `DiffRgn`.
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a && !b)
+    }
+
+    /// Returns the region shrunk by `dh` horizontally and `dv` vertically,
+    /// like `InsetRgn`. Negative values grow the region instead.
+    #[must_use]
+    pub fn inset(&self, dh: i16, dv: i16) -> Self {
+        self.inset_axis(dh, |region, step| region.offset(step, 0))
+            .inset_axis(dv, |region, step| region.offset(0, step))
+    }
+
+    /// Returns the area which is inside both regions, like `SectRgn`.
+    #[must_use]
+    pub fn intersect(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a && b)
+    }
+
+    /// Returns whether any part of the given rectangle is inside the region,
+    /// like `RectInRgn`.
+    #[must_use]
+    pub fn intersects_rect(&self, rect: Rect) -> bool {
+        !self.intersect(&Self::from(rect)).is_empty()
+    }
+
+    /// Returns whether the region contains no points, like `EmptyRgn`.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    /// Returns whether the region is a single rectangle.
+    #[must_use]
+    pub fn is_rect(&self) -> bool {
+        matches!(self.bands.as_slice(), [ band ] if band.spans.len() == 1)
+    }
+
+    /// Returns the region scaled from the coordinate system of `from` to the
+    /// coordinate system of `to`, like `MapRgn`.
+    #[must_use]
+    pub fn map(&self, from: Rect, to: Rect) -> Self {
+        let map_x = |x| map_value(x, from.left, from.width(), to.left, to.width());
+        let map_y = |y| map_value(y, from.top, from.height(), to.top, to.height());
+        Self::from_bands(self.bands.iter().map(|band| Band {
+            top: map_y(band.top),
+            bottom: map_y(band.bottom),
+            spans: band.spans.iter().map(|&(start, end)| (map_x(start), map_x(end))).collect(),
+        }))
+    }
+
+    /// Returns the region moved by `dh` horizontally and `dv` vertically, like
+    /// `OffsetRgn`.
+    #[must_use]
+    pub fn offset(&self, dh: i16, dv: i16) -> Self {
+        Self::from_bands(self.bands.iter().map(|band| Band {
+            top: band.top.saturating_add(dv),
+            bottom: band.bottom.saturating_add(dv),
+            spans: band.spans.iter()
+                .map(|&(start, end)| (start.saturating_add(dh), end.saturating_add(dh)))
+                .collect(),
+        }))
+    }
+
+    /// Returns an iterator over each row of the region from top to bottom,
+    /// as `(y, spans)`, where each span is a range of columns `start..end`
+    /// inside the region. Rows which contain no part of the region are
+    /// skipped.
+    pub fn scan_lines(&self) -> impl Iterator<Item = (i16, &[(i16, i16)])> + '_ {
+        self.bands.iter().flat_map(|band| {
+            (band.top..band.bottom).map(move |y| (y, band.spans.as_slice()))
+        })
+    }
+
+    /// Returns the area which is inside either region, like `UnionRgn`.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a || b)
+    }
+
+    /// Returns the area which is inside exactly one of the regions, like
+    /// `XorRgn`.
+    #[must_use]
+    pub fn xor(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a != b)
+    }
+
+    /// Combines two regions using a function which decides whether a point
+    /// is in the result given whether it is in each of the regions.
+    fn combine(&self, other: &Self, op: impl Fn(bool, bool) -> bool) -> Self {
+        let mut rows = self.bands.iter().chain(&other.bands)
+            .flat_map(|band| once(band.top).chain(once(band.bottom)))
+            .collect::<Vec<_>>();
+        rows.sort_unstable();
+        rows.dedup();
+
+        Self::from_bands(rows.windows(2).map(|rows| Band {
+            top: rows[0],
+            bottom: rows[1],
+            spans: combine_spans(self.spans_at(rows[0]), other.spans_at(rows[0]), &op),
+        }))
+    }
+
+    /// Creates a region from a list of bands which are sorted from top to
+    /// bottom and do not overlap, but may otherwise be unnormalised.
+    fn from_bands(bands: impl IntoIterator<Item = Band>) -> Self {
+        let mut normalised = Vec::<Band>::new();
+        for mut band in bands {
+            band.spans = combine_spans(&band.spans, &[], &|a, _| a);
+            if band.top >= band.bottom || band.spans.is_empty() {
+                continue;
+            }
+
+            if let Some(last) = normalised.last_mut() {
+                if last.bottom == band.top && last.spans == band.spans {
+                    last.bottom = band.bottom;
+                    continue;
+                }
+            }
+
+            normalised.push(band);
+        }
+
+        let bounds = normalised.iter().fold(Rect::default(), |bounds, band| {
+            bounds.union(Rect {
+                top: band.top,
+                left: band.spans[0].0,
+                bottom: band.bottom,
+                right: band.spans[band.spans.len() - 1].1,
+            })
+        });
+
+        Self { bounds, bands: normalised }
+    }
+
+    /// Grows or shrinks the region along one axis by intersecting or uniting
+    /// it with copies of itself moved up to `amount` pixels in each direction.
+    fn inset_axis(&self, amount: i16, offset: impl Fn(&Self, i16) -> Self) -> Self {
+        let mut result = self.clone();
+        for step in 1..=i32::from(amount).abs() {
+            for &step in &[ step as i16, -step as i16 ] {
+                let moved = offset(self, step);
+                result = if amount > 0 { result.intersect(&moved) } else { result.union(&moved) };
+            }
+        }
+        result
+    }
+
+    fn spans_at(&self, y: i16) -> &[Span] {
+        let band = self.bands.binary_search_by(|band| {
+            if band.bottom <= y {
+                Ordering::Less
+            } else if band.top > y {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        });
+
+        match band {
+            Ok(index) => &self.bands[index].spans,
+            Err(_) => &[],
+        }
+    }
+}
+
+impl From<Rect> for Region {
+    fn from(rect: Rect) -> Self {
+        if rect.is_empty() {
+            Self::default()
+        } else {
+            Self {
+                bounds: rect,
+                bands: vec![ Band {
+                    top: rect.top,
+                    bottom: rect.bottom,
+                    spans: vec![ (rect.left, rect.right) ],
+                } ],
+            }
+        }
+    }
+}
+
+impl Resource for Region {
+    type Context = ();
+    fn load(input: &mut Input<impl Reader>, _: u32, _: &Self::Context) -> AResult<Self> where Self: Sized {
+        let size = input.read_u16().context("Can’t read region size")?;
+        let bounds = Rect::load(input, Rect::SIZE, &()).context("Can’t read region bounds")?;
+        if size <= Self::RECT_SIZE {
+            return Ok(Self::from(bounds));
+        }
+
+        let mut data = vec![ 0; usize::from(size - Self::RECT_SIZE) ];
+        input.read_exact(&mut data).context("Can’t read region data")?;
+
+        // Each scan line lists the columns where the region changes between
+        // the previous row and this row
+        let mut words = data.chunks_exact(2).map(BigEndian::read_i16);
+        let mut bands = Vec::<Band>::new();
+        let mut inversion_points = Vec::new();
+        loop {
+            let y = words.next().context("Region data ended before its end marker")?;
+            if y == Self::END_MARKER {
+                break;
+            }
+
+            if let Some(last) = bands.last_mut() {
+                if y <= last.top {
+                    bail!("Region scan line {} is out of order", y);
+                }
+                last.bottom = y;
+            }
+
+            loop {
+                let x = words.next().with_context(|| format!("Region scan line {} has no end marker", y))?;
+                if x == Self::END_MARKER {
+                    break;
+                }
+
+                match inversion_points.binary_search(&x) {
+                    Ok(index) => { inversion_points.remove(index); },
+                    Err(index) => inversion_points.insert(index, x),
+                }
+            }
+
+            if inversion_points.len() % 2 != 0 {
+                bail!("Region scan line {} has an odd number of inversion points", y);
+            }
+
+            bands.push(Band {
+                top: y,
+                bottom: y,
+                spans: inversion_points.chunks_exact(2).map(|span| (span[0], span[1])).collect(),
+            });
+        }
+
+        if !inversion_points.is_empty() {
+            bail!("Region is not closed");
+        }
+
+        Ok(Self::from_bands(bands))
+    }
+}
+
+/// Combines two lists of spans using a function which decides whether a
+/// column is in the result given whether it is in each of the lists.
+fn combine_spans(a: &[Span], b: &[Span], op: &impl Fn(bool, bool) -> bool) -> Vec<Span> {
+    let mut columns = a.iter().chain(b)
+        .flat_map(|&(start, end)| once(start).chain(once(end)))
+        .collect::<Vec<_>>();
+    columns.sort_unstable();
+    columns.dedup();
+
+    let inside = |spans: &[Span], x| spans.iter().any(|&(start, end)| x >= start && x < end);
+    let mut spans = Vec::<Span>::new();
+    for columns in columns.windows(2) {
+        let (start, end) = (columns[0], columns[1]);
+        if op(inside(a, start), inside(b, start)) {
+            match spans.last_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => spans.push((start, end)),
+            }
+        }
+    }
+    spans
+}
+
+fn map_value(value: i16, from_start: i16, from_size: i16, to_start: i16, to_size: i16) -> i16 {
+    if from_size == 0 {
+        to_start
+    } else {
+        let offset = (i32::from(value) - i32::from(from_start)) * i32::from(to_size) / i32::from(from_size);
+        (i32::from(to_start) + offset) as i16
+    }
+}
+
+/// Maps a point from the coordinate system of `from` to the coordinate system
+/// of `to`, like `MapPt`.
+#[must_use]
+pub fn map_point(point: Point, from: Rect, to: Rect) -> Point {
+    Point {
+        x: map_value(point.x, from.left, from.width(), to.left, to.width()),
+        y: map_value(point.y, from.top, from.height(), to.top, to.height()),
+    }
+}
+
+/// Maps a rectangle from the coordinate system of `from` to the coordinate
+/// system of `to`, like `MapRect`.
+#[must_use]
+pub fn map_rect(rect: Rect, from: Rect, to: Rect) -> Rect {
+    let top_left = map_point(Point { x: rect.left, y: rect.top }, from, to);
+    let bottom_right = map_point(Point { x: rect.right, y: rect.bottom }, from, to);
+    Rect {
+        top: top_left.y,
+        left: top_left.x,
+        bottom: bottom_right.y,
+        right: bottom_right.x,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+
+    fn rect(top: i16, left: i16, bottom: i16, right: i16) -> Rect {
+        Rect { top, left, bottom, right }
+    }
+
+    fn rows(region: &Region) -> Vec<(i16, Vec<(i16, i16)>)> {
+        region.scan_lines().map(|(y, spans)| (y, spans.to_vec())).collect()
+    }
+
+    /// A 10×10 square with a 4×4 hole in the middle.
+    fn donut() -> Region {
+        Region::from(rect(0, 0, 10, 10)).difference(&Region::from(rect(3, 3, 7, 7)))
+    }
+
+    #[test]
+    fn parse_rect() {
+        let region = Region::parse(b"\0\x0a\0\x01\0\x02\0\x03\0\x04").unwrap();
+        assert_eq!(region, Region::from(rect(1, 2, 3, 4)));
+        assert!(region.is_rect());
+    }
+
+    #[test]
+    fn parse_complex() {
+        let region = Region::parse(&[
+            0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x0a,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x7f, 0xff,
+            0x00, 0x03, 0x00, 0x03, 0x00, 0x07, 0x7f, 0xff,
+            0x00, 0x07, 0x00, 0x03, 0x00, 0x07, 0x7f, 0xff,
+            0x00, 0x0a, 0x00, 0x00, 0x00, 0x0a, 0x7f, 0xff,
+            0x7f, 0xff,
+        ]).unwrap();
+        assert_eq!(region, donut());
+        assert_eq!(region.bounds(), rect(0, 0, 10, 10));
+        assert!(!region.is_rect());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        // Missing end marker
+        assert!(Region::parse(b"\0\x0e\0\0\0\0\0\x01\0\x01\0\0\0\0").is_err());
+        // Unclosed scan line
+        assert!(Region::parse(b"\0\x10\0\0\0\0\0\x01\0\x01\0\0\0\0\x7f\xff").is_err());
+    }
+
+    #[test]
+    fn contains() {
+        let region = donut();
+        assert!(region.contains(Point { x: 0, y: 0 }));
+        assert!(region.contains(Point { x: 9, y: 5 }));
+        assert!(!region.contains(Point { x: 5, y: 5 }));
+        assert!(!region.contains(Point { x: 10, y: 0 }));
+        assert!(region.intersects_rect(rect(2, 2, 4, 4)));
+        assert!(!region.intersects_rect(rect(4, 4, 6, 6)));
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = Region::from(rect(0, 0, 2, 4));
+        let b = Region::from(rect(1, 2, 3, 6));
+        assert_eq!(rows(&a.union(&b)), vec![
+            (0, vec![ (0, 4) ]),
+            (1, vec![ (0, 6) ]),
+            (2, vec![ (2, 6) ]),
+        ]);
+        assert_eq!(a.intersect(&b), Region::from(rect(1, 2, 2, 4)));
+        assert_eq!(rows(&a.difference(&b)), vec![
+            (0, vec![ (0, 4) ]),
+            (1, vec![ (0, 2) ]),
+        ]);
+        assert_eq!(rows(&a.xor(&b)), vec![
+            (0, vec![ (0, 4) ]),
+            (1, vec![ (0, 2), (4, 6) ]),
+            (2, vec![ (2, 6) ]),
+        ]);
+        assert_eq!(a.union(&b).difference(&b).union(&b), a.union(&b));
+        assert!(a.difference(&a).is_empty());
+        assert_eq!(a.difference(&a).bounds(), Rect::default());
+    }
+
+    #[test]
+    fn inset() {
+        let region = donut();
+        assert_eq!(region.inset(1, 1), Region::from(rect(1, 1, 9, 9)).difference(&Region::from(rect(2, 2, 8, 8))));
+        assert!(region.inset(2, 2).is_empty());
+        assert_eq!(Region::from(rect(2, 2, 4, 4)).inset(-1, -2), Region::from(rect(0, 1, 6, 5)));
+    }
+
+    #[test]
+    fn map_and_offset() {
+        let region = donut();
+        assert_eq!(region.offset(5, -5).bounds(), rect(-5, 5, 5, 15));
+        let mapped = region.map(rect(0, 0, 10, 10), rect(0, 0, 20, 20));
+        assert_eq!(mapped, Region::from(rect(0, 0, 20, 20)).difference(&Region::from(rect(6, 6, 14, 14))));
+    }
+}
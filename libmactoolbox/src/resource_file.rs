@@ -3,15 +3,21 @@ use binread::BinRead;
 use bitflags::bitflags;
 use byteorder::{ByteOrder, BigEndian};
 use byteordered::{ByteOrdered, Endianness};
-use crate::{ApplicationVise, OSType, ResourceId};
+use crate::{ApplicationVise, Dcmp, OSType, ResourceId};
 use derive_more::Display;
-use libcommon::{Reader, encodings::MAC_ROMAN, string::ReadExt, binread_flags};
+use libcommon::{Reader, Resource, encodings::MAC_ROMAN, string::ReadExt, binread_flags};
 use std::{any::Any, cell::RefCell, convert::{TryFrom, TryInto}, io::{Cursor, Read, Seek, SeekFrom}, rc::{Weak, Rc}, sync::atomic::{Ordering, AtomicI16}};
 
 #[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
 pub struct RefNum(pub i16);
 static REF_NUM: AtomicI16 = AtomicI16::new(1);
 
+/// The size of the resource file header.
+pub(crate) const HEADER_SIZE: u32 = 16;
+
+/// The size of the system and application data following the header.
+pub(crate) const RESERVED_SIZE: u32 = 240;
+
 pub trait ResourceSource {
     fn contains(&self, id: impl Into<ResourceId>) -> bool;
     fn load<R: 'static + libcommon::Resource>(&self, id: ResourceId, context: &R::Context) -> AResult<Rc<R>>;
@@ -54,17 +60,18 @@ impl<T: Reader> ResourceFile<T> {
         self.find_kind(os_type).map_or(0, |kind| kind.count)
     }
 
+    /// Returns the flags of the resource with the given ID.
+    ///
+    /// `GetResAttrs`
+    pub fn flags(&self, id: ResourceId) -> Option<ResourceFlags> {
+        self.find_item(id).map(|res| res.flags)
+    }
+
     pub fn id_of_name(&self, os_type: impl Into<OSType>, name: impl AsRef<[u8]>) -> Option<ResourceId> {
         let os_type = os_type.into();
         self.find_kind(os_type)
             .and_then(|kind| kind.resources.iter().find(|res| {
-                if res.name_offset == -1 {
-                    return false;
-                }
-
-                let start = usize::try_from(res.name_offset).unwrap();
-                let end = start + usize::from(self.resource_map.names[start]);
-                *name.as_ref() == self.resource_map.names[start + 1..=end]
+                self.item_name(res) == Some(name.as_ref())
             }))
             .map(|res| ResourceId::new(os_type, res.id))
     }
@@ -94,6 +101,13 @@ impl<T: Reader> ResourceFile<T> {
             .flat_map(move |kind| kind.resources.iter().map(move |r| ResourceId::new(os_type, r.id)))
     }
 
+    /// Returns the raw name of the resource with the given ID, if it has one.
+    ///
+    /// `GetResInfo`
+    pub fn name_of(&self, id: ResourceId) -> Option<&[u8]> {
+        self.find_item(id).and_then(|res| self.item_name(res))
+    }
+
     /// Returns the name embedded in the Resource File. For applications, this
     /// is the name of the application.
     pub fn name(&self) -> Option<String> {
@@ -107,6 +121,38 @@ impl<T: Reader> ResourceFile<T> {
         self.resource_map.ref_num
     }
 
+    /// The attributes of the resource map.
+    pub(crate) fn attributes(&self) -> i16 {
+        self.resource_map.attributes
+    }
+
+    /// Reads the data of the resource with the given ID exactly as it is
+    /// stored in the file, without decompressing it or caching it.
+    pub(crate) fn raw_data(&self, id: ResourceId) -> AResult<Vec<u8>> {
+        let entry = self.find_item(id)
+            .with_context(|| format!("Resource {} not found", id))?;
+
+        let mut input = self.input.try_borrow_mut()?;
+        input.seek(SeekFrom::Start(entry.data_offset.into()))
+            .with_context(|| format!("Can’t seek to resource {}", id))?;
+        let size = input.read_u32()
+            .with_context(|| format!("Can’t read size of resource {}", id))?;
+        Vec::<u8>::load(&mut input.as_mut(), size, &())
+            .with_context(|| format!("Can’t read resource {}", id))
+    }
+
+    /// Reads the system and application data which sit between the header and
+    /// the resource data.
+    pub(crate) fn reserved_data(&self) -> AResult<Vec<u8>> {
+        let size = self.resource_map.data_offset.min(HEADER_SIZE + RESERVED_SIZE)
+            .saturating_sub(HEADER_SIZE);
+        let mut input = self.input.try_borrow_mut()?;
+        input.seek(SeekFrom::Start(HEADER_SIZE.into()))
+            .context("Can’t seek to reserved data")?;
+        Vec::<u8>::load(&mut input.as_mut(), size, &())
+            .context("Can’t read reserved data")
+    }
+
     fn decompress(&self, data: &[u8]) -> AResult<Vec<u8>> {
         // https://stackoverflow.com/questions/33495933/how-to-end-a-borrow-in-a-match-or-if-let-expression
         let resource_id = if let DecompressorState::Waiting = *self.decompressor.borrow() {
@@ -143,6 +189,16 @@ impl<T: Reader> ResourceFile<T> {
         let os_type = os_type.into();
         self.resource_map.kinds.iter().find(move |&kind| kind.kind == os_type)
     }
+
+    fn item_name(&self, item: &ResourceItem) -> Option<&[u8]> {
+        if item.name_offset == -1 {
+            return None;
+        }
+
+        let start = usize::try_from(item.name_offset).unwrap();
+        let end = start + usize::from(*self.resource_map.names.get(start)?);
+        self.resource_map.names.get(start + 1..=end)
+    }
 }
 
 impl <T: Reader> ResourceSource for ResourceFile<T> {
@@ -154,8 +210,6 @@ impl <T: Reader> ResourceSource for ResourceFile<T> {
         let entry = self.find_item(id)
             .with_context(|| format!("Resource {} not found", id))?;
 
-        ensure!(!entry.flags.contains(ResourceFlags::COMPRESSED), "Resource {} uses unsupported compression", id);
-
         if let Some(data) = entry.data.borrow().as_ref().and_then(Weak::upgrade) {
             return data.downcast::<R>()
                 .map_err(|_| anyhow!("Invalid data type for resource {}", id));
@@ -168,20 +222,29 @@ impl <T: Reader> ResourceSource for ResourceFile<T> {
         let size = input.read_u32()
             .with_context(|| format!("Can’t read size of resource {}", id))?;
 
-        let is_vise_compressed = {
+        let compression = {
             let mut sig = [ 0; 4 ];
             input.read_exact(&mut sig).ok();
             input.seek(SeekFrom::Start((entry.data_offset + 4).into()))
                 .with_context(|| format!("Can’t seek to resource {}", id))?;
-            ApplicationVise::is_compressed(&sig)
+            if ApplicationVise::is_compressed(&sig) {
+                Some(Compression::ApplicationVise)
+            } else if entry.flags.contains(ResourceFlags::COMPRESSED) {
+                ensure!(Dcmp::is_compressed(&sig), "Resource {} uses unsupported compression", id);
+                Some(Compression::Dcmp)
+            } else {
+                None
+            }
         };
 
-        if is_vise_compressed {
+        if let Some(compression) = compression {
             let data = {
                 let mut compressed_data = Vec::with_capacity(size.try_into().unwrap());
                 input.as_mut().take(size.into()).read_to_end(&mut compressed_data)?;
-                self.decompress(&compressed_data)
-                    .with_context(|| format!("Can’t decompress resource {}", id))?
+                match compression {
+                    Compression::ApplicationVise => self.decompress(&compressed_data),
+                    Compression::Dcmp => Dcmp::decompress(&compressed_data).context("Decompression failure"),
+                }.with_context(|| format!("Can’t decompress resource {}", id))?
             };
             let decompressed_size = u32::try_from(data.len()).unwrap();
             R::load(&mut ByteOrdered::new(Cursor::new(data), Endianness::Big), decompressed_size, context)
@@ -227,6 +290,12 @@ bitflags! {
 
 binread_flags!(ResourceFlags, u8);
 
+#[derive(Clone, Copy, Debug)]
+enum Compression {
+    ApplicationVise,
+    Dcmp,
+}
+
 #[derive(Debug)]
 enum DecompressorState {
     Waiting,
@@ -243,7 +312,7 @@ struct ResourceMap {
     _next_map_handle: u32,
     #[br(pad_before(2), calc = RefNum(REF_NUM.fetch_add(1, Ordering::Relaxed)))]
     ref_num: RefNum,
-    _attributes: i16,
+    attributes: i16,
     type_list_offset: u16,
     name_list_offset: u16,
     #[br(assert(count < 2727, anyhow!("Bad resource kind count")), map = |count: i16| count + 1)]
@@ -285,5 +354,71 @@ struct ResourceItem {
 
 #[cfg(test)]
 mod tests {
-    // TODO
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+    use crate::{ResourceFileBuilder, vfs::MemoryFileSystem};
+    use libcommon::vfs::VirtualFileSystem;
+
+    /// A resource fork containing a single `STR ` 128 named “Name”.
+    fn resource_fork() -> Vec<u8> {
+        let data = b"\0\0\0\x06\x05Hello";
+        let header = {
+            let mut header = Vec::new();
+            header.extend_from_slice(&16_u32.to_be_bytes());
+            header.extend_from_slice(&(16 + data.len() as u32).to_be_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            header.extend_from_slice(&55_u32.to_be_bytes());
+            header
+        };
+
+        let mut fork = header.clone();
+        fork.extend_from_slice(data);
+        // Map header
+        fork.extend_from_slice(&header);
+        fork.extend_from_slice(&[ 0; 4 + 2 + 2 ]);
+        fork.extend_from_slice(&28_u16.to_be_bytes());
+        fork.extend_from_slice(&50_u16.to_be_bytes());
+        // Type list
+        fork.extend_from_slice(&0_i16.to_be_bytes());
+        fork.extend_from_slice(b"STR ");
+        fork.extend_from_slice(&0_i16.to_be_bytes());
+        fork.extend_from_slice(&10_u16.to_be_bytes());
+        // Reference list
+        fork.extend_from_slice(&128_i16.to_be_bytes());
+        fork.extend_from_slice(&0_i16.to_be_bytes());
+        fork.extend_from_slice(&[ 0; 1 + 3 + 4 ]);
+        // Name list
+        fork.extend_from_slice(b"\x04Name");
+        fork
+    }
+
+    #[test]
+    fn read_resource_fork() {
+        let fs = MemoryFileSystem::new().with_resource_fork("Test", resource_fork());
+        let file = ResourceFile::new(fs.open_resource_fork(&"Test").unwrap()).unwrap();
+        let id = ResourceId::new(b"STR ", 128);
+        assert_eq!(file.count(b"STR "), 1);
+        assert_eq!(file.count(b"CODE"), 0);
+        assert!(file.contains(id));
+        assert_eq!(file.id_of_name(b"STR ", b"Name"), Some(id));
+        assert_eq!(file.id_of_index(b"STR ", 0), Some(id));
+        assert_eq!(file.iter().collect::<Vec<_>>(), vec![ id ]);
+        assert_eq!(*file.load::<Vec<u8>>(id, &()).unwrap(), b"\x05Hello");
+        assert!(file.load::<Vec<u8>>(ResourceId::new(b"STR ", 129), &()).is_err());
+    }
+
+    #[test]
+    fn read_dcmp_compressed() {
+        let id = ResourceId::new(b"CODE", 1);
+        let bad_id = ResourceId::new(b"CODE", 2);
+        let mut builder = ResourceFileBuilder::new();
+        builder.add(id, None, &b"\xa8\x9f\x65\x72\x00\x12\x08\x01\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x4c\x4d\xff"[..]).unwrap();
+        builder.set_flags(id, ResourceFlags::COMPRESSED).unwrap();
+        builder.add(bad_id, None, &b"\x00\x00\x00\x00"[..]).unwrap();
+        builder.set_flags(bad_id, ResourceFlags::COMPRESSED).unwrap();
+
+        let file = ResourceFile::new(Cursor::new(builder.build().unwrap())).unwrap();
+        assert_eq!(*file.load::<Vec<u8>>(id, &()).unwrap(), b"\x4e\xba\x00\x08");
+        assert!(file.load::<Vec<u8>>(bad_id, &()).is_err());
+    }
 }
@@ -0,0 +1,442 @@
+use anyhow::{bail, Context, ensure, Result as AResult};
+use byteorder::{BigEndian, WriteBytesExt};
+use crate::{
+    OSType,
+    resource_file::{HEADER_SIZE, RESERVED_SIZE},
+    ResourceFile,
+    ResourceFlags,
+    ResourceId,
+};
+use libcommon::Reader;
+use std::{convert::TryFrom, io::Write};
+
+/// A Macintosh Resource File Format file writer.
+///
+/// Resources are kept in memory and written out in the order they were
+/// added. Resources which are added or modified are marked with
+/// [`ResourceFlags::CHANGED`]. The flag only exists in memory and is never
+/// written to the file, and writing the file does not clear it.
+#[derive(Clone, Debug)]
+pub struct ResourceFileBuilder {
+    attributes: i16,
+    reserved: Vec<u8>,
+    kinds: Vec<BuilderKind>,
+}
+
+impl Default for ResourceFileBuilder {
+    fn default() -> Self {
+        Self {
+            attributes: 0,
+            reserved: vec![ 0; RESERVED_SIZE as usize ],
+            kinds: Vec::new(),
+        }
+    }
+}
+
+impl ResourceFileBuilder {
+    /// The size of the fixed part of the resource map, up to the type list.
+    const MAP_HEADER_SIZE: u16 = 28;
+    /// The size of a type list entry.
+    const KIND_ENTRY_SIZE: u16 = 8;
+    /// The size of a reference list entry.
+    const ITEM_ENTRY_SIZE: u16 = 12;
+    /// The largest data offset which fits in a reference list entry.
+    const MAX_DATA_OFFSET: usize = 0xff_ffff;
+
+    /// Makes a new empty `ResourceFileBuilder`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes a new `ResourceFileBuilder` containing a copy of every resource
+    /// in an existing `ResourceFile`.
+    ///
+    /// Resource data is copied as-is, so compressed resources stay
+    /// compressed.
+    pub fn from_resource_file<T: Reader>(file: &ResourceFile<T>) -> AResult<Self> {
+        let mut builder = Self {
+            attributes: file.attributes(),
+            reserved: {
+                let mut reserved = file.reserved_data().context("Can’t read reserved data")?;
+                reserved.resize(RESERVED_SIZE as usize, 0);
+                reserved
+            },
+            kinds: Vec::new(),
+        };
+
+        for id in file.iter() {
+            let data = file.raw_data(id)?;
+            builder.kind_mut(id.os_type()).items.push(BuilderItem {
+                id: id.id(),
+                name: file.name_of(id).map(<[u8]>::to_vec),
+                flags: file.flags(id).unwrap_or_else(ResourceFlags::empty) - ResourceFlags::CHANGED,
+                data,
+            });
+        }
+
+        Ok(builder)
+    }
+
+    /// Adds a new resource.
+    ///
+    /// `AddResource`
+    pub fn add(&mut self, id: ResourceId, name: Option<&[u8]>, data: impl Into<Vec<u8>>) -> AResult<()> {
+        ensure!(!self.contains(id), "Resource {} already exists", id);
+        if let Some(name) = name {
+            ensure!(name.len() <= 255, "Name of resource {} is too long", id);
+        }
+
+        self.kind_mut(id.os_type()).items.push(BuilderItem {
+            id: id.id(),
+            name: name.map(<[u8]>::to_vec),
+            flags: ResourceFlags::CHANGED,
+            data: data.into(),
+        });
+
+        Ok(())
+    }
+
+    /// Returns `true` if a resource with the given ID exists.
+    #[must_use]
+    pub fn contains(&self, id: ResourceId) -> bool {
+        self.find_item(id).is_some()
+    }
+
+    /// Returns the data of the resource with the given ID.
+    #[must_use]
+    pub fn data(&self, id: ResourceId) -> Option<&[u8]> {
+        self.find_item(id).map(|item| item.data.as_slice())
+    }
+
+    /// Removes a resource.
+    ///
+    /// `RemoveResource`
+    pub fn delete(&mut self, id: ResourceId) -> AResult<()> {
+        let os_type = id.os_type();
+        let kind_index = self.kinds.iter().position(|kind| kind.kind == os_type)
+            .with_context(|| format!("Resource {} not found", id))?;
+        let kind = &mut self.kinds[kind_index];
+        let item_index = kind.items.iter().position(|item| item.id == id.id())
+            .with_context(|| format!("Resource {} not found", id))?;
+        kind.items.remove(item_index);
+        if kind.items.is_empty() {
+            self.kinds.remove(kind_index);
+        }
+        Ok(())
+    }
+
+    /// Returns the flags of the resource with the given ID.
+    ///
+    /// `GetResAttrs`
+    #[must_use]
+    pub fn flags(&self, id: ResourceId) -> Option<ResourceFlags> {
+        self.find_item(id).map(|item| item.flags)
+    }
+
+    /// Returns `true` if the resource with the given ID was added or modified
+    /// since the builder was created.
+    #[must_use]
+    pub fn is_changed(&self, id: ResourceId) -> bool {
+        self.flags(id).map_or(false, |flags| flags.contains(ResourceFlags::CHANGED))
+    }
+
+    /// Returns an iterator over the IDs of all resources, in file order.
+    pub fn iter(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.kinds.iter().flat_map(|kind| {
+            let os_type = kind.kind;
+            kind.items.iter().map(move |item| ResourceId::new(os_type, item.id))
+        })
+    }
+
+    /// Returns the raw name of the resource with the given ID, if it has one.
+    #[must_use]
+    pub fn name_of(&self, id: ResourceId) -> Option<&[u8]> {
+        self.find_item(id).and_then(|item| item.name.as_deref())
+    }
+
+    /// Changes the name of a resource. A name of `None` removes the name.
+    ///
+    /// `SetResInfo`
+    pub fn rename(&mut self, id: ResourceId, name: Option<&[u8]>) -> AResult<()> {
+        if let Some(name) = name {
+            ensure!(name.len() <= 255, "Name of resource {} is too long", id);
+        }
+        let item = self.find_item_mut(id)?;
+        item.name = name.map(<[u8]>::to_vec);
+        item.flags |= ResourceFlags::CHANGED;
+        Ok(())
+    }
+
+    /// Replaces the data of an existing resource.
+    ///
+    /// `ChangedResource`
+    pub fn replace(&mut self, id: ResourceId, data: impl Into<Vec<u8>>) -> AResult<()> {
+        let item = self.find_item_mut(id)?;
+        item.data = data.into();
+        item.flags |= ResourceFlags::CHANGED;
+        Ok(())
+    }
+
+    /// Sets the flags of a resource. The `CHANGED` flag is managed by the
+    /// builder and cannot be cleared this way.
+    ///
+    /// `SetResAttrs`
+    pub fn set_flags(&mut self, id: ResourceId, flags: ResourceFlags) -> AResult<()> {
+        let item = self.find_item_mut(id)?;
+        item.flags = flags | ResourceFlags::CHANGED;
+        Ok(())
+    }
+
+    /// Serialises the resource file into a new buffer.
+    pub fn build(&self) -> AResult<Vec<u8>> {
+        let mut output = Vec::new();
+        self.write(&mut output)?;
+        Ok(output)
+    }
+
+    /// Serialises the resource file.
+    ///
+    /// `UpdateResFile`
+    pub fn write(&self, output: &mut impl Write) -> AResult<()> {
+        let num_items = self.kinds.iter().map(|kind| kind.items.len()).sum::<usize>();
+
+        let (data, data_offsets) = {
+            let mut data = Vec::new();
+            let mut data_offsets = Vec::with_capacity(num_items);
+            for (id, item) in self.iter().zip(self.kinds.iter().flat_map(|kind| kind.items.iter())) {
+                ensure!(data.len() <= Self::MAX_DATA_OFFSET, "Too much resource data to fit resource {}", id);
+                data_offsets.push(data.len() as u32);
+                let size = u32::try_from(item.data.len())
+                    .with_context(|| format!("Resource {} is too large", id))?;
+                data.write_u32::<BigEndian>(size)?;
+                data.extend_from_slice(&item.data);
+            }
+            (data, data_offsets)
+        };
+
+        let (names, name_offsets) = {
+            let mut names = Vec::new();
+            let mut name_offsets = Vec::with_capacity(num_items);
+            for (id, item) in self.iter().zip(self.kinds.iter().flat_map(|kind| kind.items.iter())) {
+                if let Some(name) = &item.name {
+                    let offset = i16::try_from(names.len())
+                        .with_context(|| format!("Too many names to fit resource {}", id))?;
+                    name_offsets.push(offset);
+                    names.push(name.len() as u8);
+                    names.extend_from_slice(name);
+                } else {
+                    name_offsets.push(-1);
+                }
+            }
+            (names, name_offsets)
+        };
+
+        let num_kinds = u16::try_from(self.kinds.len()).ok()
+            .filter(|&count| count <= 2727)
+            .context("Too many resource types")?;
+        let type_list_size = 2 + Self::KIND_ENTRY_SIZE * num_kinds;
+        let name_list_offset = {
+            let offset = usize::from(Self::MAP_HEADER_SIZE)
+                + usize::from(type_list_size)
+                + usize::from(Self::ITEM_ENTRY_SIZE) * num_items;
+            u16::try_from(offset).context("Too many resources")?
+        };
+
+        let data_offset = HEADER_SIZE + RESERVED_SIZE;
+        let data_size = u32::try_from(data.len()).context("Too much resource data")?;
+        let map_offset = data_offset.checked_add(data_size).context("Too much resource data")?;
+        let map_size = u32::from(name_list_offset) + u32::try_from(names.len()).unwrap();
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.write_u32::<BigEndian>(data_offset)?;
+        header.write_u32::<BigEndian>(map_offset)?;
+        header.write_u32::<BigEndian>(data_size)?;
+        header.write_u32::<BigEndian>(map_size)?;
+
+        let mut map = Vec::with_capacity(map_size as usize);
+        map.extend_from_slice(&header);
+        // Handle to next resource map and file reference number, both of
+        // which are only meaningful in memory
+        map.write_u32::<BigEndian>(0)?;
+        map.write_u16::<BigEndian>(0)?;
+        map.write_i16::<BigEndian>(self.attributes)?;
+        map.write_u16::<BigEndian>(Self::MAP_HEADER_SIZE)?;
+        map.write_u16::<BigEndian>(name_list_offset)?;
+
+        // Type list
+        map.write_i16::<BigEndian>(num_kinds as i16 - 1)?;
+        let mut ref_list_offset = type_list_size;
+        for kind in &self.kinds {
+            let count = i16::try_from(kind.items.len()).ok()
+                .filter(|&count| count <= 2727)
+                .with_context(|| format!("Too many {} resources", kind.kind))?;
+            map.extend_from_slice(kind.kind.as_bytes());
+            map.write_i16::<BigEndian>(count - 1)?;
+            map.write_u16::<BigEndian>(ref_list_offset)?;
+            ref_list_offset += Self::ITEM_ENTRY_SIZE * count as u16;
+        }
+
+        // Reference lists
+        let items = self.kinds.iter().flat_map(|kind| kind.items.iter());
+        for ((item, data_offset), name_offset) in items.zip(data_offsets).zip(name_offsets) {
+            map.write_i16::<BigEndian>(item.id)?;
+            map.write_i16::<BigEndian>(name_offset)?;
+            map.write_u8((item.flags - ResourceFlags::CHANGED).bits())?;
+            map.write_u24::<BigEndian>(data_offset)?;
+            // Handle to resource, which is only meaningful in memory
+            map.write_u32::<BigEndian>(0)?;
+        }
+
+        // Name list
+        map.extend_from_slice(&names);
+
+        output.write_all(&header).context("Can’t write resource file header")?;
+        output.write_all(&self.reserved).context("Can’t write reserved data")?;
+        output.write_all(&data).context("Can’t write resource data")?;
+        output.write_all(&map).context("Can’t write resource map")?;
+        Ok(())
+    }
+
+    /// Sets the attributes of the resource map.
+    pub fn set_attributes(&mut self, attributes: i16) {
+        self.attributes = attributes;
+    }
+
+    /// Sets the system and application data which sits between the header
+    /// and the resource data. For applications, this includes the name of the
+    /// application.
+    pub fn set_reserved_data(&mut self, data: &[u8]) -> AResult<()> {
+        if data.len() > RESERVED_SIZE as usize {
+            bail!("Reserved data must be at most {} bytes", RESERVED_SIZE);
+        }
+        self.reserved.clear();
+        self.reserved.extend_from_slice(data);
+        self.reserved.resize(RESERVED_SIZE as usize, 0);
+        Ok(())
+    }
+
+    fn find_item(&self, id: ResourceId) -> Option<&BuilderItem> {
+        let os_type = id.os_type();
+        self.kinds.iter()
+            .find(|kind| kind.kind == os_type)
+            .and_then(|kind| kind.items.iter().find(|item| item.id == id.id()))
+    }
+
+    fn find_item_mut(&mut self, id: ResourceId) -> AResult<&mut BuilderItem> {
+        let os_type = id.os_type();
+        self.kinds.iter_mut()
+            .find(|kind| kind.kind == os_type)
+            .and_then(|kind| kind.items.iter_mut().find(|item| item.id == id.id()))
+            .with_context(|| format!("Resource {} not found", id))
+    }
+
+    fn kind_mut(&mut self, os_type: OSType) -> &mut BuilderKind {
+        if let Some(index) = self.kinds.iter().position(|kind| kind.kind == os_type) {
+            &mut self.kinds[index]
+        } else {
+            self.kinds.push(BuilderKind { kind: os_type, items: Vec::new() });
+            self.kinds.last_mut().unwrap()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct BuilderKind {
+    kind: OSType,
+    items: Vec<BuilderItem>,
+}
+
+#[derive(Clone, Debug)]
+struct BuilderItem {
+    id: i16,
+    name: Option<Vec<u8>>,
+    flags: ResourceFlags,
+    data: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+    use crate::ResourceSource;
+    use std::io::Cursor;
+
+    fn read(builder: &ResourceFileBuilder) -> ResourceFile<Cursor<Vec<u8>>> {
+        ResourceFile::new(Cursor::new(builder.build().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn empty() {
+        let file = read(&ResourceFileBuilder::new());
+        assert_eq!(file.iter().count(), 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let str_id = ResourceId::new(b"STR ", 128);
+        let other_str_id = ResourceId::new(b"STR ", 129);
+        let code_id = ResourceId::new(b"CODE", 0);
+
+        let mut builder = ResourceFileBuilder::new();
+        builder.add(str_id, Some(&b"Hello"[..]), &b"\x05Hello"[..]).unwrap();
+        builder.add(code_id, None, vec![ 0x4e, 0x75 ]).unwrap();
+        builder.add(other_str_id, Some(&b"World"[..]), &b"\x05World"[..]).unwrap();
+        builder.set_flags(code_id, ResourceFlags::PURGEABLE | ResourceFlags::LOCKED).unwrap();
+        assert!(builder.add(str_id, None, Vec::new()).is_err());
+        assert!(builder.is_changed(str_id));
+
+        let file = read(&builder);
+        assert_eq!(file.iter().collect::<Vec<_>>(), vec![ str_id, other_str_id, code_id ]);
+        assert_eq!(file.count(b"STR "), 2);
+        assert_eq!(file.id_of_name(b"STR ", b"World"), Some(other_str_id));
+        assert_eq!(file.name_of(str_id), Some(&b"Hello"[..]));
+        assert_eq!(file.name_of(code_id), None);
+        assert_eq!(file.flags(code_id), Some(ResourceFlags::PURGEABLE | ResourceFlags::LOCKED));
+        assert_eq!(file.flags(str_id), Some(ResourceFlags::empty()));
+        assert_eq!(*file.load::<Vec<u8>>(other_str_id, &()).unwrap(), b"\x05World");
+        assert_eq!(*file.load::<Vec<u8>>(code_id, &()).unwrap(), [ 0x4e, 0x75 ]);
+
+        let copy = ResourceFileBuilder::from_resource_file(&file).unwrap();
+        assert!(!copy.is_changed(str_id));
+        assert_eq!(copy.build().unwrap(), builder.build().unwrap());
+    }
+
+    #[test]
+    fn modify() {
+        let str_id = ResourceId::new(b"STR ", 128);
+        let code_id = ResourceId::new(b"CODE", 0);
+
+        let mut builder = ResourceFileBuilder::new();
+        builder.add(str_id, Some(&b"Hello"[..]), &b"\x05Hello"[..]).unwrap();
+        builder.add(code_id, None, vec![ 0x4e, 0x75 ]).unwrap();
+        let mut builder = ResourceFileBuilder::from_resource_file(&read(&builder)).unwrap();
+
+        builder.replace(str_id, &b"\x03Bye"[..]).unwrap();
+        builder.rename(str_id, None).unwrap();
+        builder.rename(code_id, Some(&b"Main"[..])).unwrap();
+        assert!(builder.is_changed(str_id));
+        assert!(builder.replace(ResourceId::new(b"STR ", 1), Vec::new()).is_err());
+
+        let file = read(&builder);
+        assert_eq!(file.name_of(str_id), None);
+        assert_eq!(file.id_of_name(b"CODE", b"Main"), Some(code_id));
+        assert_eq!(*file.load::<Vec<u8>>(str_id, &()).unwrap(), b"\x03Bye");
+
+        builder.delete(str_id).unwrap();
+        assert!(builder.delete(str_id).is_err());
+        let file = read(&builder);
+        assert_eq!(file.count(b"STR "), 0);
+        assert_eq!(file.iter().collect::<Vec<_>>(), vec![ code_id ]);
+    }
+
+    #[test]
+    fn reserved_data() {
+        let mut builder = ResourceFileBuilder::new();
+        let mut reserved = vec![ 0; 0x20 ];
+        reserved.extend_from_slice(b"\x07Example");
+        builder.set_reserved_data(&reserved).unwrap();
+        assert!(builder.set_reserved_data(&[ 0; 241 ]).is_err());
+        assert_eq!(read(&builder).name().as_deref(), Some("Example"));
+    }
+}
@@ -1,5 +1,26 @@
 use libcommon::{
-    encodings::{Decoder, DecoderRef, MAC_CYRILLIC, MAC_JAPANESE, MAC_ROMAN},
+    encodings::{
+        Decoder,
+        DecoderRef,
+        Encoder,
+        EncoderRef,
+        MAC_ARABIC,
+        MAC_CENTRAL_EUROPE,
+        MAC_CHINESE_SIMPLIFIED,
+        MAC_CHINESE_TRADITIONAL,
+        MAC_CROATIAN,
+        MAC_CYRILLIC,
+        MAC_FARSI,
+        MAC_GREEK,
+        MAC_HEBREW,
+        MAC_ICELANDIC,
+        MAC_JAPANESE,
+        MAC_KOREAN,
+        MAC_ROMAN,
+        MAC_ROMANIAN,
+        MAC_THAI,
+        MAC_TURKISH,
+    },
     Reader,
 };
 use num_derive::FromPrimitive;
@@ -128,31 +149,60 @@ impl CountryCode {
         // available in the resource fork.
         #![allow(clippy::match_same_arms)]
         match self {
-            Self::Turkey | Self::Croatia | Self::Slovenian | Self::YugoCroatian |
-            Self::Iceland | Self::FaroeIsl | Self::Ireland | Self::ScottishGaelic |
-            Self::ManxGaelic | Self::Breton | Self::Welsh | Self::IrishGaelicScript |
-            Self::Greece => unimplemented!(),
+            Self::Turkey => MAC_TURKISH as &dyn Decoder,
+            Self::Croatia | Self::Slovenian | Self::YugoCroatian => MAC_CROATIAN as &dyn Decoder,
+            Self::Iceland | Self::FaroeIsl => MAC_ICELANDIC as &dyn Decoder,
+            Self::Romania => MAC_ROMANIAN as &dyn Decoder,
+            // TODO: Mac OS Celtic and Mac OS Gaelic
+            Self::Ireland | Self::ScottishGaelic | Self::ManxGaelic | Self::Breton |
+            Self::Welsh | Self::IrishGaelicScript => MAC_ROMAN as &dyn Decoder,
+            Self::Greece => MAC_GREEK as &dyn Decoder,
             Self::Japan => MAC_JAPANESE as &dyn Decoder,
-            Self::China => unimplemented!(),
-            Self::Korea => unimplemented!(),
-            Self::Arabic => unimplemented!(),
-            Self::Iran => unimplemented!(),
-            Self::Israel => unimplemented!(),
-            Self::Russia => MAC_CYRILLIC as &dyn Decoder,
-            Self::Ukraine => unimplemented!(),
-            Self::IndiaHindi => unimplemented!(),
-            Self::Thailand => unimplemented!(),
-            Self::Taiwan => unimplemented!(),
-            Self::Tibetan => unimplemented!(),
-            Self::Nunavut => unimplemented!(),
+            Self::China => MAC_CHINESE_SIMPLIFIED as &dyn Decoder,
+            Self::Korea => MAC_KOREAN as &dyn Decoder,
+            Self::Arabic => MAC_ARABIC as &dyn Decoder,
+            Self::Iran => MAC_FARSI as &dyn Decoder,
+            Self::Israel => MAC_HEBREW as &dyn Decoder,
+            Self::Russia | Self::Ukraine => MAC_CYRILLIC as &dyn Decoder,
+            Self::Thailand => MAC_THAI as &dyn Decoder,
+            Self::Taiwan => MAC_CHINESE_TRADITIONAL as &dyn Decoder,
             Self::Poland | Self::Czech | Self::Slovak | Self::Hungary |
-            Self::Estonia | Self::Latvia | Self::Lithuania => unimplemented!(),
+            Self::Estonia | Self::Latvia | Self::Lithuania => MAC_CENTRAL_EUROPE as &dyn Decoder,
+            // TODO: Hindi, Tibetan, and Inuktitut have no mappings yet.
             _ => MAC_ROMAN as &dyn Decoder,
         }
     }
+
+    /// The script system normally used in this region.
+    #[must_use]
+    pub fn script(self) -> ScriptCode {
+        match self {
+            Self::Japan => ScriptCode::Japanese,
+            Self::Taiwan => ScriptCode::ChineseTraditional,
+            Self::Korea => ScriptCode::Korean,
+            Self::Arabic | Self::Iran | Self::PakistanUrdu | Self::IndiaUrdu => ScriptCode::Arabic,
+            Self::Israel => ScriptCode::Hebrew,
+            Self::Greece | Self::GreecePoly => ScriptCode::Greek,
+            Self::Russia | Self::Ukraine | Self::ByeloRussian | Self::Bulgaria |
+            Self::Serbian | Self::Macedonian => ScriptCode::Russian,
+            Self::IndiaHindi | Self::Marathi | Self::Nepal => ScriptCode::Devanagari,
+            Self::Punjabi => ScriptCode::Gurmukhi,
+            Self::Gujarati => ScriptCode::Gujarati,
+            Self::Bengali => ScriptCode::Bengali,
+            Self::Thailand => ScriptCode::Thai,
+            Self::Georgian => ScriptCode::Georgian,
+            Self::Armenian => ScriptCode::Armenian,
+            Self::China => ScriptCode::ChineseSimplified,
+            Self::Tibetan | Self::Bhutan => ScriptCode::Tibetan,
+            Self::Vietnam => ScriptCode::Vietnamese,
+            Self::Poland | Self::Czech | Self::Slovak | Self::Hungary | Self::Magyar |
+            Self::Estonia | Self::Latvia | Self::Lithuania => ScriptCode::NonCyrillicSlavic,
+            _ => ScriptCode::Roman,
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, EnumVariantNames, FromPrimitive)]
+#[derive(Clone, Copy, Debug, EnumVariantNames, Eq, FromPrimitive, PartialEq)]
 #[strum(serialize_all = "kebab-case")]
 pub enum ScriptCode {
     Roman = 0,
@@ -166,6 +216,7 @@ pub enum ScriptCode {
     RightLeftSymbols,
     Devanagari,
     Gurmukhi,
+    Gujarati,
     Oriya,
     Bengali,
     Tamil,
@@ -189,17 +240,54 @@ pub enum ScriptCode {
     UninterpretedSymbols,
 }
 
+impl ScriptCode {
+    /// The decoder for text written in this script. Scripts which have no
+    /// published mapping to Unicode are decoded as Mac OS Roman, which is what
+    /// a system without the corresponding script system would do.
+    #[must_use]
+    pub fn encoding(self) -> DecoderRef {
+        match self {
+            Self::Japanese => MAC_JAPANESE as &dyn Decoder,
+            Self::ChineseTraditional => MAC_CHINESE_TRADITIONAL as &dyn Decoder,
+            Self::Korean => MAC_KOREAN as &dyn Decoder,
+            Self::Arabic | Self::Sindhi => MAC_ARABIC as &dyn Decoder,
+            Self::Hebrew => MAC_HEBREW as &dyn Decoder,
+            Self::Greek => MAC_GREEK as &dyn Decoder,
+            Self::Russian => MAC_CYRILLIC as &dyn Decoder,
+            Self::Thai => MAC_THAI as &dyn Decoder,
+            Self::ChineseSimplified => MAC_CHINESE_SIMPLIFIED as &dyn Decoder,
+            Self::NonCyrillicSlavic => MAC_CENTRAL_EUROPE as &dyn Decoder,
+            // TODO: Devanagari, Gurmukhi, and Gujarati are ISCII-derived and
+            // need a contextual decoder.
+            _ => MAC_ROMAN as &dyn Decoder,
+        }
+    }
+
+    /// The encoder for text written in this script, which is the inverse of
+    /// [`encoding`](Self::encoding).
+    #[must_use]
+    pub fn encoder(self) -> EncoderRef {
+        match self {
+            Self::Japanese => MAC_JAPANESE as &dyn Encoder,
+            Self::ChineseTraditional => MAC_CHINESE_TRADITIONAL as &dyn Encoder,
+            Self::Korean => MAC_KOREAN as &dyn Encoder,
+            Self::Arabic | Self::Sindhi => MAC_ARABIC as &dyn Encoder,
+            Self::Hebrew => MAC_HEBREW as &dyn Encoder,
+            Self::Greek => MAC_GREEK as &dyn Encoder,
+            Self::Russian => MAC_CYRILLIC as &dyn Encoder,
+            Self::Thai => MAC_THAI as &dyn Encoder,
+            Self::ChineseSimplified => MAC_CHINESE_SIMPLIFIED as &dyn Encoder,
+            Self::NonCyrillicSlavic => MAC_CENTRAL_EUROPE as &dyn Encoder,
+            _ => MAC_ROMAN as &dyn Encoder,
+        }
+    }
+}
+
 // TODO: This is not sufficient; region codes are needed in addition to the
 // script code for correct decoding of Turkish, Croatian, Icelandic, Romanian,
 // Celtic, Gaelic, Greek, and Farsi.
 pub fn decode_text<T: Reader>(input: &mut T, script_code: u8) -> String {
-    #![allow(clippy::match_same_arms)]
-    match ScriptCode::from_u8(script_code) {
-        Some(ScriptCode::Roman) | None       => MAC_ROMAN.decode_stream(input),
-        Some(ScriptCode::Japanese)           => MAC_JAPANESE.decode_stream(input),
-        Some(ScriptCode::ChineseTraditional) => todo!("Chinese traditional decoder"),
-        Some(ScriptCode::Korean)             => todo!("Korean decoder"),
-        Some(ScriptCode::Russian)            => MAC_CYRILLIC.decode_stream(input),
-        _ => unimplemented!("no currently known uses of other script codes"),
-    }
+    ScriptCode::from_u8(script_code)
+        .map_or(MAC_ROMAN as &dyn Decoder, ScriptCode::encoding)
+        .decode_stream(input)
 }
@@ -0,0 +1,537 @@
+use crate::{Point, quickdraw::{CGrafPort, Font, GrafVerb, Region}, Rect};
+
+/// Character codes of the keys which `TextEdit` handles specially.
+const BACKSPACE: u8 = 0x08;
+const RETURN: u8 = 0x0d;
+const LEFT_ARROW: u8 = 0x1c;
+const RIGHT_ARROW: u8 = 0x1d;
+const UP_ARROW: u8 = 0x1e;
+const DOWN_ARROW: u8 = 0x1f;
+const FORWARD_DELETE: u8 = 0x7f;
+
+/// A block of editable monostyled text, like a `TextEdit` record.
+#[derive(Clone, Debug)]
+pub struct TEHandle {
+    /// The rectangle which the text is wrapped to. This moves when the text
+    /// is scrolled.
+    dest_rect: Rect,
+    /// The rectangle where the text is visible.
+    view_rect: Rect,
+    font: Font,
+    line_height: i16,
+    font_ascent: i16,
+    sel_start: usize,
+    sel_end: usize,
+    /// Whether lines only break at carriage returns.
+    cr_only: bool,
+    active: bool,
+    caret_state: bool,
+    text: Vec<u8>,
+    /// The offset of the first character of each line.
+    line_starts: Vec<usize>,
+}
+
+impl TEHandle {
+    /// Creates an empty edit record which wraps text to `dest_rect` and
+    /// displays it in `view_rect`, like `TENew`.
+    #[must_use]
+    pub fn new(dest_rect: Rect, view_rect: Rect, font: Font) -> Self {
+        let info = font.info();
+        Self {
+            dest_rect,
+            view_rect,
+            font,
+            line_height: info.ascent + info.descent + info.leading,
+            font_ascent: info.ascent,
+            sel_start: 0,
+            sel_end: 0,
+            cr_only: false,
+            active: false,
+            caret_state: false,
+            text: Vec::new(),
+            line_starts: vec![ 0 ],
+        }
+    }
+
+    /// Shows the selection and starts blinking the caret, like `TEActivate`.
+    pub fn activate(&mut self) {
+        self.active = true;
+        self.caret_state = true;
+    }
+
+    /// Returns the rectangle of the insertion point, or `None` if there is a
+    /// selection range instead.
+    #[must_use]
+    pub fn caret_rect(&self) -> Option<Rect> {
+        if self.sel_start == self.sel_end {
+            let point = self.get_point(self.sel_start);
+            Some(Rect { top: point.y, left: point.x - 1, bottom: point.y + self.line_height, right: point.x })
+        } else {
+            None
+        }
+    }
+
+    /// Extends the selection or moves the insertion point to the character
+    /// under the given point, like `TEClick`.
+    pub fn click(&mut self, point: Point, extend: bool) {
+        let offset = self.get_offset(point);
+        if !extend {
+            self.set_select(offset, offset);
+        } else if offset < self.sel_start {
+            self.sel_start = offset;
+        } else {
+            self.sel_end = offset;
+        }
+        self.caret_state = true;
+    }
+
+    /// Copies the selected text into the scrap, like `TECopy`.
+    pub fn copy(&self, scrap: &mut Vec<u8>) {
+        scrap.clear();
+        scrap.extend_from_slice(self.selection());
+    }
+
+    /// Copies the selected text into the scrap and then deletes it, like
+    /// `TECut`.
+    pub fn cut(&mut self, scrap: &mut Vec<u8>) {
+        self.copy(scrap);
+        self.delete();
+    }
+
+    /// Hides the selection and stops blinking the caret, like
+    /// `TEDeactivate`.
+    pub fn deactivate(&mut self) {
+        self.active = false;
+        self.caret_state = false;
+    }
+
+    /// Deletes the selected text, like `TEDelete`.
+    pub fn delete(&mut self) {
+        self.replace_selection(&[]);
+    }
+
+    /// The rectangle which the text is wrapped to.
+    #[must_use]
+    pub fn dest_rect(&self) -> Rect {
+        self.dest_rect
+    }
+
+    /// Returns the offset of the character boundary nearest to the given
+    /// point, like `TEGetOffset`.
+    #[must_use]
+    pub fn get_offset(&self, point: Point) -> usize {
+        let line = (i32::from(point.y) - i32::from(self.dest_rect.top)) / i32::from(self.line_height.max(1));
+        let line = if line < 0 { 0 } else { (line as usize).min(self.line_starts.len() - 1) };
+        let (start, end) = self.line_range(line);
+
+        let mut x = i32::from(self.dest_rect.left);
+        for offset in start..end {
+            let width = i32::from(self.font.char_width(self.text[offset]));
+            if i32::from(point.x) < x + width / 2 {
+                return offset;
+            }
+            x += width;
+        }
+        end
+    }
+
+    /// Returns the top-left corner of the character at the given offset,
+    /// like `TEGetPoint`.
+    #[must_use]
+    pub fn get_point(&self, offset: usize) -> Point {
+        let offset = offset.min(self.text.len());
+        let line = self.line_of(offset);
+        let start = self.line_starts[line];
+        Point {
+            x: self.dest_rect.left + self.font.text_width(&self.text[start..offset]),
+            y: self.dest_rect.top + line as i16 * self.line_height,
+        }
+    }
+
+    /// Blinks the caret, like `TEIdle`. The caller decides how often this is
+    /// called.
+    pub fn idle(&mut self) {
+        if self.active {
+            self.caret_state = !self.caret_state;
+        }
+    }
+
+    /// Inserts text before the selection without replacing it, like
+    /// `TEInsert`.
+    pub fn insert(&mut self, text: &[u8]) {
+        let (start, end) = (self.sel_start, self.sel_end);
+        self.text.splice(start..start, text.iter().copied());
+        self.cal_text();
+        self.sel_start = start + text.len();
+        self.sel_end = end + text.len();
+    }
+
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Handles a character typed by the user, like `TEKey`. Arrow keys move
+    /// the insertion point, delete keys remove text, and every other
+    /// character replaces the selection.
+    pub fn key(&mut self, key: u8) {
+        let (start, end) = (self.sel_start, self.sel_end);
+        match key {
+            BACKSPACE if start == end => {
+                if start > 0 {
+                    self.set_select(start - 1, start);
+                    self.delete();
+                }
+            },
+            FORWARD_DELETE if start == end => {
+                if end < self.text.len() {
+                    self.set_select(start, end + 1);
+                    self.delete();
+                }
+            },
+            BACKSPACE | FORWARD_DELETE => self.delete(),
+            LEFT_ARROW => {
+                let offset = if start == end { start.saturating_sub(1) } else { start };
+                self.set_select(offset, offset);
+            },
+            RIGHT_ARROW => {
+                let offset = if start == end { end + 1 } else { end };
+                self.set_select(offset, offset);
+            },
+            UP_ARROW | DOWN_ARROW => {
+                let point = self.get_point(start);
+                let dv = if key == UP_ARROW { -self.line_height } else { self.line_height };
+                let offset = if key == UP_ARROW && self.line_of(start) == 0 {
+                    0
+                } else {
+                    self.get_offset(Point { x: point.x, y: point.y + dv })
+                };
+                self.set_select(offset, offset);
+            },
+            _ => self.replace_selection(&[ key ]),
+        }
+        self.caret_state = true;
+        self.sel_view();
+    }
+
+    /// The height of each line of text.
+    #[must_use]
+    pub fn line_height(&self) -> i16 {
+        self.line_height
+    }
+
+    /// The number of lines of text, like the `nLines` field of a `TextEdit`
+    /// record.
+    #[must_use]
+    pub fn n_lines(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Replaces the selection with the text from the scrap, like `TEPaste`.
+    pub fn paste(&mut self, scrap: &[u8]) {
+        self.replace_selection(scrap);
+        self.sel_view();
+    }
+
+    /// Scrolls the text by `dh` pixels horizontally and `dv` pixels
+    /// vertically, like `TEScroll`. Positive values move the text down and to
+    /// the right.
+    pub fn scroll(&mut self, dh: i16, dv: i16) {
+        self.dest_rect = self.dest_rect.offset(dh, dv);
+    }
+
+    /// The distance from the top of the text to the top of the view.
+    #[must_use]
+    pub fn scroll_top(&self) -> i16 {
+        self.view_rect.top - self.dest_rect.top
+    }
+
+    /// The offset of the end of the selection.
+    #[must_use]
+    pub fn sel_end(&self) -> usize {
+        self.sel_end
+    }
+
+    /// The offset of the start of the selection.
+    #[must_use]
+    pub fn sel_start(&self) -> usize {
+        self.sel_start
+    }
+
+    /// Scrolls the text vertically so that the insertion point is visible,
+    /// like `TESelView`.
+    pub fn sel_view(&mut self) {
+        let top = self.get_point(self.sel_end).y;
+        let bottom = top + self.line_height;
+        if top < self.view_rect.top {
+            self.scroll(0, self.view_rect.top - top);
+        } else if bottom > self.view_rect.bottom {
+            self.scroll(0, (self.view_rect.bottom - bottom).max(self.view_rect.top - top));
+        }
+    }
+
+    /// The selected text.
+    #[must_use]
+    pub fn selection(&self) -> &[u8] {
+        &self.text[self.sel_start..self.sel_end]
+    }
+
+    /// Sets whether lines only break at carriage returns, instead of also
+    /// wrapping at the right edge of the destination rectangle, like the
+    /// `crOnly` field of a `TextEdit` record.
+    pub fn set_cr_only(&mut self, cr_only: bool) {
+        if self.cr_only != cr_only {
+            self.cr_only = cr_only;
+            self.cal_text();
+        }
+    }
+
+    /// Selects the text between two offsets, like `TESetSelect`. Offsets past
+    /// the end of the text select up to the end.
+    pub fn set_select(&mut self, start: usize, end: usize) {
+        let start = start.min(self.text.len());
+        let end = end.min(self.text.len());
+        self.sel_start = start.min(end);
+        self.sel_end = start.max(end);
+    }
+
+    /// Replaces the text and moves the insertion point to the end, like
+    /// `TESetText`.
+    pub fn set_text(&mut self, text: &[u8]) {
+        self.text = text.to_vec();
+        self.cal_text();
+        self.set_select(self.text.len(), self.text.len());
+    }
+
+    /// The text, like `TEGetText`.
+    #[must_use]
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    /// Draws the text and the selection which are inside the given
+    /// rectangle, like `TEUpdate`.
+    pub fn update(&self, port: &mut CGrafPort, rect: Rect) {
+        let old_clip = port.clip_rgn().clone();
+        port.set_clip(old_clip.intersect(&Region::from(rect.intersect(self.view_rect))));
+
+        for line in 0..self.n_lines() {
+            let (start, end) = self.line_range(line);
+            let top = self.dest_rect.top + line as i16 * self.line_height;
+            if top + self.line_height <= self.view_rect.top || top >= self.view_rect.bottom {
+                continue;
+            }
+            port.move_to(self.dest_rect.left, top + self.font_ascent);
+            port.draw_text(&self.font, &self.text[start..end]);
+        }
+
+        if self.active {
+            if let Some(caret) = self.caret_rect() {
+                if self.caret_state {
+                    port.std_rect(GrafVerb::Invert, caret);
+                }
+            } else {
+                for rect in self.selection_rects() {
+                    port.std_rect(GrafVerb::Invert, rect);
+                }
+            }
+        }
+
+        port.set_clip(old_clip);
+    }
+
+    /// The rectangle where the text is visible.
+    #[must_use]
+    pub fn view_rect(&self) -> Rect {
+        self.view_rect
+    }
+
+    /// Recalculates the line starts, like `TECalText`.
+    fn cal_text(&mut self) {
+        self.line_starts.clear();
+        self.line_starts.push(0);
+
+        let max_width = self.dest_rect.width();
+        let mut line_start = 0;
+        let mut width = 0;
+        let mut word_break = None;
+        for (offset, &c) in self.text.iter().enumerate() {
+            if c == RETURN {
+                line_start = offset + 1;
+                self.line_starts.push(line_start);
+                width = 0;
+                word_break = None;
+                continue;
+            }
+
+            // Spaces may hang past the right edge, like in TextEdit
+            let char_width = self.font.char_width(c);
+            if !self.cr_only && c != b' ' && offset > line_start && width + char_width > max_width {
+                line_start = word_break.unwrap_or(offset);
+                self.line_starts.push(line_start);
+                width = self.font.text_width(&self.text[line_start..offset]);
+                word_break = None;
+            }
+
+            if c == b' ' {
+                word_break = Some(offset + 1);
+            }
+            width += char_width;
+        }
+
+        self.sel_start = self.sel_start.min(self.text.len());
+        self.sel_end = self.sel_end.min(self.text.len());
+    }
+
+    /// Returns the line which contains the given offset.
+    fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.iter().rposition(|&start| start <= offset).unwrap_or(0)
+    }
+
+    /// Returns the range of visible characters in the given line, which
+    /// excludes the carriage return which ends the line.
+    fn line_range(&self, line: usize) -> (usize, usize) {
+        let start = self.line_starts[line];
+        let mut end = self.line_starts.get(line + 1).copied().unwrap_or(self.text.len());
+        if end > start && self.text[end - 1] == RETURN {
+            end -= 1;
+        }
+        (start, end)
+    }
+
+    fn replace_selection(&mut self, text: &[u8]) {
+        let start = self.sel_start;
+        self.text.splice(start..self.sel_end, text.iter().copied());
+        self.cal_text();
+        self.set_select(start + text.len(), start + text.len());
+    }
+
+    /// The rectangles which are highlighted for the selection range, one per
+    /// line.
+    fn selection_rects(&self) -> Vec<Rect> {
+        let (first, last) = (self.line_of(self.sel_start), self.line_of(self.sel_end));
+        (first..=last).filter_map(|line| {
+            let (start, end) = self.line_range(line);
+            let left = self.get_point(start.max(self.sel_start)).x;
+            let right = if line == last {
+                self.get_point(self.sel_end).x
+            } else {
+                self.dest_rect.right.max(self.get_point(end).x)
+            };
+            let top = self.dest_rect.top + line as i16 * self.line_height;
+            let rect = Rect { top, left, bottom: top + self.line_height, right };
+            if rect.is_empty() { None } else { Some(rect) }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use byteordered::{ByteOrdered, Endianness};
+    use crate::quickdraw::{BitmapFont, Style};
+    use libcommon::Resource;
+    use std::{io::Cursor, rc::Rc};
+    use super::*;
+
+    /// Builds a font with no glyph images where every printable ASCII
+    /// character is 6 pixels wide, with a line height of 12 pixels.
+    fn test_font() -> Font {
+        let mut data = Vec::new();
+        for value in &[
+            0x9000_u16, // font type
+            0x20, 0x7e, // first and last characters
+            6, 0, 0xfffe, // maximum width, maximum kern, negated descent
+            6, 0, 0, // font rectangle width and height, ow table offset
+            8, 2, 2, // ascent, descent, leading
+            0, // row words
+        ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        for _ in 0..97 {
+            data.extend_from_slice(&0_u16.to_be_bytes());
+        }
+        for _ in 0..97 {
+            data.extend_from_slice(&6_u16.to_be_bytes());
+        }
+        let mut input = ByteOrdered::new(Cursor::new(&data), Endianness::Big);
+        let strike = BitmapFont::load(&mut input, data.len() as u32, &()).unwrap();
+        Font::new(Rc::new(strike), Style::empty())
+    }
+
+    fn edit(text: &[u8]) -> TEHandle {
+        let rect = Rect { top: 0, left: 0, bottom: 24, right: 60 };
+        let mut te = TEHandle::new(rect, rect, test_font());
+        te.set_text(text);
+        te
+    }
+
+    #[test]
+    fn word_wrap() {
+        let mut te = edit(b"hello world wide\rweb");
+        assert_eq!(te.line_starts, vec![ 0, 6, 17 ]);
+        assert_eq!(te.get_point(14), Point { x: 48, y: 12 });
+        assert_eq!(te.get_offset(Point { x: 14, y: 13 }), 8);
+        assert_eq!(te.get_offset(Point { x: 200, y: 13 }), 16);
+        assert_eq!(te.get_offset(Point { x: 200, y: 200 }), 20);
+
+        te.set_cr_only(true);
+        assert_eq!(te.line_starts, vec![ 0, 17 ]);
+    }
+
+    #[test]
+    fn typing() {
+        let mut te = edit(b"abc");
+        assert_eq!((te.sel_start(), te.sel_end()), (3, 3));
+        te.key(BACKSPACE);
+        te.key(LEFT_ARROW);
+        te.key(b'x');
+        assert_eq!(te.text(), b"axb");
+        te.set_select(0, 2);
+        te.key(b'y');
+        assert_eq!(te.text(), b"yb");
+        te.key(FORWARD_DELETE);
+        assert_eq!(te.text(), b"y");
+        te.key(RETURN);
+        te.key(b'z');
+        assert_eq!(te.text(), b"y\rz");
+        te.key(UP_ARROW);
+        assert_eq!(te.sel_start(), 1);
+        te.key(UP_ARROW);
+        assert_eq!(te.sel_start(), 0);
+    }
+
+    #[test]
+    fn clipboard_and_selection() {
+        let mut te = edit(b"one two");
+        let mut scrap = Vec::new();
+        te.click(Point { x: 1, y: 1 }, false);
+        te.click(Point { x: 17, y: 1 }, true);
+        assert_eq!(te.selection(), b"one");
+        te.cut(&mut scrap);
+        assert_eq!(scrap, b"one");
+        assert_eq!(te.text(), b" two");
+        te.set_select(4, 4);
+        te.paste(&scrap);
+        assert_eq!(te.text(), b" twoone");
+        assert_eq!((te.sel_start(), te.sel_end()), (7, 7));
+        te.set_select(1, 10);
+        te.copy(&mut scrap);
+        assert_eq!(scrap, b"twoone");
+    }
+
+    #[test]
+    fn scroll_to_selection() {
+        let mut te = edit(b"");
+        for _ in 0..3 {
+            te.key(RETURN);
+        }
+        assert_eq!(te.n_lines(), 4);
+        assert_eq!(te.scroll_top(), 24);
+        te.set_select(0, 0);
+        te.sel_view();
+        assert_eq!(te.scroll_top(), 0);
+        te.scroll(0, -5);
+        assert_eq!(te.dest_rect().top, -5);
+    }
+}
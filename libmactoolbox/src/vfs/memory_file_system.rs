@@ -0,0 +1,170 @@
+use anyhow::{anyhow, bail, Result as AResult};
+use libcommon::vfs::{ForkKind, VirtualFile, VirtualFileSystem};
+use std::{collections::HashMap, io::{prelude::*, Cursor, self}, path::{Path, PathBuf}, rc::Rc};
+
+/// A virtual file system whose files exist only in memory.
+///
+/// Each file may have a data fork, a resource fork, or both. This is useful
+/// for embedded fixtures and for building synthetic test data without needing
+/// any files on disk.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    files: HashMap<PathBuf, MemoryForks>,
+}
+
+impl MemoryFileSystem {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if a file exists at the given path.
+    #[must_use]
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        self.files.contains_key(path.as_ref())
+    }
+
+    /// Adds or replaces one fork of the file at the given path.
+    pub fn insert(&mut self, path: impl AsRef<Path>, kind: ForkKind, data: impl Into<Rc<[u8]>>) {
+        let forks = self.files.entry(path.as_ref().to_path_buf()).or_default();
+        let data = Some(data.into());
+        match kind {
+            ForkKind::Data => forks.data = data,
+            ForkKind::Resource => forks.resource = data,
+        }
+    }
+
+    /// Removes the file at the given path, including all of its forks.
+    /// Returns `true` if the file existed.
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> bool {
+        self.files.remove(path.as_ref()).is_some()
+    }
+
+    /// Adds or replaces the data fork of the file at the given path.
+    #[must_use]
+    pub fn with_data_fork(mut self, path: impl AsRef<Path>, data: impl Into<Rc<[u8]>>) -> Self {
+        self.insert(path, ForkKind::Data, data);
+        self
+    }
+
+    /// Adds or replaces the resource fork of the file at the given path.
+    #[must_use]
+    pub fn with_resource_fork(mut self, path: impl AsRef<Path>, data: impl Into<Rc<[u8]>>) -> Self {
+        self.insert(path, ForkKind::Resource, data);
+        self
+    }
+
+    fn open_impl(&self, path: impl AsRef<Path>, kind: ForkKind) -> AResult<Box<dyn VirtualFile>> {
+        let path = path.as_ref();
+        let forks = self.files.get(path)
+            .ok_or_else(|| anyhow!("File not found"))?;
+
+        let data = match kind {
+            ForkKind::Data => &forks.data,
+            ForkKind::Resource => &forks.resource,
+        };
+
+        match data {
+            Some(data) => Ok(Box::new(MemoryFile {
+                path: path.to_path_buf(),
+                inner: Cursor::new(Rc::clone(data)),
+            })),
+            None => bail!("No {} fork", match kind {
+                ForkKind::Data => "data",
+                ForkKind::Resource => "resource"
+            })
+        }
+    }
+}
+
+impl VirtualFileSystem for MemoryFileSystem {
+    fn open<'a>(&'a self, path: &dyn AsRef<Path>) -> AResult<Box<dyn VirtualFile + 'a>> {
+        self.open_impl(path, ForkKind::Data)
+    }
+
+    fn open_resource_fork<'a>(&'a self, path: &dyn AsRef<Path>) -> AResult<Box<dyn VirtualFile + 'a>> {
+        self.open_impl(path, ForkKind::Resource)
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryForks {
+    data: Option<Rc<[u8]>>,
+    resource: Option<Rc<[u8]>>,
+}
+
+#[derive(Debug)]
+struct MemoryFile {
+    path: PathBuf,
+    inner: Cursor<Rc<[u8]>>,
+}
+
+impl VirtualFile for MemoryFile {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::wildcard_imports)]
+    use super::*;
+    use libcommon::Reader;
+
+    #[test]
+    fn open_forks() {
+        let fs = MemoryFileSystem::new()
+            .with_data_fork("a", &b"data"[..])
+            .with_resource_fork("a", vec![ 1_u8, 2, 3 ]);
+
+        let mut data = Vec::new();
+        fs.open(&"a").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"data");
+
+        let mut file = fs.open_resource_fork(&"a").unwrap();
+        assert_eq!(file.path(), Path::new("a"));
+        assert_eq!(file.name(), Some(Path::new("a")));
+        assert_eq!(file.len().unwrap(), 3);
+        file.seek(io::SeekFrom::Start(1)).unwrap();
+        let mut resource = Vec::new();
+        file.read_to_end(&mut resource).unwrap();
+        assert_eq!(resource, [ 2, 3 ]);
+    }
+
+    #[test]
+    fn files_are_independent() {
+        let fs = MemoryFileSystem::new().with_data_fork("a", &b"abc"[..]);
+        let mut first = fs.open(&"a").unwrap();
+        let mut second = fs.open(&"a").unwrap();
+        first.skip(2).unwrap();
+        assert_eq!(first.pos().unwrap(), 2);
+        assert_eq!(second.pos().unwrap(), 0);
+        let mut byte = [ 0; 1 ];
+        second.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, *b"a");
+    }
+
+    #[test]
+    fn missing_files() {
+        let mut fs = MemoryFileSystem::new().with_resource_fork("a", &b"rsrc"[..]);
+        assert!(fs.open(&"a").is_err());
+        assert!(fs.open(&"b").is_err());
+        assert!(fs.open_resource_fork(&"b").is_err());
+        assert!(fs.contains("a"));
+        assert!(fs.remove("a"));
+        assert!(!fs.contains("a"));
+        assert!(fs.open_resource_fork(&"a").is_err());
+    }
+}
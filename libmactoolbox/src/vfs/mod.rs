@@ -1,7 +1,9 @@
 mod host_file_system;
+mod memory_file_system;
 #[cfg(feature = "vfs_zip")]
 mod zip;
 
 pub use host_file_system::HostFileSystem;
+pub use memory_file_system::MemoryFileSystem;
 #[cfg(feature = "vfs_zip")]
 pub use self::zip::Zip;